@@ -1,11 +1,15 @@
 use crate::device::Device;
-use crate::device::{self, DeviceId};
+use crate::device::{self, DeviceId, DynamicBatteryFields};
+use embassy_futures::select::{select, Either};
 use embassy_sync::channel::Channel;
+use embassy_sync::mutex::Mutex;
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::TrySendError};
-use embassy_time::{with_timeout, Duration};
+use embassy_time::{with_timeout, Duration, Ticker, Timer};
+use embedded_batteries_async::smart_battery::{CapacityModeSignedValue, CapacityModeValue, Minutes};
+use embedded_services::comms::{self, EndpointID, External, Internal};
 use embedded_services::{debug, error, info, intrusive_list, trace, warn, IntrusiveList};
 
-use core::cell::RefCell;
+use core::cell::Cell;
 use core::ops::DerefMut;
 
 /// Battery service states.
@@ -35,13 +39,89 @@ pub enum OperationalSubstate {
 
 /// Battery state machine events.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum BatteryEventInner {
     DoInit,
     PollDynamicData,
     PollStaticData,
     Timeout,
     Oem(u8, &'static [u8]),
+    /// Set the remaining capacity alarm threshold.
+    SetCapacityAlarm(CapacityModeValue),
+    /// Set the remaining time alarm threshold.
+    SetTimeAlarm(Minutes),
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for BatteryEventInner {
+    fn format(&self, fmt: defmt::Formatter) {
+        match self {
+            BatteryEventInner::DoInit => defmt::write!(fmt, "DoInit"),
+            BatteryEventInner::PollDynamicData => defmt::write!(fmt, "PollDynamicData"),
+            BatteryEventInner::PollStaticData => defmt::write!(fmt, "PollStaticData"),
+            BatteryEventInner::Timeout => defmt::write!(fmt, "Timeout"),
+            BatteryEventInner::Oem(id, data) => defmt::write!(fmt, "Oem({}, {})", id, data),
+            BatteryEventInner::SetCapacityAlarm(capacity) => {
+                defmt::write!(fmt, "SetCapacityAlarm({})", defmt::Debug2Format(capacity))
+            }
+            BatteryEventInner::SetTimeAlarm(minutes) => defmt::write!(fmt, "SetTimeAlarm({})", minutes),
+        }
+    }
+}
+
+/// Remaining capacity level relative to the configured thresholds, ordered by severity.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CapacityLevel {
+    #[default]
+    Normal,
+    Low,
+    Critical,
+}
+
+/// Remaining capacity thresholds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CapacityThresholds {
+    /// Low capacity threshold in mWh.
+    pub low_mwh: u32,
+
+    /// Critical capacity threshold in mWh.
+    pub critical_mwh: u32,
+
+    /// Capacity above a threshold required to leave its level in mWh.
+    pub hysteresis_mwh: u32,
+}
+
+impl CapacityThresholds {
+    /// Get the new level for the remaining capacity given the current level.
+    fn level(&self, remaining_mwh: u32, current: CapacityLevel) -> CapacityLevel {
+        let mut level = if remaining_mwh <= self.critical_mwh {
+            CapacityLevel::Critical
+        } else if remaining_mwh <= self.low_mwh {
+            CapacityLevel::Low
+        } else {
+            CapacityLevel::Normal
+        };
+
+        // Hold each level until capacity is clear of its threshold by the hysteresis
+        if current >= CapacityLevel::Low && remaining_mwh <= self.low_mwh.saturating_add(self.hysteresis_mwh) {
+            level = level.max(CapacityLevel::Low);
+        }
+        if current >= CapacityLevel::Critical && remaining_mwh <= self.critical_mwh.saturating_add(self.hysteresis_mwh)
+        {
+            level = CapacityLevel::Critical;
+        }
+        level
+    }
+}
+
+/// Notification sent to the host when remaining capacity drops to a more severe level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CapacityThresholdEvent {
+    pub device_id: DeviceId,
+    pub level: CapacityLevel,
+    pub remaining_capacity_mwh: u32,
 }
 
 /// Battery state machine response.
@@ -93,10 +173,17 @@ pub struct BatteryEvent {
 /// Battery service context, hardware agnostic state.
 pub struct Context {
     fuel_gauges: IntrusiveList,
-    state: RefCell<State>,
     battery_event: Channel<NoopRawMutex, BatteryEvent, 1>,
     battery_response: Channel<NoopRawMutex, BatteryResponse, 1>,
     state_machine_timeout_ms: Duration,
+    dynamic_poll_fields: Cell<DynamicBatteryFields>,
+    poll_interval: Cell<Option<Duration>>,
+    /// Device polled last in the current poll round, the rest of the round follows it in the device list
+    poll_round: Cell<Option<DeviceId>>,
+    poll_ticker: Mutex<NoopRawMutex, Option<(Duration, Ticker)>>,
+    retry_count: Cell<u8>,
+    retry_delay: Cell<Duration>,
+    capacity_thresholds: Cell<Option<CapacityThresholds>>,
 }
 
 impl Context {
@@ -104,13 +191,54 @@ impl Context {
     pub fn new() -> Self {
         Self {
             fuel_gauges: IntrusiveList::new(),
-            state: RefCell::new(State::NotPresent),
             battery_event: Channel::new(),
             battery_response: Channel::new(),
             state_machine_timeout_ms: Duration::from_secs(120),
+            dynamic_poll_fields: Cell::new(DynamicBatteryFields::all()),
+            poll_interval: Cell::new(None),
+            poll_round: Cell::new(None),
+            poll_ticker: Mutex::new(None),
+            retry_count: Cell::new(0),
+            retry_delay: Cell::new(Duration::from_millis(0)),
+            capacity_thresholds: Cell::new(None),
         }
     }
 
+    /// Set the remaining capacity thresholds that notify the host when crossed, None disables notifications.
+    pub fn set_capacity_thresholds(&self, thresholds: Option<CapacityThresholds>) {
+        self.capacity_thresholds.set(thresholds);
+    }
+
+    /// Set how many times a failed cache update is retried and the delay between retries.
+    pub fn set_retry(&self, count: u8, delay: Duration) {
+        self.retry_count.set(count);
+        self.retry_delay.set(delay);
+    }
+
+    /// Set the interval for autonomous dynamic data polling, a zero interval disables autonomous polling.
+    pub fn set_poll_interval(&self, interval: Duration) {
+        self.poll_interval.set(if interval == Duration::from_ticks(0) {
+            None
+        } else {
+            Some(interval)
+        });
+    }
+
+    /// Get the interval for autonomous dynamic data polling, None if disabled.
+    pub fn get_poll_interval(&self) -> Option<Duration> {
+        self.poll_interval.get()
+    }
+
+    /// Set the dynamic fields to read when polling dynamic data.
+    pub fn set_dynamic_poll_fields(&self, fields: DynamicBatteryFields) {
+        self.dynamic_poll_fields.set(fields);
+    }
+
+    /// Get the dynamic fields to read when polling dynamic data.
+    pub fn get_dynamic_poll_fields(&self) -> DynamicBatteryFields {
+        self.dynamic_poll_fields.get()
+    }
+
     /// Get global state machine timeout.
     fn get_state_machine_timeout(&self) -> Duration {
         self.state_machine_timeout_ms
@@ -181,14 +309,75 @@ impl Context {
                 trace!("State = {:?}", *state);
                 Ok(State::Present(PresentSubstate::NotOperational))
             }
-            BatteryEventInner::Oem(_, _items) => todo!(),
+            // OEM commands and alarm configuration are handled before the state machine runs and never transition state
+            BatteryEventInner::Oem(_, _)
+            | BatteryEventInner::SetCapacityAlarm(_)
+            | BatteryEventInner::SetTimeAlarm(_) => Ok(*state),
+        }
+    }
+
+    /// Forward an alarm configuration command to the fuel gauge, only valid while operational.
+    async fn configure_alarm(&self, state: State, id: DeviceId, command: device::Command) -> StateMachineResponse {
+        if !matches!(state, State::Present(PresentSubstate::Operational(_))) {
+            error!("Battery Service: received alarm configuration while not operational");
+            trace!("State = {:?}", state);
+            return Err(StateMachineError::InvalidActionInState);
+        }
+
+        match self.execute_device_command(id, command).await {
+            Ok(Ok(_)) => Ok(InnerStateMachineResponse::Complete),
+            _ => {
+                error!("Error configuring alarm on fuel gauge with ID {:?}", id);
+                Err(StateMachineError::DeviceError)
+            }
+        }
+    }
+
+    /// Forward an OEM command to the fuel gauge, valid whenever a battery is present.
+    async fn oem_command(&self, state: State, id: DeviceId, oem_id: u8, data: &'static [u8]) -> StateMachineResponse {
+        if state == State::NotPresent {
+            error!("Battery Service: received OEM command while not present");
+            return Err(StateMachineError::InvalidActionInState);
+        }
+
+        match self
+            .execute_device_command(id, device::Command::Oem(oem_id, data))
+            .await
+        {
+            Ok(Ok(_)) => Ok(InnerStateMachineResponse::Complete),
+            _ => {
+                error!("Error executing OEM command {} on fuel gauge with ID {:?}", oem_id, id);
+                Err(StateMachineError::DeviceError)
+            }
         }
     }
 
     /// Main battery service state machine
-    #[allow(clippy::await_holding_refcell_ref)]
+    ///
+    /// Runs on the state of the addressed fuel gauge, concurrent calls for the same fuel gauge are serialized by its state lock.
     async fn do_state_machine(&self, event: BatteryEvent) -> StateMachineResponse {
-        let mut state = self.state.borrow_mut();
+        let Some(device) = self.get_fuel_gauge(event.device_id) else {
+            error!("Fuel gauge with ID {:?} not found", event.device_id);
+            return Err(StateMachineError::DeviceError);
+        };
+        let mut state = device.lock_state().await;
+
+        match event.event {
+            BatteryEventInner::SetCapacityAlarm(capacity) => {
+                return self
+                    .configure_alarm(*state, event.device_id, device::Command::SetCapacityAlarm(capacity))
+                    .await
+            }
+            BatteryEventInner::SetTimeAlarm(minutes) => {
+                return self
+                    .configure_alarm(*state, event.device_id, device::Command::SetTimeAlarm(minutes))
+                    .await
+            }
+            BatteryEventInner::Oem(oem_id, data) => {
+                return self.oem_command(*state, event.device_id, oem_id, data).await;
+            }
+            _ => (),
+        }
 
         // BatteryEventInner can transition state, or an invalid event can cause the state machine to return
         match self.handle_event(state.deref_mut(), event.event) {
@@ -230,10 +419,9 @@ impl Context {
                     PresentSubstate::Operational(operational_substate) => match operational_substate {
                         OperationalSubstate::Init => {
                             // Collect static data
-                            // TODO: Add retry logic
                             info!("Collecting fuel gauge static cache with ID {:?}", event.device_id);
                             if self
-                                .execute_device_command(event.device_id, device::Command::UpdateStaticCache)
+                                .execute_device_command_with_retry(event.device_id, device::Command::UpdateStaticCache)
                                 .await
                                 .is_err()
                             {
@@ -245,10 +433,12 @@ impl Context {
                         }
                         OperationalSubstate::Polling => {
                             // Collect dynamic data
-                            // TODO: Add retry logic
                             info!("Collecting fuel gauge dynamic cache with ID {:?}", event.device_id);
                             if self
-                                .execute_device_command(event.device_id, device::Command::UpdateDynamicCache)
+                                .execute_device_command_with_retry(
+                                    event.device_id,
+                                    device::Command::UpdateDynamicCache(self.get_dynamic_poll_fields()),
+                                )
                                 .await
                                 .is_err()
                             {
@@ -258,6 +448,8 @@ impl Context {
                                 );
                                 return Err(StateMachineError::DeviceError);
                             }
+
+                            self.check_capacity_thresholds(device).await;
                         }
                     },
                 },
@@ -268,6 +460,58 @@ impl Context {
         }
     }
 
+    /// Notify the host if the remaining capacity dropped to a more severe level.
+    async fn check_capacity_thresholds(&self, device: &Device) {
+        let Some(thresholds) = self.capacity_thresholds.get() else {
+            return;
+        };
+
+        let remaining_capacity_mwh = device.get_dynamic_battery_cache().remaining_capacity_mwh;
+        let current = device.capacity_level();
+        let level = thresholds.level(remaining_capacity_mwh, current);
+        device.set_capacity_level(level);
+
+        if level > current {
+            info!(
+                "Fuel gauge with ID {:?} remaining capacity {} mWh is {:?}",
+                device.id(),
+                remaining_capacity_mwh,
+                level
+            );
+            let _ = comms::send(
+                EndpointID::Internal(Internal::Battery),
+                EndpointID::External(External::Host),
+                &CapacityThresholdEvent {
+                    device_id: device.id(),
+                    level,
+                    remaining_capacity_mwh,
+                },
+            )
+            .await;
+        }
+    }
+
+    /// Find the first device in the polling substate, only considering devices after `after` in the device list
+    async fn next_polling_device(&self, after: Option<DeviceId>) -> Option<DeviceId> {
+        let mut skipping = after.is_some();
+        for device in &self.fuel_gauges {
+            let Some(data) = device.data::<Device>() else {
+                error!("Non-device located in devices list");
+                continue;
+            };
+
+            if skipping {
+                skipping = Some(data.id()) != after;
+                continue;
+            }
+
+            if data.state().await == State::Present(PresentSubstate::Operational(OperationalSubstate::Polling)) {
+                return Some(data.id());
+            }
+        }
+        None
+    }
+
     fn get_fuel_gauge(&self, id: DeviceId) -> Option<&'static Device> {
         for device in &self.fuel_gauges {
             if let Some(data) = device.data::<Device>() {
@@ -290,6 +534,48 @@ impl Context {
         self.fuel_gauges.push(device)
     }
 
+    /// Set the at-rate on a fuel gauge and return whether it can be sustained along with the predicted time to empty.
+    pub async fn at_rate_prediction(
+        &self,
+        id: DeviceId,
+        rate: CapacityModeSignedValue,
+    ) -> Result<device::AtRatePrediction, ContextError> {
+        let device = self.get_fuel_gauge(id).ok_or(ContextError::DeviceNotFound)?;
+        // Hold the state lock so the query doesn't interleave with the state machine
+        let state = device.lock_state().await;
+        if !matches!(*state, State::Present(PresentSubstate::Operational(_))) {
+            error!("Battery Service: received at-rate query while not operational");
+            trace!("State = {:?}", *state);
+            return Err(ContextError::StateError(StateMachineError::InvalidActionInState));
+        }
+
+        match self
+            .execute_device_command(id, device::Command::AtRatePrediction(rate))
+            .await?
+        {
+            Ok(device::InternalResponse::AtRate(prediction)) => Ok(prediction),
+            _ => {
+                error!("Error getting at-rate prediction from fuel gauge with ID {:?}", id);
+                Err(ContextError::StateError(StateMachineError::DeviceError))
+            }
+        }
+    }
+
+    /// Returns true if any registered fuel gauge has a battery present.
+    pub async fn any_present(&self) -> bool {
+        for device in self.fuel_gauges.iter_only::<Device>() {
+            if device.state().await != State::NotPresent {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Limit the number of fuel gauges that can be registered, None removes the limit.
+    pub fn set_max_fuel_gauges(&self, max: Option<usize>) {
+        self.fuel_gauges.set_max_len(max);
+    }
+
     async fn send_event(&self, event: BatteryEvent) {
         self.battery_event.send(event).await;
     }
@@ -313,6 +599,83 @@ impl Context {
         self.battery_event.receive().await
     }
 
+    /// Wait for battery event, or a dynamic data poll event when autonomous polling is enabled.
+    ///
+    /// The poll ticker only runs while a device is in the polling substate, each tick polls every such device.
+    pub async fn wait_event_or_poll(&self) -> BatteryEvent {
+        let poll_event = |device_id| BatteryEvent {
+            event: BatteryEventInner::PollDynamicData,
+            device_id,
+        };
+
+        // Finish the current round before waiting on the next tick
+        if let Some(last) = self.poll_round.get() {
+            self.poll_round.set(None);
+            if self.poll_interval.get().is_some() {
+                if let Some(device_id) = self.next_polling_device(Some(last)).await {
+                    self.poll_round.set(Some(device_id));
+                    return poll_event(device_id);
+                }
+            }
+        }
+
+        let polling = self.next_polling_device(None).await.is_some();
+        let mut poll_ticker = self.poll_ticker.lock().await;
+
+        let (Some(interval), true) = (self.poll_interval.get(), polling) else {
+            // Restart the ticker once polling resumes instead of catching up on missed ticks
+            *poll_ticker = None;
+            drop(poll_ticker);
+            return self.wait_event().await;
+        };
+
+        if poll_ticker.as_ref().is_some_and(|(current, _)| *current != interval) {
+            *poll_ticker = None;
+        }
+        let (_, ticker) = poll_ticker.get_or_insert_with(|| (interval, Ticker::every(interval)));
+
+        match select(self.wait_event(), ticker.next()).await {
+            Either::First(event) => event,
+            Either::Second(()) => {
+                drop(poll_ticker);
+                // The polling devices can change while waiting on the tick
+                match self.next_polling_device(None).await {
+                    Some(device_id) => {
+                        trace!("Battery poll ticker fired for fuel gauge with ID {:?}", device_id);
+                        self.poll_round.set(Some(device_id));
+                        poll_event(device_id)
+                    }
+                    None => self.wait_event().await,
+                }
+            }
+        }
+    }
+
+    /// Execute a device command, retrying device errors and timeouts up to the configured retry count.
+    async fn execute_device_command_with_retry(
+        &self,
+        id: DeviceId,
+        command: device::Command,
+    ) -> Result<device::InternalResponse, ContextError> {
+        let mut retries = self.retry_count.get();
+        loop {
+            let res = match self.execute_device_command(id, command).await {
+                Ok(Ok(response)) => return Ok(response),
+                Ok(Err(_)) => Err(ContextError::StateError(StateMachineError::DeviceError)),
+                Err(ContextError::Timeout) => Err(ContextError::Timeout),
+                Err(e) => return Err(e),
+            };
+
+            if retries == 0 {
+                return res;
+            }
+            retries -= 1;
+
+            warn!("Retrying command {:?} on fuel gauge with ID {:?}", command, id);
+            Timer::after(self.retry_delay.get()).await;
+        }
+    }
+
     async fn execute_device_command(
         &self,
         id: DeviceId,
@@ -343,3 +706,481 @@ impl Default for Context {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use core::cell::RefCell;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    use embassy_futures::block_on;
+    use embassy_futures::join::join3;
+    use embassy_futures::select::{select3, Either3};
+    use embassy_time::{Instant, Timer};
+
+    use super::*;
+
+    /// Responds to every device command, counting dynamic data polls
+    async fn respond(device: &Device, dynamic_polls: &Cell<usize>) {
+        loop {
+            if let device::Command::UpdateDynamicCache(_) = device.receive_command().await {
+                dynamic_polls.set(dynamic_polls.get() + 1);
+            }
+            device.send_response(Ok(device::InternalResponse::Complete)).await;
+        }
+    }
+
+    #[test]
+    fn test_poll_ticker() {
+        const ID: DeviceId = DeviceId(0);
+        const INTERVAL: Duration = Duration::from_millis(20);
+
+        let context = Context::new();
+        let device: &'static Device = Box::leak(Box::new(Device::new(ID)));
+        let dynamic_polls = Cell::new(0);
+        block_on(context.register_fuel_gauge(device)).unwrap();
+
+        // Disabled by default and with a zero interval
+        assert_eq!(context.get_poll_interval(), None);
+        context.set_poll_interval(Duration::from_ticks(0));
+        assert_eq!(context.get_poll_interval(), None);
+        context.set_poll_interval(INTERVAL);
+        assert_eq!(context.get_poll_interval(), Some(INTERVAL));
+
+        let test = async {
+            // Ticker doesn't run before reaching the polling state
+            assert!(matches!(
+                select(context.wait_event_or_poll(), Timer::after(INTERVAL * 3)).await,
+                Either::Second(())
+            ));
+
+            context
+                .process(BatteryEvent {
+                    event: BatteryEventInner::DoInit,
+                    device_id: ID,
+                })
+                .await;
+            assert_eq!(context.wait_response().await, Ok(ContextResponse::Ack));
+            assert_eq!(dynamic_polls.get(), 1);
+
+            // Dynamic data is collected on each tick
+            for i in 0..3 {
+                let start = Instant::now();
+                let event = context.wait_event_or_poll().await;
+                assert!(start.elapsed() >= INTERVAL / 2);
+                assert_eq!(
+                    event,
+                    BatteryEvent {
+                        event: BatteryEventInner::PollDynamicData,
+                        device_id: ID,
+                    }
+                );
+
+                context.process(event).await;
+                assert_eq!(context.wait_response().await, Ok(ContextResponse::Ack));
+                assert_eq!(dynamic_polls.get(), 2 + i);
+            }
+
+            // Disabling stops autonomous polling
+            context.set_poll_interval(Duration::from_ticks(0));
+            assert!(matches!(
+                select(context.wait_event_or_poll(), Timer::after(INTERVAL * 3)).await,
+                Either::Second(())
+            ));
+        };
+
+        block_on(async {
+            match select(respond(device, &dynamic_polls), test).await {
+                Either::First(_) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+    }
+
+    #[test]
+    fn test_poll_ticker_all_devices() {
+        const IDS: [DeviceId; 2] = [DeviceId(0), DeviceId(1)];
+        const INTERVAL: Duration = Duration::from_millis(20);
+
+        let context = Context::new();
+        let devices = IDS.map(|id| -> &'static Device { Box::leak(Box::new(Device::new(id))) });
+        let dynamic_polls = [Cell::new(0), Cell::new(0)];
+        for device in devices {
+            block_on(context.register_fuel_gauge(device)).unwrap();
+        }
+        context.set_poll_interval(INTERVAL);
+
+        let test = async {
+            for id in IDS {
+                context
+                    .process(BatteryEvent {
+                        event: BatteryEventInner::DoInit,
+                        device_id: id,
+                    })
+                    .await;
+                assert_eq!(context.wait_response().await, Ok(ContextResponse::Ack));
+            }
+
+            // Each tick polls both devices, not just the last one to start polling
+            for round in 1..=2 {
+                let mut polled = Vec::new();
+                for _ in IDS {
+                    let event = context.wait_event_or_poll().await;
+                    assert_eq!(event.event, BatteryEventInner::PollDynamicData);
+                    polled.push(event.device_id);
+
+                    context.process(event).await;
+                    assert_eq!(context.wait_response().await, Ok(ContextResponse::Ack));
+                }
+                polled.sort_unstable_by_key(|id| id.0);
+                assert_eq!(polled, IDS);
+                assert!(dynamic_polls.iter().all(|polls| polls.get() == 1 + round));
+            }
+        };
+
+        block_on(async {
+            match select3(
+                respond(devices[0], &dynamic_polls[0]),
+                respond(devices[1], &dynamic_polls[1]),
+                test,
+            )
+            .await
+            {
+                Either3::Third(()) => (),
+                _ => unreachable!(),
+            }
+        });
+    }
+
+    /// Accepts OEM command 1 and records its payload, rejects other OEM commands as unsupported
+    async fn respond_oem(device: &Device, payload: &Cell<Option<&'static [u8]>>) {
+        loop {
+            let response = match device.receive_command().await {
+                device::Command::Oem(1, data) => {
+                    payload.set(Some(data));
+                    Ok(device::InternalResponse::Complete)
+                }
+                device::Command::Oem(_, _) => Err(device::FuelGaugeError::Unsupported),
+                _ => Ok(device::InternalResponse::Complete),
+            };
+            device.send_response(response).await;
+        }
+    }
+
+    async fn execute(context: &Context, event: BatteryEventInner) -> BatteryResponse {
+        context
+            .process(BatteryEvent {
+                event,
+                device_id: DeviceId(0),
+            })
+            .await;
+        context.wait_response().await
+    }
+
+    #[test]
+    fn test_oem_command() {
+        const ID: DeviceId = DeviceId(0);
+        const POLLING: State = State::Present(PresentSubstate::Operational(OperationalSubstate::Polling));
+
+        let context = Context::new();
+        let device: &'static Device = Box::leak(Box::new(Device::new(ID)));
+        let payload = Cell::new(None);
+        block_on(context.register_fuel_gauge(device)).unwrap();
+
+        let test = async {
+            // No battery present yet
+            assert_eq!(
+                execute(&context, BatteryEventInner::Oem(1, &[0x01, 0x02])).await,
+                Err(ContextError::StateError(StateMachineError::InvalidActionInState))
+            );
+            assert_eq!(device.state().await, State::NotPresent);
+            assert_eq!(payload.get(), None);
+
+            assert_eq!(
+                execute(&context, BatteryEventInner::DoInit).await,
+                Ok(ContextResponse::Ack)
+            );
+            assert_eq!(device.state().await, POLLING);
+
+            // Payload is forwarded to the device without changing state
+            assert_eq!(
+                execute(&context, BatteryEventInner::Oem(1, &[0x01, 0x02])).await,
+                Ok(ContextResponse::Ack)
+            );
+            assert_eq!(payload.get(), Some(&[0x01u8, 0x02][..]));
+            assert_eq!(device.state().await, POLLING);
+
+            // Unsupported command surfaces a device error
+            assert_eq!(
+                execute(&context, BatteryEventInner::Oem(2, &[])).await,
+                Err(ContextError::StateError(StateMachineError::DeviceError))
+            );
+            assert_eq!(device.state().await, POLLING);
+        };
+
+        block_on(async {
+            match select(respond_oem(device, &payload), test).await {
+                Either::First(_) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+    }
+
+    #[test]
+    fn test_retry_cache_update() {
+        const ID: DeviceId = DeviceId(0);
+
+        let context = Context::new();
+        let device: &'static Device = Box::leak(Box::new(Device::new(ID)));
+        let static_failures = Cell::new(2);
+        block_on(context.register_fuel_gauge(device)).unwrap();
+        context.set_retry(2, Duration::from_millis(1));
+
+        // Static cache update fails twice before succeeding
+        let respond = async {
+            loop {
+                let response = match device.receive_command().await {
+                    device::Command::UpdateStaticCache if static_failures.get() > 0 => {
+                        static_failures.set(static_failures.get() - 1);
+                        Err(device::FuelGaugeError::BusError)
+                    }
+                    _ => Ok(device::InternalResponse::Complete),
+                };
+                device.send_response(response).await;
+            }
+        };
+
+        let test = async {
+            assert_eq!(
+                execute(&context, BatteryEventInner::DoInit).await,
+                Ok(ContextResponse::Ack)
+            );
+            assert_eq!(static_failures.get(), 0);
+            assert_eq!(
+                device.state().await,
+                State::Present(PresentSubstate::Operational(OperationalSubstate::Polling))
+            );
+        };
+
+        block_on(async {
+            match select(respond, test).await {
+                Either::First(()) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+    }
+
+    #[test]
+    fn test_independent_device_state() {
+        let context = Context::new();
+        let device0: &'static Device = Box::leak(Box::new(Device::new(DeviceId(0))));
+        let device1: &'static Device = Box::leak(Box::new(Device::new(DeviceId(1))));
+        let dynamic_polls = Cell::new(0);
+        block_on(context.register_fuel_gauge(device0)).unwrap();
+        block_on(context.register_fuel_gauge(device1)).unwrap();
+
+        let test = async {
+            assert!(!context.any_present().await);
+
+            assert_eq!(
+                execute(&context, BatteryEventInner::DoInit).await,
+                Ok(ContextResponse::Ack)
+            );
+            assert_eq!(
+                device0.state().await,
+                State::Present(PresentSubstate::Operational(OperationalSubstate::Polling))
+            );
+            assert_eq!(device1.state().await, State::NotPresent);
+            assert!(context.any_present().await);
+
+            // Polling the device that isn't present is rejected without affecting the other
+            context
+                .process(BatteryEvent {
+                    event: BatteryEventInner::PollDynamicData,
+                    device_id: DeviceId(1),
+                })
+                .await;
+            assert_eq!(
+                context.wait_response().await,
+                Err(ContextError::StateError(StateMachineError::InvalidActionInState))
+            );
+            assert_eq!(device1.state().await, State::NotPresent);
+            assert_eq!(
+                device0.state().await,
+                State::Present(PresentSubstate::Operational(OperationalSubstate::Polling))
+            );
+        };
+
+        block_on(async {
+            match select3(respond(device0, &dynamic_polls), respond(device1, &dynamic_polls), test).await {
+                Either3::First(_) | Either3::Second(_) => unreachable!(),
+                Either3::Third(()) => (),
+            }
+        });
+    }
+
+    #[test]
+    fn test_concurrent_process() {
+        const ID: DeviceId = DeviceId(0);
+
+        let context = Context::new();
+        let device: &'static Device = Box::leak(Box::new(Device::new(ID)));
+        let commands = RefCell::new(Vec::new());
+        block_on(context.register_fuel_gauge(device)).unwrap();
+
+        // Yields while handling each command so unserialized state machine runs would interleave
+        let respond = async {
+            loop {
+                let command = device.receive_command().await;
+                commands.borrow_mut().push(command);
+                Timer::after_millis(1).await;
+                device.send_response(Ok(device::InternalResponse::Complete)).await;
+            }
+        };
+
+        let test = async {
+            let init = context.process(BatteryEvent {
+                event: BatteryEventInner::DoInit,
+                device_id: ID,
+            });
+            // Only valid once the init has completed
+            let poll = context.process(BatteryEvent {
+                event: BatteryEventInner::PollDynamicData,
+                device_id: ID,
+            });
+            let responses = async { [context.wait_response().await, context.wait_response().await] };
+
+            let ((), (), responses) = join3(init, poll, responses).await;
+            assert_eq!(responses, [Ok(ContextResponse::Ack), Ok(ContextResponse::Ack)]);
+            assert_eq!(
+                device.state().await,
+                State::Present(PresentSubstate::Operational(OperationalSubstate::Polling))
+            );
+        };
+
+        block_on(async {
+            match select(respond, test).await {
+                Either::First(()) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+
+        assert!(matches!(
+            commands.borrow().as_slice(),
+            [
+                device::Command::Ping,
+                device::Command::Initialize,
+                device::Command::UpdateStaticCache,
+                device::Command::UpdateDynamicCache(_),
+                device::Command::UpdateDynamicCache(_),
+            ]
+        ));
+    }
+
+    struct HostDelegate {
+        events: RefCell<Vec<CapacityThresholdEvent>>,
+    }
+
+    impl comms::MailboxDelegate for HostDelegate {
+        fn receive(&self, message: &comms::Message) -> Result<(), comms::MailboxDelegateError> {
+            if let Some(event) = message.data.get::<CapacityThresholdEvent>() {
+                self.events.borrow_mut().push(*event);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_capacity_thresholds() {
+        const ID: DeviceId = DeviceId(0);
+
+        block_on(embedded_services::init());
+        let host: &'static HostDelegate = Box::leak(Box::new(HostDelegate {
+            events: RefCell::new(Vec::new()),
+        }));
+        let endpoint: &'static comms::Endpoint =
+            Box::leak(Box::new(comms::Endpoint::uninit(EndpointID::External(External::Host))));
+        block_on(comms::register_endpoint(host, endpoint)).unwrap();
+
+        let context = Context::new();
+        let device: &'static Device = Box::leak(Box::new(Device::new(ID)));
+        block_on(context.register_fuel_gauge(device)).unwrap();
+        context.set_capacity_thresholds(Some(CapacityThresholds {
+            low_mwh: 2000,
+            critical_mwh: 500,
+            hysteresis_mwh: 100,
+        }));
+
+        let capacity = Cell::new(5000);
+        let respond = async {
+            loop {
+                if let device::Command::UpdateDynamicCache(_) = device.receive_command().await {
+                    device.set_dynamic_battery_cache(DynamicBatteryMsgs {
+                        remaining_capacity_mwh: capacity.get(),
+                        ..Default::default()
+                    });
+                }
+                device.send_response(Ok(device::InternalResponse::Complete)).await;
+            }
+        };
+
+        let test = async {
+            assert_eq!(
+                execute(&context, BatteryEventInner::DoInit).await,
+                Ok(ContextResponse::Ack)
+            );
+
+            // Hovering around the low threshold only notifies once until clear of the hysteresis
+            for remaining in [2100, 1900, 2050, 1950, 2150, 1990, 400, 550, 450] {
+                capacity.set(remaining);
+                assert_eq!(
+                    execute(&context, BatteryEventInner::PollDynamicData).await,
+                    Ok(ContextResponse::Ack)
+                );
+            }
+        };
+
+        block_on(async {
+            match select(respond, test).await {
+                Either::First(()) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+
+        let levels: Vec<_> = host
+            .events
+            .borrow()
+            .iter()
+            .filter(|event| event.device_id == ID)
+            .map(|event| (event.level, event.remaining_capacity_mwh))
+            .collect();
+        assert_eq!(
+            levels,
+            [
+                (CapacityLevel::Low, 1900),
+                (CapacityLevel::Low, 1990),
+                (CapacityLevel::Critical, 400),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_capacity_level_hysteresis() {
+        let thresholds = CapacityThresholds {
+            low_mwh: 2000,
+            critical_mwh: 500,
+            hysteresis_mwh: 100,
+        };
+
+        assert_eq!(thresholds.level(3000, CapacityLevel::Normal), CapacityLevel::Normal);
+        assert_eq!(thresholds.level(2000, CapacityLevel::Normal), CapacityLevel::Low);
+        assert_eq!(thresholds.level(100, CapacityLevel::Normal), CapacityLevel::Critical);
+
+        // Recovering from critical holds each level until clear of the hysteresis
+        assert_eq!(thresholds.level(600, CapacityLevel::Critical), CapacityLevel::Critical);
+        assert_eq!(thresholds.level(601, CapacityLevel::Critical), CapacityLevel::Low);
+        assert_eq!(thresholds.level(2050, CapacityLevel::Critical), CapacityLevel::Low);
+        assert_eq!(thresholds.level(2101, CapacityLevel::Critical), CapacityLevel::Normal);
+    }
+}
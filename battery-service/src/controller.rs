@@ -2,7 +2,7 @@ use core::future::Future;
 
 use embassy_time::Duration;
 
-use crate::device::{DynamicBatteryMsgs, StaticBatteryMsgs};
+use crate::device::{DynamicBatteryFields, DynamicBatteryMsgs, StaticBatteryMsgs};
 
 /// Fuel gauge hardware events
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -16,6 +16,36 @@ pub trait Controller: embedded_batteries_async::smart_battery::SmartBattery {
     fn initialize(&mut self) -> impl Future<Output = Result<(), Self::ControllerError>>;
     fn get_static_data(&mut self) -> impl Future<Output = Result<StaticBatteryMsgs, Self::ControllerError>>;
     fn get_dynamic_data(&mut self) -> impl Future<Output = Result<DynamicBatteryMsgs, Self::ControllerError>>;
+    /// Read only the given dynamic fields, fields not selected may be left at any value.
+    ///
+    /// Returns None if the driver has no faster way to read a subset of the fields, the selected fields are then
+    /// read with one smart battery command each. Max/sustained power and the desired charging voltage/current have
+    /// no such command, selecting any of them falls back to [`get_dynamic_data`](Self::get_dynamic_data).
+    fn get_dynamic_data_fields(
+        &mut self,
+        _fields: DynamicBatteryFields,
+    ) -> impl Future<Output = Option<Result<DynamicBatteryMsgs, Self::ControllerError>>> {
+        async { None }
+    }
+    /// Read `buf.len()` bytes of contiguous registers starting at `register` in a single bus transaction.
+    ///
+    /// Returns None if the gauge doesn't support block reads, dynamic data is then read field by field, see
+    /// [`get_dynamic_data_fields`](Self::get_dynamic_data_fields).
+    fn read_block(
+        &mut self,
+        _register: u8,
+        _buf: &mut [u8],
+    ) -> impl Future<Output = Option<Result<(), Self::ControllerError>>> {
+        async { None }
+    }
+    /// Handle an OEM defined command, returns None if the driver doesn't support OEM commands.
+    fn oem_command(
+        &mut self,
+        _id: u8,
+        _data: &'static [u8],
+    ) -> impl Future<Output = Option<Result<(), Self::ControllerError>>> {
+        async { None }
+    }
     fn get_device_event(&mut self) -> impl Future<Output = ControllerEvent>;
     fn ping(&mut self) -> impl Future<Output = Result<(), Self::ControllerError>>;
 
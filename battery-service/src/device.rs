@@ -2,25 +2,56 @@ use core::cell::Cell;
 
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::channel::Channel;
+use embassy_sync::mutex::{Mutex, MutexGuard};
+use embassy_sync::signal::Signal;
 use embassy_time::Duration;
+use embedded_batteries_async::smart_battery::{CapacityModeSignedValue, CapacityModeValue, Minutes};
 use embedded_services::{Node, NodeContainer};
 
+use crate::context::{CapacityLevel, State};
+
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 /// Device errors.
 pub enum FuelGaugeError {
     Timeout,
     BusError,
+    /// The device doesn't support the command.
+    Unsupported,
 }
 
 #[derive(Debug, Clone, Copy)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 /// Device commands.
 pub enum Command {
     Initialize,
     Ping,
     UpdateStaticCache,
-    UpdateDynamicCache,
+    UpdateDynamicCache(DynamicBatteryFields),
+    SetCapacityAlarm(CapacityModeValue),
+    SetTimeAlarm(Minutes),
+    AtRatePrediction(CapacityModeSignedValue),
+    /// OEM defined command with its payload.
+    Oem(u8, &'static [u8]),
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Command {
+    fn format(&self, fmt: defmt::Formatter) {
+        match self {
+            Command::Initialize => defmt::write!(fmt, "Initialize"),
+            Command::Ping => defmt::write!(fmt, "Ping"),
+            Command::UpdateStaticCache => defmt::write!(fmt, "UpdateStaticCache"),
+            Command::UpdateDynamicCache(fields) => defmt::write!(fmt, "UpdateDynamicCache({})", fields),
+            Command::SetCapacityAlarm(capacity) => {
+                defmt::write!(fmt, "SetCapacityAlarm({})", defmt::Debug2Format(capacity))
+            }
+            Command::SetTimeAlarm(minutes) => defmt::write!(fmt, "SetTimeAlarm({})", minutes),
+            Command::AtRatePrediction(rate) => {
+                defmt::write!(fmt, "AtRatePrediction({})", defmt::Debug2Format(rate))
+            }
+            Command::Oem(id, data) => defmt::write!(fmt, "Oem({}, {})", id, data),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -28,6 +59,18 @@ pub enum Command {
 /// Device response.
 pub enum InternalResponse {
     Complete,
+    AtRate(AtRatePrediction),
+}
+
+/// Fuel gauge prediction for a given at-rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AtRatePrediction {
+    /// True if the battery can sustain the rate.
+    pub ok: bool,
+
+    /// Predicted time to empty at the rate.
+    pub time_to_empty: Minutes,
 }
 
 /// External device response.
@@ -59,6 +102,96 @@ pub struct StaticBatteryMsgs {
     pub serial_num: [u8; 4],
 }
 
+/// Version of the [`StaticBatteryMsgs`] wire format.
+pub const STATIC_BATTERY_MSGS_VERSION: u8 = 1;
+
+/// Length of an encoded [`StaticBatteryMsgs`].
+pub const STATIC_BATTERY_MSGS_ENCODED_LEN: usize = 60;
+
+/// Battery data encoding errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EncodingError {
+    /// Buffer is too small, contains the expected and actual lengths.
+    InvalidSize(usize, usize),
+    /// Encoded data is malformed.
+    InvalidData,
+}
+
+/// Copy a NUL padded string, anything after the first NUL is cleared.
+fn encode_str(dst: &mut [u8], src: &[u8]) {
+    let len = src.iter().position(|b| *b == 0).unwrap_or(src.len());
+    dst[..len].copy_from_slice(&src[..len]);
+    dst[len..].fill(0);
+}
+
+/// Read a NUL padded string, all bytes after the first NUL must be zero.
+fn decode_str<const N: usize>(src: &[u8]) -> Result<[u8; N], EncodingError> {
+    let len = src.iter().position(|b| *b == 0).unwrap_or(N);
+    if src[len..].iter().any(|b| *b != 0) {
+        return Err(EncodingError::InvalidData);
+    }
+
+    let mut dst = [0u8; N];
+    dst.copy_from_slice(src);
+    Ok(dst)
+}
+
+impl StaticBatteryMsgs {
+    /// Serializes the static data into the slice for transport to the host, returns the number of bytes written.
+    ///
+    /// Layout, multi-byte values are little endian:
+    /// | Offset | Length | Field                        |
+    /// |--------|--------|------------------------------|
+    /// | 0      | 1      | Version                      |
+    /// | 1      | 21     | Manufacturer name            |
+    /// | 22     | 21     | Device name                  |
+    /// | 43     | 5      | Device chemistry             |
+    /// | 48     | 4      | Design capacity in mWh       |
+    /// | 52     | 2      | Design voltage in mV         |
+    /// | 54     | 2      | Device chemistry ID          |
+    /// | 56     | 4      | Serial number                |
+    ///
+    /// Name and chemistry strings are NUL padded, bytes after the first NUL are encoded as zero.
+    pub fn encode_into_slice(&self, buf: &mut [u8]) -> Result<usize, EncodingError> {
+        if buf.len() < STATIC_BATTERY_MSGS_ENCODED_LEN {
+            return Err(EncodingError::InvalidSize(STATIC_BATTERY_MSGS_ENCODED_LEN, buf.len()));
+        }
+
+        buf[0] = STATIC_BATTERY_MSGS_VERSION;
+        encode_str(&mut buf[1..22], &self.manufacturer_name);
+        encode_str(&mut buf[22..43], &self.device_name);
+        encode_str(&mut buf[43..48], &self.device_chemistry);
+        buf[48..52].copy_from_slice(&self.design_capacity_mwh.to_le_bytes());
+        buf[52..54].copy_from_slice(&self.design_voltage_mv.to_le_bytes());
+        buf[54..56].copy_from_slice(&self.device_chemistry_id);
+        buf[56..60].copy_from_slice(&self.serial_num);
+
+        Ok(STATIC_BATTERY_MSGS_ENCODED_LEN)
+    }
+
+    /// Deserializes the static data from the slice.
+    pub fn decode_from_slice(buf: &[u8]) -> Result<Self, EncodingError> {
+        if buf.len() < STATIC_BATTERY_MSGS_ENCODED_LEN {
+            return Err(EncodingError::InvalidSize(STATIC_BATTERY_MSGS_ENCODED_LEN, buf.len()));
+        }
+
+        if buf[0] != STATIC_BATTERY_MSGS_VERSION {
+            return Err(EncodingError::InvalidData);
+        }
+
+        Ok(Self {
+            manufacturer_name: decode_str(&buf[1..22])?,
+            device_name: decode_str(&buf[22..43])?,
+            device_chemistry: decode_str(&buf[43..48])?,
+            design_capacity_mwh: u32::from_le_bytes([buf[48], buf[49], buf[50], buf[51]]),
+            design_voltage_mv: u16::from_le_bytes([buf[52], buf[53]]),
+            device_chemistry_id: [buf[54], buf[55]],
+            serial_num: [buf[56], buf[57], buf[58], buf[59]],
+        })
+    }
+}
+
 /// Standard dynamic battery data cache
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -106,6 +239,248 @@ pub struct DynamicBatteryMsgs {
     pub average_current_ma: i16,
 }
 
+bitflags::bitflags! {
+    /// Set of dynamic battery data fields to poll.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct DynamicBatteryFields: u16 {
+        /// Battery Max Power.
+        const MAX_POWER = 1 << 0;
+
+        /// Battery Sustained Power.
+        const SUS_POWER = 1 << 1;
+
+        /// Full Charge Capacity.
+        const FULL_CHARGE_CAPACITY = 1 << 2;
+
+        /// Remaining Capacity.
+        const REMAINING_CAPACITY = 1 << 3;
+
+        /// Rsoc.
+        const RELATIVE_SOC = 1 << 4;
+
+        /// Charge/Discharge Cycle Count.
+        const CYCLE_COUNT = 1 << 5;
+
+        /// Battery Voltage.
+        const VOLTAGE = 1 << 6;
+
+        /// Maximum Error.
+        const MAX_ERROR = 1 << 7;
+
+        /// Battery Status.
+        const BATTERY_STATUS = 1 << 8;
+
+        /// Desired Charging Voltage.
+        const CHARGING_VOLTAGE = 1 << 9;
+
+        /// Desired Charging Current.
+        const CHARGING_CURRENT = 1 << 10;
+
+        /// Battery Temperature.
+        const BATTERY_TEMP = 1 << 11;
+
+        /// Battery Current.
+        const CURRENT = 1 << 12;
+
+        /// Battery Avg Current.
+        const AVERAGE_CURRENT = 1 << 13;
+    }
+}
+
+impl DynamicBatteryFields {
+    /// Fields read by a single block read of the contiguous smart battery registers, see [`SBS_DYNAMIC_BLOCK_START`].
+    pub const SBS_BLOCK: Self = Self::all().difference(Self::MAX_POWER.union(Self::SUS_POWER));
+}
+
+impl Default for DynamicBatteryFields {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for DynamicBatteryFields {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "DynamicBatteryFields({=u16:#x})", self.bits())
+    }
+}
+
+/// First smart battery register of the dynamic data block, Temperature().
+pub const SBS_DYNAMIC_BLOCK_START: u8 = 0x08;
+
+/// Length of the dynamic data block, sixteen 16-bit registers from Temperature() to CycleCount().
+pub const SBS_DYNAMIC_BLOCK_LEN: usize = 32;
+
+impl DynamicBatteryMsgs {
+    /// Decode a block read of the registers starting at [`SBS_DYNAMIC_BLOCK_START`].
+    ///
+    /// Only the [`DynamicBatteryFields::SBS_BLOCK`] fields are populated. Capacities are copied as reported,
+    /// so the gauge must be in the capacity mode expected by the cache.
+    pub fn from_sbs_block(block: &[u8; SBS_DYNAMIC_BLOCK_LEN]) -> Self {
+        let register = |address: u8| {
+            let offset = usize::from(address - SBS_DYNAMIC_BLOCK_START) * 2;
+            u16::from_le_bytes([block[offset], block[offset + 1]])
+        };
+
+        Self {
+            battery_temp_dk: register(0x08),
+            voltage_mv: register(0x09),
+            current_ma: register(0x0a) as i16,
+            average_current_ma: register(0x0b) as i16,
+            max_error_pct: register(0x0c),
+            relative_soc_pct: register(0x0d),
+            remaining_capacity_mwh: register(0x0f).into(),
+            full_charge_capacity_mwh: register(0x10).into(),
+            charging_current_ma: register(0x14),
+            charging_voltage_mv: register(0x15),
+            battery_status: register(0x16),
+            cycle_count: register(0x17),
+            ..Default::default()
+        }
+    }
+
+    /// Update only the given fields with new values, leaving all other fields untouched.
+    pub fn update_fields(&mut self, new_values: &DynamicBatteryMsgs, fields: DynamicBatteryFields) {
+        if fields.contains(DynamicBatteryFields::MAX_POWER) {
+            self.max_power_mw = new_values.max_power_mw;
+        }
+        if fields.contains(DynamicBatteryFields::SUS_POWER) {
+            self.sus_power_mw = new_values.sus_power_mw;
+        }
+        if fields.contains(DynamicBatteryFields::FULL_CHARGE_CAPACITY) {
+            self.full_charge_capacity_mwh = new_values.full_charge_capacity_mwh;
+        }
+        if fields.contains(DynamicBatteryFields::REMAINING_CAPACITY) {
+            self.remaining_capacity_mwh = new_values.remaining_capacity_mwh;
+        }
+        if fields.contains(DynamicBatteryFields::RELATIVE_SOC) {
+            self.relative_soc_pct = new_values.relative_soc_pct;
+        }
+        if fields.contains(DynamicBatteryFields::CYCLE_COUNT) {
+            self.cycle_count = new_values.cycle_count;
+        }
+        if fields.contains(DynamicBatteryFields::VOLTAGE) {
+            self.voltage_mv = new_values.voltage_mv;
+        }
+        if fields.contains(DynamicBatteryFields::MAX_ERROR) {
+            self.max_error_pct = new_values.max_error_pct;
+        }
+        if fields.contains(DynamicBatteryFields::BATTERY_STATUS) {
+            self.battery_status = new_values.battery_status;
+        }
+        if fields.contains(DynamicBatteryFields::CHARGING_VOLTAGE) {
+            self.charging_voltage_mv = new_values.charging_voltage_mv;
+        }
+        if fields.contains(DynamicBatteryFields::CHARGING_CURRENT) {
+            self.charging_current_ma = new_values.charging_current_ma;
+        }
+        if fields.contains(DynamicBatteryFields::BATTERY_TEMP) {
+            self.battery_temp_dk = new_values.battery_temp_dk;
+        }
+        if fields.contains(DynamicBatteryFields::CURRENT) {
+            self.current_ma = new_values.current_ma;
+        }
+        if fields.contains(DynamicBatteryFields::AVERAGE_CURRENT) {
+            self.average_current_ma = new_values.average_current_ma;
+        }
+    }
+}
+
+/// Smart battery alarms reported in the battery status.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BatteryAlarms {
+    pub over_charged: bool,
+    pub terminate_charge: bool,
+    pub over_temp: bool,
+    pub terminate_discharge: bool,
+    pub remaining_capacity: bool,
+    pub remaining_time: bool,
+}
+
+impl BatteryAlarms {
+    const OVER_CHARGED_ALARM: u16 = 1 << 15;
+    const TERMINATE_CHARGE_ALARM: u16 = 1 << 14;
+    const OVER_TEMP_ALARM: u16 = 1 << 12;
+    const TERMINATE_DISCHARGE_ALARM: u16 = 1 << 11;
+    const REMAINING_CAPACITY_ALARM: u16 = 1 << 9;
+    const REMAINING_TIME_ALARM: u16 = 1 << 8;
+
+    /// Decode alarms from a Smart Battery battery status value.
+    pub fn from_battery_status(status: u16) -> Self {
+        Self {
+            over_charged: status & Self::OVER_CHARGED_ALARM != 0,
+            terminate_charge: status & Self::TERMINATE_CHARGE_ALARM != 0,
+            over_temp: status & Self::OVER_TEMP_ALARM != 0,
+            terminate_discharge: status & Self::TERMINATE_DISCHARGE_ALARM != 0,
+            remaining_capacity: status & Self::REMAINING_CAPACITY_ALARM != 0,
+            remaining_time: status & Self::REMAINING_TIME_ALARM != 0,
+        }
+    }
+
+    /// Returns true if any alarm is set.
+    pub fn any(&self) -> bool {
+        *self != Self::default()
+    }
+}
+
+/// ACPI `_BST` battery state derived from the battery status.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BatteryState {
+    pub discharging: bool,
+    pub charging: bool,
+    pub critical: bool,
+    pub charge_limiting: bool,
+}
+
+impl BatteryState {
+    const TERMINATE_CHARGE_ALARM: u16 = 1 << 14;
+    const TERMINATE_DISCHARGE_ALARM: u16 = 1 << 11;
+    const DISCHARGING: u16 = 1 << 6;
+    const FULLY_CHARGED: u16 = 1 << 5;
+    const FULLY_DISCHARGED: u16 = 1 << 4;
+
+    const ACPI_DISCHARGING: u32 = 1 << 0;
+    const ACPI_CHARGING: u32 = 1 << 1;
+    const ACPI_CRITICAL: u32 = 1 << 2;
+    const ACPI_CHARGE_LIMITING: u32 = 1 << 3;
+
+    /// Decode the battery state from a Smart Battery battery status value and the battery current.
+    ///
+    /// The battery status has no charging flag, so charging requires current flowing into the battery.
+    pub fn from_battery_status(status: u16, current_ma: i16) -> Self {
+        let discharging = status & Self::DISCHARGING != 0;
+        // A fully charged battery isn't taking any more charge, an idle battery is neither charging nor discharging
+        let charging = !discharging && status & Self::FULLY_CHARGED == 0 && current_ma > 0;
+
+        Self {
+            discharging,
+            charging,
+            critical: status & (Self::FULLY_DISCHARGED | Self::TERMINATE_DISCHARGE_ALARM) != 0,
+            charge_limiting: charging && status & Self::TERMINATE_CHARGE_ALARM != 0,
+        }
+    }
+
+    /// Encode as the ACPI `_BST` state word.
+    pub fn to_acpi(&self) -> u32 {
+        let mut state = 0;
+        if self.discharging {
+            state |= Self::ACPI_DISCHARGING;
+        }
+        if self.charging {
+            state |= Self::ACPI_CHARGING;
+        }
+        if self.critical {
+            state |= Self::ACPI_CRITICAL;
+        }
+        if self.charge_limiting {
+            state |= Self::ACPI_CHARGE_LIMITING;
+        }
+        state
+    }
+}
+
 /// Fuel gauge ID
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -120,6 +495,9 @@ pub struct Device {
     dynamic_battery_cache: Cell<DynamicBatteryMsgs>,
     static_battery_cache: Cell<StaticBatteryMsgs>,
     timeout: Cell<Duration>,
+    alarm: Signal<NoopRawMutex, BatteryAlarms>,
+    state: Mutex<NoopRawMutex, State>,
+    capacity_level: Cell<CapacityLevel>,
 }
 
 impl Device {
@@ -132,6 +510,9 @@ impl Device {
             dynamic_battery_cache: Cell::default(),
             static_battery_cache: Cell::default(),
             timeout: Cell::new(Duration::from_secs(60)),
+            alarm: Signal::new(),
+            state: Mutex::new(State::NotPresent),
+            capacity_level: Cell::new(CapacityLevel::Normal),
         }
     }
 
@@ -140,6 +521,25 @@ impl Device {
         self.id
     }
 
+    /// Get the battery service state of this device.
+    pub async fn state(&self) -> State {
+        *self.state.lock().await
+    }
+
+    /// Get the remaining capacity level last reported for this device.
+    pub fn capacity_level(&self) -> CapacityLevel {
+        self.capacity_level.get()
+    }
+
+    pub(crate) fn set_capacity_level(&self, level: CapacityLevel) {
+        self.capacity_level.set(level);
+    }
+
+    /// Lock the state of this device, state machine runs for the device are serialized by this lock.
+    pub(crate) async fn lock_state(&self) -> MutexGuard<'_, NoopRawMutex, State> {
+        self.state.lock().await
+    }
+
     /// Send command to the device.
     pub async fn send_command(&self, cmd: Command) {
         self.command.send(cmd).await
@@ -195,6 +595,16 @@ impl Device {
     pub fn get_timeout(&self) -> Duration {
         self.timeout.get()
     }
+
+    /// Notify that battery alarms have been raised.
+    pub fn signal_alarm(&self, alarms: BatteryAlarms) {
+        self.alarm.signal(alarms);
+    }
+
+    /// Wait for battery alarms to be raised.
+    pub async fn wait_alarm(&self) -> BatteryAlarms {
+        self.alarm.wait().await
+    }
 }
 
 impl NodeContainer for Device {
@@ -202,3 +612,144 @@ impl NodeContainer for Device {
         &self.node
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_update_selected_fields() {
+        let mut cache = DynamicBatteryMsgs {
+            cycle_count: 10,
+            battery_temp_dk: 2981,
+            ..Default::default()
+        };
+        let new_values = DynamicBatteryMsgs {
+            relative_soc_pct: 75,
+            voltage_mv: 12400,
+            cycle_count: 11,
+            battery_temp_dk: 3000,
+            ..Default::default()
+        };
+
+        cache.update_fields(
+            &new_values,
+            DynamicBatteryFields::RELATIVE_SOC | DynamicBatteryFields::VOLTAGE,
+        );
+        assert_eq!(cache.relative_soc_pct, 75);
+        assert_eq!(cache.voltage_mv, 12400);
+        // Fields not selected are left as is
+        assert_eq!(cache.cycle_count, 10);
+        assert_eq!(cache.battery_temp_dk, 2981);
+    }
+
+    #[test]
+    fn test_battery_alarms() {
+        assert!(!BatteryAlarms::from_battery_status(0x00C0).any());
+
+        let alarms = BatteryAlarms::from_battery_status(0x1200);
+        assert!(alarms.any());
+        assert!(alarms.over_temp);
+        assert!(alarms.remaining_capacity);
+        assert!(!alarms.over_charged);
+        assert!(!alarms.terminate_charge);
+        assert!(!alarms.terminate_discharge);
+        assert!(!alarms.remaining_time);
+    }
+
+    #[test]
+    fn test_battery_state() {
+        const CHARGE_MA: i16 = 500;
+        const DISCHARGE_MA: i16 = -500;
+
+        // Discharging and charging keep their original encoding
+        assert_eq!(BatteryState::from_battery_status(0x0040, DISCHARGE_MA).to_acpi(), 0x01);
+        assert_eq!(BatteryState::from_battery_status(0x0000, CHARGE_MA).to_acpi(), 0x02);
+
+        // Idle and fully charged are neither charging nor discharging
+        assert_eq!(BatteryState::from_battery_status(0x0000, 0).to_acpi(), 0x00);
+        assert_eq!(BatteryState::from_battery_status(0x0020, CHARGE_MA).to_acpi(), 0x00);
+
+        // Critical while discharging, from either fully discharged or the terminate discharge alarm
+        let state = BatteryState::from_battery_status(0x0050, DISCHARGE_MA);
+        assert!(state.discharging && state.critical && !state.charging);
+        assert_eq!(state.to_acpi(), 0x05);
+        assert_eq!(BatteryState::from_battery_status(0x0840, DISCHARGE_MA).to_acpi(), 0x05);
+
+        // Terminate charge alarm while charging limits the charge
+        assert_eq!(BatteryState::from_battery_status(0x4000, CHARGE_MA).to_acpi(), 0x0A);
+        assert_eq!(BatteryState::from_battery_status(0x4000, 0).to_acpi(), 0x00);
+        assert_eq!(BatteryState::from_battery_status(0x4020, CHARGE_MA).to_acpi(), 0x00);
+    }
+
+    #[test]
+    fn test_default_fields() {
+        let mut cache = DynamicBatteryMsgs::default();
+        let new_values = DynamicBatteryMsgs {
+            max_power_mw: 1,
+            average_current_ma: -1,
+            ..Default::default()
+        };
+
+        cache.update_fields(&new_values, DynamicBatteryFields::default());
+        assert_eq!(cache, new_values);
+    }
+
+    /// Fill a NUL padded string field
+    fn padded<const N: usize>(s: &str) -> [u8; N] {
+        let mut buf = [0u8; N];
+        buf[..s.len()].copy_from_slice(s.as_bytes());
+        buf
+    }
+
+    #[test]
+    fn test_static_data_encoding() {
+        let msgs = StaticBatteryMsgs {
+            manufacturer_name: padded("ACME"),
+            device_name: padded("BAT-1"),
+            device_chemistry: padded("LION"),
+            design_capacity_mwh: 0x0001_0203,
+            design_voltage_mv: 11550,
+            device_chemistry_id: [0x12, 0x34],
+            serial_num: [0xde, 0xad, 0xbe, 0xef],
+        };
+
+        let mut expected = [0u8; STATIC_BATTERY_MSGS_ENCODED_LEN];
+        expected[0] = STATIC_BATTERY_MSGS_VERSION;
+        expected[1..5].copy_from_slice(b"ACME");
+        expected[22..27].copy_from_slice(b"BAT-1");
+        expected[43..47].copy_from_slice(b"LION");
+        expected[48..52].copy_from_slice(&[0x03, 0x02, 0x01, 0x00]);
+        expected[52..54].copy_from_slice(&[0x1e, 0x2d]);
+        expected[54..56].copy_from_slice(&[0x12, 0x34]);
+        expected[56..60].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+
+        let mut buf = [0xffu8; STATIC_BATTERY_MSGS_ENCODED_LEN];
+        assert_eq!(msgs.encode_into_slice(&mut buf), Ok(STATIC_BATTERY_MSGS_ENCODED_LEN));
+        assert_eq!(buf, expected);
+        assert_eq!(StaticBatteryMsgs::decode_from_slice(&buf), Ok(msgs));
+
+        // Bytes after the terminator aren't leaked to the host
+        let mut stale = msgs;
+        stale.device_chemistry = *b"LI\0ON";
+        assert_eq!(stale.encode_into_slice(&mut buf), Ok(STATIC_BATTERY_MSGS_ENCODED_LEN));
+        assert_eq!(&buf[43..48], b"LI\0\0\0");
+
+        // Garbage after the terminator is rejected
+        buf[46] = b'X';
+        assert_eq!(
+            StaticBatteryMsgs::decode_from_slice(&buf),
+            Err(EncodingError::InvalidData)
+        );
+
+        let mut short = [0u8; STATIC_BATTERY_MSGS_ENCODED_LEN - 1];
+        assert_eq!(
+            msgs.encode_into_slice(&mut short),
+            Err(EncodingError::InvalidSize(STATIC_BATTERY_MSGS_ENCODED_LEN, short.len()))
+        );
+        assert_eq!(
+            StaticBatteryMsgs::decode_from_slice(&expected[1..]),
+            Err(EncodingError::InvalidSize(STATIC_BATTERY_MSGS_ENCODED_LEN, short.len()))
+        );
+    }
+}
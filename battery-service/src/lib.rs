@@ -31,7 +31,7 @@ impl Service {
 
     /// Main battery service processing function.
     pub async fn process(&self) {
-        let event = self.context.wait_event().await;
+        let event = self.context.wait_event_or_poll().await;
         self.context.process(event).await;
     }
 }
@@ -87,6 +87,16 @@ pub async fn execute_event(event: BatteryEvent) -> context::BatteryResponse {
     service.context.execute_event(event).await
 }
 
+/// Query whether a fuel gauge can sustain the given rate, and the predicted time to empty at that rate.
+pub async fn at_rate_prediction(
+    device_id: device::DeviceId,
+    rate: embedded_batteries_async::smart_battery::CapacityModeSignedValue,
+) -> Result<device::AtRatePrediction, context::ContextError> {
+    let service = SERVICE.get().await;
+
+    service.context.at_rate_prediction(device_id, rate).await
+}
+
 /// Wait for a response from the battery service.
 ///
 /// Use this function after sending the battery service a message via the comms system.
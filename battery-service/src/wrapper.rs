@@ -1,25 +1,85 @@
 use core::cell::RefCell;
+use core::convert::Infallible;
 
-use embassy_futures::select::select;
-use embedded_services::trace;
+use embassy_futures::select::{select3, Either3};
+use embedded_batteries_async::smart_battery::{CapacityModeSignedValue, CapacityModeValue};
+use embedded_hal_async::digital::Wait;
+use embedded_services::{error, info, trace};
 
 use crate::{
     controller::{Controller, ControllerEvent},
-    device::{Command, Device},
+    device::{
+        AtRatePrediction, BatteryAlarms, Command, Device, DynamicBatteryFields, DynamicBatteryMsgs, FuelGaugeError,
+        SBS_DYNAMIC_BLOCK_LEN, SBS_DYNAMIC_BLOCK_START,
+    },
 };
 
+/// Dynamic fields without a smart battery command of their own, only available through
+/// [`Controller::get_dynamic_data`].
+const DRIVER_ONLY_FIELDS: DynamicBatteryFields = DynamicBatteryFields::MAX_POWER
+    .union(DynamicBatteryFields::SUS_POWER)
+    .union(DynamicBatteryFields::CHARGING_VOLTAGE)
+    .union(DynamicBatteryFields::CHARGING_CURRENT);
+
+/// Capacity as reported, the gauge must be in the capacity mode expected by the cache.
+fn capacity(value: CapacityModeValue) -> u32 {
+    match value {
+        CapacityModeValue::MilliAmpUnsigned(value) | CapacityModeValue::CentiWattUnsigned(value) => value.into(),
+    }
+}
+
+/// Placeholder for a fuel gauge without an ALERT pin, never signals.
+pub struct NoAlert;
+
+impl embedded_hal::digital::ErrorType for NoAlert {
+    type Error = Infallible;
+}
+
+impl Wait for NoAlert {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        core::future::pending().await
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        core::future::pending().await
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        core::future::pending().await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        core::future::pending().await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        core::future::pending().await
+    }
+}
+
 /// Wrapper object to bind device to fuel gauge hardware driver.
-pub struct Wrapper<'a, C: Controller> {
+pub struct Wrapper<'a, C: Controller, A: Wait = NoAlert> {
     device: &'a Device,
     controller: RefCell<C>,
+    alert: RefCell<A>,
 }
 
 impl<'a, C: Controller> Wrapper<'a, C> {
     /// Create a new fuel gauge wrapper.
     pub fn new(device: &'a Device, controller: C) -> Self {
+        Self::new_with_alert(device, controller, NoAlert)
+    }
+}
+
+impl<'a, C: Controller, A: Wait> Wrapper<'a, C, A> {
+    /// Create a new fuel gauge wrapper with an active-low ALERT pin.
+    ///
+    /// An ALERT edge triggers an immediate status read and alarm evaluation instead of waiting for the next poll.
+    pub fn new_with_alert(device: &'a Device, controller: C, alert: A) -> Self {
         Self {
             device,
             controller: RefCell::new(controller),
+            alert: RefCell::new(alert),
         }
     }
 
@@ -27,21 +87,154 @@ impl<'a, C: Controller> Wrapper<'a, C> {
     #[allow(clippy::await_holding_refcell_ref)]
     pub async fn process(&self) {
         let mut controller = self.controller.borrow_mut();
+        let mut alert = self.alert.borrow_mut();
         loop {
-            let res = select(controller.get_device_event(), self.device.receive_command()).await;
+            let res = select3(
+                controller.get_device_event(),
+                self.device.receive_command(),
+                alert.wait_for_falling_edge(),
+            )
+            .await;
             match res {
-                embassy_futures::select::Either::First(event) => {
+                Either3::First(event) => {
                     trace!("New fuel gauge hardware device event.");
                     self.process_device_event(&mut controller, self.device, event).await;
                 }
-                embassy_futures::select::Either::Second(cmd) => {
+                Either3::Second(cmd) => {
                     trace!("New fuel gauge state machine command.");
                     self.process_context_command(&mut controller, self.device, cmd).await;
                 }
+                Either3::Third(Ok(())) => {
+                    trace!("New fuel gauge alert.");
+                    self.process_alert(&mut controller, self.device).await;
+                }
+                Either3::Third(Err(_e)) => {
+                    error!("Error waiting for fuel gauge alert");
+                }
             };
         }
     }
 
+    async fn process_alert(&self, controller: &mut C, device: &Device) {
+        match Self::read_dynamic_fields(controller, DynamicBatteryFields::BATTERY_STATUS).await {
+            Ok(dynamic_data) => {
+                let mut cache = device.get_dynamic_battery_cache();
+                cache.update_fields(&dynamic_data, DynamicBatteryFields::BATTERY_STATUS);
+                device.set_dynamic_battery_cache(cache);
+
+                let alarms = BatteryAlarms::from_battery_status(cache.battery_status);
+                if alarms.any() {
+                    info!("Fuel gauge alarms: {:?}", alarms);
+                    device.signal_alarm(alarms);
+                }
+            }
+            Err(_e) => {
+                error!("Error reading fuel gauge status on alert");
+            }
+        }
+    }
+
+    async fn at_rate_prediction(
+        controller: &mut C,
+        rate: CapacityModeSignedValue,
+    ) -> Result<AtRatePrediction, C::Error> {
+        controller.set_at_rate(rate).await?;
+        Ok(AtRatePrediction {
+            ok: controller.at_rate_ok().await?,
+            time_to_empty: controller.at_rate_time_to_empty().await?,
+        })
+    }
+
+    /// Read the given fields with one smart battery command each
+    async fn read_smart_battery_fields(
+        controller: &mut C,
+        fields: DynamicBatteryFields,
+    ) -> Result<DynamicBatteryMsgs, C::Error> {
+        let mut data = DynamicBatteryMsgs::default();
+        if fields.contains(DynamicBatteryFields::FULL_CHARGE_CAPACITY) {
+            data.full_charge_capacity_mwh = capacity(controller.full_charge_capacity().await?);
+        }
+        if fields.contains(DynamicBatteryFields::REMAINING_CAPACITY) {
+            data.remaining_capacity_mwh = capacity(controller.remaining_capacity().await?);
+        }
+        if fields.contains(DynamicBatteryFields::RELATIVE_SOC) {
+            data.relative_soc_pct = controller.relative_state_of_charge().await?.into();
+        }
+        if fields.contains(DynamicBatteryFields::CYCLE_COUNT) {
+            data.cycle_count = controller.cycle_count().await?;
+        }
+        if fields.contains(DynamicBatteryFields::VOLTAGE) {
+            data.voltage_mv = controller.voltage().await?;
+        }
+        if fields.contains(DynamicBatteryFields::MAX_ERROR) {
+            data.max_error_pct = controller.max_error().await?.into();
+        }
+        if fields.contains(DynamicBatteryFields::BATTERY_STATUS) {
+            data.battery_status = controller.battery_status().await?.into_bits();
+        }
+        if fields.contains(DynamicBatteryFields::BATTERY_TEMP) {
+            data.battery_temp_dk = controller.temperature().await?;
+        }
+        if fields.contains(DynamicBatteryFields::CURRENT) {
+            data.current_ma = controller.current().await?;
+        }
+        if fields.contains(DynamicBatteryFields::AVERAGE_CURRENT) {
+            data.average_current_ma = controller.average_current().await?;
+        }
+        Ok(data)
+    }
+
+    /// Read only the given dynamic fields, fields not selected may be left at any value
+    async fn read_dynamic_fields(
+        controller: &mut C,
+        fields: DynamicBatteryFields,
+    ) -> Result<DynamicBatteryMsgs, FuelGaugeError> {
+        if let Some(result) = controller.get_dynamic_data_fields(fields).await {
+            return result.map_err(|_| FuelGaugeError::BusError);
+        }
+
+        if fields.intersects(DRIVER_ONLY_FIELDS) {
+            controller
+                .get_dynamic_data()
+                .await
+                .map_err(|_| FuelGaugeError::BusError)
+        } else {
+            Self::read_smart_battery_fields(controller, fields)
+                .await
+                .map_err(|_| FuelGaugeError::BusError)
+        }
+    }
+
+    /// Update the given fields of the dynamic cache
+    ///
+    /// Fields covered by the smart battery register block are fetched in one transaction if the gauge supports it
+    async fn update_dynamic_cache(
+        controller: &mut C,
+        device: &Device,
+        fields: DynamicBatteryFields,
+    ) -> Result<(), FuelGaugeError> {
+        let mut cache = device.get_dynamic_battery_cache();
+        let mut remaining = fields;
+
+        let block_fields = fields.intersection(DynamicBatteryFields::SBS_BLOCK);
+        if !block_fields.is_empty() {
+            let mut block = [0; SBS_DYNAMIC_BLOCK_LEN];
+            if let Some(result) = controller.read_block(SBS_DYNAMIC_BLOCK_START, &mut block).await {
+                result.map_err(|_| FuelGaugeError::BusError)?;
+                cache.update_fields(&DynamicBatteryMsgs::from_sbs_block(&block), block_fields);
+                remaining = fields.difference(DynamicBatteryFields::SBS_BLOCK);
+            }
+        }
+
+        if !remaining.is_empty() {
+            let dynamic_data = Self::read_dynamic_fields(controller, remaining).await?;
+            cache.update_fields(&dynamic_data, remaining);
+        }
+
+        device.set_dynamic_battery_cache(cache);
+        Ok(())
+    }
+
     async fn process_device_event(&self, _controller: &mut C, _device: &Device, event: ControllerEvent) {
         // TODO: add events
         match event {}
@@ -83,18 +276,492 @@ impl<'a, C: Controller> Wrapper<'a, C> {
                     device.send_response(Err(crate::device::FuelGaugeError::BusError)).await;
                 }
             },
-            Command::UpdateDynamicCache => match controller.get_dynamic_data().await {
-                Ok(dynamic_data) => {
-                    device.set_dynamic_battery_cache(dynamic_data);
+            Command::UpdateDynamicCache(fields) => match Self::update_dynamic_cache(controller, device, fields).await {
+                Ok(()) => {
                     device
                         .send_response(Ok(crate::device::InternalResponse::Complete))
                         .await;
                 }
+                Err(e) => {
+                    device.send_response(Err(e)).await;
+                }
+            },
+            Command::SetCapacityAlarm(capacity) => match controller.set_remaining_capacity_alarm(capacity).await {
+                Ok(_) => {
+                    device
+                        .send_response(Ok(crate::device::InternalResponse::Complete))
+                        .await;
+                }
+                Err(_e) => {
+                    // TODO: Add specific error handling
+                    device.send_response(Err(crate::device::FuelGaugeError::BusError)).await;
+                }
+            },
+            Command::SetTimeAlarm(minutes) => match controller.set_remaining_time_alarm(minutes).await {
+                Ok(_) => {
+                    device
+                        .send_response(Ok(crate::device::InternalResponse::Complete))
+                        .await;
+                }
+                Err(_e) => {
+                    // TODO: Add specific error handling
+                    device.send_response(Err(crate::device::FuelGaugeError::BusError)).await;
+                }
+            },
+            Command::AtRatePrediction(rate) => match Self::at_rate_prediction(controller, rate).await {
+                Ok(prediction) => {
+                    device
+                        .send_response(Ok(crate::device::InternalResponse::AtRate(prediction)))
+                        .await;
+                }
                 Err(_e) => {
                     // TODO: Add specific error handling
                     device.send_response(Err(crate::device::FuelGaugeError::BusError)).await;
                 }
             },
+            Command::Oem(id, data) => match controller.oem_command(id, data).await {
+                Some(Ok(())) => {
+                    device
+                        .send_response(Ok(crate::device::InternalResponse::Complete))
+                        .await;
+                }
+                Some(Err(_e)) => {
+                    // TODO: Add specific error handling
+                    device.send_response(Err(crate::device::FuelGaugeError::BusError)).await;
+                }
+                None => {
+                    error!("Fuel gauge doesn't support OEM command {}", id);
+                    device
+                        .send_response(Err(crate::device::FuelGaugeError::Unsupported))
+                        .await;
+                }
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use core::cell::Cell;
+    use std::vec::Vec;
+
+    use embassy_futures::block_on;
+    use embassy_futures::select::{select, Either};
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embassy_sync::signal::Signal;
+    use embassy_time::Duration;
+    use embedded_batteries_async::charger::MilliVolts;
+    use embedded_batteries_async::smart_battery::{
+        self, BatteryModeFields, BatteryStatusFields, CapacityModeSignedValue, CapacityModeValue, Cycles, DeciKelvin,
+        ManufactureDate, MilliAmpsSigned, Minutes, Percent, SmartBattery, SpecificationInfoFields,
+    };
+
+    use super::*;
+    use crate::device::{DeviceId, FuelGaugeError, InternalResponse, StaticBatteryMsgs};
+
+    /// Over temperature alarm set in the battery status
+    const OVER_TEMP_STATUS: u16 = 1 << 12;
+
+    #[derive(Default)]
+    struct MockState {
+        /// Smart battery commands used to read dynamic fields, in order
+        field_reads: RefCell<Vec<&'static str>>,
+        status_reads: Cell<usize>,
+        full_reads: Cell<usize>,
+        capacity_alarm: Cell<Option<CapacityModeValue>>,
+        time_alarm: Cell<Option<Minutes>>,
+        at_rate: Cell<Option<CapacityModeSignedValue>>,
+        /// Register block returned by block reads, block reads are unsupported if None
+        block: Option<[u8; SBS_DYNAMIC_BLOCK_LEN]>,
+        block_reads: Cell<usize>,
+    }
+
+    /// Highest discharge current the mock battery can sustain
+    const MAX_DISCHARGE_MA: i16 = 3000;
+
+    /// Capacity used for the mock time to empty prediction
+    const CAPACITY_MAH: i32 = 3000;
+
+    struct MockController<'a> {
+        state: &'a MockState,
+    }
+
+    impl smart_battery::ErrorType for MockController<'_> {
+        type Error = Infallible;
+    }
+
+    impl SmartBattery for MockController<'_> {
+        async fn absolute_state_of_charge(&mut self) -> Result<Percent, Self::Error> {
+            unimplemented!()
+        }
+        async fn at_rate(&mut self) -> Result<CapacityModeSignedValue, Self::Error> {
+            unimplemented!()
+        }
+        async fn at_rate_ok(&mut self) -> Result<bool, Self::Error> {
+            match self.state.at_rate.get() {
+                Some(CapacityModeSignedValue::MilliAmpSigned(ma)) => Ok(-ma <= MAX_DISCHARGE_MA),
+                _ => Ok(false),
+            }
+        }
+        async fn at_rate_time_to_empty(&mut self) -> Result<Minutes, Self::Error> {
+            match self.state.at_rate.get() {
+                Some(CapacityModeSignedValue::MilliAmpSigned(ma)) if ma < 0 => {
+                    Ok((CAPACITY_MAH * 60 / -(ma as i32)) as Minutes)
+                }
+                _ => Ok(Minutes::MAX),
+            }
+        }
+        async fn at_rate_time_to_full(&mut self) -> Result<Minutes, Self::Error> {
+            unimplemented!()
+        }
+        async fn average_current(&mut self) -> Result<MilliAmpsSigned, Self::Error> {
+            self.state.field_reads.borrow_mut().push("average_current");
+            Ok(-250)
+        }
+        async fn average_time_to_empty(&mut self) -> Result<Minutes, Self::Error> {
+            unimplemented!()
+        }
+        async fn average_time_to_full(&mut self) -> Result<Minutes, Self::Error> {
+            unimplemented!()
+        }
+        async fn battery_mode(&mut self) -> Result<BatteryModeFields, Self::Error> {
+            unimplemented!()
+        }
+        async fn battery_status(&mut self) -> Result<BatteryStatusFields, Self::Error> {
+            self.state.field_reads.borrow_mut().push("battery_status");
+            self.state.status_reads.set(self.state.status_reads.get() + 1);
+            Ok(BatteryStatusFields::from_bits(OVER_TEMP_STATUS))
+        }
+        async fn current(&mut self) -> Result<MilliAmpsSigned, Self::Error> {
+            self.state.field_reads.borrow_mut().push("current");
+            Ok(-300)
+        }
+        async fn cycle_count(&mut self) -> Result<Cycles, Self::Error> {
+            self.state.field_reads.borrow_mut().push("cycle_count");
+            Ok(42)
+        }
+        async fn design_capacity(&mut self) -> Result<CapacityModeValue, Self::Error> {
+            unimplemented!()
+        }
+        async fn design_voltage(&mut self) -> Result<MilliVolts, Self::Error> {
+            unimplemented!()
+        }
+        async fn device_chemistry(&mut self, _chemistry: &mut [u8]) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+        async fn device_name(&mut self, _name: &mut [u8]) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+        async fn full_charge_capacity(&mut self) -> Result<CapacityModeValue, Self::Error> {
+            self.state.field_reads.borrow_mut().push("full_charge_capacity");
+            Ok(CapacityModeValue::CentiWattUnsigned(5000))
+        }
+        async fn manufacture_date(&mut self) -> Result<ManufactureDate, Self::Error> {
+            unimplemented!()
+        }
+        async fn manufacturer_name(&mut self, _name: &mut [u8]) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+        async fn max_error(&mut self) -> Result<Percent, Self::Error> {
+            self.state.field_reads.borrow_mut().push("max_error");
+            Ok(1)
+        }
+        async fn relative_state_of_charge(&mut self) -> Result<Percent, Self::Error> {
+            self.state.field_reads.borrow_mut().push("relative_state_of_charge");
+            Ok(75)
+        }
+        async fn remaining_capacity(&mut self) -> Result<CapacityModeValue, Self::Error> {
+            self.state.field_reads.borrow_mut().push("remaining_capacity");
+            Ok(CapacityModeValue::CentiWattUnsigned(3750))
+        }
+        async fn remaining_capacity_alarm(&mut self) -> Result<CapacityModeValue, Self::Error> {
+            unimplemented!()
+        }
+        async fn remaining_time_alarm(&mut self) -> Result<Minutes, Self::Error> {
+            unimplemented!()
+        }
+        async fn run_time_to_empty(&mut self) -> Result<Minutes, Self::Error> {
+            unimplemented!()
+        }
+        async fn serial_number(&mut self) -> Result<u16, Self::Error> {
+            unimplemented!()
+        }
+        async fn set_at_rate(&mut self, rate: CapacityModeSignedValue) -> Result<(), Self::Error> {
+            self.state.at_rate.set(Some(rate));
+            Ok(())
+        }
+        async fn set_battery_mode(&mut self, _flags: BatteryModeFields) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+        async fn set_remaining_capacity_alarm(&mut self, capacity: CapacityModeValue) -> Result<(), Self::Error> {
+            self.state.capacity_alarm.set(Some(capacity));
+            Ok(())
+        }
+        async fn set_remaining_time_alarm(&mut self, time: Minutes) -> Result<(), Self::Error> {
+            self.state.time_alarm.set(Some(time));
+            Ok(())
+        }
+        async fn specification_info(&mut self) -> Result<SpecificationInfoFields, Self::Error> {
+            unimplemented!()
+        }
+        async fn temperature(&mut self) -> Result<DeciKelvin, Self::Error> {
+            self.state.field_reads.borrow_mut().push("temperature");
+            Ok(2981)
+        }
+        async fn voltage(&mut self) -> Result<MilliVolts, Self::Error> {
+            self.state.field_reads.borrow_mut().push("voltage");
+            Ok(12400)
+        }
+    }
+
+    impl Controller for MockController<'_> {
+        type ControllerError = Infallible;
+
+        async fn initialize(&mut self) -> Result<(), Self::ControllerError> {
+            Ok(())
+        }
+
+        async fn get_static_data(&mut self) -> Result<StaticBatteryMsgs, Self::ControllerError> {
+            Ok(StaticBatteryMsgs::default())
+        }
+
+        async fn get_dynamic_data(&mut self) -> Result<DynamicBatteryMsgs, Self::ControllerError> {
+            self.state.full_reads.set(self.state.full_reads.get() + 1);
+            Ok(DynamicBatteryMsgs {
+                battery_status: OVER_TEMP_STATUS,
+                ..Default::default()
+            })
+        }
+
+        async fn read_block(&mut self, register: u8, buf: &mut [u8]) -> Option<Result<(), Self::ControllerError>> {
+            let block = self.state.block?;
+            assert_eq!(register, SBS_DYNAMIC_BLOCK_START);
+            self.state.block_reads.set(self.state.block_reads.get() + 1);
+            buf.copy_from_slice(&block);
+            Some(Ok(()))
+        }
+
+        async fn get_device_event(&mut self) -> ControllerEvent {
+            core::future::pending().await
+        }
+
+        async fn ping(&mut self) -> Result<(), Self::ControllerError> {
+            Ok(())
+        }
+
+        fn get_timeout(&self) -> Duration {
+            Duration::from_secs(1)
+        }
+
+        fn set_timeout(&mut self, _duration: Duration) {}
+    }
+
+    /// ALERT pin that produces a falling edge when signaled
+    struct MockAlert<'a> {
+        edge: &'a Signal<NoopRawMutex, ()>,
+    }
+
+    impl embedded_hal::digital::ErrorType for MockAlert<'_> {
+        type Error = Infallible;
+    }
+
+    impl Wait for MockAlert<'_> {
+        async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+            self.edge.wait().await;
+            Ok(())
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+            self.wait_for_falling_edge().await
+        }
+    }
+
+    #[test]
+    fn test_alert_edge() {
+        let device = Device::new(DeviceId(0));
+        let state = MockState::default();
+        let edge = Signal::new();
+        let wrapper = Wrapper::new_with_alert(&device, MockController { state: &state }, MockAlert { edge: &edge });
+
+        edge.signal(());
+        let alarms = match block_on(select(wrapper.process(), device.wait_alarm())) {
+            Either::First(_) => unreachable!(),
+            Either::Second(alarms) => alarms,
+        };
+
+        assert_eq!(state.status_reads.get(), 1);
+        assert!(alarms.over_temp);
+        assert!(!alarms.remaining_capacity);
+        assert_eq!(device.get_dynamic_battery_cache().battery_status, OVER_TEMP_STATUS);
+    }
+
+    #[test]
+    fn test_set_alarms() {
+        let device = Device::new(DeviceId(0));
+        let state = MockState::default();
+        let wrapper = Wrapper::new(&device, MockController { state: &state });
+
+        let execute = |command| match block_on(select(wrapper.process(), device.execute_command(command))) {
+            Either::First(_) => unreachable!(),
+            Either::Second(response) => response,
+        };
+
+        assert!(execute(Command::SetCapacityAlarm(CapacityModeValue::MilliAmpUnsigned(300))).is_ok());
+        assert_eq!(
+            state.capacity_alarm.get(),
+            Some(CapacityModeValue::MilliAmpUnsigned(300))
+        );
+        assert_eq!(state.time_alarm.get(), None);
+
+        assert!(execute(Command::SetTimeAlarm(10)).is_ok());
+        assert_eq!(state.time_alarm.get(), Some(10));
+    }
+
+    #[test]
+    fn test_at_rate_prediction() {
+        let device = Device::new(DeviceId(0));
+        let state = MockState::default();
+        let wrapper = Wrapper::new(&device, MockController { state: &state });
+
+        let execute = |command| match block_on(select(wrapper.process(), device.execute_command(command))) {
+            Either::First(_) => unreachable!(),
+            Either::Second(response) => response,
+        };
+
+        // 5A discharge can't be sustained
+        assert!(matches!(
+            execute(Command::AtRatePrediction(CapacityModeSignedValue::MilliAmpSigned(
+                -5000
+            ))),
+            Ok(InternalResponse::AtRate(AtRatePrediction {
+                ok: false,
+                time_to_empty: 36
+            }))
+        ));
+        assert_eq!(
+            state.at_rate.get(),
+            Some(CapacityModeSignedValue::MilliAmpSigned(-5000))
+        );
+
+        // 1A discharge is fine
+        assert!(matches!(
+            execute(Command::AtRatePrediction(CapacityModeSignedValue::MilliAmpSigned(
+                -1000
+            ))),
+            Ok(InternalResponse::AtRate(AtRatePrediction {
+                ok: true,
+                time_to_empty: 180
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_oem_unsupported() {
+        let device = Device::new(DeviceId(0));
+        let state = MockState::default();
+        let wrapper = Wrapper::new(&device, MockController { state: &state });
+
+        assert!(matches!(
+            block_on(select(
+                wrapper.process(),
+                device.execute_command(Command::Oem(1, &[0xaa]))
+            )),
+            Either::Second(Err(FuelGaugeError::Unsupported))
+        ));
+    }
+
+    #[test]
+    fn test_dynamic_block_read() {
+        let device = Device::new(DeviceId(0));
+        // Register n holds 0x100 + n
+        let mut block = [0; SBS_DYNAMIC_BLOCK_LEN];
+        for (i, register) in block.chunks_exact_mut(2).enumerate() {
+            register.copy_from_slice(&(0x100 + SBS_DYNAMIC_BLOCK_START as u16 + i as u16).to_le_bytes());
+        }
+        let state = MockState {
+            block: Some(block),
+            ..Default::default()
+        };
+        let wrapper = Wrapper::new(&device, MockController { state: &state });
+
+        let execute = |command| match block_on(select(wrapper.process(), device.execute_command(command))) {
+            Either::First(_) => unreachable!(),
+            Either::Second(response) => response,
+        };
+
+        assert!(execute(Command::UpdateDynamicCache(DynamicBatteryFields::SBS_BLOCK)).is_ok());
+        assert_eq!(state.block_reads.get(), 1);
+        assert_eq!(state.full_reads.get(), 0);
+        assert!(state.field_reads.borrow().is_empty());
+        assert_eq!(
+            device.get_dynamic_battery_cache(),
+            DynamicBatteryMsgs {
+                battery_temp_dk: 0x108,
+                voltage_mv: 0x109,
+                current_ma: 0x10a,
+                average_current_ma: 0x10b,
+                max_error_pct: 0x10c,
+                relative_soc_pct: 0x10d,
+                remaining_capacity_mwh: 0x10f,
+                full_charge_capacity_mwh: 0x110,
+                charging_current_ma: 0x114,
+                charging_voltage_mv: 0x115,
+                battery_status: 0x116,
+                cycle_count: 0x117,
+                ..Default::default()
+            }
+        );
+
+        // Fields outside the block still need an individual read
+        assert!(execute(Command::UpdateDynamicCache(DynamicBatteryFields::all())).is_ok());
+        assert_eq!(state.block_reads.get(), 2);
+        assert_eq!(state.full_reads.get(), 1);
+    }
+
+    #[test]
+    fn test_selected_dynamic_fields() {
+        let device = Device::new(DeviceId(0));
+        let state = MockState::default();
+        let wrapper = Wrapper::new(&device, MockController { state: &state });
+
+        let execute = |command| match block_on(select(wrapper.process(), device.execute_command(command))) {
+            Either::First(_) => unreachable!(),
+            Either::Second(response) => response,
+        };
+
+        assert!(execute(Command::UpdateDynamicCache(
+            DynamicBatteryFields::RELATIVE_SOC | DynamicBatteryFields::VOLTAGE
+        ))
+        .is_ok());
+        // Only the selected fields are read from the gauge
+        assert_eq!(*state.field_reads.borrow(), ["relative_state_of_charge", "voltage"]);
+        assert_eq!(state.full_reads.get(), 0);
+        assert_eq!(
+            device.get_dynamic_battery_cache(),
+            DynamicBatteryMsgs {
+                relative_soc_pct: 75,
+                voltage_mv: 12400,
+                ..Default::default()
+            }
+        );
+
+        // Fields without a smart battery command need the driver's full read
+        state.field_reads.borrow_mut().clear();
+        assert!(execute(Command::UpdateDynamicCache(DynamicBatteryFields::MAX_POWER)).is_ok());
+        assert!(state.field_reads.borrow().is_empty());
+        assert_eq!(state.full_reads.get(), 1);
+    }
+}
@@ -3,7 +3,7 @@ use embassy_sync::once_lock::OnceLock;
 use embedded_cfu_protocol::client::CfuReceiveContent;
 use embedded_cfu_protocol::protocol_definitions::*;
 use embedded_services::cfu::component::*;
-use embedded_services::cfu::{CfuError, ContextToken};
+use embedded_services::cfu::{self, CfuError, ContextToken, FwVersionChanged};
 use embedded_services::{comms, error, info};
 
 pub mod host;
@@ -34,35 +34,94 @@ impl CfuClient {
         match request.data {
             RequestData::FwVersionRequest => {
                 info!("Received FwVersionRequest, comp {}", comp);
+                // Ask the component itself so versions computed at runtime are reported
+                let resp = match cfu::route_request(comp, RequestData::FwVersionRequest).await {
+                    Ok(InternalResponseData::FwVersionResponse(r)) => {
+                        let ver = r.component_info[0].fw_version;
+                        info!("got fw version {:?} for comp {}", ver, comp);
+                        Ok(InternalResponseData::FwVersionResponse(r))
+                    }
+                    Ok(InternalResponseData::ComponentSkipped) => {
+                        info!("comp {} not present, skipped", comp);
+                        Ok(InternalResponseData::ComponentSkipped)
+                    }
+                    Ok(resp) => {
+                        error!("Invalid response to get fw version {:?} from comp {}", resp, comp);
+                        Err(CfuError::ProtocolError(CfuProtocolError::BadResponse))
+                    }
+                    Err(e) => Err(e),
+                };
+                self.respond(resp).await
+            }
+            RequestData::GiveOffer(_) | RequestData::PrepareComponentForUpdate => {
+                info!("Received {:?}, comp {}", request.data, comp);
+                // Components mid-update reject new offers, missing components are handled by routing
                 if let Ok(device) = self.context.get_device(comp).await {
-                    let resp = device
-                        .execute_device_request(request.data)
-                        .await
-                        .map_err(CfuError::ProtocolError)?;
+                    if device.state().await.state == ComponentState::Busy {
+                        info!("comp {} busy, rejecting", comp);
+                        self.context
+                            .send_response(Ok(InternalResponseData::ComponentBusy))
+                            .await;
+                        return Err(CfuError::ComponentBusy);
+                    }
+                }
 
-                    // TODO replace with signal to component to get its own fw version
-                    //cfu::send_request(comp, RequestData::FwVersionRequest).await?;
-                    match resp {
-                        InternalResponseData::FwVersionResponse(r) => {
-                            let ver = r.component_info[0].fw_version;
-                            info!("got fw version {:?} for comp {}", ver, comp);
-                        }
-                        _ => {
-                            error!("Invalid response to get fw version {:?} from comp {}", resp, comp);
-                            return Err(CfuError::ProtocolError(CfuProtocolError::BadResponse));
+                self.respond(cfu::route_request(comp, request.data).await).await
+            }
+            RequestData::GiveContent(_)
+            | RequestData::GiveContentRef(..)
+            | RequestData::RollbackUpdate
+            | RequestData::GetActiveBank
+            | RequestData::SwitchBank => {
+                info!("Forwarding request to comp {}", comp);
+                self.respond(cfu::route_request(comp, request.data).await).await
+            }
+            RequestData::FinalizeUpdate => {
+                info!("Received FinalizeUpdate, comp {}", comp);
+                let resp = cfu::route_request(comp, request.data).await;
+                self.context.send_response(resp).await;
+                match resp? {
+                    InternalResponseData::ComponentBusy => Err(CfuError::ComponentBusy),
+                    InternalResponseData::ComponentSkipped => Ok(()),
+                    _ => {
+                        // The update is already finalized, a failed notification doesn't fail the request
+                        if let Err(e) = self.notify_fw_version(comp).await {
+                            error!("Failed to notify fw version of comp {}: {:?}", comp, e);
                         }
+                        Ok(())
                     }
-                    self.context.send_response(resp).await;
-                    return Ok(());
                 }
-                Err(CfuError::InvalidComponent)
             }
-            RequestData::GiveContent(_content_cmd) => Ok(()),
-            RequestData::GiveOffer(_offer_cmd) => Ok(()),
-            RequestData::PrepareComponentForUpdate => Ok(()),
-            RequestData::FinalizeUpdate => Ok(()),
         }
     }
+
+    /// Send the response to a request, errors are sent to the requester as well as returned
+    async fn respond(&self, response: Result<InternalResponseData, CfuError>) -> Result<(), CfuError> {
+        self.context.send_response(response).await;
+        response.map(|_| ())
+    }
+
+    /// Query the new firmware version of a component and notify the host of the change
+    async fn notify_fw_version(&self, comp: ComponentId) -> Result<(), CfuError> {
+        let fw_version = match cfu::route_request(comp, RequestData::FwVersionRequest).await? {
+            InternalResponseData::FwVersionResponse(r) => r.component_info[0].fw_version,
+            resp => {
+                error!("Invalid response to get fw version {:?} from comp {}", resp, comp);
+                return Err(CfuError::ProtocolError(CfuProtocolError::BadResponse));
+            }
+        };
+
+        info!("comp {} updated to fw version {:?}", comp, fw_version);
+        let notification = FwVersionChanged {
+            component_id: comp,
+            fw_version,
+        };
+        let _ = self
+            .tp
+            .send(comms::EndpointID::External(comms::External::Host), &notification)
+            .await;
+        Ok(())
+    }
 }
 
 impl comms::MailboxDelegate for CfuClient {}
@@ -84,3 +143,318 @@ pub async fn task() {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use std::boxed::Box;
+    use std::sync::{Mutex, MutexGuard, OnceLock};
+
+    use core::cell::Cell;
+    use embassy_futures::{block_on, join::join, select::select};
+    use embedded_cfu_protocol::CfuWriterDefault;
+
+    use super::*;
+
+    const COMPONENT_ID: ComponentId = 1;
+    const DYNAMIC_ID: ComponentId = 2;
+    const DEFAULT_ID: ComponentId = 3;
+    const BUSY_ID: ComponentId = 4;
+    const NEW_VERSION: FwVersion = FwVersion {
+        major: 2,
+        minor: 1,
+        variant: 0,
+    };
+
+    struct HostDelegate {
+        notification: Cell<Option<FwVersionChanged>>,
+    }
+
+    impl comms::MailboxDelegate for HostDelegate {
+        fn receive(&self, message: &comms::Message) -> Result<(), comms::MailboxDelegateError> {
+            if let Some(notification) = message.data.get::<FwVersionChanged>() {
+                self.notification.set(Some(*notification));
+            }
+            Ok(())
+        }
+    }
+
+    /// Client and host shared by all tests
+    struct Shared {
+        client: CfuClient,
+        host: &'static HostDelegate,
+    }
+
+    // Safety: only accessed while holding the lock returned by `shared`
+    unsafe impl Send for Shared {}
+    unsafe impl Sync for Shared {}
+
+    /// Only one CFU client can be created per process, so tests share it and run one at a time
+    fn shared() -> (MutexGuard<'static, ()>, &'static Shared) {
+        static LOCK: Mutex<()> = Mutex::new(());
+        static SHARED: OnceLock<Shared> = OnceLock::new();
+
+        let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let shared = SHARED.get_or_init(|| {
+            let host: &'static HostDelegate = Box::leak(Box::new(HostDelegate {
+                notification: Cell::new(None),
+            }));
+            let host_endpoint: &'static comms::Endpoint = Box::leak(Box::new(comms::Endpoint::uninit(
+                comms::EndpointID::External(comms::External::Host),
+            )));
+            block_on(async {
+                embedded_services::init().await;
+                comms::register_endpoint(host, host_endpoint).await.unwrap();
+            });
+
+            Shared {
+                client: CfuClient::create().unwrap(),
+                host,
+            }
+        });
+        shared.host.notification.set(None);
+        (guard, shared)
+    }
+
+    fn fw_version_response(version: FwVersion, component_id: ComponentId) -> InternalResponseData {
+        InternalResponseData::FwVersionResponse(GetFwVersionResponse {
+            header: GetFwVersionResponseHeader::new(1, GetFwVerRespHeaderByte3::NoSpecialFlags),
+            component_info: [FwVerComponentInfo::new(version, component_id); MAX_CMPT_COUNT],
+        })
+    }
+
+    /// Answer finalize and version requests the way an updated component would
+    async fn respond(device: &CfuDevice) {
+        loop {
+            let resp = match device.wait_request().await {
+                RequestData::FinalizeUpdate => InternalResponseData::UpdateFinalized,
+                RequestData::FwVersionRequest => fw_version_response(NEW_VERSION, COMPONENT_ID),
+                _ => InternalResponseData::ComponentBusy,
+            };
+            device.send_response(resp).await;
+        }
+    }
+
+    #[test]
+    fn test_finalize_notifies_version_change() {
+        let (_guard, shared) = shared();
+        let client = &shared.client;
+        let device: &'static CfuDevice = Box::leak(Box::new(CfuDevice::new(COMPONENT_ID)));
+
+        block_on(async {
+            cfu::register_device(device).await.unwrap();
+
+            let update = async {
+                let (resp, result) = join(
+                    cfu::send_request(COMPONENT_ID, RequestData::FinalizeUpdate),
+                    client.process_request(),
+                )
+                .await;
+                assert_eq!(resp, Ok(InternalResponseData::UpdateFinalized));
+                assert_eq!(result, Ok(()));
+            };
+
+            select(update, respond(device)).await;
+        });
+
+        assert_eq!(
+            shared.host.notification.get(),
+            Some(FwVersionChanged {
+                component_id: COMPONENT_ID,
+                fw_version: NEW_VERSION,
+            })
+        );
+    }
+
+    #[test]
+    fn test_fw_version_request_is_forwarded() {
+        let (_guard, shared) = shared();
+        let client = &shared.client;
+        let device: &'static CfuDevice = Box::leak(Box::new(CfuDevice::new(DYNAMIC_ID)));
+        let minor = Cell::new(0);
+
+        // Reports a new minor version on every request
+        let respond_dynamic = async {
+            loop {
+                let resp = match device.wait_request().await {
+                    RequestData::FwVersionRequest => {
+                        minor.set(minor.get() + 1);
+                        let version = FwVersion {
+                            major: 1,
+                            minor: minor.get(),
+                            variant: 0,
+                        };
+                        fw_version_response(version, DYNAMIC_ID)
+                    }
+                    _ => InternalResponseData::ComponentBusy,
+                };
+                device.send_response(resp).await;
+            }
+        };
+
+        block_on(async {
+            cfu::register_device(device).await.unwrap();
+
+            let requests = async {
+                for expected_minor in 1..=2 {
+                    let (resp, result) = join(
+                        cfu::send_request(DYNAMIC_ID, RequestData::FwVersionRequest),
+                        client.process_request(),
+                    )
+                    .await;
+                    assert_eq!(result, Ok(()));
+                    match resp {
+                        Ok(InternalResponseData::FwVersionResponse(r)) => {
+                            assert_eq!(r.component_info[0].fw_version.minor, expected_minor);
+                        }
+                        r => panic!("Unexpected response {:?}", r),
+                    }
+                }
+            };
+
+            select(requests, respond_dynamic).await;
+        });
+    }
+
+    /// Run a request through the client
+    async fn request(
+        client: &CfuClient,
+        data: RequestData,
+    ) -> (Result<InternalResponseData, CfuError>, Result<(), CfuError>) {
+        join(cfu::send_request(DEFAULT_ID, data), client.process_request()).await
+    }
+
+    #[test]
+    fn test_offer_content_finalize() {
+        let (_guard, shared) = shared();
+        let client = &shared.client;
+        let component: &'static CfuComponentDefault<CfuWriterDefault> = Box::leak(Box::new(CfuComponentDefault::new(
+            DEFAULT_ID,
+            false,
+            [None; MAX_SUBCMPT_COUNT],
+            CfuWriterDefault::default(),
+        )));
+        let offer = FwUpdateOffer::new(HostToken::Driver, DEFAULT_ID, NEW_VERSION, 0, 0);
+
+        block_on(async {
+            cfu::register_device(component).await.unwrap();
+
+            let update = async {
+                let (resp, result) = request(client, RequestData::PrepareComponentForUpdate).await;
+                assert_eq!(resp, Ok(InternalResponseData::ComponentPrepared));
+                assert_eq!(result, Ok(()));
+
+                let (resp, result) = request(client, RequestData::GiveOffer(offer)).await;
+                assert_eq!(result, Ok(()));
+                match resp {
+                    Ok(InternalResponseData::OfferResponse(r)) => assert_eq!(r.status, OfferStatus::Accept),
+                    r => panic!("Unexpected response {:?}", r),
+                }
+
+                let content = FwUpdateContentCommand::default();
+                let (resp, result) = request(client, RequestData::GiveContent(content)).await;
+                assert_eq!(result, Ok(()));
+                assert!(matches!(resp, Ok(InternalResponseData::ContentResponse(_))));
+
+                let (resp, result) = request(client, RequestData::FinalizeUpdate).await;
+                assert_eq!(resp, Ok(InternalResponseData::UpdateFinalized));
+                assert_eq!(result, Ok(()));
+            };
+
+            select(update, async {
+                loop {
+                    component.process_request().await.unwrap();
+                }
+            })
+            .await;
+        });
+
+        // Default component always reports the default version
+        assert_eq!(
+            shared.host.notification.get(),
+            Some(FwVersionChanged {
+                component_id: DEFAULT_ID,
+                fw_version: FwVersion::default(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_busy_component_rejects_offer() {
+        let (_guard, shared) = shared();
+        let client = &shared.client;
+        let device: &'static CfuDevice = Box::leak(Box::new(CfuDevice::new(BUSY_ID)));
+        let offer = FwUpdateOffer::new(HostToken::Driver, BUSY_ID, NEW_VERSION, 0, 0);
+
+        block_on(async {
+            cfu::register_device(device).await.unwrap();
+            device.set_state(InternalState::new(ComponentState::Busy)).await;
+
+            // The component never sees the offer
+            let (resp, result) = join(
+                cfu::send_request(BUSY_ID, RequestData::GiveOffer(offer)),
+                client.process_request(),
+            )
+            .await;
+            assert_eq!(resp, Ok(InternalResponseData::ComponentBusy));
+            assert_eq!(result, Err(CfuError::ComponentBusy));
+        });
+    }
+
+    #[test]
+    fn test_offer_to_missing_component() {
+        const MISSING: ComponentId = 100;
+        let (_guard, shared) = shared();
+        let client = &shared.client;
+        let offer = FwUpdateOffer::new(HostToken::Driver, MISSING, NEW_VERSION, 0, 0);
+
+        block_on(async {
+            cfu::set_unknown_component_mode(cfu::UnknownComponentMode::Skip);
+            for data in [RequestData::PrepareComponentForUpdate, RequestData::GiveOffer(offer)] {
+                let (resp, result) = join(cfu::send_request(MISSING, data), client.process_request()).await;
+                assert_eq!(resp, Ok(InternalResponseData::ComponentSkipped));
+                assert_eq!(result, Ok(()));
+            }
+
+            // The requester is told about the missing component rather than left waiting
+            cfu::set_unknown_component_mode(cfu::UnknownComponentMode::Strict);
+            let (resp, result) = join(
+                cfu::send_request(MISSING, RequestData::GiveOffer(offer)),
+                client.process_request(),
+            )
+            .await;
+            assert_eq!(resp, Err(CfuError::InvalidComponent));
+            assert_eq!(result, Err(CfuError::InvalidComponent));
+        });
+    }
+
+    #[test]
+    fn test_finalize_missing_component() {
+        const MISSING: ComponentId = 101;
+        let (_guard, shared) = shared();
+        let client = &shared.client;
+
+        block_on(async {
+            let (resp, result) = join(
+                cfu::send_request(MISSING, RequestData::FinalizeUpdate),
+                client.process_request(),
+            )
+            .await;
+            assert_eq!(resp, Err(CfuError::InvalidComponent));
+            assert_eq!(result, Err(CfuError::InvalidComponent));
+
+            cfu::set_unknown_component_mode(cfu::UnknownComponentMode::Skip);
+            let (resp, result) = join(
+                cfu::send_request(MISSING, RequestData::FinalizeUpdate),
+                client.process_request(),
+            )
+            .await;
+            cfu::set_unknown_component_mode(cfu::UnknownComponentMode::Strict);
+            assert_eq!(resp, Ok(InternalResponseData::ComponentSkipped));
+            assert_eq!(result, Ok(()));
+        });
+
+        // Nothing was updated so the host isn't notified
+        assert_eq!(shared.host.notification.get(), None);
+    }
+}
@@ -4,7 +4,7 @@
 //!
 //! This modules provides `OwnedRef` and `SharedSlice` types. `OwnedRef` represents ownership of the underlying buffer
 //! and allows mutable access to the buffer. This type does not implement `Copy` or `Clone` so as to provide compile-time
-//! ownership guarantees. `SharedRef` represents an immutable reference into the buffer. This type can be cloned
+//! ownership guarantees. `SharedRef` represents an immutable reference into the buffer. This type can be copied
 //! and can be created from an `OwnedRef`. `Access` and `AccessMut` are guard types that provide access to the buffer through
 //! references tied to the lifetime of the guard struct. These types enforce Rust's aliasing and mutability rules dynamically,
 //! similar to RefCell.
@@ -147,38 +147,49 @@ impl<T> Drop for AccessMut<'_, T> {
 }
 
 /// A immutable reference to a buffer
-#[derive(Clone)]
+/// Two references are equal if they refer to the same range of the same buffer
 pub struct SharedRef<'a, T> {
     buffer: &'a Buffer<'a, T>,
-    slice: Range<usize>,
+    // Stored as bounds rather than a `Range` so the reference can be `Copy`
+    start: usize,
+    end: usize,
 }
 
 impl<'a, T> SharedRef<'a, T> {
     /// Creates a new immutable buffer refference
     pub fn new(buffer: &'a Buffer<'a, T>, slice: Range<usize>) -> Self {
-        Self { buffer, slice }
+        Self {
+            buffer,
+            start: slice.start,
+            end: slice.end,
+        }
     }
 
     /// Borrows the buffer immutably
     /// Panics if the buffer is already borrowed mutably
     pub fn borrow<'s>(&'s self) -> Access<'a, T> {
-        Access::new(self.buffer, self.slice.clone())
+        Access::new(self.buffer, self.range())
     }
 
     /// Produces a new slice into the buffer
     pub fn slice(&self, range: Range<usize>) -> SharedRef<'a, T> {
-        if range.start >= self.slice.len() || range.end > self.slice.len() {
+        if range.start >= self.len() || range.end > self.len() {
             panic!("Slice out of bounds");
         }
 
-        let start = self.slice.start + range.start;
+        let start = self.start + range.start;
         let end = start + range.len();
         SharedRef::new(self.buffer, start..end)
     }
 
+    /// Range of the underlying buffer this reference covers
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
     /// Returns the length of the buffer
     pub fn len(&self) -> usize {
-        self.slice.len()
+        self.end - self.start
     }
 
     /// Returns true if the buffer is empty
@@ -187,6 +198,39 @@ impl<'a, T> SharedRef<'a, T> {
     }
 }
 
+// Manual impls, deriving would require `T: Copy`
+impl<T> Clone for SharedRef<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for SharedRef<'_, T> {}
+
+impl<T> PartialEq for SharedRef<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        core::ptr::eq(self.buffer, other.buffer) && self.start == other.start && self.end == other.end
+    }
+}
+
+impl<T> Eq for SharedRef<'_, T> {}
+
+impl<T> core::fmt::Debug for SharedRef<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SharedRef")
+            .field("start", &self.start)
+            .field("end", &self.end)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T> defmt::Format for SharedRef<'_, T> {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "SharedRef {{ start: {}, end: {} }}", self.start, self.end)
+    }
+}
+
 /// Guard struct for immutable buffer access
 pub struct Access<'a, T> {
     buffer: &'a Buffer<'a, T>,
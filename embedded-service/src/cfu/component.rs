@@ -1,15 +1,19 @@
 //! Device struct and methods for component communication
+use core::borrow::Borrow;
+use core::cell::Cell;
 use core::future::Future;
 
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
 use embedded_cfu_protocol::components::{CfuComponentInfo, CfuComponentStorage, CfuComponentTraits};
 use embedded_cfu_protocol::protocol_definitions::*;
 use embedded_cfu_protocol::{CfuWriter, CfuWriterError};
 use heapless::Vec;
 
 use super::CfuError;
+use crate::buffer::SharedRef;
 use crate::cfu::route_request;
 use crate::intrusive_list;
 
@@ -57,6 +61,40 @@ impl Default for InternalState {
     }
 }
 
+/// Firmware bank of a dual bank component
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Bank {
+    /// First bank, the only bank of a single bank component
+    #[default]
+    A,
+    /// Second bank
+    B,
+}
+
+impl Bank {
+    /// The other bank
+    pub fn other(self) -> Self {
+        match self {
+            Bank::A => Bank::B,
+            Bank::B => Bank::A,
+        }
+    }
+}
+
+/// Action taken once a component has finalized an update
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FinalizePolicy {
+    /// Reset the component to run the new firmware
+    ResetComponent,
+    /// Run from the bank the update was written to, then reset the component
+    SwitchBankThenReset,
+    /// The new firmware is applied without a reset
+    #[default]
+    ApplyLive,
+}
+
 /// CFU Request types and necessary data
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -67,10 +105,18 @@ pub enum RequestData {
     GiveOffer(FwUpdateOffer),
     /// Contains bytes for an accepted fw offer
     GiveContent(FwUpdateContentCommand),
+    /// Contains the header and a reference to the bytes for an accepted fw offer, written without an intermediate copy
+    GiveContentRef(FwUpdateContentHeader, SharedRef<'static, u8>),
     /// Request for component to prepare itself for an update
     PrepareComponentForUpdate,
     /// Request for component to execute any logic needed to finalize update
     FinalizeUpdate,
+    /// Request for component to revert to its firmware from before the update
+    RollbackUpdate,
+    /// Request for the bank the component is running from
+    GetActiveBank,
+    /// Request for a dual bank component to run from the bank content was written to
+    SwitchBank,
 }
 
 /// CFU Response types and necessary data
@@ -91,6 +137,24 @@ pub enum InternalResponseData {
     SubcomponentFwVersionResponse([FwVerComponentInfo; MAX_CMPT_COUNT - 1]),
     /// Component is ready to receive offers
     ComponentPrepared,
+    /// Component has finalized its update
+    UpdateFinalized,
+    /// Component has reverted its update
+    UpdateRolledBack,
+    /// Component is not present and the request was skipped
+    ComponentSkipped,
+    /// Bank the component is running from after the request
+    ActiveBank(Bank),
+}
+
+/// Progress of a firmware update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UpdateProgress {
+    /// Bytes written so far and the total image size, if set with [`CfuComponentDefault::set_image_size`]
+    Written(u32, Option<u32>),
+    /// Writing content failed
+    WriteFailed,
 }
 
 /// Channel size for device requests
@@ -104,6 +168,8 @@ pub struct CfuDevice {
     state: Mutex<NoopRawMutex, InternalState>,
     request: Channel<NoopRawMutex, RequestData, DEVICE_CHANNEL_SIZE>,
     response: Channel<NoopRawMutex, InternalResponseData, DEVICE_CHANNEL_SIZE>,
+    progress: Signal<NoopRawMutex, UpdateProgress>,
+    reset: Signal<NoopRawMutex, ()>,
 }
 
 impl intrusive_list::NodeContainer for CfuDevice {
@@ -133,6 +199,8 @@ impl CfuDevice {
             state: Mutex::new(InternalState::default()),
             request: Channel::new(),
             response: Channel::new(),
+            progress: Signal::new(),
+            reset: Signal::new(),
         }
     }
     /// Getter for component id
@@ -144,6 +212,10 @@ impl CfuDevice {
     pub async fn state(&self) -> InternalState {
         *self.state.lock().await
     }
+    /// Set the component state
+    pub async fn set_state(&self, state: InternalState) {
+        *self.state.lock().await = state;
+    }
     /// Sends a request to this device and returns a response
     pub async fn execute_device_request(&self, request: RequestData) -> Result<InternalResponseData, CfuProtocolError> {
         self.request.send(request).await;
@@ -159,6 +231,16 @@ impl CfuDevice {
     pub async fn send_response(&self, response: InternalResponseData) {
         self.response.send(response).await;
     }
+
+    /// Update progress, published as content is written
+    pub fn progress(&self) -> &Signal<NoopRawMutex, UpdateProgress> {
+        &self.progress
+    }
+
+    /// Component reset request, signaled after finalizing an update that needs a reset
+    pub fn reset(&self) -> &Signal<NoopRawMutex, ()> {
+        &self.reset
+    }
 }
 
 /// Example for CFU Component
@@ -169,6 +251,16 @@ pub struct CfuComponentDefault<W: CfuWriter> {
     storage_offset: usize,
     subcomponents: [Option<ComponentId>; MAX_SUBCMPT_COUNT],
     writer: Mutex<NoopRawMutex, W>,
+    /// Bytes written in the current update
+    written: Cell<u32>,
+    /// Size of the image being updated, the CFU offer doesn't carry it
+    image_size: Cell<Option<u32>>,
+    /// Size of each bank, bank B starts this far past bank A
+    bank_size: usize,
+    /// Bank the component is running from
+    active_bank: Mutex<NoopRawMutex, Bank>,
+    /// Action taken after a successful finalize
+    finalize_policy: Cell<FinalizePolicy>,
 }
 
 impl<W: CfuWriter + Default> Default for CfuComponentDefault<W> {
@@ -198,8 +290,60 @@ impl<W: CfuWriter> CfuComponentDefault<W> {
             storage_offset: 0,
             subcomponents,
             writer: Mutex::new(writer),
+            written: Cell::new(0),
+            image_size: Cell::new(None),
+            bank_size: 0,
+            active_bank: Mutex::new(Bank::A),
+            finalize_policy: Cell::new(FinalizePolicy::default()),
+        }
+    }
+
+    /// Constructor for a dual bank component, content is written to the inactive bank
+    pub fn new_dual_bank(
+        id: ComponentId,
+        is_primary: bool,
+        subcomponents: [Option<ComponentId>; MAX_SUBCMPT_COUNT],
+        writer: W,
+        bank_size: usize,
+    ) -> Self {
+        Self {
+            is_dual_bank: true,
+            bank_size,
+            ..Self::new(id, is_primary, subcomponents, writer)
+        }
+    }
+
+    /// Set the size of the image for the next update, reported as the total in [`UpdateProgress`]
+    pub fn set_image_size(&self, size: Option<u32>) {
+        self.image_size.set(size);
+    }
+
+    /// Set the action taken after a successful finalize
+    pub fn set_finalize_policy(&self, policy: FinalizePolicy) {
+        self.finalize_policy.set(policy);
+    }
+
+    /// Bank the component is running from
+    pub async fn active_bank(&self) -> Bank {
+        *self.active_bank.lock().await
+    }
+
+    /// Bank content is written to, the inactive bank of a dual bank component
+    pub async fn target_bank(&self) -> Bank {
+        let active = self.active_bank().await;
+        if self.is_dual_bank {
+            active.other()
+        } else {
+            active
         }
     }
+
+    /// Run from the target bank, returns the new active bank
+    pub async fn switch_bank(&self) -> Bank {
+        let target = self.target_bank().await;
+        *self.active_bank.lock().await = target;
+        target
+    }
     /// wait for a request and process it
     pub async fn process_request(&self) -> Result<(), CfuError> {
         match self.device.wait_request().await {
@@ -247,29 +391,143 @@ impl<W: CfuWriter> CfuComponentDefault<W> {
                 self.storage_prepare()
                     .await
                     .map_err(|_| CfuError::ProtocolError(CfuProtocolError::BadResponse))?;
+                self.device.send_response(InternalResponseData::ComponentPrepared).await;
             }
             RequestData::GiveOffer(buf) => {
                 // accept any and all offers regardless of what version it is
-                if buf.component_info.component_id == self.get_component_id() {
-                    let resp = FwUpdateOfferResponse::new_accept(HostToken::Driver);
-                    self.device
-                        .send_response(InternalResponseData::OfferResponse(resp))
-                        .await;
-                }
+                let resp = if buf.component_info.component_id == self.get_component_id() {
+                    self.written.set(0);
+                    FwUpdateOfferResponse::new_accept(HostToken::Driver)
+                } else {
+                    FwUpdateOfferResponse::new_with_failure(
+                        HostToken::Driver,
+                        OfferRejectReason::InvalidComponent,
+                        OfferStatus::Reject,
+                    )
+                };
+                self.device
+                    .send_response(InternalResponseData::OfferResponse(resp))
+                    .await;
             }
-            RequestData::GiveContent(buf) => {
-                let offset = buf.header.firmware_address as usize;
-                self.writer
-                    .lock()
-                    .await
-                    .cfu_write(Some(offset), &buf.data)
+            RequestData::GiveContent(buf) => self.write_content(buf.header, &buf.data).await?,
+            RequestData::GiveContentRef(header, data) => {
+                let access = data.borrow();
+                let bytes: &[u8] = access.borrow();
+                self.write_content(header, bytes).await?;
+            }
+            RequestData::FinalizeUpdate => {
+                self.storage_finalize()
                     .await
                     .map_err(|e| CfuError::ProtocolError(CfuProtocolError::WriterError(e)))?;
+                self.device.send_response(InternalResponseData::UpdateFinalized).await;
+                self.apply_finalize_policy().await;
+            }
+            RequestData::RollbackUpdate => {
+                self.device.send_response(InternalResponseData::UpdateRolledBack).await;
+            }
+            RequestData::GetActiveBank => {
+                let bank = self.active_bank().await;
+                self.device.send_response(InternalResponseData::ActiveBank(bank)).await;
+            }
+            RequestData::SwitchBank => {
+                let bank = self.switch_bank().await;
+                self.device.send_response(InternalResponseData::ActiveBank(bank)).await;
             }
-            RequestData::FinalizeUpdate => {}
         }
         Ok(())
     }
+
+    /// Take the configured action after the update has been finalized and the response sent
+    async fn apply_finalize_policy(&self) {
+        match self.finalize_policy.get() {
+            FinalizePolicy::ResetComponent => self.device.reset.signal(()),
+            FinalizePolicy::SwitchBankThenReset => {
+                self.switch_bank().await;
+                self.device.reset.signal(());
+            }
+            FinalizePolicy::ApplyLive => {}
+        }
+    }
+
+    /// Write a block of content, then report progress and respond to the request
+    async fn write_content(&self, header: FwUpdateContentHeader, data: &[u8]) -> Result<(), CfuError> {
+        let bank_offset = match self.target_bank().await {
+            Bank::A => 0,
+            Bank::B => self.bank_size,
+        };
+        let offset = self.storage_offset + bank_offset + header.firmware_address as usize;
+        let result = self.writer.lock().await.cfu_write(Some(offset), data).await;
+        let status = if result.is_ok() {
+            // Only content that made it to storage counts towards progress
+            let written = self.written.get() + header.data_length as u32;
+            self.written.set(written);
+            self.device
+                .progress
+                .signal(UpdateProgress::Written(written, self.image_size.get()));
+            CfuUpdateContentResponseStatus::Success
+        } else {
+            self.device.progress.signal(UpdateProgress::WriteFailed);
+            CfuUpdateContentResponseStatus::ErrorWrite
+        };
+        self.device
+            .send_response(InternalResponseData::ContentResponse(FwUpdateContentResponse::new(
+                header.sequence_num,
+                status,
+            )))
+            .await;
+        result.map_err(|e| CfuError::ProtocolError(CfuProtocolError::WriterError(e)))
+    }
+
+    /// Update each subcomponent in order, rolling back already updated subcomponents if any update fails
+    pub async fn update_all_subcomponents(&self, offer: FwUpdateOffer) -> Result<(), CfuError> {
+        let mut updated = Vec::<ComponentId, MAX_SUBCMPT_COUNT>::new();
+
+        for id in self.get_subcomponents().into_iter().flatten() {
+            if let Err(e) = update_subcomponent(id, offer).await {
+                for id in updated.iter().rev() {
+                    // Keep going so every subcomponent gets a chance to roll back
+                    let _ = route_request(*id, RequestData::RollbackUpdate).await;
+                }
+                return Err(e);
+            }
+
+            // Can't fail, there are at most MAX_SUBCMPT_COUNT subcomponents
+            let _ = updated.push(id);
+        }
+
+        Ok(())
+    }
+}
+
+/// Check a subcomponent responded as expected
+fn check_response(resp: InternalResponseData, expected: InternalResponseData) -> Result<(), CfuError> {
+    match resp {
+        r if r == expected => Ok(()),
+        InternalResponseData::ComponentBusy => Err(CfuError::ComponentBusy),
+        _ => Err(CfuError::ProtocolError(CfuProtocolError::BadResponse)),
+    }
+}
+
+/// Prepare, offer and finalize an update on a single subcomponent
+async fn update_subcomponent(id: ComponentId, mut offer: FwUpdateOffer) -> Result<(), CfuError> {
+    let resp = route_request(id, RequestData::PrepareComponentForUpdate).await?;
+    if resp == InternalResponseData::ComponentSkipped {
+        return Ok(());
+    }
+    check_response(resp, InternalResponseData::ComponentPrepared)?;
+
+    offer.component_info.component_id = id;
+    match route_request(id, RequestData::GiveOffer(offer)).await? {
+        InternalResponseData::OfferResponse(r) if r.status == OfferStatus::Accept => {}
+        InternalResponseData::OfferResponse(_) => return Err(CfuError::BadImage),
+        InternalResponseData::ComponentBusy => return Err(CfuError::ComponentBusy),
+        _ => return Err(CfuError::ProtocolError(CfuProtocolError::BadResponse)),
+    }
+
+    check_response(
+        route_request(id, RequestData::FinalizeUpdate).await?,
+        InternalResponseData::UpdateFinalized,
+    )
 }
 
 impl<W: CfuWriter> CfuComponentInfo for CfuComponentDefault<W> {
@@ -350,3 +608,356 @@ impl<C: CfuComponentTraits + CfuDeviceContainer + 'static> CfuWrapperDefault<C>
         Self { component }
     }
 }
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use core::cell::{Cell, RefCell};
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    use embassy_futures::join::{join, join3};
+    use embassy_futures::{block_on, select::select};
+
+    use super::*;
+    use crate::{cfu, define_static_buffer};
+
+    /// Writable flash size of the mock writer
+    const FLASH_SIZE: usize = 96;
+    /// Size of the image being updated
+    const IMAGE_SIZE: u32 = 128;
+    const BLOCK_SIZE: u8 = 32;
+
+    /// Writer that fails past the end of a small flash
+    #[derive(Default)]
+    struct MockWriter;
+
+    impl CfuWriter for MockWriter {
+        async fn cfu_write(&self, mem_offset: Option<usize>, _data: &[u8]) -> Result<(), CfuWriterError> {
+            if mem_offset.unwrap_or(0) < FLASH_SIZE {
+                Ok(())
+            } else {
+                Err(CfuWriterError::Other)
+            }
+        }
+
+        async fn cfu_write_read(
+            &self,
+            _mem_offset: Option<usize>,
+            _data: &[u8],
+            _read: &mut [u8],
+        ) -> Result<(), CfuWriterError> {
+            Ok(())
+        }
+
+        async fn cfu_read(&self, _mem_offset: Option<usize>, _read: &mut [u8]) -> Result<(), CfuWriterError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_update_progress() {
+        let component = CfuComponentDefault::new(1, false, [None; MAX_SUBCMPT_COUNT], MockWriter);
+        let device = component.get_cfu_component_device();
+        let offer = FwUpdateOffer::new(HostToken::Driver, 1, FwVersion::default(), 0, 0);
+        component.set_image_size(Some(IMAGE_SIZE));
+
+        block_on(async {
+            let (resp, result) = join(
+                device.execute_device_request(RequestData::GiveOffer(offer)),
+                component.process_request(),
+            )
+            .await;
+            assert!(matches!(resp, Ok(InternalResponseData::OfferResponse(_))));
+            assert_eq!(result, Ok(()));
+
+            let mut content = FwUpdateContentCommand::default();
+            content.header.data_length = BLOCK_SIZE;
+            let mut last = 0;
+            for block in 0..3u32 {
+                content.header.firmware_address = block * BLOCK_SIZE as u32;
+                let (resp, result) = join(
+                    device.execute_device_request(RequestData::GiveContent(content)),
+                    component.process_request(),
+                )
+                .await;
+                assert!(matches!(resp, Ok(InternalResponseData::ContentResponse(_))));
+                assert_eq!(result, Ok(()));
+
+                match device.progress().try_take() {
+                    Some(UpdateProgress::Written(written, total)) => {
+                        assert!(written > last);
+                        assert_eq!(written, (block + 1) * BLOCK_SIZE as u32);
+                        assert_eq!(total, Some(IMAGE_SIZE));
+                        last = written;
+                    }
+                    p => panic!("Unexpected progress {:?}", p),
+                }
+            }
+
+            // Last block is past the end of flash
+            content.header.firmware_address = FLASH_SIZE as u32;
+            let (resp, result) = join(
+                device.execute_device_request(RequestData::GiveContent(content)),
+                component.process_request(),
+            )
+            .await;
+            assert!(matches!(resp, Ok(InternalResponseData::ContentResponse(_))));
+            assert!(result.is_err());
+            assert_eq!(device.progress().try_take(), Some(UpdateProgress::WriteFailed));
+
+            // The failed block isn't counted once it's retried successfully
+            content.header.firmware_address = 2 * BLOCK_SIZE as u32;
+            let (_, result) = join(
+                device.execute_device_request(RequestData::GiveContent(content)),
+                component.process_request(),
+            )
+            .await;
+            assert_eq!(result, Ok(()));
+            assert_eq!(
+                device.progress().try_take(),
+                Some(UpdateProgress::Written(4 * BLOCK_SIZE as u32, Some(IMAGE_SIZE)))
+            );
+        });
+    }
+
+    /// Writer that records where the written bytes live
+    #[derive(Default)]
+    struct RecordingWriter {
+        written: Cell<Option<(*const u8, usize)>>,
+        offset: Cell<Option<usize>>,
+    }
+
+    impl CfuWriter for RecordingWriter {
+        async fn cfu_write(&self, mem_offset: Option<usize>, data: &[u8]) -> Result<(), CfuWriterError> {
+            self.written.set(Some((data.as_ptr(), data.len())));
+            self.offset.set(mem_offset);
+            Ok(())
+        }
+
+        async fn cfu_write_read(
+            &self,
+            _mem_offset: Option<usize>,
+            _data: &[u8],
+            _read: &mut [u8],
+        ) -> Result<(), CfuWriterError> {
+            Ok(())
+        }
+
+        async fn cfu_read(&self, _mem_offset: Option<usize>, _read: &mut [u8]) -> Result<(), CfuWriterError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_content_ref_zero_copy() {
+        define_static_buffer!(content_buffer, u8, [0xa5; 64]);
+        let component = CfuComponentDefault::new(1, false, [None; MAX_SUBCMPT_COUNT], RecordingWriter::default());
+        let device = component.get_cfu_component_device();
+
+        let owned = content_buffer::get_mut().unwrap();
+        let content = owned.reference().slice(16..48);
+        let source = {
+            let access = content.borrow();
+            let bytes: &[u8] = access.borrow();
+            bytes.as_ptr()
+        };
+
+        let header = FwUpdateContentHeader {
+            data_length: content.len() as u8,
+            sequence_num: 7,
+            ..Default::default()
+        };
+
+        block_on(async {
+            let (resp, result) = join(
+                device.execute_device_request(RequestData::GiveContentRef(header, content)),
+                component.process_request(),
+            )
+            .await;
+            assert_eq!(result, Ok(()));
+            assert_eq!(
+                resp,
+                Ok(InternalResponseData::ContentResponse(FwUpdateContentResponse::new(
+                    7,
+                    CfuUpdateContentResponseStatus::Success
+                )))
+            );
+
+            // The writer saw the source buffer itself rather than a copy
+            assert_eq!(
+                component.writer.lock().await.written.get(),
+                Some((source, content.len()))
+            );
+        });
+    }
+
+    #[test]
+    fn test_dual_bank_update() {
+        const BANK_SIZE: usize = 0x1000;
+
+        let component = CfuComponentDefault::new_dual_bank(
+            1,
+            false,
+            [None; MAX_SUBCMPT_COUNT],
+            RecordingWriter::default(),
+            BANK_SIZE,
+        );
+        let device = component.get_cfu_component_device();
+        let mut content = FwUpdateContentCommand::default();
+        content.header.firmware_address = 0x10;
+
+        block_on(async {
+            let request = |request| join(device.execute_device_request(request), component.process_request());
+
+            let (resp, result) = request(RequestData::GetActiveBank).await;
+            assert_eq!(resp, Ok(InternalResponseData::ActiveBank(Bank::A)));
+            assert_eq!(result, Ok(()));
+
+            // Content lands in the inactive bank and the component keeps running from the active one
+            let (_, result) = request(RequestData::GiveContent(content)).await;
+            assert_eq!(result, Ok(()));
+            assert_eq!(component.writer.lock().await.offset.get(), Some(BANK_SIZE + 0x10));
+            assert_eq!(component.active_bank().await, Bank::A);
+
+            let (resp, _) = request(RequestData::SwitchBank).await;
+            assert_eq!(resp, Ok(InternalResponseData::ActiveBank(Bank::B)));
+            assert_eq!(component.active_bank().await, Bank::B);
+
+            // The next update targets the bank that is now inactive
+            let (_, result) = request(RequestData::GiveContent(content)).await;
+            assert_eq!(result, Ok(()));
+            assert_eq!(component.writer.lock().await.offset.get(), Some(0x10));
+        });
+    }
+
+    #[test]
+    fn test_finalize_policy() {
+        for (policy, reset, bank) in [
+            (FinalizePolicy::ApplyLive, false, Bank::A),
+            (FinalizePolicy::ResetComponent, true, Bank::A),
+            (FinalizePolicy::SwitchBankThenReset, true, Bank::B),
+        ] {
+            let component = CfuComponentDefault::new_dual_bank(1, false, [None; MAX_SUBCMPT_COUNT], MockWriter, 0x1000);
+            component.set_finalize_policy(policy);
+            let device = component.get_cfu_component_device();
+
+            block_on(async {
+                let (resp, result) = join(
+                    device.execute_device_request(RequestData::FinalizeUpdate),
+                    component.process_request(),
+                )
+                .await;
+                assert_eq!(resp, Ok(InternalResponseData::UpdateFinalized));
+                assert_eq!(result, Ok(()));
+            });
+
+            // A reset is requested exactly once, and only by policies that need one
+            assert_eq!(device.reset().try_take().is_some(), reset, "{:?}", policy);
+            assert_eq!(device.reset().try_take(), None);
+            assert_eq!(block_on(component.active_bank()), bank, "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn test_subcomponent_rollback() {
+        const PRIMARY: ComponentId = 20;
+        const SUBS: [ComponentId; 3] = [21, 22, 23];
+
+        cfu::init();
+        let mut subcomponents = [None; MAX_SUBCMPT_COUNT];
+        for (sub, id) in subcomponents.iter_mut().zip(SUBS) {
+            *sub = Some(id);
+        }
+        let primary = CfuComponentDefault::new(PRIMARY, true, subcomponents, MockWriter);
+        let offer = FwUpdateOffer::new(HostToken::Driver, PRIMARY, FwVersion::default(), 0, 0);
+
+        let log = RefCell::new(Vec::new());
+        let failing = Cell::new(None);
+
+        // Records requests, finalizing fails on the failing subcomponent
+        let respond = |device: &'static CfuDevice| {
+            let log = &log;
+            let failing = &failing;
+            async move {
+                let id = device.component_id();
+                loop {
+                    let request = device.wait_request().await;
+                    log.borrow_mut().push((id, request));
+                    let resp = match request {
+                        RequestData::PrepareComponentForUpdate => InternalResponseData::ComponentPrepared,
+                        RequestData::GiveOffer(_) => {
+                            InternalResponseData::OfferResponse(FwUpdateOfferResponse::new_accept(HostToken::Driver))
+                        }
+                        RequestData::FinalizeUpdate if failing.get() == Some(id) => InternalResponseData::ComponentBusy,
+                        RequestData::FinalizeUpdate => InternalResponseData::UpdateFinalized,
+                        RequestData::RollbackUpdate => InternalResponseData::UpdateRolledBack,
+                        _ => InternalResponseData::ComponentBusy,
+                    };
+                    device.send_response(resp).await;
+                }
+            }
+        };
+
+        // Kind of request each subcomponent received, in order
+        let requests = || {
+            log.take()
+                .into_iter()
+                .map(|(id, request)| {
+                    let kind = match request {
+                        RequestData::PrepareComponentForUpdate => "prepare",
+                        RequestData::GiveOffer(offer) => {
+                            assert_eq!(offer.component_info.component_id, id);
+                            "offer"
+                        }
+                        RequestData::FinalizeUpdate => "finalize",
+                        RequestData::RollbackUpdate => "rollback",
+                        _ => "other",
+                    };
+                    (id, kind)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        block_on(async {
+            let devices = SUBS.map(|id| -> &'static CfuDevice { Box::leak(Box::new(CfuDevice::new(id))) });
+            for device in devices {
+                cfu::register_device(device).await.unwrap();
+            }
+
+            let update = async {
+                assert_eq!(primary.update_all_subcomponents(offer).await, Ok(()));
+                let expected: Vec<_> = SUBS
+                    .iter()
+                    .flat_map(|id| [(*id, "prepare"), (*id, "offer"), (*id, "finalize")])
+                    .collect();
+                assert_eq!(requests(), expected);
+
+                // Second subcomponent fails, the first is rolled back and the third is never touched
+                failing.set(Some(SUBS[1]));
+                assert_eq!(
+                    primary.update_all_subcomponents(offer).await,
+                    Err(CfuError::ComponentBusy)
+                );
+                assert_eq!(
+                    requests(),
+                    [
+                        (SUBS[0], "prepare"),
+                        (SUBS[0], "offer"),
+                        (SUBS[0], "finalize"),
+                        (SUBS[1], "prepare"),
+                        (SUBS[1], "offer"),
+                        (SUBS[1], "finalize"),
+                        (SUBS[0], "rollback"),
+                    ]
+                );
+            };
+
+            select(
+                update,
+                join3(respond(devices[0]), respond(devices[1]), respond(devices[2])),
+            )
+            .await;
+        });
+    }
+}
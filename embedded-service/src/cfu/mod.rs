@@ -7,10 +7,12 @@ use core::sync::atomic::{AtomicBool, Ordering};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::once_lock::OnceLock;
-use embedded_cfu_protocol::protocol_definitions::{CfuProtocolError, ComponentId};
+use embedded_cfu_protocol::protocol_definitions::{CfuProtocolError, ComponentId, FwVersion};
 
-use crate::cfu::component::{CfuDevice, CfuDeviceContainer, InternalResponseData, RequestData, DEVICE_CHANNEL_SIZE};
-use crate::{error, intrusive_list};
+use crate::cfu::component::{
+    CfuDevice, CfuDeviceContainer, ComponentState, InternalResponseData, RequestData, DEVICE_CHANNEL_SIZE,
+};
+use crate::{error, info, intrusive_list};
 
 /// Error type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -36,6 +38,34 @@ pub struct Request {
     pub data: RequestData,
 }
 
+/// Notification sent by the CFU client when a component's firmware version changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FwVersionChanged {
+    /// Component that was updated
+    pub component_id: ComponentId,
+    /// Firmware version reported after the update
+    pub fw_version: FwVersion,
+}
+
+/// Behavior when routing a request to a component that isn't registered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UnknownComponentMode {
+    /// Return [`CfuError::InvalidComponent`]
+    #[default]
+    Strict,
+    /// Skip the request and respond with [`InternalResponseData::ComponentSkipped`]
+    Skip,
+}
+
+static SKIP_UNKNOWN_COMPONENTS: AtomicBool = AtomicBool::new(false);
+
+/// Set the behavior when routing a request to a component that isn't registered
+pub fn set_unknown_component_mode(mode: UnknownComponentMode) {
+    SKIP_UNKNOWN_COMPONENTS.store(mode == UnknownComponentMode::Skip, Ordering::SeqCst);
+}
+
 /// Cfu context
 struct ClientContext {
     /// Registered devices
@@ -43,7 +73,7 @@ struct ClientContext {
     /// Request to components
     request: Channel<NoopRawMutex, Request, { DEVICE_CHANNEL_SIZE }>,
     /// Response from components
-    response: Channel<NoopRawMutex, InternalResponseData, { DEVICE_CHANNEL_SIZE }>,
+    response: Channel<NoopRawMutex, Result<InternalResponseData, CfuError>, { DEVICE_CHANNEL_SIZE }>,
 }
 
 impl ClientContext {
@@ -63,6 +93,11 @@ pub fn init() {
     CONTEXT.get_or_init(ClientContext::new);
 }
 
+/// Limit the number of CFU devices that can be registered, None removes the limit
+pub async fn set_max_devices(max: Option<usize>) {
+    CONTEXT.get().await.devices.set_max_len(max);
+}
+
 /// Register a device with the Cfu Client service
 pub async fn register_device(device: &'static impl CfuDeviceContainer) -> Result<(), intrusive_list::Error> {
     let device = device.get_cfu_component_device();
@@ -88,6 +123,20 @@ async fn get_device(id: ComponentId) -> Option<&'static CfuDevice> {
     None
 }
 
+/// Get the current update state of every registered component
+pub async fn component_states<const N: usize>() -> heapless::Vec<(ComponentId, ComponentState), N> {
+    let mut states = heapless::Vec::new();
+
+    for device in CONTEXT.get().await.devices.iter_only::<CfuDevice>() {
+        let id = device.component_id();
+        if states.push((id, device.state().await.state)).is_err() {
+            error!("Component {}: No space to record state", id);
+        }
+    }
+
+    states
+}
+
 /// Convenience function to send a request to the Cfu service
 pub async fn send_request(from: ComponentId, request: RequestData) -> Result<InternalResponseData, CfuError> {
     let context = CONTEXT.get().await;
@@ -98,13 +147,17 @@ pub async fn send_request(from: ComponentId, request: RequestData) -> Result<Int
             data: request,
         })
         .await;
-    Ok(context.response.receive().await)
+    context.response.receive().await
 }
 
 /// Convenience function to route a request to a specific component
 pub async fn route_request(to: ComponentId, request: RequestData) -> Result<InternalResponseData, CfuError> {
     let device = get_device(to).await;
     if device.is_none() {
+        if SKIP_UNKNOWN_COMPONENTS.load(Ordering::SeqCst) {
+            info!("Component {}: Not present, skipping request", to);
+            return Ok(InternalResponseData::ComponentSkipped);
+        }
         return Err(CfuError::InvalidComponent);
     }
     device
@@ -135,7 +188,7 @@ impl ContextToken {
     }
 
     /// Send a response to a cfu request
-    pub async fn send_response(&self, response: InternalResponseData) {
+    pub async fn send_response(&self, response: Result<InternalResponseData, CfuError>) {
         CONTEXT.get().await.response.send(response).await
     }
 
@@ -149,3 +202,54 @@ impl ContextToken {
         &CONTEXT.get().await.devices
     }
 }
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use std::boxed::Box;
+
+    use embassy_futures::block_on;
+
+    use super::component::InternalState;
+    use super::*;
+
+    #[test]
+    fn test_component_states() {
+        init();
+        let states = [
+            (10, ComponentState::Idle),
+            (11, ComponentState::Ready),
+            (12, ComponentState::Busy),
+            (13, ComponentState::FinalizingUpdate),
+        ];
+
+        for (id, state) in states {
+            let device: &'static CfuDevice = Box::leak(Box::new(CfuDevice::new(id)));
+            block_on(device.set_state(InternalState::new(state)));
+            block_on(register_device(device)).unwrap();
+        }
+
+        let reported = block_on(component_states::<8>());
+        for expected in states {
+            assert!(reported.contains(&expected));
+        }
+    }
+
+    #[test]
+    fn test_route_unknown_component() {
+        init();
+        const MISSING: ComponentId = 200;
+
+        set_unknown_component_mode(UnknownComponentMode::Skip);
+        assert_eq!(
+            block_on(route_request(MISSING, RequestData::FwVersionRequest)),
+            Ok(InternalResponseData::ComponentSkipped)
+        );
+
+        set_unknown_component_mode(UnknownComponentMode::Strict);
+        assert_eq!(
+            block_on(route_request(MISSING, RequestData::FwVersionRequest)),
+            Err(CfuError::InvalidComponent)
+        );
+    }
+}
@@ -4,7 +4,12 @@ use core::any::{Any, TypeId};
 use core::cell::Cell;
 use core::convert::Infallible;
 
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::mutex;
 use embassy_sync::once_lock::OnceLock;
+use embassy_sync::signal::Signal;
+use embassy_time::{with_timeout, Duration, Timer};
 use serde::{Deserialize, Serialize};
 
 use crate::intrusive_list::{self, Node, NodeContainer};
@@ -57,6 +62,8 @@ pub enum Internal {
     TimeAlarm,
 
     /// OEM defined receiver
+    ///
+    /// Custom services register under their own key, messages are only delivered to endpoints with a matching key
     Oem(OemKey),
 }
 
@@ -97,6 +104,32 @@ impl From<External> for EndpointID {
     }
 }
 
+/// Class of endpoints a [`broadcast`] is delivered to
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EndpointClass {
+    /// All internal endpoints
+    Internal,
+
+    /// All external endpoints
+    External,
+
+    /// Every endpoint
+    All,
+}
+
+impl EndpointClass {
+    /// Returns true if the endpoint ID belongs to this class
+    pub fn contains(self, id: EndpointID) -> bool {
+        matches!(
+            (self, id),
+            (EndpointClass::All, _)
+                | (EndpointClass::Internal, EndpointID::Internal(_))
+                | (EndpointClass::External, EndpointID::External(_))
+        )
+    }
+}
+
 /// Data reference -- generalized such that any stack variable can be transmitted "in place" as needed
 #[derive(Copy, Clone, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -134,6 +167,79 @@ impl<'a> Data<'a> {
     }
 }
 
+/// Acknowledgment slot for messages sent with [`Endpoint::send_acked`], one per sending endpoint
+struct AckSlot {
+    signal: Signal<CriticalSectionRawMutex, ()>,
+    /// Sequence number of the message waiting to be acknowledged, if any
+    pending: Mutex<CriticalSectionRawMutex, Cell<Option<u32>>>,
+    next_sequence: Cell<u32>,
+}
+
+impl AckSlot {
+    const fn new() -> Self {
+        Self {
+            signal: Signal::new(),
+            pending: Mutex::new(Cell::new(None)),
+            next_sequence: Cell::new(0),
+        }
+    }
+
+    /// Start waiting on a new message, returning the acknowledgment to send with it
+    /// Acknowledgments for any previous message are ignored from now on
+    fn expect(&'static self) -> Ack {
+        let sequence = self.next_sequence.get();
+        self.next_sequence.set(sequence.wrapping_add(1));
+
+        self.signal.reset();
+        self.pending.lock(|pending| pending.set(Some(sequence)));
+        Ack { slot: self, sequence }
+    }
+
+    fn acknowledge(&self, sequence: u32) {
+        let expected = self.pending.lock(|pending| {
+            let matches = pending.get() == Some(sequence);
+            if matches {
+                pending.set(None);
+            }
+            matches
+        });
+
+        if expected {
+            self.signal.signal(());
+        }
+    }
+}
+
+/// Delivery acknowledgment requested by the sender of a message, see [`Endpoint::send_acked`]
+#[derive(Copy, Clone)]
+pub struct Ack {
+    slot: &'static AckSlot,
+    sequence: u32,
+}
+
+impl Ack {
+    /// Signal that the message has been processed
+    /// Has no effect if the sender has stopped waiting, e.g. after a timeout
+    pub fn acknowledge(&self) {
+        self.slot.acknowledge(self.sequence);
+    }
+}
+
+impl core::fmt::Debug for Ack {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Ack")
+            .field("sequence", &self.sequence)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Ack {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "Ack {{ sequence: {} }}", self.sequence)
+    }
+}
+
 /// Message to receive
 #[derive(Copy, Clone, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -146,6 +252,78 @@ pub struct Message<'a> {
 
     /// message content
     pub data: Data<'a>,
+
+    /// acknowledgment requested by the sender, if any
+    ack: Option<Ack>,
+
+    /// ID matching a response to its request, if any
+    correlation_id: Option<u32>,
+}
+
+impl Message<'_> {
+    /// Get the acknowledgment requested by the sender, if any
+    /// Delegates should call [`Ack::acknowledge`] once the message has been processed
+    pub fn ack(&self) -> Option<Ack> {
+        self.ack
+    }
+
+    /// Get the ID matching a response to its request, if any
+    /// Responders should reply with [`Endpoint::send_response`] using this ID
+    pub fn correlation_id(&self) -> Option<u32> {
+        self.correlation_id
+    }
+}
+
+/// Receives the response to a single in-flight [`Endpoint::request`]
+pub struct ResponseSlot<T> {
+    id: Mutex<CriticalSectionRawMutex, Cell<Option<u32>>>,
+    response: Signal<CriticalSectionRawMutex, T>,
+}
+
+impl<T: Copy + Any> ResponseSlot<T> {
+    /// Create a new response slot, typically placed in a static
+    pub const fn new() -> Self {
+        Self {
+            id: Mutex::new(Cell::new(None)),
+            response: Signal::new(),
+        }
+    }
+
+    /// Take the response if the message answers the request this slot is waiting on
+    /// Returns true if the message was consumed, delegates should process it normally otherwise
+    pub fn receive(&self, message: &Message) -> bool {
+        let Some(id) = message.correlation_id else {
+            return false;
+        };
+
+        let Some(response) = message.data.get::<T>() else {
+            return false;
+        };
+
+        let expected = self.id.lock(|expected| {
+            let matches = expected.get() == Some(id);
+            if matches {
+                expected.set(None);
+            }
+            matches
+        });
+
+        if expected {
+            self.response.signal(*response);
+        }
+        expected
+    }
+
+    fn expect(&self, id: u32) {
+        self.response.reset();
+        self.id.lock(|expected| expected.set(Some(id)));
+    }
+}
+
+impl<T: Copy + Any> Default for ResponseSlot<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Trait to receive messages
@@ -157,6 +335,8 @@ pub trait MailboxDelegate {
 }
 
 /// Message transmission Error
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum MailboxDelegateError {
     /// Buffer is full
     BufferFull,
@@ -181,11 +361,46 @@ pub enum MailboxDelegateError {
     Other,
 }
 
+/// Error returned by [`try_send_checked`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SendError {
+    /// No endpoint with the destination ID is registered
+    EndpointNotFound,
+    /// The destination's mailbox rejected the message, e.g. [`MailboxDelegateError::BufferFull`]
+    Mailbox(MailboxDelegateError),
+}
+
+/// Error returned by [`Endpoint::send_acked`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AckError {
+    /// The message could not be delivered, so no acknowledgment will arrive
+    Send(SendError),
+    /// The receiver didn't acknowledge the message before the timeout
+    Timeout,
+}
+
+/// Bounded retry for deliveries rejected with [`MailboxDelegateError::BufferFull`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RetryPolicy {
+    /// Number of retries after the initial attempt
+    pub max_retries: u8,
+    /// Delay before the first retry, doubled for each subsequent retry
+    pub backoff: Duration,
+}
+
 /// Primary node registration for receiving messages from the comms service
 pub struct Endpoint {
     node: Node,
     id: EndpointID,
     delegator: Cell<Option<&'static dyn MailboxDelegate>>,
+    retry: Cell<Option<RetryPolicy>>,
+    next_correlation_id: Cell<u32>,
+    ack: AckSlot,
+    /// Serializes acknowledged sends so each has the acknowledgment slot to itself
+    ack_lock: mutex::Mutex<CriticalSectionRawMutex, ()>,
 }
 
 impl NodeContainer for Endpoint {
@@ -206,23 +421,143 @@ impl Endpoint {
             node: Node::uninit(),
             id,
             delegator: Cell::new(None),
+            retry: Cell::new(None),
+            next_correlation_id: Cell::new(0),
+            ack: AckSlot::new(),
+            ack_lock: mutex::Mutex::new(()),
         }
     }
 
+    /// Retry messages sent from this endpoint when the receiver is full, None fails immediately (default)
+    pub fn set_retry_policy(&self, retry: Option<RetryPolicy>) {
+        self.retry.set(retry);
+    }
+
     /// Send a generic message to an endpoint
     pub async fn send(&self, to: EndpointID, data: &impl Any) -> Result<(), Infallible> {
-        send(self.id, to, data).await
+        send_with_retry(self.id, to, data, self.retry.get()).await
+    }
+
+    /// Send a generic message to an endpoint, failing if it isn't registered or rejects the message
+    pub async fn try_send_checked(&self, to: EndpointID, data: &impl Any) -> Result<(), SendError> {
+        try_send_checked_with_retry(self.id, to, data, self.retry.get()).await
+    }
+
+    /// Send a request and wait for the response carrying the same correlation ID
+    ///
+    /// Each in-flight request needs its own `slot`, and this endpoint's delegate must pass inbound messages
+    /// to it with [`ResponseSlot::receive`]. Responses to other requests are left to their own slots.
+    pub async fn request<T: Copy + Any>(
+        &self,
+        to: EndpointID,
+        data: &impl Any,
+        slot: &ResponseSlot<T>,
+    ) -> Result<T, SendError> {
+        let id = self.next_correlation_id.get();
+        self.next_correlation_id.set(id.wrapping_add(1));
+
+        #[cfg(feature = "comms-recorder")]
+        recorder::record(self.id, to, core::any::type_name_of_val(data));
+
+        // Expect the response before sending, the responder may reply during delivery
+        slot.expect(id);
+        route_checked(
+            &Message {
+                from: self.id,
+                to,
+                data: Data::new(data),
+                ack: None,
+                correlation_id: Some(id),
+            },
+            self.retry.get(),
+        )
+        .await?;
+
+        Ok(slot.response.wait().await)
+    }
+
+    /// Send the response to a request received with the given correlation ID
+    pub async fn send_response(&self, to: EndpointID, correlation_id: u32, data: &impl Any) -> Result<(), Infallible> {
+        #[cfg(feature = "comms-recorder")]
+        recorder::record(self.id, to, core::any::type_name_of_val(data));
+
+        route_with_retry(
+            &Message {
+                from: self.id,
+                to,
+                data: Data::new(data),
+                ack: None,
+                correlation_id: Some(correlation_id),
+            },
+            self.retry.get(),
+        )
+        .await
+    }
+
+    /// Send a generic message to an endpoint and wait for the receiver to acknowledge it, with no timeout
+    ///
+    /// Resolves once a receiver has called [`Ack::acknowledge`], or immediately if the message couldn't be delivered.
+    /// Acknowledged sends from the same endpoint are serialized.
+    pub async fn send_acked_no_timeout(&'static self, to: EndpointID, data: &impl Any) -> Result<(), SendError> {
+        let _guard = self.ack_lock.lock().await;
+
+        #[cfg(feature = "comms-recorder")]
+        recorder::record(self.id, to, core::any::type_name_of_val(data));
+
+        // Expect the acknowledgment before sending, the receiver may acknowledge during delivery
+        let ack = self.ack.expect();
+        route_checked(
+            &Message {
+                from: self.id,
+                to,
+                data: Data::new(data),
+                ack: Some(ack),
+                correlation_id: None,
+            },
+            self.retry.get(),
+        )
+        .await?;
+
+        self.ack.signal.wait().await;
+        Ok(())
+    }
+
+    /// Send a generic message to an endpoint and wait for the receiver to acknowledge it
+    pub async fn send_acked(&'static self, to: EndpointID, data: &impl Any, timeout: Duration) -> Result<(), AckError> {
+        match with_timeout(timeout, self.send_acked_no_timeout(to, data)).await {
+            Ok(result) => result.map_err(AckError::Send),
+            Err(_) => Err(AckError::Timeout),
+        }
     }
 
     fn init(&self, rx: &'static dyn MailboxDelegate) {
         self.delegator.set(Some(rx));
     }
 
-    fn process(&self, message: &Message) {
-        if let Some(delegator) = self.delegator.get() {
-            // REVISIT: Continue to propagate error
-            let _res = delegator.receive(message);
+    fn process(&self, message: &Message) -> Result<(), MailboxDelegateError> {
+        match self.delegator.get() {
+            Some(delegator) => delegator.receive(message),
+            None => Ok(()),
+        }
+    }
+
+    async fn deliver(&self, message: &Message<'_>, retry: Option<RetryPolicy>) -> Result<(), MailboxDelegateError> {
+        let mut res = self.process(message);
+
+        if let Some(retry) = retry {
+            let mut backoff = retry.backoff;
+            for _ in 0..retry.max_retries {
+                if !matches!(res, Err(MailboxDelegateError::BufferFull)) {
+                    break;
+                }
+
+                Timer::after(backoff).await;
+                backoff *= 2;
+                res = self.process(message);
+            }
         }
+
+        res
     }
 }
 
@@ -291,22 +626,95 @@ fn get_list(target: EndpointID) -> &'static OnceLock<IntrusiveList> {
 
 /// Send a generic message to an endpoint
 pub async fn send(from: EndpointID, to: EndpointID, data: &impl Any) -> Result<(), Infallible> {
-    route(Message {
-        from,
-        to,
-        data: Data::new(data),
-    })
+    send_with_retry(from, to, data, None).await
+}
+
+async fn send_with_retry(
+    from: EndpointID,
+    to: EndpointID,
+    data: &impl Any,
+    retry: Option<RetryPolicy>,
+) -> Result<(), Infallible> {
+    #[cfg(feature = "comms-recorder")]
+    recorder::record(from, to, core::any::type_name_of_val(data));
+
+    route_with_retry(
+        &Message {
+            from,
+            to,
+            data: Data::new(data),
+            ack: None,
+            correlation_id: None,
+        },
+        retry,
+    )
+    .await
+}
+
+/// Send a generic message to an endpoint, failing if it isn't registered or rejects the message
+pub async fn try_send_checked(from: EndpointID, to: EndpointID, data: &impl Any) -> Result<(), SendError> {
+    try_send_checked_with_retry(from, to, data, None).await
+}
+
+async fn try_send_checked_with_retry(
+    from: EndpointID,
+    to: EndpointID,
+    data: &impl Any,
+    retry: Option<RetryPolicy>,
+) -> Result<(), SendError> {
+    #[cfg(feature = "comms-recorder")]
+    recorder::record(from, to, core::any::type_name_of_val(data));
+
+    route_checked(
+        &Message {
+            from,
+            to,
+            data: Data::new(data),
+            ack: None,
+            correlation_id: None,
+        },
+        retry,
+    )
     .await
 }
 
-/// route a message to any valid receiver nodes
-async fn route(message: Message<'_>) -> Result<(), Infallible> {
+/// Send a message to every registered endpoint in `class`, except the sender
+/// A receiver rejecting the message, e.g. with [`MailboxDelegateError::BufferFull`], does not stop delivery to the others
+pub async fn broadcast(from: &Endpoint, class: EndpointClass, data: &impl Any) -> Result<(), Infallible> {
+    for id in LIST_IDS.into_iter().filter(|id| class.contains(*id)) {
+        for rxq in get_list(id).get().await {
+            if let Some(endpoint) = rxq.data::<Endpoint>() {
+                if core::ptr::eq(endpoint, from) {
+                    continue;
+                }
+
+                #[cfg(feature = "comms-recorder")]
+                recorder::record(from.id, endpoint.id, core::any::type_name_of_val(data));
+
+                let message = Message {
+                    from: from.id,
+                    to: endpoint.id,
+                    data: Data::new(data),
+                    ack: None,
+                    correlation_id: None,
+                };
+                // Rejected deliveries don't stop the broadcast
+                let _ = endpoint.deliver(&message, from.retry.get()).await;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// route a message to any valid receiver nodes, retrying receivers that are full
+async fn route_with_retry(message: &Message<'_>, retry: Option<RetryPolicy>) -> Result<(), Infallible> {
     let list = get_list(message.to).get().await;
 
     for rxq in list {
         if let Some(endpoint) = rxq.data::<Endpoint>() {
             if message.to == endpoint.id {
-                endpoint.process(&message);
+                let _ = endpoint.deliver(message, retry).await;
             }
         }
     }
@@ -314,25 +722,582 @@ async fn route(message: Message<'_>) -> Result<(), Infallible> {
     Ok(())
 }
 
+/// route a message to every receiver with the destination ID, reporting a missing or rejecting receiver
+async fn route_checked(message: &Message<'_>, retry: Option<RetryPolicy>) -> Result<(), SendError> {
+    let mut result = Err(SendError::EndpointNotFound);
+
+    for rxq in get_list(message.to).get().await {
+        if let Some(endpoint) = rxq.data::<Endpoint>() {
+            if message.to == endpoint.id {
+                // Keep delivering to the other receivers, but report the first rejection
+                let delivered = endpoint.deliver(message, retry).await.map_err(SendError::Mailbox);
+                if !matches!(result, Err(SendError::Mailbox(_))) {
+                    result = delivered;
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// One endpoint ID for each subscriber list, OEM endpoints share a list regardless of key
+const LIST_IDS: [EndpointID; 17] = [
+    EndpointID::Internal(Internal::PlatformInfo),
+    EndpointID::Internal(Internal::Keyboard),
+    EndpointID::Internal(Internal::Hid),
+    EndpointID::Internal(Internal::HostBoot),
+    EndpointID::Internal(Internal::Power),
+    EndpointID::Internal(Internal::Usbc),
+    EndpointID::Internal(Internal::Thermal),
+    EndpointID::Internal(Internal::Trackpad),
+    EndpointID::Internal(Internal::Battery),
+    EndpointID::Internal(Internal::Nonvol),
+    EndpointID::Internal(Internal::Debug),
+    EndpointID::Internal(Internal::Security),
+    EndpointID::Internal(Internal::TimeAlarm),
+    EndpointID::Internal(Internal::Oem(0)),
+    EndpointID::External(External::Debug),
+    EndpointID::External(External::Host),
+    EndpointID::External(External::Oem(0)),
+];
+
 pub(crate) fn init() {
-    // initialize internal subscriber lists
-    get_list(Internal::PlatformInfo.into()).get_or_init(IntrusiveList::new);
-    get_list(Internal::Keyboard.into()).get_or_init(IntrusiveList::new);
-    get_list(Internal::Hid.into()).get_or_init(IntrusiveList::new);
-    get_list(Internal::HostBoot.into()).get_or_init(IntrusiveList::new);
-    get_list(Internal::Power.into()).get_or_init(IntrusiveList::new);
-    get_list(Internal::Usbc.into()).get_or_init(IntrusiveList::new);
-    get_list(Internal::Thermal.into()).get_or_init(IntrusiveList::new);
-    get_list(Internal::Trackpad.into()).get_or_init(IntrusiveList::new);
-    get_list(Internal::Battery.into()).get_or_init(IntrusiveList::new);
-    get_list(Internal::Nonvol.into()).get_or_init(IntrusiveList::new);
-    get_list(Internal::Debug.into()).get_or_init(IntrusiveList::new);
-    get_list(Internal::Security.into()).get_or_init(IntrusiveList::new);
-    get_list(Internal::TimeAlarm.into()).get_or_init(IntrusiveList::new);
-    get_list(Internal::Oem(0).into()).get_or_init(IntrusiveList::new);
-
-    // initialize external subscriber lists
-    get_list(External::Debug.into()).get_or_init(IntrusiveList::new);
-    get_list(External::Host.into()).get_or_init(IntrusiveList::new);
-    get_list(External::Oem(0).into()).get_or_init(IntrusiveList::new);
+    // initialize internal and external subscriber lists
+    for id in LIST_IDS {
+        get_list(id).get_or_init(IntrusiveList::new);
+    }
+}
+
+/// Diagnostic recorder that captures message deliveries for later replay
+#[cfg(feature = "comms-recorder")]
+pub mod recorder {
+    use core::any::Any;
+    use core::cell::RefCell;
+
+    use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+    use embassy_time::Instant;
+    use heapless::HistoryBuffer;
+
+    use super::{route, Data, EndpointID, Message};
+
+    /// Number of deliveries kept by the recorder, older entries are overwritten
+    pub const RECORDER_DEPTH: usize = 32;
+
+    /// Metadata for a single recorded delivery
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    pub struct Record {
+        /// where the message came from
+        pub from: EndpointID,
+        /// where the message was going
+        pub to: EndpointID,
+        /// type name of the message contents
+        pub type_name: &'static str,
+        /// time the message was sent
+        pub timestamp: Instant,
+    }
+
+    static RECORDS: Mutex<CriticalSectionRawMutex, RefCell<HistoryBuffer<Record, RECORDER_DEPTH>>> =
+        Mutex::new(RefCell::new(HistoryBuffer::new()));
+
+    pub(super) fn record(from: EndpointID, to: EndpointID, type_name: &'static str) {
+        let record = Record {
+            from,
+            to,
+            type_name,
+            timestamp: Instant::now(),
+        };
+        RECORDS.lock(|records| records.borrow_mut().write(record));
+    }
+
+    /// Remove and return all recorded deliveries, oldest first
+    pub fn drain() -> heapless::Vec<Record, RECORDER_DEPTH> {
+        RECORDS.lock(|records| {
+            let mut records = records.borrow_mut();
+            let drained = records.oldest_ordered().copied().collect();
+            records.clear();
+            drained
+        })
+    }
+
+    /// Re-inject recorded messages in order
+    ///
+    /// Message contents are not recorded, `data` provides the contents for each record, records it returns `None` for are skipped.
+    /// Replayed messages are not recorded again.
+    pub async fn replay<'a>(records: &[Record], mut data: impl FnMut(&Record) -> Option<&'a dyn Any>) {
+        for record in records {
+            if let Some(contents) = data(record) {
+                let message = Message {
+                    from: record.from,
+                    to: record.to,
+                    data: Data { contents },
+                    ack: None,
+                    correlation_id: None,
+                };
+                let _ = route_with_retry(&message, None).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use std::boxed::Box;
+
+    use core::cell::{Cell, RefCell};
+    use embassy_futures::join::{join, join3};
+    use embassy_futures::{block_on, yield_now};
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embassy_sync::channel::Channel;
+    use embassy_time::Instant;
+
+    use super::*;
+
+    /// Holds acknowledgments so the test decides when each message has been processed
+    struct AckDelegate {
+        pending: RefCell<std::vec::Vec<(EndpointID, Ack)>>,
+    }
+
+    impl MailboxDelegate for AckDelegate {
+        fn receive(&self, message: &Message) -> Result<(), MailboxDelegateError> {
+            // Defer processing and acknowledgment
+            let ack = message.ack().ok_or(MailboxDelegateError::InvalidData)?;
+            self.pending.borrow_mut().push((message.from, ack));
+            Ok(())
+        }
+    }
+
+    fn register_ack_delegate(id: EndpointID) -> &'static AckDelegate {
+        let delegate: &'static AckDelegate = Box::leak(Box::new(AckDelegate {
+            pending: RefCell::new(std::vec::Vec::new()),
+        }));
+        let endpoint: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(id)));
+        block_on(register_endpoint(delegate, endpoint)).unwrap();
+        delegate
+    }
+
+    /// Wait for a message from the given sender and take its acknowledgment
+    async fn take_ack(delegate: &AckDelegate, from: EndpointID) -> Ack {
+        loop {
+            {
+                let mut pending = delegate.pending.borrow_mut();
+                if let Some(i) = pending.iter().position(|(sender, _)| *sender == from) {
+                    return pending.remove(i).1;
+                }
+            }
+            yield_now().await;
+        }
+    }
+
+    #[test]
+    fn test_send_acked() {
+        const TO: EndpointID = EndpointID::Internal(Internal::Oem(1));
+
+        init();
+        let delegate = register_ack_delegate(TO);
+        let sender: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(Internal::Debug.into())));
+        let processed = Cell::new(false);
+
+        block_on(async {
+            let sending = async {
+                assert_eq!(sender.send_acked_no_timeout(TO, &0u32).await, Ok(()));
+                // Only resolves once the delegate has processed the message
+                assert!(processed.get());
+            };
+
+            let processor = async {
+                let ack = take_ack(delegate, sender.get_id()).await;
+                processed.set(true);
+                ack.acknowledge();
+            };
+
+            join(sending, processor).await;
+        });
+    }
+
+    #[test]
+    fn test_send_acked_timeout() {
+        const TO: EndpointID = EndpointID::Internal(Internal::Oem(8));
+
+        init();
+        let delegate = register_ack_delegate(TO);
+        let sender: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(Internal::Debug.into())));
+
+        block_on(async {
+            // Delivered but never acknowledged
+            assert_eq!(
+                sender.send_acked(TO, &0u32, Duration::from_millis(20)).await,
+                Err(AckError::Timeout)
+            );
+            let stale = take_ack(delegate, sender.get_id()).await;
+
+            // A late acknowledgment of the timed out message doesn't acknowledge the next one
+            let sending = async {
+                assert_eq!(
+                    sender.send_acked(TO, &1u32, Duration::from_millis(50)).await,
+                    Err(AckError::Timeout)
+                );
+            };
+            let processor = async {
+                let _next = take_ack(delegate, sender.get_id()).await;
+                stale.acknowledge();
+            };
+            join(sending, processor).await;
+        });
+    }
+
+    #[test]
+    fn test_send_acked_undeliverable() {
+        const UNREGISTERED: EndpointID = EndpointID::Internal(Internal::Oem(10));
+
+        init();
+        let sender: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(Internal::Debug.into())));
+
+        // Fails immediately instead of waiting for an acknowledgment that can't arrive
+        let start = Instant::now();
+        assert_eq!(
+            block_on(sender.send_acked(UNREGISTERED, &0u32, Duration::from_secs(5))),
+            Err(AckError::Send(SendError::EndpointNotFound))
+        );
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_send_acked_concurrent() {
+        const TO: EndpointID = EndpointID::Internal(Internal::Oem(9));
+
+        init();
+        let delegate = register_ack_delegate(TO);
+        let first: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(Internal::Oem(11).into())));
+        let second: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(Internal::Oem(12).into())));
+        let first_done = Cell::new(false);
+        let second_done = Cell::new(false);
+
+        block_on(async {
+            let send_first = async {
+                assert_eq!(first.send_acked(TO, &1u32, Duration::from_secs(5)).await, Ok(()));
+                first_done.set(true);
+            };
+            let send_second = async {
+                assert_eq!(second.send_acked(TO, &2u32, Duration::from_secs(5)).await, Ok(()));
+                second_done.set(true);
+            };
+
+            // Each sender only resolves on the acknowledgment of its own message
+            let processor = async {
+                let first_ack = take_ack(delegate, first.get_id()).await;
+                let second_ack = take_ack(delegate, second.get_id()).await;
+
+                second_ack.acknowledge();
+                Timer::after_millis(10).await;
+                assert!(second_done.get());
+                assert!(!first_done.get());
+
+                first_ack.acknowledge();
+            };
+
+            join3(send_first, send_second, processor).await;
+        });
+        assert!(first_done.get());
+    }
+
+    #[cfg(feature = "comms-recorder")]
+    struct ReplayDelegate {
+        received: Cell<u32>,
+    }
+
+    #[cfg(feature = "comms-recorder")]
+    impl MailboxDelegate for ReplayDelegate {
+        fn receive(&self, message: &Message) -> Result<(), MailboxDelegateError> {
+            let value = message.data.get::<u32>().ok_or(MailboxDelegateError::InvalidData)?;
+            self.received.set(self.received.get() + value);
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "comms-recorder")]
+    #[test]
+    fn test_record_and_replay() {
+        const TO: EndpointID = EndpointID::Internal(Internal::Oem(2));
+        const FROM: EndpointID = EndpointID::Internal(Internal::Debug);
+
+        init();
+        block_on(async {
+            send(FROM, TO, &1u32).await.unwrap();
+            send(FROM, TO, &2u8).await.unwrap();
+            send(FROM, TO, &3u32).await.unwrap();
+        });
+
+        // Other tests may be sending concurrently
+        let records: std::vec::Vec<_> = recorder::drain().into_iter().filter(|r| r.to == TO).collect();
+        assert_eq!(records.len(), 3);
+        assert!(records.iter().all(|r| r.from == FROM));
+        assert_eq!(records[0].type_name, "u32");
+        assert_eq!(records[1].type_name, "u8");
+        assert_eq!(records[2].type_name, "u32");
+        assert!(records[0].timestamp <= records[1].timestamp);
+        assert!(records[1].timestamp <= records[2].timestamp);
+
+        let delegate: &'static ReplayDelegate = Box::leak(Box::new(ReplayDelegate { received: Cell::new(0) }));
+        let endpoint: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(TO)));
+        block_on(async {
+            register_endpoint(delegate, endpoint).await.unwrap();
+            recorder::replay(&records, |r| {
+                if r.type_name == "u32" {
+                    Some(&10u32 as &dyn Any)
+                } else {
+                    None
+                }
+            })
+            .await;
+        });
+
+        // Only the two u32 messages were replayed
+        assert_eq!(delegate.received.get(), 20);
+        assert!(recorder::drain().iter().all(|r| r.to != TO));
+    }
+
+    struct CountDelegate {
+        received: Cell<usize>,
+    }
+
+    impl MailboxDelegate for CountDelegate {
+        fn receive(&self, _message: &Message) -> Result<(), MailboxDelegateError> {
+            self.received.set(self.received.get() + 1);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_oem_routing() {
+        const SENSOR_HUB: EndpointID = EndpointID::Internal(Internal::Oem(0x5348));
+        const OTHER: EndpointID = EndpointID::Internal(Internal::Oem(0x5349));
+
+        init();
+        let hub: &'static CountDelegate = Box::leak(Box::new(CountDelegate { received: Cell::new(0) }));
+        let hub_endpoint: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(SENSOR_HUB)));
+        let other: &'static CountDelegate = Box::leak(Box::new(CountDelegate { received: Cell::new(0) }));
+        let other_endpoint: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(OTHER)));
+
+        block_on(async {
+            register_endpoint(hub, hub_endpoint).await.unwrap();
+            register_endpoint(other, other_endpoint).await.unwrap();
+
+            send(Internal::Debug.into(), SENSOR_HUB, &0u32).await.unwrap();
+            send(Internal::Debug.into(), SENSOR_HUB, &1u32).await.unwrap();
+            send(Internal::Debug.into(), OTHER, &2u32).await.unwrap();
+        });
+
+        // Only endpoints with a matching key receive the message
+        assert_eq!(hub.received.get(), 2);
+        assert_eq!(other.received.get(), 1);
+        assert_ne!(SENSOR_HUB, OTHER);
+    }
+
+    /// Single-slot mailbox that rejects messages while full
+    struct QueueDelegate {
+        queue: Channel<NoopRawMutex, u32, 1>,
+    }
+
+    impl MailboxDelegate for QueueDelegate {
+        fn receive(&self, message: &Message) -> Result<(), MailboxDelegateError> {
+            let value = message.data.get::<u32>().ok_or(MailboxDelegateError::InvalidData)?;
+            self.queue
+                .try_send(*value)
+                .map_err(|_| MailboxDelegateError::BufferFull)
+        }
+    }
+
+    #[test]
+    fn test_retry_buffer_full() {
+        const TO: EndpointID = EndpointID::Internal(Internal::Oem(3));
+
+        init();
+        let delegate: &'static QueueDelegate = Box::leak(Box::new(QueueDelegate { queue: Channel::new() }));
+        let endpoint: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(TO)));
+        let sender = Endpoint::uninit(Internal::Debug.into());
+
+        block_on(async {
+            register_endpoint(delegate, endpoint).await.unwrap();
+            sender.send(TO, &1u32).await.unwrap();
+
+            // Fails immediately by default
+            sender.send(TO, &2u32).await.unwrap();
+            assert_eq!(delegate.queue.receive().await, 1);
+            assert!(delegate.queue.try_receive().is_err());
+
+            sender.send(TO, &3u32).await.unwrap();
+            sender.set_retry_policy(Some(RetryPolicy {
+                max_retries: 5,
+                backoff: Duration::from_millis(2),
+            }));
+
+            // Mailbox frees up while the sender is backing off
+            let drain = async {
+                Timer::after_millis(5).await;
+                delegate.queue.receive().await
+            };
+            let (_, drained) = join(sender.send(TO, &4u32), drain).await;
+            assert_eq!(drained, 3);
+            assert_eq!(delegate.queue.try_receive().ok(), Some(4));
+        });
+    }
+
+    #[test]
+    fn test_try_send_checked() {
+        const TO: EndpointID = EndpointID::Internal(Internal::Oem(4));
+        const UNREGISTERED: EndpointID = EndpointID::Internal(Internal::Oem(5));
+
+        init();
+        let delegate: &'static QueueDelegate = Box::leak(Box::new(QueueDelegate { queue: Channel::new() }));
+        let endpoint: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(TO)));
+        let sender = Endpoint::uninit(Internal::Debug.into());
+
+        block_on(async {
+            register_endpoint(delegate, endpoint).await.unwrap();
+            assert_eq!(sender.try_send_checked(TO, &1u32).await, Ok(()));
+            assert_eq!(
+                sender.try_send_checked(TO, &2u32).await,
+                Err(SendError::Mailbox(MailboxDelegateError::BufferFull))
+            );
+            assert_eq!(delegate.queue.try_receive().ok(), Some(1));
+
+            assert_eq!(
+                try_send_checked(sender.get_id(), UNREGISTERED, &3u32).await,
+                Err(SendError::EndpointNotFound)
+            );
+            // Unchecked sends still silently drop the message
+            assert_eq!(sender.send(UNREGISTERED, &3u32).await, Ok(()));
+        });
+    }
+
+    /// Notification only counted by [`BroadcastDelegate`]
+    struct SleepNotification;
+
+    struct BroadcastDelegate {
+        received: Cell<usize>,
+        full: bool,
+    }
+
+    impl MailboxDelegate for BroadcastDelegate {
+        fn receive(&self, message: &Message) -> Result<(), MailboxDelegateError> {
+            if message.data.is_a::<SleepNotification>() {
+                self.received.set(self.received.get() + 1);
+                if self.full {
+                    return Err(MailboxDelegateError::BufferFull);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_broadcast() {
+        init();
+        let register = |id: EndpointID, full| {
+            let delegate: &'static BroadcastDelegate = Box::leak(Box::new(BroadcastDelegate {
+                received: Cell::new(0),
+                full,
+            }));
+            let endpoint: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(id)));
+            block_on(register_endpoint(delegate, endpoint)).unwrap();
+            (delegate, endpoint)
+        };
+
+        // Debug is delivered before the OEM list, its rejection must not stop the broadcast
+        let (full, _) = register(External::Debug.into(), true);
+        let (first, _) = register(External::Oem(0x4242).into(), false);
+        let (second, _) = register(External::Oem(0x4243).into(), false);
+        let (sender, sender_endpoint) = register(External::Oem(0x4244).into(), false);
+
+        block_on(broadcast(sender_endpoint, EndpointClass::External, &SleepNotification)).unwrap();
+
+        assert_eq!(full.received.get(), 1);
+        assert_eq!(first.received.get(), 1);
+        assert_eq!(second.received.get(), 1);
+        // The sender doesn't receive its own broadcast
+        assert_eq!(sender.received.get(), 0);
+
+        assert!(EndpointClass::Internal.contains(Internal::Power.into()));
+        assert!(!EndpointClass::Internal.contains(External::Host.into()));
+        assert!(EndpointClass::All.contains(External::Host.into()));
+    }
+
+    /// Answers requests for a u32 with ten times the value
+    struct Responder {
+        pending: RefCell<std::vec::Vec<(EndpointID, u32, u32)>>,
+    }
+
+    impl MailboxDelegate for Responder {
+        fn receive(&self, message: &Message) -> Result<(), MailboxDelegateError> {
+            let value = message.data.get::<u32>().ok_or(MailboxDelegateError::InvalidData)?;
+            let id = message.correlation_id().ok_or(MailboxDelegateError::InvalidData)?;
+            self.pending.borrow_mut().push((message.from, id, *value));
+            Ok(())
+        }
+    }
+
+    /// Requester with a response slot for each in-flight request
+    struct Requester {
+        slots: [ResponseSlot<u32>; 2],
+    }
+
+    impl MailboxDelegate for Requester {
+        fn receive(&self, message: &Message) -> Result<(), MailboxDelegateError> {
+            if self.slots.iter().any(|slot| slot.receive(message)) {
+                Ok(())
+            } else {
+                Err(MailboxDelegateError::MessageNotFound)
+            }
+        }
+    }
+
+    #[test]
+    fn test_correlated_responses() {
+        const REQUESTER: EndpointID = EndpointID::Internal(Internal::Oem(6));
+        const RESPONDER: EndpointID = EndpointID::Internal(Internal::Oem(7));
+
+        init();
+        let requester: &'static Requester = Box::leak(Box::new(Requester {
+            slots: [ResponseSlot::new(), ResponseSlot::new()],
+        }));
+        let requester_endpoint: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(REQUESTER)));
+        let responder: &'static Responder = Box::leak(Box::new(Responder {
+            pending: RefCell::new(std::vec::Vec::new()),
+        }));
+        let responder_endpoint: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(RESPONDER)));
+
+        block_on(async {
+            register_endpoint(requester, requester_endpoint).await.unwrap();
+            register_endpoint(responder, responder_endpoint).await.unwrap();
+
+            let requests = join(
+                requester_endpoint.request(RESPONDER, &1u32, &requester.slots[0]),
+                requester_endpoint.request(RESPONDER, &2u32, &requester.slots[1]),
+            );
+
+            // Answer both requests in the reverse order they were sent
+            let respond = async {
+                while responder.pending.borrow().len() < 2 {
+                    yield_now().await;
+                }
+
+                for (from, id, value) in responder.pending.take().into_iter().rev() {
+                    responder_endpoint.send_response(from, id, &(value * 10)).await.unwrap();
+                }
+            };
+
+            let ((first, second), _) = join(requests, respond).await;
+            assert_eq!(first, Ok(10));
+            assert_eq!(second, Ok(20));
+
+            // Responses without a correlation ID aren't taken by a slot
+            assert_eq!(
+                try_send_checked(RESPONDER, REQUESTER, &30u32).await,
+                Err(SendError::Mailbox(MailboxDelegateError::MessageNotFound))
+            );
+        });
+    }
 }
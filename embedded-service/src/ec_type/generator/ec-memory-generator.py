@@ -8,7 +8,7 @@ def yaml_to_rust(data):
     for key, value in data.items():
         rust_code += "#[allow(missing_docs)]\n"
         rust_code += "#[repr(C, packed)]\n"
-        rust_code += "#[derive(Clone, Copy, Debug, Default)]\n"
+        rust_code += "#[derive(Clone, Copy, Debug, Default, PartialEq)]\n"
         rust_code += f"pub struct {key} {{\n"
         for sub_key, sub_value in value.items():
             if isinstance(sub_value, dict) and 'type' in sub_value:
@@ -1,7 +1,7 @@
 //! EC Internal Messages
 
 #[allow(missing_docs)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum CapabilitiesMessage {
     Events(u32),
     FwVersion(super::structure::Version),
@@ -12,6 +12,7 @@ pub enum CapabilitiesMessage {
     TempMask(u16),
     KeyMask(u16),
     DebugMask(u16),
+    Res0(u16),
 }
 
 #[allow(missing_docs)]
@@ -85,4 +86,11 @@ pub enum ThermalMessage {
     Tmp1Timeout(u32),
     Tmp1Low(u32),
     Tmp1High(u32),
+    Fan2OnTemp(u32),
+    Fan2RampTemp(u32),
+    Fan2MaxTemp(u32),
+    Fan2CrtTemp(u32),
+    Fan2HotTemp(u32),
+    Fan2MaxRpm(u32),
+    Fan2CurRpm(u32),
 }
@@ -1,5 +1,6 @@
 //! Standard EC types
 use core::mem::offset_of;
+use core::ops::Range;
 
 pub mod message;
 pub mod structure;
@@ -11,6 +12,140 @@ pub enum Error {
     InvalidLocation,
 }
 
+/// Section of the memory map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Region {
+    /// Version section
+    Version,
+    /// Capabilities section
+    Capabilities,
+    /// Notifications section
+    Notifications,
+    /// Time alarm section
+    TimeAlarm,
+    /// Battery section
+    Battery,
+    /// Thermal section
+    Thermal,
+}
+
+impl Region {
+    /// All regions, in memory map order
+    pub const ALL: [Region; 6] = [
+        Region::Version,
+        Region::Capabilities,
+        Region::Notifications,
+        Region::TimeAlarm,
+        Region::Battery,
+        Region::Thermal,
+    ];
+
+    /// Byte range of this region within the memory map
+    pub const fn range(self) -> Range<usize> {
+        let (start, len) = match self {
+            Region::Version => (offset_of!(structure::ECMemory, ver), size_of::<structure::Version>()),
+            Region::Capabilities => (
+                offset_of!(structure::ECMemory, caps),
+                size_of::<structure::Capabilities>(),
+            ),
+            Region::Notifications => (
+                offset_of!(structure::ECMemory, notif),
+                size_of::<structure::Notifications>(),
+            ),
+            Region::TimeAlarm => (
+                offset_of!(structure::ECMemory, alarm),
+                size_of::<structure::TimeAlarm>(),
+            ),
+            Region::Battery => (offset_of!(structure::ECMemory, batt), size_of::<structure::Battery>()),
+            Region::Thermal => (offset_of!(structure::ECMemory, therm), size_of::<structure::Thermal>()),
+        };
+        start..start + len
+    }
+
+    const fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Tracks which memory map regions were modified since the last host read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DirtyRegions(u8);
+
+impl DirtyRegions {
+    /// Create a tracker with no dirty regions
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Mark a region as modified
+    pub fn mark(&mut self, region: Region) {
+        self.0 |= region.mask();
+    }
+
+    /// Returns true if the region was modified since the last host read
+    pub fn is_dirty(&self, region: Region) -> bool {
+        self.0 & region.mask() != 0
+    }
+
+    /// Returns true if any region was modified since the last host read
+    pub fn any(&self) -> bool {
+        self.0 != 0
+    }
+
+    /// Iterate over the dirty regions
+    pub fn iter(&self) -> impl Iterator<Item = Region> + '_ {
+        Region::ALL.into_iter().filter(|region| self.is_dirty(*region))
+    }
+
+    /// Record a host read of `length` bytes at `offset`, clearing every region it overlaps
+    pub fn host_read(&mut self, offset: usize, length: usize) {
+        let end = offset.saturating_add(length);
+        for region in Region::ALL {
+            let range = region.range();
+            if offset < range.end && range.start < end {
+                self.0 &= !region.mask();
+            }
+        }
+    }
+
+    /// Return the current dirty set and clear it
+    pub fn take(&mut self) -> Self {
+        core::mem::take(self)
+    }
+}
+
+/// CRC algorithm for the memory map checksum, the same CRC-32/ISO-HDLC as
+/// `platform_service::embedded_crc::CrcAlgorithm::Crc32IsoHdlc` so either side can verify it.
+///
+/// The platform helpers can't be used here: platform-service depends on this crate, and its `compute` is async and
+/// goes through the shared hardware CRC engine, while the checksum is updated synchronously with the memory map.
+const CHECKSUM_CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+/// Compute the memory map checksum over every byte except the checksum field itself
+pub fn compute_checksum(memory_map: &structure::ECMemory) -> u32 {
+    // SAFETY: ECMemory is packed and contains only integers, so every byte is initialized
+    let bytes = unsafe {
+        core::slice::from_raw_parts(
+            (memory_map as *const structure::ECMemory).cast::<u8>(),
+            size_of::<structure::ECMemory>(),
+        )
+    };
+    let checksum = offset_of!(structure::ECMemory, checksum);
+
+    let mut digest = CHECKSUM_CRC.digest();
+    digest.update(&bytes[..checksum]);
+    digest.update(&bytes[checksum + size_of::<u32>()..]);
+    digest.finalize()
+}
+
+/// Returns true if the stored checksum matches the memory map contents
+pub fn validate_checksum(memory_map: &structure::ECMemory) -> bool {
+    let checksum = memory_map.checksum;
+    checksum == compute_checksum(memory_map)
+}
+
 /// Update battery section of memory map based on battery message
 pub fn update_battery_section(msg: &message::BatteryMessage, memory_map: &mut structure::ECMemory) {
     match msg {
@@ -56,6 +191,7 @@ pub fn update_capabilities_section(msg: &message::CapabilitiesMessage, memory_ma
         message::CapabilitiesMessage::TempMask(temp_mask) => memory_map.caps.temp_mask = *temp_mask,
         message::CapabilitiesMessage::KeyMask(key_mask) => memory_map.caps.key_mask = *key_mask,
         message::CapabilitiesMessage::DebugMask(debug_mask) => memory_map.caps.debug_mask = *debug_mask,
+        message::CapabilitiesMessage::Res0(res0) => memory_map.caps.res0 = *res0,
     }
 }
 
@@ -78,6 +214,13 @@ pub fn update_thermal_section(msg: &message::ThermalMessage, memory_map: &mut st
         message::ThermalMessage::Tmp1Timeout(tmp1_timeout) => memory_map.therm.tmp1_timeout = *tmp1_timeout,
         message::ThermalMessage::Tmp1Low(tmp1_low) => memory_map.therm.tmp1_low = *tmp1_low,
         message::ThermalMessage::Tmp1High(tmp1_high) => memory_map.therm.tmp1_high = *tmp1_high,
+        message::ThermalMessage::Fan2OnTemp(fan2_on_temp) => memory_map.therm.fan2_on_temp = *fan2_on_temp,
+        message::ThermalMessage::Fan2RampTemp(fan2_ramp_temp) => memory_map.therm.fan2_ramp_temp = *fan2_ramp_temp,
+        message::ThermalMessage::Fan2MaxTemp(fan2_max_temp) => memory_map.therm.fan2_max_temp = *fan2_max_temp,
+        message::ThermalMessage::Fan2CrtTemp(fan2_crt_temp) => memory_map.therm.fan2_crt_temp = *fan2_crt_temp,
+        message::ThermalMessage::Fan2HotTemp(fan2_hot_temp) => memory_map.therm.fan2_hot_temp = *fan2_hot_temp,
+        message::ThermalMessage::Fan2MaxRpm(fan2_max_rpm) => memory_map.therm.fan2_max_rpm = *fan2_max_rpm,
+        message::ThermalMessage::Fan2CurRpm(fan2_cur_rpm) => memory_map.therm.fan2_cur_rpm = *fan2_cur_rpm,
     }
 }
 
@@ -114,6 +257,85 @@ macro_rules! into_message {
     };
 }
 
+/// Convert from memory map offset and length to capabilities message
+/// Modifies offset and length
+pub fn mem_map_to_capabilities_msg(
+    memory_map: &structure::ECMemory,
+    offset: &mut usize,
+    length: &mut usize,
+) -> Result<message::CapabilitiesMessage, Error> {
+    let local_offset = *offset - offset_of!(structure::ECMemory, caps);
+
+    if local_offset == offset_of!(structure::Capabilities, events) {
+        into_message!(
+            offset,
+            length,
+            memory_map.caps.events,
+            message::CapabilitiesMessage::Events
+        );
+    } else if local_offset == offset_of!(structure::Capabilities, fw_version) {
+        into_message!(
+            offset,
+            length,
+            memory_map.caps.fw_version,
+            message::CapabilitiesMessage::FwVersion
+        );
+    } else if local_offset == offset_of!(structure::Capabilities, secure_state) {
+        into_message!(
+            offset,
+            length,
+            memory_map.caps.secure_state,
+            message::CapabilitiesMessage::SecureState
+        );
+    } else if local_offset == offset_of!(structure::Capabilities, boot_status) {
+        into_message!(
+            offset,
+            length,
+            memory_map.caps.boot_status,
+            message::CapabilitiesMessage::BootStatus
+        );
+    } else if local_offset == offset_of!(structure::Capabilities, fan_mask) {
+        into_message!(
+            offset,
+            length,
+            memory_map.caps.fan_mask,
+            message::CapabilitiesMessage::FanMask
+        );
+    } else if local_offset == offset_of!(structure::Capabilities, battery_mask) {
+        into_message!(
+            offset,
+            length,
+            memory_map.caps.battery_mask,
+            message::CapabilitiesMessage::BatteryMask
+        );
+    } else if local_offset == offset_of!(structure::Capabilities, temp_mask) {
+        into_message!(
+            offset,
+            length,
+            memory_map.caps.temp_mask,
+            message::CapabilitiesMessage::TempMask
+        );
+    } else if local_offset == offset_of!(structure::Capabilities, key_mask) {
+        into_message!(
+            offset,
+            length,
+            memory_map.caps.key_mask,
+            message::CapabilitiesMessage::KeyMask
+        );
+    } else if local_offset == offset_of!(structure::Capabilities, debug_mask) {
+        into_message!(
+            offset,
+            length,
+            memory_map.caps.debug_mask,
+            message::CapabilitiesMessage::DebugMask
+        );
+    } else if local_offset == offset_of!(structure::Capabilities, res0) {
+        into_message!(offset, length, memory_map.caps.res0, message::CapabilitiesMessage::Res0);
+    } else {
+        Err(Error::InvalidLocation)
+    }
+}
+
 /// Convert from memory map offset and length to battery message
 /// Modifies offset and length
 pub fn mem_map_to_battery_msg(
@@ -404,6 +626,55 @@ pub fn mem_map_to_thermal_msg(
             memory_map.therm.tmp1_high,
             message::ThermalMessage::Tmp1High
         );
+    } else if local_offset == offset_of!(structure::Thermal, fan2_on_temp) {
+        into_message!(
+            offset,
+            length,
+            memory_map.therm.fan2_on_temp,
+            message::ThermalMessage::Fan2OnTemp
+        );
+    } else if local_offset == offset_of!(structure::Thermal, fan2_ramp_temp) {
+        into_message!(
+            offset,
+            length,
+            memory_map.therm.fan2_ramp_temp,
+            message::ThermalMessage::Fan2RampTemp
+        );
+    } else if local_offset == offset_of!(structure::Thermal, fan2_max_temp) {
+        into_message!(
+            offset,
+            length,
+            memory_map.therm.fan2_max_temp,
+            message::ThermalMessage::Fan2MaxTemp
+        );
+    } else if local_offset == offset_of!(structure::Thermal, fan2_crt_temp) {
+        into_message!(
+            offset,
+            length,
+            memory_map.therm.fan2_crt_temp,
+            message::ThermalMessage::Fan2CrtTemp
+        );
+    } else if local_offset == offset_of!(structure::Thermal, fan2_hot_temp) {
+        into_message!(
+            offset,
+            length,
+            memory_map.therm.fan2_hot_temp,
+            message::ThermalMessage::Fan2HotTemp
+        );
+    } else if local_offset == offset_of!(structure::Thermal, fan2_max_rpm) {
+        into_message!(
+            offset,
+            length,
+            memory_map.therm.fan2_max_rpm,
+            message::ThermalMessage::Fan2MaxRpm
+        );
+    } else if local_offset == offset_of!(structure::Thermal, fan2_cur_rpm) {
+        into_message!(
+            offset,
+            length,
+            memory_map.therm.fan2_cur_rpm,
+            message::ThermalMessage::Fan2CurRpm
+        );
     } else {
         Err(Error::InvalidLocation)
     }
@@ -502,6 +773,142 @@ pub fn mem_map_to_time_alarm_msg(
     }
 }
 
+/// Location of a message's field within its section, and the field size
+macro_rules! field_location {
+    ($section:ty, $field:ident, $value:expr) => {
+        (offset_of!($section, $field), size_of_val($value))
+    };
+}
+
+/// Check the field is at offset and fits within length, then advance both past it
+fn advance_write(
+    section_offset: usize,
+    (field_offset, size): (usize, usize),
+    offset: &mut usize,
+    length: &mut usize,
+) -> Result<(), Error> {
+    if offset.checked_sub(section_offset) != Some(field_offset) || *length < size {
+        return Err(Error::InvalidLocation);
+    }
+
+    *offset += size;
+    *length -= size;
+    Ok(())
+}
+
+/// Write a battery message to the memory map at offset, the inverse of [`mem_map_to_battery_msg`]
+/// Modifies offset and length
+pub fn write_battery_msg(
+    msg: &message::BatteryMessage,
+    memory_map: &mut structure::ECMemory,
+    offset: &mut usize,
+    length: &mut usize,
+) -> Result<(), Error> {
+    let location = match msg {
+        message::BatteryMessage::Events(value) => field_location!(structure::Battery, events, value),
+        message::BatteryMessage::Status(value) => field_location!(structure::Battery, status, value),
+        message::BatteryMessage::LastFullCharge(value) => field_location!(structure::Battery, last_full_charge, value),
+        message::BatteryMessage::CycleCount(value) => field_location!(structure::Battery, cycle_count, value),
+        message::BatteryMessage::State(value) => field_location!(structure::Battery, state, value),
+        message::BatteryMessage::PresentRate(value) => field_location!(structure::Battery, present_rate, value),
+        message::BatteryMessage::RemainCap(value) => field_location!(structure::Battery, remain_cap, value),
+        message::BatteryMessage::PresentVolt(value) => field_location!(structure::Battery, present_volt, value),
+        message::BatteryMessage::PsrState(value) => field_location!(structure::Battery, psr_state, value),
+        message::BatteryMessage::PsrMaxOut(value) => field_location!(structure::Battery, psr_max_out, value),
+        message::BatteryMessage::PsrMaxIn(value) => field_location!(structure::Battery, psr_max_in, value),
+        message::BatteryMessage::PeakLevel(value) => field_location!(structure::Battery, peak_level, value),
+        message::BatteryMessage::PeakPower(value) => field_location!(structure::Battery, peak_power, value),
+        message::BatteryMessage::SusLevel(value) => field_location!(structure::Battery, sus_level, value),
+        message::BatteryMessage::SusPower(value) => field_location!(structure::Battery, sus_power, value),
+        message::BatteryMessage::PeakThres(value) => field_location!(structure::Battery, peak_thres, value),
+        message::BatteryMessage::SusThres(value) => field_location!(structure::Battery, sus_thres, value),
+        message::BatteryMessage::TripThres(value) => field_location!(structure::Battery, trip_thres, value),
+        message::BatteryMessage::BmcData(value) => field_location!(structure::Battery, bmc_data, value),
+        message::BatteryMessage::BmdData(value) => field_location!(structure::Battery, bmd_data, value),
+        message::BatteryMessage::BmdFlags(value) => field_location!(structure::Battery, bmd_flags, value),
+        message::BatteryMessage::BmdCount(value) => field_location!(structure::Battery, bmd_count, value),
+        message::BatteryMessage::ChargeTime(value) => field_location!(structure::Battery, charge_time, value),
+        message::BatteryMessage::RunTime(value) => field_location!(structure::Battery, run_time, value),
+        message::BatteryMessage::SampleTime(value) => field_location!(structure::Battery, sample_time, value),
+    };
+
+    advance_write(offset_of!(structure::ECMemory, batt), location, offset, length)?;
+    update_battery_section(msg, memory_map);
+    Ok(())
+}
+
+/// Write a thermal message to the memory map at offset, the inverse of [`mem_map_to_thermal_msg`]
+/// Modifies offset and length
+pub fn write_thermal_msg(
+    msg: &message::ThermalMessage,
+    memory_map: &mut structure::ECMemory,
+    offset: &mut usize,
+    length: &mut usize,
+) -> Result<(), Error> {
+    let location = match msg {
+        message::ThermalMessage::Events(value) => field_location!(structure::Thermal, events, value),
+        message::ThermalMessage::CoolMode(value) => field_location!(structure::Thermal, cool_mode, value),
+        message::ThermalMessage::DbaLimit(value) => field_location!(structure::Thermal, dba_limit, value),
+        message::ThermalMessage::SonneLimit(value) => field_location!(structure::Thermal, sonne_limit, value),
+        message::ThermalMessage::MaLimit(value) => field_location!(structure::Thermal, ma_limit, value),
+        message::ThermalMessage::Fan1OnTemp(value) => field_location!(structure::Thermal, fan1_on_temp, value),
+        message::ThermalMessage::Fan1RampTemp(value) => field_location!(structure::Thermal, fan1_ramp_temp, value),
+        message::ThermalMessage::Fan1MaxTemp(value) => field_location!(structure::Thermal, fan1_max_temp, value),
+        message::ThermalMessage::Fan1CrtTemp(value) => field_location!(structure::Thermal, fan1_crt_temp, value),
+        message::ThermalMessage::Fan1HotTemp(value) => field_location!(structure::Thermal, fan1_hot_temp, value),
+        message::ThermalMessage::Fan1MaxRpm(value) => field_location!(structure::Thermal, fan1_max_rpm, value),
+        message::ThermalMessage::Fan1CurRpm(value) => field_location!(structure::Thermal, fan1_cur_rpm, value),
+        message::ThermalMessage::Tmp1Val(value) => field_location!(structure::Thermal, tmp1_val, value),
+        message::ThermalMessage::Tmp1Timeout(value) => field_location!(structure::Thermal, tmp1_timeout, value),
+        message::ThermalMessage::Tmp1Low(value) => field_location!(structure::Thermal, tmp1_low, value),
+        message::ThermalMessage::Tmp1High(value) => field_location!(structure::Thermal, tmp1_high, value),
+        message::ThermalMessage::Fan2OnTemp(value) => field_location!(structure::Thermal, fan2_on_temp, value),
+        message::ThermalMessage::Fan2RampTemp(value) => field_location!(structure::Thermal, fan2_ramp_temp, value),
+        message::ThermalMessage::Fan2MaxTemp(value) => field_location!(structure::Thermal, fan2_max_temp, value),
+        message::ThermalMessage::Fan2CrtTemp(value) => field_location!(structure::Thermal, fan2_crt_temp, value),
+        message::ThermalMessage::Fan2HotTemp(value) => field_location!(structure::Thermal, fan2_hot_temp, value),
+        message::ThermalMessage::Fan2MaxRpm(value) => field_location!(structure::Thermal, fan2_max_rpm, value),
+        message::ThermalMessage::Fan2CurRpm(value) => field_location!(structure::Thermal, fan2_cur_rpm, value),
+    };
+
+    advance_write(offset_of!(structure::ECMemory, therm), location, offset, length)?;
+    update_thermal_section(msg, memory_map);
+    Ok(())
+}
+
+/// Write a time alarm message to the memory map at offset, the inverse of [`mem_map_to_time_alarm_msg`]
+/// Modifies offset and length
+pub fn write_time_alarm_msg(
+    msg: &message::TimeAlarmMessage,
+    memory_map: &mut structure::ECMemory,
+    offset: &mut usize,
+    length: &mut usize,
+) -> Result<(), Error> {
+    let location = match msg {
+        message::TimeAlarmMessage::Events(value) => field_location!(structure::TimeAlarm, events, value),
+        message::TimeAlarmMessage::Capability(value) => field_location!(structure::TimeAlarm, capability, value),
+        message::TimeAlarmMessage::Year(value) => field_location!(structure::TimeAlarm, year, value),
+        message::TimeAlarmMessage::Month(value) => field_location!(structure::TimeAlarm, month, value),
+        message::TimeAlarmMessage::Day(value) => field_location!(structure::TimeAlarm, day, value),
+        message::TimeAlarmMessage::Hour(value) => field_location!(structure::TimeAlarm, hour, value),
+        message::TimeAlarmMessage::Minute(value) => field_location!(structure::TimeAlarm, minute, value),
+        message::TimeAlarmMessage::Second(value) => field_location!(structure::TimeAlarm, second, value),
+        message::TimeAlarmMessage::Valid(value) => field_location!(structure::TimeAlarm, valid, value),
+        message::TimeAlarmMessage::Daylight(value) => field_location!(structure::TimeAlarm, daylight, value),
+        message::TimeAlarmMessage::Res1(value) => field_location!(structure::TimeAlarm, res1, value),
+        message::TimeAlarmMessage::Milli(value) => field_location!(structure::TimeAlarm, milli, value),
+        message::TimeAlarmMessage::TimeZone(value) => field_location!(structure::TimeAlarm, time_zone, value),
+        message::TimeAlarmMessage::Res2(value) => field_location!(structure::TimeAlarm, res2, value),
+        message::TimeAlarmMessage::AlarmStatus(value) => field_location!(structure::TimeAlarm, alarm_status, value),
+        message::TimeAlarmMessage::AcTimeVal(value) => field_location!(structure::TimeAlarm, ac_time_val, value),
+        message::TimeAlarmMessage::DcTimeVal(value) => field_location!(structure::TimeAlarm, dc_time_val, value),
+    };
+
+    advance_write(offset_of!(structure::ECMemory, alarm), location, offset, length)?;
+    update_time_alarm_section(msg, memory_map);
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -518,6 +925,132 @@ mod tests {
         };
     }
 
+    #[test]
+    fn test_mem_map_to_capabilities_msg() {
+        use crate::ec_type::message::CapabilitiesMessage;
+        use crate::ec_type::structure::{Capabilities, ECMemory, Version};
+
+        let memory_map = ECMemory {
+            caps: Capabilities {
+                events: 1,
+                fw_version: Version {
+                    major: 2,
+                    minor: 3,
+                    spin: 4,
+                    res0: 0,
+                },
+                secure_state: 5,
+                boot_status: 6,
+                fan_mask: 7,
+                battery_mask: 8,
+                temp_mask: 9,
+                key_mask: 10,
+                debug_mask: 11,
+                res0: 0,
+            },
+            ..Default::default()
+        };
+
+        let mut offset = offset_of!(ECMemory, caps);
+        let mut length = size_of::<Capabilities>();
+
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.caps.events,
+            mem_map_to_capabilities_msg,
+            CapabilitiesMessage::Events
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.caps.fw_version,
+            mem_map_to_capabilities_msg,
+            CapabilitiesMessage::FwVersion
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.caps.secure_state,
+            mem_map_to_capabilities_msg,
+            CapabilitiesMessage::SecureState
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.caps.boot_status,
+            mem_map_to_capabilities_msg,
+            CapabilitiesMessage::BootStatus
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.caps.fan_mask,
+            mem_map_to_capabilities_msg,
+            CapabilitiesMessage::FanMask
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.caps.battery_mask,
+            mem_map_to_capabilities_msg,
+            CapabilitiesMessage::BatteryMask
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.caps.temp_mask,
+            mem_map_to_capabilities_msg,
+            CapabilitiesMessage::TempMask
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.caps.key_mask,
+            mem_map_to_capabilities_msg,
+            CapabilitiesMessage::KeyMask
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.caps.debug_mask,
+            mem_map_to_capabilities_msg,
+            CapabilitiesMessage::DebugMask
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.caps.res0,
+            mem_map_to_capabilities_msg,
+            CapabilitiesMessage::Res0
+        );
+
+        assert_eq!(length, 0);
+    }
+
+    #[test]
+    fn test_mem_map_to_capabilities_msg_error() {
+        use crate::ec_type::structure::{Capabilities, ECMemory};
+
+        let memory_map = ECMemory::default();
+
+        let mut offset = offset_of!(ECMemory, caps) + 1;
+        let mut length = size_of::<Capabilities>();
+
+        let res = mem_map_to_capabilities_msg(&memory_map, &mut offset, &mut length);
+        assert!(res.is_err() && res.unwrap_err() == Error::InvalidLocation);
+    }
+
     #[test]
     fn test_mem_map_to_battery_msg() {
         use crate::ec_type::message::BatteryMessage;
@@ -826,6 +1359,13 @@ mod tests {
                 tmp1_timeout: 14,
                 tmp1_low: 15,
                 tmp1_high: 16,
+                fan2_on_temp: 17,
+                fan2_ramp_temp: 18,
+                fan2_max_temp: 19,
+                fan2_crt_temp: 20,
+                fan2_hot_temp: 21,
+                fan2_max_rpm: 22,
+                fan2_cur_rpm: 23,
             },
             ..Default::default()
         };
@@ -961,6 +1501,62 @@ mod tests {
             mem_map_to_thermal_msg,
             ThermalMessage::Tmp1High
         );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.therm.fan2_on_temp,
+            mem_map_to_thermal_msg,
+            ThermalMessage::Fan2OnTemp
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.therm.fan2_ramp_temp,
+            mem_map_to_thermal_msg,
+            ThermalMessage::Fan2RampTemp
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.therm.fan2_max_temp,
+            mem_map_to_thermal_msg,
+            ThermalMessage::Fan2MaxTemp
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.therm.fan2_crt_temp,
+            mem_map_to_thermal_msg,
+            ThermalMessage::Fan2CrtTemp
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.therm.fan2_hot_temp,
+            mem_map_to_thermal_msg,
+            ThermalMessage::Fan2HotTemp
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.therm.fan2_max_rpm,
+            mem_map_to_thermal_msg,
+            ThermalMessage::Fan2MaxRpm
+        );
+        test_field!(
+            memory_map,
+            offset,
+            length,
+            memory_map.therm.fan2_cur_rpm,
+            mem_map_to_thermal_msg,
+            ThermalMessage::Fan2CurRpm
+        );
 
         assert_eq!(length, 0);
     }
@@ -987,6 +1583,13 @@ mod tests {
                 tmp1_timeout: 14,
                 tmp1_low: 15,
                 tmp1_high: 16,
+                fan2_on_temp: 17,
+                fan2_ramp_temp: 18,
+                fan2_max_temp: 19,
+                fan2_crt_temp: 20,
+                fan2_hot_temp: 21,
+                fan2_max_rpm: 22,
+                fan2_cur_rpm: 23,
             },
             ..Default::default()
         };
@@ -998,6 +1601,43 @@ mod tests {
         assert!(res.is_err() && res.unwrap_err() == Error::InvalidLocation);
     }
 
+    #[test]
+    fn test_write_thermal_msg_fan2() {
+        use crate::ec_type::message::ThermalMessage;
+        use crate::ec_type::structure::{ECMemory, Thermal};
+
+        let messages = [
+            ThermalMessage::Fan2OnTemp(17),
+            ThermalMessage::Fan2RampTemp(18),
+            ThermalMessage::Fan2MaxTemp(19),
+            ThermalMessage::Fan2CrtTemp(20),
+            ThermalMessage::Fan2HotTemp(21),
+            ThermalMessage::Fan2MaxRpm(22),
+            ThermalMessage::Fan2CurRpm(23),
+        ];
+
+        let mut memory_map = ECMemory::default();
+        let mut offset = offset_of!(ECMemory, therm) + offset_of!(Thermal, fan2_on_temp);
+        let mut length = size_of::<Thermal>() - offset_of!(Thermal, fan2_on_temp);
+
+        for msg in messages {
+            write_thermal_msg(&msg, &mut memory_map, &mut offset, &mut length).unwrap();
+        }
+        assert_eq!(length, 0);
+        assert_eq!({ memory_map.therm.fan2_on_temp }, 17);
+        assert_eq!({ memory_map.therm.fan2_cur_rpm }, 23);
+        // Fan 1 is untouched
+        assert_eq!({ memory_map.therm.fan1_on_temp }, 0);
+
+        // Reading back yields the messages that were written
+        let mut offset = offset_of!(ECMemory, therm) + offset_of!(Thermal, fan2_on_temp);
+        let mut length = size_of::<Thermal>() - offset_of!(Thermal, fan2_on_temp);
+        for msg in messages {
+            assert_eq!(mem_map_to_thermal_msg(&memory_map, &mut offset, &mut length), Ok(msg));
+        }
+        assert_eq!(length, 0);
+    }
+
     #[test]
     fn test_mem_map_to_time_alarm_msg() {
         use crate::ec_type::message::TimeAlarmMessage;
@@ -1202,4 +1842,148 @@ mod tests {
         let res = mem_map_to_time_alarm_msg(&memory_map, &mut offset, &mut length);
         assert!(res.is_err() && res.unwrap_err() == Error::InvalidLocation);
     }
+
+    #[test]
+    fn test_write_battery_msg() {
+        use crate::ec_type::message::BatteryMessage;
+        use crate::ec_type::structure::{Battery, ECMemory};
+
+        let messages = [
+            BatteryMessage::Events(1),
+            BatteryMessage::Status(2),
+            BatteryMessage::LastFullCharge(3),
+            BatteryMessage::CycleCount(4),
+            BatteryMessage::State(5),
+            BatteryMessage::PresentRate(6),
+            BatteryMessage::RemainCap(7),
+            BatteryMessage::PresentVolt(8),
+            BatteryMessage::PsrState(9),
+            BatteryMessage::PsrMaxOut(10),
+            BatteryMessage::PsrMaxIn(11),
+            BatteryMessage::PeakLevel(12),
+            BatteryMessage::PeakPower(13),
+            BatteryMessage::SusLevel(14),
+            BatteryMessage::SusPower(15),
+            BatteryMessage::PeakThres(16),
+            BatteryMessage::SusThres(17),
+            BatteryMessage::TripThres(18),
+            BatteryMessage::BmcData(19),
+            BatteryMessage::BmdData(20),
+            BatteryMessage::BmdFlags(21),
+            BatteryMessage::BmdCount(22),
+            BatteryMessage::ChargeTime(23),
+            BatteryMessage::RunTime(24),
+            BatteryMessage::SampleTime(25),
+        ];
+
+        let mut memory_map = ECMemory::default();
+        let mut offset = offset_of!(ECMemory, batt);
+        let mut length = size_of::<Battery>();
+
+        for msg in messages {
+            let next_offset = offset + size_of::<u32>();
+            let next_length = length - size_of::<u32>();
+            write_battery_msg(&msg, &mut memory_map, &mut offset, &mut length).unwrap();
+            assert_eq!(offset, next_offset);
+            assert_eq!(length, next_length);
+        }
+
+        assert_eq!(length, 0);
+
+        // Reading back yields the messages that were written
+        let mut offset = offset_of!(ECMemory, batt);
+        let mut length = size_of::<Battery>();
+        for msg in messages {
+            assert_eq!(mem_map_to_battery_msg(&memory_map, &mut offset, &mut length), Ok(msg));
+        }
+        assert_eq!(length, 0);
+    }
+
+    #[test]
+    fn test_write_battery_msg_error() {
+        use crate::ec_type::message::BatteryMessage;
+        use crate::ec_type::structure::{Battery, ECMemory};
+
+        let mut memory_map = ECMemory::default();
+
+        // Offset doesn't match the message's field
+        let mut offset = offset_of!(ECMemory, batt);
+        let mut length = size_of::<Battery>();
+        let res = write_battery_msg(&BatteryMessage::Status(2), &mut memory_map, &mut offset, &mut length);
+        assert_eq!(res, Err(Error::InvalidLocation));
+        assert_eq!(offset, offset_of!(ECMemory, batt));
+        assert_eq!(length, size_of::<Battery>());
+
+        // Field doesn't fit in the remaining length
+        let mut length = 2;
+        let res = write_battery_msg(&BatteryMessage::Events(1), &mut memory_map, &mut offset, &mut length);
+        assert_eq!(res, Err(Error::InvalidLocation));
+        assert_eq!({ memory_map.batt.events }, 0);
+    }
+
+    #[test]
+    fn test_checksum() {
+        use crate::ec_type::structure::ECMemory;
+
+        // Standard check value of CRC-32/ISO-HDLC, the same as the platform CRC
+        assert_eq!(CHECKSUM_CRC.checksum(b"123456789"), 0xcbf4_3926);
+
+        let mut memory_map = ECMemory::default();
+        memory_map.batt.status = 2;
+        memory_map.therm.fan1_cur_rpm = 3000;
+        memory_map.checksum = compute_checksum(&memory_map);
+        assert!(validate_checksum(&memory_map));
+
+        // The checksum does not cover itself
+        let expected = compute_checksum(&memory_map);
+        memory_map.checksum ^= 1;
+        assert_eq!(compute_checksum(&memory_map), expected);
+        assert!(!validate_checksum(&memory_map));
+    }
+
+    #[test]
+    fn test_checksum_corruption() {
+        use crate::ec_type::structure::ECMemory;
+
+        let mut memory_map = ECMemory::default();
+        memory_map.checksum = compute_checksum(&memory_map);
+
+        let checksum = offset_of!(ECMemory, checksum);
+        for i in (0..size_of::<ECMemory>()).filter(|i| !(checksum..checksum + size_of::<u32>()).contains(i)) {
+            let mut corrupted = memory_map;
+            // SAFETY: ECMemory is packed and contains only integers, so any byte value is valid
+            let bytes = unsafe {
+                core::slice::from_raw_parts_mut((&mut corrupted as *mut ECMemory).cast::<u8>(), size_of::<ECMemory>())
+            };
+            bytes[i] ^= 0xff;
+            assert!(!validate_checksum(&corrupted), "corruption at byte {i} not detected");
+        }
+    }
+
+    #[test]
+    fn test_dirty_regions() {
+        use crate::ec_type::message::BatteryMessage;
+        use crate::ec_type::structure::ECMemory;
+
+        let mut memory_map = ECMemory::default();
+        let mut dirty = DirtyRegions::new();
+        assert!(!dirty.any());
+
+        update_battery_section(&BatteryMessage::RemainCap(1234), &mut memory_map);
+        dirty.mark(Region::Battery);
+        assert!(dirty.is_dirty(Region::Battery));
+        assert!(!dirty.is_dirty(Region::Thermal));
+
+        // Reading an unrelated region leaves the battery region dirty
+        let therm = Region::Thermal.range();
+        dirty.host_read(therm.start, therm.len());
+        assert!(dirty.is_dirty(Region::Battery));
+
+        dirty.host_read(
+            offset_of!(ECMemory, batt) + offset_of!(structure::Battery, remain_cap),
+            4,
+        );
+        assert!(!dirty.is_dirty(Region::Battery));
+        assert!(!dirty.any());
+    }
 }
@@ -10,7 +10,7 @@ pub const EC_MEMMAP_VERSION: Version = Version {
 
 #[allow(missing_docs)]
 #[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Version {
     pub major: u8,
     pub minor: u8,
@@ -20,7 +20,7 @@ pub struct Version {
 
 #[allow(missing_docs)]
 #[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Capabilities {
     pub events: u32,
     pub fw_version: Version,
@@ -36,7 +36,7 @@ pub struct Capabilities {
 
 #[allow(missing_docs)]
 #[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct TimeAlarm {
     pub events: u32,
     pub capability: u32,
@@ -59,7 +59,7 @@ pub struct TimeAlarm {
 
 #[allow(missing_docs)]
 #[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Battery {
     pub events: u32,
     pub status: u32,
@@ -90,7 +90,7 @@ pub struct Battery {
 
 #[allow(missing_docs)]
 #[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Thermal {
     pub events: u32,
     pub cool_mode: u32,
@@ -108,11 +108,18 @@ pub struct Thermal {
     pub tmp1_timeout: u32,
     pub tmp1_low: u32,
     pub tmp1_high: u32,
+    pub fan2_on_temp: u32,
+    pub fan2_ramp_temp: u32,
+    pub fan2_max_temp: u32,
+    pub fan2_crt_temp: u32,
+    pub fan2_hot_temp: u32,
+    pub fan2_max_rpm: u32,
+    pub fan2_cur_rpm: u32,
 }
 
 #[allow(missing_docs)]
 #[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Notifications {
     pub service: u16,
     pub event: u16,
@@ -120,7 +127,7 @@ pub struct Notifications {
 
 #[allow(missing_docs)]
 #[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct ECMemory {
     pub ver: Version,
     pub caps: Capabilities,
@@ -128,4 +135,5 @@ pub struct ECMemory {
     pub alarm: TimeAlarm,
     pub batt: Battery,
     pub therm: Thermal,
+    pub checksum: u32,
 }
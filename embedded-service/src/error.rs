@@ -0,0 +1,84 @@
+//! Error type spanning all services
+use embedded_usb_pd::PdError;
+
+use crate::{cfu, hid, intrusive_list, power};
+
+/// Error from any service, allows code spanning several services to use `?`
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ServiceError {
+    /// CFU error
+    Cfu(cfu::CfuError),
+    /// HID error
+    Hid(hid::Error),
+    /// Intrusive list error, e.g. from registering a device
+    IntrusiveList(intrusive_list::Error),
+    /// Power policy error
+    PowerPolicy(power::policy::Error),
+    /// Type-C error
+    TypeC(PdError),
+}
+
+impl From<cfu::CfuError> for ServiceError {
+    fn from(value: cfu::CfuError) -> Self {
+        Self::Cfu(value)
+    }
+}
+
+impl From<hid::Error> for ServiceError {
+    fn from(value: hid::Error) -> Self {
+        Self::Hid(value)
+    }
+}
+
+impl From<intrusive_list::Error> for ServiceError {
+    fn from(value: intrusive_list::Error) -> Self {
+        Self::IntrusiveList(value)
+    }
+}
+
+impl From<power::policy::Error> for ServiceError {
+    fn from(value: power::policy::Error) -> Self {
+        Self::PowerPolicy(value)
+    }
+}
+
+impl From<PdError> for ServiceError {
+    fn from(value: PdError) -> Self {
+        Self::TypeC(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Convert through `?` the way service code would
+    fn convert<E: Into<ServiceError>>(error: E) -> Result<(), ServiceError> {
+        Err(error)?
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert!(matches!(
+            convert(cfu::CfuError::ComponentBusy),
+            Err(ServiceError::Cfu(cfu::CfuError::ComponentBusy))
+        ));
+        assert!(matches!(
+            convert(hid::Error::InvalidData),
+            Err(ServiceError::Hid(hid::Error::InvalidData))
+        ));
+        assert!(matches!(
+            convert(intrusive_list::Error::NodeAlreadyInList),
+            Err(ServiceError::IntrusiveList(intrusive_list::Error::NodeAlreadyInList))
+        ));
+        assert!(matches!(
+            convert(power::policy::Error::Timeout),
+            Err(ServiceError::PowerPolicy(power::policy::Error::Timeout))
+        ));
+        assert!(matches!(
+            convert(PdError::InvalidPort),
+            Err(ServiceError::TypeC(PdError::InvalidPort))
+        ));
+    }
+}
@@ -1,4 +1,6 @@
 //! Logging macro implementations and other formating functions
+//!
+//! Enable one of the `log-level-*` features to compile out every macro below that level
 
 #[cfg(all(feature = "log", feature = "defmt", not(doc)))]
 compile_error!("features `log` and `defmt` are mutually exclusive");
@@ -6,6 +8,13 @@ compile_error!("features `log` and `defmt` are mutually exclusive");
 #[cfg(all(not(doc), feature = "defmt"))]
 mod defmt {
     /// Logs a trace message using the underlying logger
+    #[cfg(not(any(
+        feature = "log-level-off",
+        feature = "log-level-error",
+        feature = "log-level-warn",
+        feature = "log-level-info",
+        feature = "log-level-debug"
+    )))]
     #[macro_export]
     #[collapse_debuginfo(yes)]
     macro_rules! trace {
@@ -18,6 +27,12 @@ mod defmt {
     }
 
     /// Logs a debug message using the underlying logger
+    #[cfg(not(any(
+        feature = "log-level-off",
+        feature = "log-level-error",
+        feature = "log-level-warn",
+        feature = "log-level-info"
+    )))]
     #[macro_export]
     #[collapse_debuginfo(yes)]
     macro_rules! debug {
@@ -30,6 +45,7 @@ mod defmt {
     }
 
     /// Logs an info message using the underlying logger
+    #[cfg(not(any(feature = "log-level-off", feature = "log-level-error", feature = "log-level-warn")))]
     #[macro_export]
     #[collapse_debuginfo(yes)]
     macro_rules! info {
@@ -42,6 +58,7 @@ mod defmt {
     }
 
     /// Logs a warning using the underlying logger
+    #[cfg(not(any(feature = "log-level-off", feature = "log-level-error")))]
     #[macro_export]
     #[collapse_debuginfo(yes)]
     macro_rules! warn {
@@ -54,6 +71,7 @@ mod defmt {
     }
 
     /// Logs an error using the underlying logger
+    #[cfg(not(feature = "log-level-off"))]
     #[macro_export]
     #[collapse_debuginfo(yes)]
     macro_rules! error {
@@ -69,6 +87,13 @@ mod defmt {
 #[cfg(all(not(doc), feature = "log"))]
 mod log {
     /// Logs a trace message using the underlying logger
+    #[cfg(not(any(
+        feature = "log-level-off",
+        feature = "log-level-error",
+        feature = "log-level-warn",
+        feature = "log-level-info",
+        feature = "log-level-debug"
+    )))]
     #[macro_export]
     #[collapse_debuginfo(yes)]
     macro_rules! trace {
@@ -80,6 +105,12 @@ mod log {
     }
 
     /// Logs a debug message using the underlying logger
+    #[cfg(not(any(
+        feature = "log-level-off",
+        feature = "log-level-error",
+        feature = "log-level-warn",
+        feature = "log-level-info"
+    )))]
     #[macro_export]
     #[collapse_debuginfo(yes)]
     macro_rules! debug {
@@ -91,6 +122,7 @@ mod log {
     }
 
     /// Logs an info message using the underlying logger
+    #[cfg(not(any(feature = "log-level-off", feature = "log-level-error", feature = "log-level-warn")))]
     #[macro_export]
     #[collapse_debuginfo(yes)]
     macro_rules! info {
@@ -102,6 +134,7 @@ mod log {
     }
 
     /// Logs a warning using the underlying logger
+    #[cfg(not(any(feature = "log-level-off", feature = "log-level-error")))]
     #[macro_export]
     #[collapse_debuginfo(yes)]
     macro_rules! warn {
@@ -113,6 +146,7 @@ mod log {
     }
 
     /// Logs an error using the underlying logger
+    #[cfg(not(feature = "log-level-off"))]
     #[macro_export]
     #[collapse_debuginfo(yes)]
     macro_rules! error {
@@ -124,6 +158,81 @@ mod log {
     }
 }
 
+// Levels compiled out by a `log-level-*` feature
+#[cfg(all(not(doc), any(feature = "defmt", feature = "log")))]
+mod filtered {
+    /// Logs a trace message using the underlying logger
+    #[cfg(any(
+        feature = "log-level-off",
+        feature = "log-level-error",
+        feature = "log-level-warn",
+        feature = "log-level-info",
+        feature = "log-level-debug"
+    ))]
+    #[macro_export]
+    #[collapse_debuginfo(yes)]
+    macro_rules! trace {
+        ($s:literal $(, $x:expr)* $(,)?) => {
+            {
+                let _ = ($s, $( &$x ),*);
+            }
+        };
+    }
+
+    /// Logs a debug message using the underlying logger
+    #[cfg(any(
+        feature = "log-level-off",
+        feature = "log-level-error",
+        feature = "log-level-warn",
+        feature = "log-level-info"
+    ))]
+    #[macro_export]
+    #[collapse_debuginfo(yes)]
+    macro_rules! debug {
+        ($s:literal $(, $x:expr)* $(,)?) => {
+            {
+                let _ = ($s, $( &$x ),*);
+            }
+        };
+    }
+
+    /// Logs an info message using the underlying logger
+    #[cfg(any(feature = "log-level-off", feature = "log-level-error", feature = "log-level-warn"))]
+    #[macro_export]
+    #[collapse_debuginfo(yes)]
+    macro_rules! info {
+        ($s:literal $(, $x:expr)* $(,)?) => {
+            {
+                let _ = ($s, $( &$x ),*);
+            }
+        };
+    }
+
+    /// Logs a warning using the underlying logger
+    #[cfg(any(feature = "log-level-off", feature = "log-level-error"))]
+    #[macro_export]
+    #[collapse_debuginfo(yes)]
+    macro_rules! warn {
+        ($s:literal $(, $x:expr)* $(,)?) => {
+            {
+                let _ = ($s, $( &$x ),*);
+            }
+        };
+    }
+
+    /// Logs an error using the underlying logger
+    #[cfg(feature = "log-level-off")]
+    #[macro_export]
+    #[collapse_debuginfo(yes)]
+    macro_rules! error {
+        ($s:literal $(, $x:expr)* $(,)?) => {
+            {
+                let _ = ($s, $( &$x ),*);
+            }
+        };
+    }
+}
+
 // Provide this implementation for `cargo doc`
 #[cfg(any(doc, not(any(feature = "defmt", feature = "log"))))]
 mod none {
@@ -182,3 +291,43 @@ mod none {
         };
     }
 }
+
+#[cfg(all(test, feature = "log", feature = "log-level-info"))]
+mod test {
+    extern crate std;
+    use std::string::{String, ToString};
+    use std::sync::Mutex;
+    use std::vec::Vec;
+
+    use crate::{debug, error, info, trace, warn};
+
+    /// Logger that records every message it receives
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl ::log::Log for Recorder {
+        fn enabled(&self, _metadata: &::log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &::log::Record) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn test_log_level_info() {
+        static RECORDER: Recorder = Recorder(Mutex::new(Vec::new()));
+        ::log::set_logger(&RECORDER).unwrap();
+        ::log::set_max_level(::log::LevelFilter::Trace);
+
+        trace!("trace {}", 0);
+        debug!("debug {}", 1);
+        info!("info {}", 2);
+        warn!("warn {}", 3);
+        error!("error {}", 4);
+
+        assert_eq!(*RECORDER.0.lock().unwrap(), ["info 2", "warn 3", "error 4"]);
+    }
+}
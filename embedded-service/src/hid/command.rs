@@ -521,11 +521,142 @@ impl<'a> Command<'a> {
 
         Ok(len)
     }
+
+    /// Decodes a u16 value from the start of a slice
+    fn decode_u16(buf: &[u8]) -> Result<u16, Error> {
+        if buf.len() < VALUE_LEN {
+            return Err(Error::InvalidSize(VALUE_LEN, buf.len()));
+        }
+
+        Ok(u16::from_le_bytes([buf[0], buf[1]]))
+    }
+
+    /// Decodes a report ID from a command, reading the extended report ID from the slice if present
+    /// Returns the report ID and the number of bytes read
+    fn decode_report_id(cmd: u16, buf: &[u8]) -> Result<(ReportId, usize), Error> {
+        if ReportId::has_extended_report_id(cmd) {
+            let id = *buf.first().ok_or(Error::InvalidSize(1, 0))?;
+            Ok((ReportId(id), 1))
+        } else {
+            Ok((ReportId::from_command(cmd), 0))
+        }
+    }
+
+    /// Decodes a u16 value prefixed by a length
+    fn decode_value(buf: &[u8]) -> Result<u16, Error> {
+        if buf.len() < LENGTH_VALUE_LEN {
+            return Err(Error::InvalidSize(LENGTH_VALUE_LEN, buf.len()));
+        }
+
+        // Length value includes the size of the length as well
+        let len = Self::decode_u16(buf)? as usize;
+        if len != LENGTH_VALUE_LEN {
+            return Err(Error::InvalidSize(LENGTH_VALUE_LEN, len));
+        }
+
+        Self::decode_u16(&buf[VALUE_LEN..])
+    }
+
+    /// Decode a command from a buffer, returns the command and number of bytes read
+    /// If has_command_reg or has_data_reg is set, the buffer is expected to contain those register addresses
+    pub fn decode_from_slice(
+        buf: &SharedRef<'a, u8>,
+        has_command_reg: bool,
+        has_data_reg: bool,
+    ) -> Result<(Self, usize), Error> {
+        let access = buf.borrow();
+        let bytes: &[u8] = access.borrow();
+        let mut len = 0;
+
+        // Skip command register address
+        if has_command_reg {
+            len += REGISTER_LEN;
+        }
+
+        if bytes.len() < len + BASIC_CMD_LEN {
+            return Err(Error::InvalidSize(len + BASIC_CMD_LEN, bytes.len()));
+        }
+
+        let cmd = Self::decode_u16(&bytes[len..])?;
+        len += BASIC_CMD_LEN;
+        let opcode = Opcode::try_from(cmd)?;
+
+        let report_id = if opcode.requires_report_id() {
+            let (report_id, id_len) = Self::decode_report_id(cmd, &bytes[len..])?;
+            len += id_len;
+            Some(report_id)
+        } else {
+            None
+        };
+
+        // Skip data register address, only present on commands that transfer data
+        if has_data_reg && !matches!(opcode, Opcode::Reset | Opcode::SetPower | Opcode::Vendor) {
+            len += REGISTER_LEN;
+            if bytes.len() < len {
+                return Err(Error::InvalidSize(len, bytes.len()));
+            }
+        }
+
+        let command = match opcode {
+            Opcode::Reset => Command::Reset,
+            Opcode::GetReport => {
+                let report_type = ReportType::try_from(cmd).map_err(|_| Error::InvalidReportType)?;
+                if report_type != ReportType::Input && report_type != ReportType::Feature {
+                    return Err(Error::InvalidReportType);
+                }
+
+                Command::GetReport(report_type, report_id.ok_or(Error::RequiresReportId)?)
+            }
+            Opcode::SetReport => {
+                let report_type = ReportType::try_from(cmd).map_err(|_| Error::InvalidReportType)?;
+                if report_type != ReportType::Output && report_type != ReportType::Feature {
+                    return Err(Error::InvalidReportType);
+                }
+
+                // Length value includes the size of the length as well
+                let total_len = Self::decode_u16(&bytes[len..])? as usize;
+                if total_len <= VALUE_LEN {
+                    return Err(Error::RequiresData);
+                }
+
+                if bytes.len() < len + total_len {
+                    return Err(Error::InvalidSize(len + total_len, bytes.len()));
+                }
+
+                let data = buf.slice(len + VALUE_LEN..len + total_len);
+                len += total_len;
+                Command::SetReport(report_type, report_id.ok_or(Error::RequiresReportId)?, data)
+            }
+            Opcode::GetIdle => Command::GetIdle(report_id.ok_or(Error::RequiresReportId)?),
+            Opcode::SetIdle => {
+                let freq = Self::decode_value(&bytes[len..])?
+                    .try_into()
+                    .map_err(|_| Error::InvalidReportFreq)?;
+                len += LENGTH_VALUE_LEN;
+                Command::SetIdle(report_id.ok_or(Error::RequiresReportId)?, freq)
+            }
+            Opcode::GetProtocol => Command::GetProtocol,
+            Opcode::SetProtocol => {
+                let protocol = Self::decode_value(&bytes[len..])?
+                    .try_into()
+                    .map_err(|_| Error::InvalidData)?;
+                len += LENGTH_VALUE_LEN;
+                Command::SetProtocol(protocol)
+            }
+            Opcode::SetPower => Command::SetPower(cmd.try_into().map_err(|_| Error::InvalidData)?),
+            Opcode::Vendor => Command::Vendor,
+        };
+
+        Ok((command, len))
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use core::borrow::BorrowMut;
+
     use super::*;
+    use crate::buffer::OwnedRef;
     use crate::define_static_buffer;
 
     const CMD_REG: u16 = 0x0005;
@@ -887,4 +1018,132 @@ mod test {
             .unwrap();
         assert_eq!(&test_buffer[0..len], [0x05, 0x00, 0x00, 0x0e]);
     }
+
+    /// Encodes the command, decodes it back and checks that re-encoding the result produces the same bytes
+    fn round_trip<'a>(buffer: &OwnedRef<'a, u8>, command: &Command<'_>, registers: bool) -> Command<'a> {
+        let (command_reg, data_reg) = if registers {
+            (Some(CMD_REG), Some(DATA_REG))
+        } else {
+            (None, None)
+        };
+
+        let len = {
+            let mut borrow = buffer.borrow_mut();
+            let buf: &mut [u8] = borrow.borrow_mut();
+            buf.fill(0);
+            command.encode_into_slice(buf, command_reg, data_reg).unwrap()
+        };
+
+        let encoded = buffer.reference().slice(0..len);
+        let (decoded, decoded_len) = Command::decode_from_slice(&encoded, registers, registers).unwrap();
+        assert_eq!(decoded_len, len);
+
+        let mut reencoded = [0u8; 16];
+        let reencoded_len = decoded
+            .encode_into_slice(&mut reencoded, command_reg, data_reg)
+            .unwrap();
+        let borrow = encoded.borrow();
+        let original: &[u8] = borrow.borrow();
+        assert_eq!(&reencoded[0..reencoded_len], original);
+
+        decoded
+    }
+
+    #[test]
+    fn test_round_trip() {
+        define_static_buffer!(encode_buffer, u8, [0u8; 16]);
+        define_static_buffer!(data_buffer, u8, [0x01, 0x02, 0x03]);
+
+        let buffer = encode_buffer::get_mut().unwrap();
+        let data = data_buffer::get_mut().unwrap();
+
+        for registers in [false, true] {
+            assert!(matches!(
+                round_trip(&buffer, &Command::Reset, registers),
+                Command::Reset
+            ));
+
+            for report_id in [REPORT_ID, EXT_REPORT_ID, ReportId(0x20)] {
+                for report_type in [ReportType::Input, ReportType::Feature] {
+                    let decoded = round_trip(&buffer, &Command::GetReport(report_type, report_id), registers);
+                    assert!(matches!(decoded, Command::GetReport(t, id) if t == report_type && id == report_id));
+                }
+
+                for report_type in [ReportType::Output, ReportType::Feature] {
+                    let decoded = round_trip(
+                        &buffer,
+                        &Command::SetReport(report_type, report_id, data.reference()),
+                        registers,
+                    );
+                    match decoded {
+                        Command::SetReport(t, id, decoded_data) => {
+                            assert_eq!(t, report_type);
+                            assert_eq!(id, report_id);
+                            assert_eq!(decoded_data.borrow(), [0x01, 0x02, 0x03]);
+                        }
+                        _ => panic!("Expected SetReport"),
+                    }
+                }
+
+                let decoded = round_trip(&buffer, &Command::GetIdle(report_id), registers);
+                assert!(matches!(decoded, Command::GetIdle(id) if id == report_id));
+
+                for freq in [ReportFreq::Infinite, ReportFreq::Msecs(0x0203)] {
+                    let decoded = round_trip(&buffer, &Command::SetIdle(report_id, freq), registers);
+                    assert!(matches!(decoded, Command::SetIdle(id, f) if id == report_id && f == freq));
+                }
+            }
+
+            assert!(matches!(
+                round_trip(&buffer, &Command::GetProtocol, registers),
+                Command::GetProtocol
+            ));
+
+            for protocol in [Protocol::Boot, Protocol::Report] {
+                let decoded = round_trip(&buffer, &Command::SetProtocol(protocol), registers);
+                assert!(matches!(decoded, Command::SetProtocol(p) if p == protocol));
+            }
+
+            for state in [PowerState::On, PowerState::Sleep] {
+                let decoded = round_trip(&buffer, &Command::SetPower(state), registers);
+                assert!(matches!(decoded, Command::SetPower(s) if s == state));
+            }
+
+            assert!(matches!(
+                round_trip(&buffer, &Command::Vendor, registers),
+                Command::Vendor
+            ));
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        // Truncated command
+        define_static_buffer!(short_buffer, u8, [0x00]);
+        assert!(matches!(
+            Command::decode_from_slice(&short_buffer::get(), false, false),
+            Err(Error::InvalidSize(2, 1))
+        ));
+
+        // Extended report ID missing
+        define_static_buffer!(ext_buffer, u8, [0x1f, 0x02]);
+        assert!(matches!(
+            Command::decode_from_slice(&ext_buffer::get(), false, false),
+            Err(Error::InvalidSize(1, 0))
+        ));
+
+        // Output report type is invalid for GetReport
+        define_static_buffer!(type_buffer, u8, [0x28, 0x02]);
+        assert!(matches!(
+            Command::decode_from_slice(&type_buffer::get(), false, false),
+            Err(Error::InvalidReportType)
+        ));
+
+        // Report data shorter than the encoded length
+        define_static_buffer!(data_buffer, u8, [0x28, 0x03, 0x06, 0x00, 0x00]);
+        assert!(matches!(
+            Command::decode_from_slice(&data_buffer::get(), false, false),
+            Err(Error::InvalidSize(8, 5))
+        ));
+    }
 }
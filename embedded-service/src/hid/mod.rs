@@ -1,12 +1,14 @@
 //! HID sevices
 //! See spec at http://msdn.microsoft.com/en-us/library/windows/hardware/hh852380.aspx
+use core::borrow::BorrowMut;
+use core::cell::Cell;
 use core::convert::Infallible;
 
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::once_lock::OnceLock;
 use embassy_sync::signal::Signal;
 
-use crate::buffer::SharedRef;
+use crate::buffer::{OwnedRef, SharedRef};
 use crate::comms::{self, Endpoint, EndpointID, External, Internal, MailboxDelegate};
 use crate::{error, intrusive_list, IntrusiveList, Node, NodeContainer};
 
@@ -92,6 +94,16 @@ impl Descriptor {
         Ok(30)
     }
 
+    /// Serializes a descriptor into the start of a shared buffer, returns a reference to the encoded bytes
+    pub fn encode_into_buffer<'a>(&self, buf: &OwnedRef<'a, u8>) -> Result<SharedRef<'a, u8>, Error> {
+        let len = {
+            let mut borrow = buf.borrow_mut();
+            let slice: &mut [u8] = borrow.borrow_mut();
+            self.encode_into_slice(slice)?
+        };
+        Ok(buf.reference().slice(0..len))
+    }
+
     /// Deserializes a descriptor from the slice
     pub fn decode_from_slice(buf: &[u8]) -> Result<Self, Error> {
         if buf.len() < DESCRIPTOR_LEN {
@@ -140,6 +152,19 @@ pub struct RegisterFile {
     pub data_reg: u16,
 }
 
+impl RegisterFile {
+    /// Returns true if `reg` is one of the registers the host can address directly
+    pub fn contains(&self, reg: u16) -> bool {
+        [
+            self.hid_desc_reg,
+            self.report_desc_reg,
+            self.input_reg,
+            self.command_reg,
+        ]
+        .contains(&reg)
+    }
+}
+
 /// HID devices commonly start with the descriptor register and increment from there in this order
 impl Default for RegisterFile {
     fn default() -> Self {
@@ -163,6 +188,8 @@ pub struct Device {
     pub id: DeviceId,
     /// Registers
     pub regs: RegisterFile,
+    /// Maximum output report length from the descriptor, unchecked until a descriptor is set
+    max_output_length: Cell<Option<u16>>,
 }
 
 /// Trait to allow access to underlying Device
@@ -186,9 +213,26 @@ impl Device {
             request: Signal::new(),
             id,
             regs,
+            max_output_length: Cell::new(None),
         }
     }
 
+    /// Store the limits from the device's descriptor so requests can be validated locally
+    pub fn set_descriptor(&self, desc: &Descriptor) {
+        self.max_output_length.set(Some(desc.w_max_output_length));
+    }
+
+    /// Validate a request against the limits from the device's descriptor
+    pub fn validate_request(&self, request: &Request<'_>) -> Result<(), Error> {
+        if let (Request::OutputReport(_, report), Some(max)) = (request, self.max_output_length.get()) {
+            if report.len() > max as usize {
+                return Err(Error::InvalidSize(max as usize, report.len()));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Wait for this device to receive a request
     pub async fn wait_request(&self) -> Request<'static> {
         self.request.wait().await
@@ -202,6 +246,14 @@ impl Device {
         };
         self.tp.send(EndpointID::External(External::Host), &message).await
     }
+
+    /// Serialize `desc` into `buf` and send it to the host as the response to a descriptor request
+    pub async fn respond_descriptor(&self, desc: &Descriptor, buf: &OwnedRef<'static, u8>) -> Result<(), Error> {
+        let desc = desc.encode_into_buffer(buf)?;
+        self.send_response(Some(Response::Descriptor(desc)))
+            .await
+            .map_err(|_| Error::Transport)
+    }
 }
 
 impl DeviceContainer for Device {
@@ -218,7 +270,14 @@ impl MailboxDelegate for Device {
             .ok_or(comms::MailboxDelegateError::MessageNotFound)?;
 
         match message.data {
+            // Requests are broadcast to all devices
+            MessageData::Request(_) if message.id != self.id => Err(comms::MailboxDelegateError::InvalidId),
             MessageData::Request(ref request) => {
+                if let Err(e) = self.validate_request(request) {
+                    error!("HID device {}: Invalid request: {:?}", self.id.0, e);
+                    return Err(comms::MailboxDelegateError::InvalidData);
+                }
+
                 self.request.signal(request.clone());
                 Ok(())
             }
@@ -305,6 +364,11 @@ pub fn init() {
     CONTEXT.get_or_init(Context::new);
 }
 
+/// Limit the number of HID devices that can be registered, None removes the limit
+pub async fn set_max_devices(max: Option<usize>) {
+    CONTEXT.get().await.devices.set_max_len(max);
+}
+
 /// Register a device with the HID service
 pub async fn register_device(device: &'static impl DeviceContainer) -> Result<(), intrusive_list::Error> {
     let device = device.get_hid_device();
@@ -338,7 +402,16 @@ pub async fn send_request(tp: &Endpoint, to: DeviceId, request: Request<'static>
 
 #[cfg(test)]
 mod test {
+    extern crate std;
+    use core::borrow::Borrow;
+    use core::cell::RefCell;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    use embassy_futures::block_on;
+
     use super::*;
+    use crate::define_static_buffer;
 
     #[test]
     fn descriptor_serialize_deserialize() {
@@ -374,4 +447,96 @@ mod test {
 
         assert_eq!(decoded, descriptor);
     }
+
+    #[test]
+    fn test_respond_descriptor() {
+        const ID: DeviceId = DeviceId(0x20);
+
+        /// Records the descriptor bytes sent to the host
+        struct HostDelegate {
+            received: RefCell<Vec<u8>>,
+        }
+
+        impl MailboxDelegate for HostDelegate {
+            fn receive(&self, message: &comms::Message) -> Result<(), comms::MailboxDelegateError> {
+                let message = message
+                    .data
+                    .get::<Message>()
+                    .ok_or(comms::MailboxDelegateError::MessageNotFound)?;
+                if let MessageData::Response(Some(Response::Descriptor(ref desc))) = message.data {
+                    if message.id == ID {
+                        let access = desc.borrow();
+                        let bytes: &[u8] = access.borrow();
+                        self.received.borrow_mut().extend_from_slice(bytes);
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        define_static_buffer!(desc_buffer, u8, [0xff; 64]);
+
+        let descriptor = Descriptor {
+            w_hid_desc_length: DESCRIPTOR_LEN as u16,
+            bcd_version: 0x0100,
+            w_report_desc_length: 0x0038,
+            w_report_desc_register: 0x0002,
+            w_input_register: 0x0003,
+            w_max_input_length: 0x0008,
+            w_output_register: 0x0004,
+            w_max_output_length: 0x002d,
+            w_command_register: 0x0005,
+            w_data_register: 0x0006,
+            w_vendor_id: 0x0483,
+            w_product_id: 0x572b,
+            w_version_id: 0x0100,
+        };
+        const EXPECTED: [u8; DESCRIPTOR_LEN] = [
+            0x1e, 0x00, 0x00, 0x01, 0x38, 0x00, 0x02, 0x00, 0x03, 0x00, 0x08, 0x00, 0x04, 0x00, 0x2d, 0x00, 0x05, 0x00,
+            0x06, 0x00, 0x83, 0x04, 0x2b, 0x57, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+        ];
+
+        comms::init();
+        let host: &'static HostDelegate = Box::leak(Box::new(HostDelegate {
+            received: RefCell::new(Vec::new()),
+        }));
+        let endpoint: &'static Endpoint = Box::leak(Box::new(Endpoint::uninit(EndpointID::External(External::Host))));
+        let device = Device::new(ID, RegisterFile::default());
+        let buffer = desc_buffer::get_mut().unwrap();
+
+        block_on(async {
+            comms::register_endpoint(host, endpoint).await.unwrap();
+            device.respond_descriptor(&descriptor, &buffer).await.unwrap();
+        });
+
+        assert_eq!(host.received.borrow().as_slice(), EXPECTED);
+        assert_eq!(Descriptor::decode_from_slice(&EXPECTED).unwrap(), descriptor);
+    }
+
+    #[test]
+    fn test_output_report_length() {
+        define_static_buffer!(report_buffer, u8, [0; 16]);
+        const MAX_OUTPUT_LEN: u16 = 8;
+
+        let device = Device::new(DeviceId(0x21), RegisterFile::default());
+        let report = report_buffer::get();
+
+        // Unchecked until the descriptor is known
+        let request = Request::OutputReport(None, report.slice(0..16));
+        assert!(device.validate_request(&request).is_ok());
+
+        device.set_descriptor(&Descriptor {
+            w_max_output_length: MAX_OUTPUT_LEN,
+            ..Default::default()
+        });
+
+        let request = Request::OutputReport(Some(ReportId(1)), report.slice(0..MAX_OUTPUT_LEN as usize + 1));
+        assert!(matches!(
+            device.validate_request(&request),
+            Err(Error::InvalidSize(8, 9))
+        ));
+
+        let request = Request::OutputReport(Some(ReportId(1)), report.slice(0..MAX_OUTPUT_LEN as usize));
+        assert!(device.validate_request(&request).is_ok());
+    }
 }
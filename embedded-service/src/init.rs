@@ -1,6 +1,11 @@
 //! Code related to initialization states and ordering
 
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::once_lock::OnceLock;
+use embassy_sync::signal::Signal;
 
 static REGISTRATION_DONE: OnceLock<()> = OnceLock::new();
 
@@ -13,3 +18,145 @@ pub async fn wait_for_registration() {
 pub fn registration_done() {
     REGISTRATION_DONE.get_or_init(|| ());
 }
+
+/// Maximum number of services tracked by an init barrier
+pub const MAX_SERVICES: u8 = 32;
+
+/// Service ID new type, must be less than [`MAX_SERVICES`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ServiceId(pub u8);
+
+impl ServiceId {
+    fn mask(self) -> Result<u32, InvalidServiceId> {
+        if self.0 < MAX_SERVICES {
+            Ok(1 << self.0)
+        } else {
+            Err(InvalidServiceId(self))
+        }
+    }
+}
+
+/// Error for a service ID that isn't less than [`MAX_SERVICES`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InvalidServiceId(pub ServiceId);
+
+/// Combined mask of the given services
+fn services_mask(services: &[ServiceId]) -> Result<u32, InvalidServiceId> {
+    services.iter().try_fold(0, |mask, service| Ok(mask | service.mask()?))
+}
+
+/// Barrier that releases once every awaited service has signaled ready
+/// Only supports a single waiter, typically the board's main task
+pub struct InitBarrier {
+    ready: Mutex<CriticalSectionRawMutex, Cell<u32>>,
+    signal: Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl InitBarrier {
+    /// Create a new barrier with no services ready
+    pub const fn new() -> Self {
+        Self {
+            ready: Mutex::new(Cell::new(0)),
+            signal: Signal::new(),
+        }
+    }
+
+    /// Signal that the given service has completed registration
+    pub fn service_ready(&self, service: ServiceId) -> Result<(), InvalidServiceId> {
+        let mask = service.mask()?;
+        self.ready.lock(|ready| ready.set(ready.get() | mask));
+        self.signal.signal(());
+        Ok(())
+    }
+
+    /// Returns true if all the given services have signaled ready
+    pub fn all_ready(&self, services: &[ServiceId]) -> Result<bool, InvalidServiceId> {
+        let mask = services_mask(services)?;
+        Ok(self.ready.lock(|ready| ready.get() & mask == mask))
+    }
+
+    /// Wait until all the given services have signaled ready
+    pub async fn await_all_ready(&self, services: &[ServiceId]) -> Result<(), InvalidServiceId> {
+        while !self.all_ready(services)? {
+            self.signal.wait().await;
+        }
+        Ok(())
+    }
+}
+
+impl Default for InitBarrier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static INIT_BARRIER: InitBarrier = InitBarrier::new();
+
+/// Get the global init barrier
+pub fn init_barrier() -> &'static InitBarrier {
+    &INIT_BARRIER
+}
+
+/// Signal on the global init barrier that the given service has completed registration
+pub fn service_ready(service: ServiceId) -> Result<(), InvalidServiceId> {
+    INIT_BARRIER.service_ready(service)
+}
+
+/// Wait on the global init barrier until all the given services have signaled ready
+pub async fn await_all_ready(services: &[ServiceId]) -> Result<(), InvalidServiceId> {
+    INIT_BARRIER.await_all_ready(services).await
+}
+
+#[cfg(test)]
+mod test {
+    use embassy_futures::block_on;
+    use embassy_futures::select::{select, Either};
+    use embassy_futures::yield_now;
+
+    use super::*;
+
+    const SERVICES: [ServiceId; 2] = [ServiceId(0), ServiceId(5)];
+
+    /// Returns true if the barrier releases without blocking
+    fn released(barrier: &InitBarrier) -> bool {
+        matches!(
+            block_on(select(barrier.await_all_ready(&SERVICES), yield_now())),
+            Either::First(Ok(()))
+        )
+    }
+
+    #[test]
+    fn test_init_barrier() {
+        let barrier = InitBarrier::new();
+        assert!(!released(&barrier));
+
+        barrier.service_ready(SERVICES[1]).unwrap();
+        assert!(!released(&barrier));
+
+        // Unrelated service doesn't release the barrier
+        barrier.service_ready(ServiceId(3)).unwrap();
+        assert!(!released(&barrier));
+
+        barrier.service_ready(SERVICES[0]).unwrap();
+        assert!(released(&barrier));
+    }
+
+    #[test]
+    fn test_invalid_service_id() {
+        let barrier = InitBarrier::new();
+        let invalid = ServiceId(MAX_SERVICES);
+
+        // Would alias service 0 if it wasn't rejected
+        assert_eq!(barrier.service_ready(invalid), Err(InvalidServiceId(invalid)));
+        assert_eq!(barrier.all_ready(&[SERVICES[0]]), Ok(false));
+        assert_eq!(
+            block_on(barrier.await_all_ready(&[SERVICES[0], invalid])),
+            Err(InvalidServiceId(invalid))
+        );
+
+        barrier.service_ready(ServiceId(MAX_SERVICES - 1)).unwrap();
+        assert_eq!(barrier.all_ready(&[ServiceId(MAX_SERVICES - 1)]), Ok(true));
+    }
+}
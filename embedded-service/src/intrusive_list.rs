@@ -1,4 +1,4 @@
-//! A static lifetime'd intrusive linked list, construction only (never remove/delete)
+//! A static lifetime'd intrusive linked list, nodes may be unlinked and pushed again later
 
 // Any type used for dynamic type coercion
 pub use core::any::Any;
@@ -6,9 +6,16 @@ pub use core::cell::Cell;
 
 /// Interface error class information
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     /// cannot push a node to any list if it's already in one
     NodeAlreadyInList,
+
+    /// cannot push a node to a list that has reached its maximum length
+    ListFull,
+
+    /// cannot unlink a node from a list it isn't in
+    NodeNotInList,
 }
 
 /// override Result type for shorthand -> Result<T>
@@ -28,6 +35,8 @@ pub struct IntrusiveNode {
 }
 
 /// node type for list allocation. Embed this in the "list wrapper" object, and init with Node::uninit()
+// transparent so a list entry can be converted back to its containing Node when unlinking
+#[repr(transparent)]
 pub struct Node {
     inner: Cell<IntrusiveNode>,
 }
@@ -62,6 +71,9 @@ pub trait NodeContainer: Any {
 pub struct IntrusiveList {
     /// traditional head pointer on list. Static reference type is used to ensure static allocations (for safety)
     head: Cell<Option<&'static IntrusiveNode>>,
+
+    /// optional maximum number of nodes, used as a safety valve against runaway registration
+    max_len: Cell<Option<usize>>,
 }
 
 impl IntrusiveNode {
@@ -94,7 +106,39 @@ impl Default for IntrusiveList {
 impl IntrusiveList {
     /// construct an empty intrusive list
     pub fn new() -> IntrusiveList {
-        IntrusiveList { head: Cell::new(None) }
+        IntrusiveList {
+            head: Cell::new(None),
+            max_len: Cell::new(None),
+        }
+    }
+
+    /// number of nodes in the list
+    pub fn len(&self) -> usize {
+        // critical section in case of multi-threaded implementation:
+        critical_section::with(|_cs| self.into_iter().count())
+    }
+
+    /// returns true if the list has no nodes
+    pub fn is_empty(&self) -> bool {
+        self.head.get().is_none()
+    }
+
+    /// returns true if the given object's node is in this list, compared by node identity
+    pub fn contains<T: NodeContainer>(&self, object: &'static T) -> bool {
+        let target = object.get_node();
+        if !target.inner.get().valid {
+            return false;
+        }
+
+        let target_ptr = target.inner.as_ptr() as *const IntrusiveNode;
+
+        // critical section in case of multi-threaded implementation:
+        critical_section::with(|_cs| self.into_iter().any(|node| core::ptr::eq(node, target_ptr)))
+    }
+
+    /// limit the number of nodes that can be pushed to this list, None removes the limit
+    pub fn set_max_len(&self, max_len: Option<usize>) {
+        self.max_len.set(max_len);
     }
 
     /// only allow pushing to the head of the list
@@ -118,6 +162,12 @@ impl IntrusiveList {
             return Err(Error::NodeAlreadyInList);
         }
 
+        if let Some(max_len) = self.max_len.get() {
+            if self.len() >= max_len {
+                return Err(Error::ListFull);
+            }
+        }
+
         // since this API is private to this module, this is the only place where
         // a node can be marked as valid.
         let node = IntrusiveNode::new(object);
@@ -130,6 +180,47 @@ impl IntrusiveList {
         Ok(())
     }
 
+    /// remove a node from the list, after which it can be pushed to any list again
+    pub fn unlink<T: NodeContainer>(&self, object: &'static T) -> Result<()> {
+        let target = object.get_node();
+        if !target.inner.get().valid {
+            return Err(Error::NodeNotInList);
+        }
+
+        let target_ptr = target.inner.as_ptr() as *const IntrusiveNode;
+
+        // critical section in case of multi-threaded implementation:
+        critical_section::with(|_cs| {
+            let mut prev: Option<&'static IntrusiveNode> = None;
+            for node in self {
+                if !core::ptr::eq(node, target_ptr) {
+                    prev = Some(node);
+                    continue;
+                }
+
+                match prev {
+                    // removing the head, just advance it
+                    None => self.head.set(node.next),
+                    Some(prev) => {
+                        // SAFETY: every node in the list was pushed from a static Node, which is transparent over
+                        // Cell<IntrusiveNode>, so the list entry can be updated through its owning Cell
+                        let prev = unsafe { &*(prev as *const IntrusiveNode as *const Node) };
+                        let mut updated = prev.inner.get();
+                        updated.next = node.next;
+                        prev.inner.set(updated);
+                    }
+                }
+
+                // clear the valid flag so the node can be pushed again
+                target.inner.set(Node::EMPTY);
+                return Ok(());
+            }
+
+            // valid but in a different list
+            Err(Error::NodeNotInList)
+        })
+    }
+
     /// Iterate over the list as if it were items of type `T`, skipping any nodes that are of a different type.
     pub fn iter_only<T: NodeContainer>(&self) -> OnlyT<T> {
         OnlyT::new(self.into_iter())
@@ -405,10 +496,139 @@ mod test {
         assert!(list2.push(empty_node_unpushable).is_err());
     }
 
+    #[test]
+    fn test_max_len() {
+        let list = IntrusiveList::new();
+        list.set_max_len(Some(2));
+        static EL: [OnceLock<RegistrationA>; 3] = [const { OnceLock::new() }; 3];
+        let els: [&'static RegistrationA; 3] = core::array::from_fn(|i| EL[i].get_or_init(RegistrationA::new));
+
+        assert!(list.push(els[0]).is_ok());
+        assert!(list.push(els[1]).is_ok());
+        assert!(matches!(list.push(els[2]), Err(Error::ListFull)));
+        assert_eq!(2, list.into_iter().count());
+
+        // removing the limit allows further pushes
+        list.set_max_len(None);
+        assert!(list.push(els[2]).is_ok());
+        assert_eq!(3, list.into_iter().count());
+    }
+
+    #[test]
+    fn test_unlink() {
+        static EL: [OnceLock<RegistrationA>; 5] = [const { OnceLock::new() }; 5];
+        let els: [&'static RegistrationA; 5] = core::array::from_fn(|i| EL[i].get_or_init(RegistrationA::new));
+        let list = IntrusiveList::new();
+
+        // push pushes to the front, so the list is [4, 3, 2, 1, 0]
+        for el in &els {
+            assert!(list.push(*el).is_ok());
+        }
+
+        let contents = |list: &IntrusiveList| -> [Option<*const RegistrationA>; 5] {
+            let mut iter = list.iter_only::<RegistrationA>().map(|el| el as *const RegistrationA);
+            core::array::from_fn(|_| iter.next())
+        };
+
+        // head
+        assert!(list.unlink(els[4]).is_ok());
+        assert_eq!(
+            contents(&list),
+            [
+                Some(els[3] as *const _),
+                Some(els[2] as *const _),
+                Some(els[1] as *const _),
+                Some(els[0] as *const _),
+                None
+            ]
+        );
+
+        // middle
+        assert!(list.unlink(els[2]).is_ok());
+        assert_eq!(
+            contents(&list),
+            [
+                Some(els[3] as *const _),
+                Some(els[1] as *const _),
+                Some(els[0] as *const _),
+                None,
+                None
+            ]
+        );
+
+        // tail
+        assert!(list.unlink(els[0]).is_ok());
+        assert_eq!(
+            contents(&list),
+            [Some(els[3] as *const _), Some(els[1] as *const _), None, None, None]
+        );
+
+        // not in the list
+        assert!(matches!(list.unlink(els[0]), Err(Error::NodeNotInList)));
+        let list2 = IntrusiveList::new();
+        assert!(matches!(list2.unlink(els[1]), Err(Error::NodeNotInList)));
+        assert_eq!(2, list.into_iter().count());
+
+        // unlinked nodes can be pushed again
+        assert!(list2.push(els[0]).is_ok());
+        assert!(list.push(els[4]).is_ok());
+        assert_eq!(
+            contents(&list),
+            [
+                Some(els[4] as *const _),
+                Some(els[3] as *const _),
+                Some(els[1] as *const _),
+                None,
+                None
+            ]
+        );
+        assert_eq!(1, list2.into_iter().count());
+    }
+
+    #[test]
+    fn test_contains() {
+        static EL: [OnceLock<RegistrationA>; 3] = [const { OnceLock::new() }; 3];
+        let els: [&'static RegistrationA; 3] = core::array::from_fn(|i| EL[i].get_or_init(RegistrationA::new));
+        let list = IntrusiveList::new();
+        let list2 = IntrusiveList::new();
+
+        assert!(list.push(els[0]).is_ok());
+        assert!(list.push(els[1]).is_ok());
+        assert!(list2.push(els[2]).is_ok());
+
+        assert!(list.contains(els[0]));
+        assert!(list.contains(els[1]));
+        // pushed, but to a different list
+        assert!(!list.contains(els[2]));
+        assert!(list2.contains(els[2]));
+
+        // fresh node
+        static FRESH: OnceLock<RegistrationA> = OnceLock::new();
+        let fresh = FRESH.get_or_init(RegistrationA::new);
+        assert!(!list.contains(fresh));
+        assert!(!list2.contains(fresh));
+    }
+
     #[test]
     fn test_empty_list() {
         let list = IntrusiveList::new();
         assert_eq!(0, list.into_iter().count());
+        assert_eq!(0, list.len());
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_len() {
+        static EL: [OnceLock<RegistrationA>; 4] = [const { OnceLock::new() }; 4];
+        let list = IntrusiveList::new();
+
+        for (i, el) in EL.iter().enumerate() {
+            assert!(list.push(el.get_or_init(RegistrationA::new)).is_ok());
+            assert_eq!(i + 1, list.len());
+            assert!(!list.is_empty());
+        }
+
+        assert_eq!(EL.len(), list.len());
     }
 
     #[test]
@@ -12,14 +12,18 @@ pub mod buffer;
 pub mod cfu;
 pub mod comms;
 pub mod ec_type;
+pub mod error;
 pub mod fmt;
 pub mod hid;
 pub mod init;
 pub mod ipc;
 pub mod keyboard;
 pub mod power;
+pub mod time;
 pub mod type_c;
 
+pub use error::ServiceError;
+
 /// initialize all service static interfaces as required. Ideally, this is done before subsystem initialization
 pub async fn init() {
     comms::init();
@@ -55,11 +55,11 @@ impl<'a, S: Kind> Device<'a, S> {
     }
 
     /// Disconnect this device
-    async fn disconnect_internal(&self) -> Result<(), Error> {
-        info!("Device {} disconnecting", self.device.id().0);
+    async fn disconnect_internal(&self, reason: policy::DisconnectReason) -> Result<(), Error> {
+        info!("Device {} disconnecting: {:?}", self.device.id().0, reason);
         self.device.set_state(device::State::Idle).await;
         self.device.exit_recovery().await;
-        policy::send_request(self.device.id(), policy::RequestData::NotifyDisconnect)
+        policy::send_request(self.device.id(), policy::RequestData::NotifyDisconnect(reason))
             .await?
             .complete_or_err()
     }
@@ -128,7 +128,12 @@ impl Device<'_, Idle> {
 impl<'a> Device<'a, ConnectedConsumer> {
     /// Disconnect this device
     pub async fn disconnect(self) -> Result<Device<'a, Idle>, Error> {
-        self.disconnect_internal().await?;
+        self.disconnect_with_reason(policy::DisconnectReason::Unspecified).await
+    }
+
+    /// Disconnect this device, giving the power policy a reason
+    pub async fn disconnect_with_reason(self, reason: policy::DisconnectReason) -> Result<Device<'a, Idle>, Error> {
+        self.disconnect_internal(reason).await?;
         Ok(Device::new(self.device))
     }
 
@@ -141,7 +146,12 @@ impl<'a> Device<'a, ConnectedConsumer> {
 impl<'a> Device<'a, ConnectedProvider> {
     /// Disconnect this device
     pub async fn disconnect(self) -> Result<Device<'a, Idle>, Error> {
-        self.disconnect_internal().await?;
+        self.disconnect_with_reason(policy::DisconnectReason::Unspecified).await
+    }
+
+    /// Disconnect this device, giving the power policy a reason
+    pub async fn disconnect_with_reason(self, reason: policy::DisconnectReason) -> Result<Device<'a, Idle>, Error> {
+        self.disconnect_internal(reason).await?;
         Ok(Device::new(self.device))
     }
 
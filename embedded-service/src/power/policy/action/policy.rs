@@ -141,6 +141,32 @@ impl<'a> Policy<'a, Idle> {
 }
 
 impl<'a> Policy<'a, ConnectedConsumer> {
+    /// Renegotiate the consumer contract on this device
+    pub async fn connect_consumer_no_timeout(
+        &self,
+        capability: PowerCapability,
+    ) -> Result<Policy<'a, ConnectedConsumer>, Error> {
+        info!("Device {} renegotiating consumer", self.device.id().0);
+
+        self.device
+            .execute_device_command(device::CommandData::ConnectConsumer(capability))
+            .await?
+            .complete_or_err()?;
+
+        self.device
+            .set_state(device::State::ConnectedConsumer(capability))
+            .await;
+        Ok(Policy::new(self.device))
+    }
+
+    /// Renegotiate the consumer contract on this device
+    pub async fn connect_consumer(&self, capability: PowerCapability) -> Result<Policy<'a, ConnectedConsumer>, Error> {
+        match with_timeout(DEFAULT_TIMEOUT, self.connect_consumer_no_timeout(capability)).await {
+            Ok(r) => r,
+            Err(TimeoutError) => Err(Error::Timeout),
+        }
+    }
+
     /// Disconnect this device
     pub async fn disconnect_no_timeout(self) -> Result<Policy<'a, Idle>, Error> {
         self.disconnect_internal_no_timeout()
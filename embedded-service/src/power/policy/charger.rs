@@ -83,6 +83,8 @@ pub enum PolicyEvent {
     InitRequest,
     /// PSU attached and we want to switch to it
     PolicyConfiguration(PowerCapability),
+    /// Request to set the charge current in mA
+    SetChargeCurrent(u16),
 }
 
 /// Data for a device request
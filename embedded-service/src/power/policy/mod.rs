@@ -4,6 +4,8 @@ pub mod charger;
 pub mod device;
 pub mod policy;
 
+use embedded_usb_pd::PdError;
+
 pub use policy::{init, register_device};
 
 /// Error type
@@ -28,6 +30,33 @@ pub enum Error {
     Failed,
 }
 
+impl From<PdError> for Error {
+    fn from(value: PdError) -> Self {
+        #[allow(unreachable_patterns)]
+        match value {
+            PdError::InvalidController | PdError::InvalidPort => Self::InvalidDevice,
+            PdError::InvalidResponse => Self::InvalidResponse,
+            PdError::Timeout => Self::Timeout,
+            PdError::Failed
+            | PdError::InvalidMode
+            | PdError::InvalidParams
+            | PdError::Rejected
+            | PdError::UnrecognizedCommand => Self::Failed,
+            // Any other PD error is a generic failure as far as power policy is concerned
+            _ => Self::Failed,
+        }
+    }
+}
+
+impl<BE> From<embedded_usb_pd::Error<BE>> for Error {
+    fn from(value: embedded_usb_pd::Error<BE>) -> Self {
+        match value {
+            embedded_usb_pd::Error::Bus(_) => Self::Bus,
+            embedded_usb_pd::Error::Pd(e) => e.into(),
+        }
+    }
+}
+
 /// Device ID new type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -70,6 +99,14 @@ pub enum CommsData {
     ConsumerDisconnected(DeviceId),
     /// Consumer connected
     ConsumerConnected(DeviceId, PowerCapability),
+    /// No consumer or provider is connected
+    Idle,
+    /// A consumer or provider is connected after being idle
+    Active,
+    /// Provider contract established
+    ProviderEnabled(DeviceId, PowerCapability),
+    /// Provider contract torn down
+    ProviderDisabled(DeviceId),
 }
 
 /// Message to send with the comms service
@@ -77,3 +114,30 @@ pub struct CommsMessage {
     /// Message data
     pub data: CommsData,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pd_error_mapping() {
+        let mapping = [
+            (PdError::InvalidController, Error::InvalidDevice),
+            (PdError::InvalidPort, Error::InvalidDevice),
+            (PdError::InvalidResponse, Error::InvalidResponse),
+            (PdError::Timeout, Error::Timeout),
+            (PdError::Failed, Error::Failed),
+            (PdError::InvalidMode, Error::Failed),
+            (PdError::InvalidParams, Error::Failed),
+            (PdError::Rejected, Error::Failed),
+            (PdError::UnrecognizedCommand, Error::Failed),
+        ];
+
+        for (pd_error, expected) in mapping {
+            assert_eq!(Error::from(pd_error), expected);
+            assert_eq!(Error::from(embedded_usb_pd::Error::<()>::Pd(pd_error)), expected);
+        }
+
+        assert_eq!(Error::from(embedded_usb_pd::Error::Bus(())), Error::Bus);
+    }
+}
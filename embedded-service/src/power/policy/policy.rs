@@ -14,6 +14,21 @@ use crate::{error, intrusive_list};
 /// Number of slots for policy requests
 const POLICY_CHANNEL_SIZE: usize = 1;
 
+/// Reason a device stopped consuming or providing power
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DisconnectReason {
+    /// No reason given
+    #[default]
+    Unspecified,
+    /// Partner was unplugged
+    Unplug,
+    /// A fault was detected on the device
+    Fault,
+    /// Power role swap
+    RoleSwap,
+}
+
 /// Data for a power policy request
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -25,7 +40,7 @@ pub enum RequestData {
     /// Request the given amount of power to provider
     RequestProviderCapability(PowerCapability),
     /// Notify that a device cannot consume or provide power anymore
-    NotifyDisconnect,
+    NotifyDisconnect(DisconnectReason),
     /// Notify that a device has detached
     NotifyDetached,
 }
@@ -100,6 +115,11 @@ pub fn init() {
     CONTEXT.get_or_init(Context::new);
 }
 
+/// Limit the number of power devices that can be registered, None removes the limit
+pub async fn set_max_devices(max: Option<usize>) {
+    CONTEXT.get().await.devices.set_max_len(max);
+}
+
 /// Register a device with the power policy service
 pub async fn register_device(device: &'static impl device::DeviceContainer) -> Result<(), intrusive_list::Error> {
     let device = device.get_power_policy_device();
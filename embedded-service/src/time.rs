@@ -0,0 +1,98 @@
+//! Time source abstraction
+
+use core::cell::Cell;
+
+use embassy_time::{Duration, Instant};
+
+/// Source of the current monotonic time
+pub trait TimeSource {
+    /// Returns the current time
+    fn now(&self) -> Instant;
+
+    /// Returns the time elapsed since `timestamp`, saturating at zero
+    fn elapsed_since(&self, timestamp: Instant) -> Duration {
+        self.now().saturating_duration_since(timestamp)
+    }
+
+    /// Returns true if `timestamp` is older than `max_age`
+    fn is_stale(&self, timestamp: Instant, max_age: Duration) -> bool {
+        self.elapsed_since(timestamp) > max_age
+    }
+}
+
+/// Time source backed by the embassy time driver
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemTime;
+
+impl TimeSource for SystemTime {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Manually driven time source for deterministic tests
+pub struct MockTime {
+    now: Cell<Instant>,
+}
+
+impl MockTime {
+    /// Create a new mock time source starting at `start`
+    pub const fn new(start: Instant) -> Self {
+        Self { now: Cell::new(start) }
+    }
+
+    /// Set the current time
+    pub fn set(&self, now: Instant) {
+        self.now.set(now);
+    }
+
+    /// Advance the current time by `duration`
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+impl Default for MockTime {
+    fn default() -> Self {
+        Self::new(Instant::from_ticks(0))
+    }
+}
+
+impl TimeSource for MockTime {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+}
+
+impl<T: TimeSource + ?Sized> TimeSource for &T {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mock_staleness() {
+        let time = MockTime::default();
+        let timestamp = time.now();
+        let max_age = Duration::from_millis(100);
+
+        assert!(!time.is_stale(timestamp, max_age));
+
+        time.advance(Duration::from_millis(100));
+        assert_eq!(time.elapsed_since(timestamp), max_age);
+        assert!(!time.is_stale(timestamp, max_age));
+
+        time.advance(Duration::from_millis(1));
+        assert!(time.is_stale(timestamp, max_age));
+
+        // Timestamps from the future are never stale
+        time.set(Instant::from_ticks(0));
+        let future = Instant::from_millis(500);
+        assert_eq!(time.elapsed_since(future), Duration::from_ticks(0));
+        assert!(!time.is_stale(future, max_age));
+    }
+}
@@ -1,11 +1,13 @@
 //! PD controller related code
+use core::cell::Cell;
 use core::future::Future;
 use core::sync::atomic::{AtomicBool, Ordering};
 
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::once_lock::OnceLock;
 use embassy_sync::signal::Signal;
-use embassy_time::{with_timeout, Duration};
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use embedded_usb_pd::pdo::{sink, source};
 use embedded_usb_pd::ucsi::lpm;
 use embedded_usb_pd::{
     pdinfo::{AltMode, PowerPathStatus},
@@ -30,8 +32,74 @@ pub enum Contract {
     Source(policy::PowerCapability),
 }
 
-/// Port status
+impl Contract {
+    /// Summarize the contract for display
+    pub fn summary(&self) -> ContractSummary {
+        let (role, capability) = match self {
+            Contract::Sink(capability) => (PowerRole::Sink, capability),
+            Contract::Source(capability) => (PowerRole::Source, capability),
+        };
+
+        ContractSummary {
+            role,
+            voltage_mv: capability.voltage_mv,
+            current_ma: capability.current_ma,
+            power_mw: capability.max_power_mw(),
+        }
+    }
+}
+
+/// Human-readable summary of a power contract
 #[derive(Copy, Clone, Debug)]
+pub struct ContractSummary {
+    /// Our power role in the contract
+    pub role: PowerRole,
+    /// Contract voltage in mV
+    pub voltage_mv: u16,
+    /// Contract current in mA
+    pub current_ma: u16,
+    /// Contract power in mW
+    pub power_mw: u32,
+}
+
+impl ContractSummary {
+    fn role_str(&self) -> &'static str {
+        match self.role {
+            PowerRole::Sink => "Sink",
+            PowerRole::Source => "Source",
+        }
+    }
+}
+
+impl core::fmt::Display for ContractSummary {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} {}mV {}mA {}mW",
+            self.role_str(),
+            self.voltage_mv,
+            self.current_ma,
+            self.power_mw
+        )
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ContractSummary {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(
+            fmt,
+            "{} {}mV {}mA {}mW",
+            self.role_str(),
+            self.voltage_mv,
+            self.current_ma,
+            self.power_mw
+        )
+    }
+}
+
+/// Port status
+#[derive(Copy, Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct PortStatus {
     /// Current available source contract
@@ -46,6 +114,8 @@ pub struct PortStatus {
     pub alt_mode: AltMode,
     /// Power path status
     pub power_path: PowerPathStatus,
+    /// Sink path FET is enabled, reported regardless of connection state
+    pub sink_path_enabled: bool,
 }
 
 impl PortStatus {
@@ -59,6 +129,7 @@ impl PortStatus {
             dual_power: false,
             alt_mode: AltMode::none(),
             power_path: PowerPathStatus::none(),
+            sink_path_enabled: false,
         }
     }
 
@@ -76,6 +147,59 @@ impl PortStatus {
     pub fn is_debug_accessory(&self) -> bool {
         matches!(self.connection_state, Some(ConnectionState::DebugAccessory))
     }
+
+    /// Returns true if the source power path is enabled
+    pub fn is_sourcing(&self) -> bool {
+        self.power_path.source_path
+    }
+
+    /// Returns true if the sink power path is enabled
+    pub fn is_sinking(&self) -> bool {
+        self.power_path.sink_path
+    }
+
+    /// Returns true if a contract present in `previous` has gone away while the port is still connected
+    pub fn contract_lost(&self, previous: &PortStatus) -> bool {
+        if !self.is_connected() || !previous.is_connected() {
+            return false;
+        }
+
+        (previous.available_sink_contract.is_some() && self.available_sink_contract.is_none())
+            || (previous.available_source_contract.is_some() && self.available_source_contract.is_none())
+    }
+
+    /// Returns the negotiated contract, if any
+    pub fn contract(&self) -> Option<Contract> {
+        if !self.is_connected() {
+            return None;
+        }
+
+        self.available_source_contract
+            .map(Contract::Source)
+            .or(self.available_sink_contract.map(Contract::Sink))
+    }
+
+    /// Returns our power role and the contract power in mW, if there is a contract
+    pub fn power(&self) -> Option<(PowerRole, u32)> {
+        self.contract().map(|contract| {
+            let summary = contract.summary();
+            (summary.role, summary.power_mw)
+        })
+    }
+
+    /// Returns the sink capability to budget for this port
+    /// Falls back to `default` if the port is connected, not sourcing, and has no explicit sink contract
+    pub fn sink_capability_or(&self, default: Option<policy::PowerCapability>) -> Option<policy::PowerCapability> {
+        if self.available_sink_contract.is_some() {
+            return self.available_sink_contract;
+        }
+
+        if self.is_connected() && self.available_source_contract.is_none() {
+            default
+        } else {
+            None
+        }
+    }
 }
 
 impl Default for PortStatus {
@@ -84,6 +208,325 @@ impl Default for PortStatus {
     }
 }
 
+/// Version of the [`PortStatus`] wire format
+pub const PORT_STATUS_VERSION: u8 = 2;
+/// Length of an encoded [`PortStatus`]
+pub const PORT_STATUS_ENCODED_LEN: usize = 12;
+
+const PORT_STATUS_SOURCE_CONTRACT: u8 = 1 << 0;
+const PORT_STATUS_SINK_CONTRACT: u8 = 1 << 1;
+const PORT_STATUS_DUAL_POWER: u8 = 1 << 2;
+const PORT_STATUS_SOURCE_PATH: u8 = 1 << 3;
+const PORT_STATUS_SINK_PATH: u8 = 1 << 4;
+const PORT_STATUS_SINK_PATH_ENABLED: u8 = 1 << 5;
+
+impl PortStatus {
+    /// Serializes the status into the slice for transport to the host, returns the number of bytes written
+    pub fn encode_into_slice(&self, buf: &mut [u8]) -> Result<usize, PdError> {
+        if buf.len() < PORT_STATUS_ENCODED_LEN {
+            return Err(PdError::InvalidParams);
+        }
+
+        let mut flags = 0;
+        for (set, flag) in [
+            (self.available_source_contract.is_some(), PORT_STATUS_SOURCE_CONTRACT),
+            (self.available_sink_contract.is_some(), PORT_STATUS_SINK_CONTRACT),
+            (self.dual_power, PORT_STATUS_DUAL_POWER),
+            (self.power_path.source_path, PORT_STATUS_SOURCE_PATH),
+            (self.power_path.sink_path, PORT_STATUS_SINK_PATH),
+            (self.sink_path_enabled, PORT_STATUS_SINK_PATH_ENABLED),
+        ] {
+            if set {
+                flags |= flag;
+            }
+        }
+
+        let source = self.available_source_contract.unwrap_or(policy::PowerCapability {
+            voltage_mv: 0,
+            current_ma: 0,
+        });
+        let sink = self.available_sink_contract.unwrap_or(policy::PowerCapability {
+            voltage_mv: 0,
+            current_ma: 0,
+        });
+
+        buf[0] = PORT_STATUS_VERSION;
+        buf[1] = flags;
+        buf[2] = match self.connection_state {
+            None => 0,
+            Some(ConnectionState::Attached) => 1,
+            Some(ConnectionState::DebugAccessory) => 2,
+            Some(ConnectionState::AudioAccessory) => 3,
+        };
+        buf[3] = self.alt_mode.into();
+        buf[4..6].copy_from_slice(&source.voltage_mv.to_le_bytes());
+        buf[6..8].copy_from_slice(&source.current_ma.to_le_bytes());
+        buf[8..10].copy_from_slice(&sink.voltage_mv.to_le_bytes());
+        buf[10..12].copy_from_slice(&sink.current_ma.to_le_bytes());
+
+        Ok(PORT_STATUS_ENCODED_LEN)
+    }
+
+    /// Deserializes a status from the slice
+    pub fn decode_from_slice(buf: &[u8]) -> Result<Self, PdError> {
+        if buf.len() < PORT_STATUS_ENCODED_LEN {
+            return Err(PdError::InvalidParams);
+        }
+
+        if buf[0] != PORT_STATUS_VERSION {
+            return Err(PdError::InvalidParams);
+        }
+
+        let flags = buf[1];
+        let capability = |offset: usize| policy::PowerCapability {
+            voltage_mv: u16::from_le_bytes([buf[offset], buf[offset + 1]]),
+            current_ma: u16::from_le_bytes([buf[offset + 2], buf[offset + 3]]),
+        };
+
+        let mut status = Self::new();
+        status.available_source_contract = (flags & PORT_STATUS_SOURCE_CONTRACT != 0).then(|| capability(4));
+        status.available_sink_contract = (flags & PORT_STATUS_SINK_CONTRACT != 0).then(|| capability(8));
+        status.connection_state = match buf[2] {
+            0 => None,
+            1 => Some(ConnectionState::Attached),
+            2 => Some(ConnectionState::DebugAccessory),
+            3 => Some(ConnectionState::AudioAccessory),
+            _ => return Err(PdError::InvalidParams),
+        };
+        status.dual_power = flags & PORT_STATUS_DUAL_POWER != 0;
+        status.alt_mode = AltMode::from(buf[3]);
+        status.power_path.source_path = flags & PORT_STATUS_SOURCE_PATH != 0;
+        status.power_path.sink_path = flags & PORT_STATUS_SINK_PATH != 0;
+        status.sink_path_enabled = flags & PORT_STATUS_SINK_PATH_ENABLED != 0;
+
+        Ok(status)
+    }
+}
+
+/// Maximum number of alternate modes reported for a port
+pub const MAX_ALT_MODES: usize = 8;
+
+/// Alternate mode supported by a port
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AltModeInfo {
+    /// Standard or vendor ID
+    pub svid: u16,
+    /// Mode VDO
+    pub vdo: u32,
+}
+
+/// List of alternate modes supported by a port
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AlternateModes {
+    modes: [AltModeInfo; MAX_ALT_MODES],
+    num_modes: usize,
+}
+
+impl AlternateModes {
+    /// Create an empty list of alternate modes
+    pub const fn new() -> Self {
+        Self {
+            modes: [AltModeInfo { svid: 0, vdo: 0 }; MAX_ALT_MODES],
+            num_modes: 0,
+        }
+    }
+
+    /// Add a mode to the list
+    pub fn push(&mut self, mode: AltModeInfo) -> Result<(), PdError> {
+        if self.num_modes >= MAX_ALT_MODES {
+            return Err(PdError::InvalidParams);
+        }
+
+        self.modes[self.num_modes] = mode;
+        self.num_modes += 1;
+        Ok(())
+    }
+
+    /// Returns the supported modes, the index of each mode is its connector alternate mode (CAM) index
+    pub fn as_slice(&self) -> &[AltModeInfo] {
+        &self.modes[..self.num_modes]
+    }
+}
+
+impl Default for AlternateModes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Maximum number of PDOs in a capabilities message
+pub const MAX_PDOS: usize = 7;
+
+/// Raw PDOs advertised by a port or its partner
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PdoList {
+    pdos: [u32; MAX_PDOS],
+    num_pdos: usize,
+}
+
+impl PdoList {
+    /// Create an empty list of PDOs
+    pub const fn new() -> Self {
+        Self {
+            pdos: [0; MAX_PDOS],
+            num_pdos: 0,
+        }
+    }
+
+    /// Add a raw PDO to the list
+    pub fn push(&mut self, pdo: u32) -> Result<(), PdError> {
+        if self.num_pdos >= MAX_PDOS {
+            return Err(PdError::InvalidParams);
+        }
+
+        self.pdos[self.num_pdos] = pdo;
+        self.num_pdos += 1;
+        Ok(())
+    }
+
+    /// Returns the raw PDOs in advertised order
+    pub fn as_slice(&self) -> &[u32] {
+        &self.pdos[..self.num_pdos]
+    }
+}
+
+impl Default for PdoList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decoded PDO
+#[derive(Copy, Clone, Debug)]
+pub enum Pdo {
+    /// Source PDO
+    Source(source::Pdo),
+    /// Sink PDO
+    Sink(sink::Pdo),
+}
+
+impl From<Pdo> for policy::PowerCapability {
+    fn from(pdo: Pdo) -> Self {
+        match pdo {
+            Pdo::Source(pdo) => pdo.into(),
+            Pdo::Sink(pdo) => pdo.into(),
+        }
+    }
+}
+
+/// USB data role
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataRole {
+    /// Downstream facing port
+    Dfp,
+    /// Upstream facing port
+    Ufp,
+}
+
+/// PD message types that can be retrieved with UCSI GET_PD_MESSAGE
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PdMessageType {
+    /// Sink_Capabilities_Extended
+    SinkCapabilitiesExtended,
+    /// Source_Capabilities_Extended
+    SourceCapabilitiesExtended,
+    /// Battery_Capabilities
+    BatteryCapabilities,
+    /// Battery_Status
+    BatteryStatus,
+    /// Discover Identity response
+    DiscoverIdentity,
+    /// Revision
+    Revision,
+}
+
+/// Maximum length of a PD message, header plus seven data objects
+pub const MAX_PD_MESSAGE_LEN: usize = 30;
+
+/// Decoded PD message header
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PdMessageHeader {
+    /// Message type, interpretation depends on `num_data_objects` and `extended`
+    pub message_type: u8,
+    /// Port data role of the sender
+    pub data_role: DataRole,
+    /// Specification revision, 0 is revision 1.0
+    pub spec_revision: u8,
+    /// Port power role of the sender
+    pub power_role: PowerRole,
+    /// Rolling message ID
+    pub message_id: u8,
+    /// Number of 32-bit data objects following the header
+    pub num_data_objects: u8,
+    /// True if this is an extended message
+    pub extended: bool,
+}
+
+impl PdMessageHeader {
+    /// Decode a raw SOP message header
+    pub fn decode(raw: u16) -> Self {
+        Self {
+            message_type: (raw & 0x1f) as u8,
+            data_role: if raw & (1 << 5) != 0 {
+                DataRole::Dfp
+            } else {
+                DataRole::Ufp
+            },
+            spec_revision: ((raw >> 6) & 0x3) as u8,
+            power_role: if raw & (1 << 8) != 0 {
+                PowerRole::Source
+            } else {
+                PowerRole::Sink
+            },
+            message_id: ((raw >> 9) & 0x7) as u8,
+            num_data_objects: ((raw >> 12) & 0x7) as u8,
+            extended: raw & (1 << 15) != 0,
+        }
+    }
+}
+
+/// Raw PD message
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PdMessage {
+    data: [u8; MAX_PD_MESSAGE_LEN],
+    len: usize,
+}
+
+impl PdMessage {
+    /// Create a message from raw bytes, the first two bytes are the little-endian header
+    pub fn new(raw: &[u8]) -> Result<Self, PdError> {
+        if raw.len() < 2 || raw.len() > MAX_PD_MESSAGE_LEN {
+            return Err(PdError::InvalidParams);
+        }
+
+        let mut data = [0; MAX_PD_MESSAGE_LEN];
+        data[..raw.len()].copy_from_slice(raw);
+        Ok(Self { data, len: raw.len() })
+    }
+
+    /// Raw message bytes, including the header
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// Decoded message header
+    pub fn header(&self) -> PdMessageHeader {
+        PdMessageHeader::decode(u16::from_le_bytes([self.data[0], self.data[1]]))
+    }
+
+    /// Message payload following the header
+    pub fn payload(&self) -> &[u8] {
+        &self.data[2..self.len]
+    }
+}
+
 /// Port-specific command data
 #[derive(Copy, Clone, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -92,6 +535,35 @@ pub enum PortCommandData {
     PortStatus,
     /// Get and clear events
     ClearEvents,
+    /// Get supported alternate modes
+    AlternateModes,
+    /// Get the index of the current connector alternate mode
+    CurrentCam,
+    /// Enter or exit the given connector alternate mode
+    SetNewCam {
+        /// Index into the supported alternate modes
+        mode_index: u8,
+        /// True to enter the mode, false to exit
+        enter: bool,
+    },
+    /// Initiate a data-role swap to the given role
+    DataRoleSwap(DataRole),
+    /// Enter the alternate mode with the given SVID
+    EnterAltMode(u16),
+    /// Exit the alternate mode with the given SVID
+    ExitAltMode(u16),
+    /// Get the most recent PD message of the given type
+    GetPdMessage {
+        /// Type of message to retrieve
+        message_type: PdMessageType,
+    },
+    /// Get advertised PDOs
+    GetPdos {
+        /// True for the port partner's PDOs, false for the port's own
+        partner: bool,
+        /// True for source PDOs, false for sink PDOs
+        source: bool,
+    },
 }
 
 /// Port-specific commands
@@ -114,6 +586,14 @@ pub enum PortResponseData {
     PortStatus(PortStatus),
     /// ClearEvents
     ClearEvents(PortEventKind),
+    /// Supported alternate modes
+    AlternateModes(AlternateModes),
+    /// Index of the current connector alternate mode, if any
+    CurrentCam(Option<u8>),
+    /// Raw PD message
+    PdMessage(PdMessage),
+    /// Raw PDOs
+    Pdos(PdoList),
 }
 
 impl PortResponseData {
@@ -137,6 +617,57 @@ pub enum InternalCommandData {
     Reset,
     /// Get controller status
     Status,
+    /// Sync controller state
+    SyncState,
+}
+
+/// Command categories, each with their own default timeout
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CommandKind {
+    /// Status reads, expected to complete quickly
+    Status,
+    /// Resets and other long-running state transitions
+    Reset,
+    /// All other commands
+    Other,
+}
+
+impl InternalCommandData {
+    /// Get the kind of this command
+    pub fn kind(&self) -> CommandKind {
+        match self {
+            InternalCommandData::Status => CommandKind::Status,
+            InternalCommandData::Reset | InternalCommandData::SyncState => CommandKind::Reset,
+        }
+    }
+}
+
+impl PortCommandData {
+    /// Get the kind of this command
+    pub fn kind(&self) -> CommandKind {
+        match self {
+            PortCommandData::PortStatus
+            | PortCommandData::ClearEvents
+            | PortCommandData::AlternateModes
+            | PortCommandData::CurrentCam
+            | PortCommandData::GetPdMessage { .. }
+            | PortCommandData::GetPdos { .. } => CommandKind::Status,
+            PortCommandData::SetNewCam { .. }
+            | PortCommandData::DataRoleSwap(_)
+            | PortCommandData::EnterAltMode(_)
+            | PortCommandData::ExitAltMode(_) => CommandKind::Other,
+        }
+    }
+}
+
+/// Get the kind of a UCSI command
+fn lpm_command_kind(command: &lpm::CommandData) -> CommandKind {
+    match command {
+        lpm::CommandData::ConnectorReset(_) => CommandKind::Reset,
+        #[allow(unreachable_patterns)]
+        _ => CommandKind::Other,
+    }
 }
 
 /// PD controller command
@@ -188,6 +719,63 @@ pub struct ControllerStatus<'a> {
     pub fw_version0: u32,
     /// FW version 1
     pub fw_version1: u32,
+    /// Controller is running its application FW and can accept commands
+    pub ready: bool,
+}
+
+/// Self-test result for a single port
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PortSelfTest {
+    /// Port ID
+    pub port: GlobalPortId,
+    /// Result of reading the port status
+    pub status: Result<(), PdError>,
+}
+
+impl PortSelfTest {
+    /// Returns true if all checks passed
+    pub fn passed(&self) -> bool {
+        self.status.is_ok()
+    }
+}
+
+/// Self-test result for a single controller, records up to `P` ports
+#[derive(Clone, Debug)]
+pub struct ControllerSelfTest<const P: usize> {
+    /// Controller ID
+    pub id: ControllerId,
+    /// Result of reading the controller status
+    pub status: Result<(), PdError>,
+    /// True if the controller booted from a valid FW bank, false if the status could not be read
+    pub valid_fw_bank: bool,
+    /// Per-port results
+    pub ports: heapless::Vec<PortSelfTest, P>,
+    /// True if the controller has more ports than could be recorded
+    pub ports_truncated: bool,
+}
+
+impl<const P: usize> ControllerSelfTest<P> {
+    /// Returns true if all checks passed
+    pub fn passed(&self) -> bool {
+        self.status.is_ok() && self.valid_fw_bank && !self.ports_truncated && self.ports.iter().all(|p| p.passed())
+    }
+}
+
+/// Self-test report covering up to `C` controllers with up to `P` ports each
+#[derive(Clone, Debug)]
+pub struct SelfTestReport<const C: usize, const P: usize> {
+    /// Per-controller results
+    pub controllers: heapless::Vec<ControllerSelfTest<P>, C>,
+    /// True if more controllers are registered than could be recorded
+    pub truncated: bool,
+}
+
+impl<const C: usize, const P: usize> SelfTestReport<C, P> {
+    /// Returns true if all checks passed on all controllers
+    pub fn passed(&self) -> bool {
+        !self.truncated && self.controllers.iter().all(|c| c.passed())
+    }
 }
 
 /// PD controller
@@ -336,10 +924,76 @@ pub trait Controller {
         port: LocalPortId,
         role: PowerRole,
     ) -> impl Future<Output = Result<(), Error<Self::BusError>>>;
+    /// Enter the alternate mode with the given SVID
+    /// Returns [`PdError::UnsupportedCommand`] if the mode isn't supported
+    fn enter_alt_mode(
+        &mut self,
+        _port: LocalPortId,
+        _svid: u16,
+    ) -> impl Future<Output = Result<(), Error<Self::BusError>>> {
+        async { PdError::UnsupportedCommand.into() }
+    }
+    /// Exit the alternate mode with the given SVID
+    /// Returns [`PdError::UnsupportedCommand`] if the mode isn't supported
+    fn exit_alt_mode(
+        &mut self,
+        _port: LocalPortId,
+        _svid: u16,
+    ) -> impl Future<Output = Result<(), Error<Self::BusError>>> {
+        async { PdError::UnsupportedCommand.into() }
+    }
+    /// Initiate a data-role swap to the given role
+    fn request_dr_swap(
+        &mut self,
+        _port: LocalPortId,
+        _role: DataRole,
+    ) -> impl Future<Output = Result<(), Error<Self::BusError>>> {
+        async { PdError::UnrecognizedCommand.into() }
+    }
     /// Get current controller status
     fn get_controller_status(
         &mut self,
     ) -> impl Future<Output = Result<ControllerStatus<'static>, Error<Self::BusError>>>;
+    /// Get the alternate modes supported by the port
+    fn get_alternate_modes(
+        &mut self,
+        _port: LocalPortId,
+    ) -> impl Future<Output = Result<AlternateModes, Error<Self::BusError>>> {
+        async { PdError::UnrecognizedCommand.into() }
+    }
+    /// Get the index of the current connector alternate mode, if any
+    fn get_current_cam(
+        &mut self,
+        _port: LocalPortId,
+    ) -> impl Future<Output = Result<Option<u8>, Error<Self::BusError>>> {
+        async { PdError::UnrecognizedCommand.into() }
+    }
+    /// Enter or exit the given connector alternate mode
+    fn set_new_cam(
+        &mut self,
+        _port: LocalPortId,
+        _mode_index: u8,
+        _enter: bool,
+    ) -> impl Future<Output = Result<(), Error<Self::BusError>>> {
+        async { PdError::UnrecognizedCommand.into() }
+    }
+    /// Get the most recent PD message of the given type received on the port
+    fn get_pd_message(
+        &mut self,
+        _port: LocalPortId,
+        _message_type: PdMessageType,
+    ) -> impl Future<Output = Result<PdMessage, Error<Self::BusError>>> {
+        async { PdError::UnrecognizedCommand.into() }
+    }
+    /// Get the raw source or sink PDOs advertised by the port or its partner
+    fn get_pdos(
+        &mut self,
+        _port: LocalPortId,
+        _partner: bool,
+        _source: bool,
+    ) -> impl Future<Output = Result<PdoList, Error<Self::BusError>>> {
+        async { PdError::UnrecognizedCommand.into() }
+    }
 }
 
 /// Internal context for managing PD controllers
@@ -367,6 +1021,11 @@ pub fn init() {
     CONTEXT.get_or_init(Context::new);
 }
 
+/// Limit the number of PD controllers that can be registered, None removes the limit
+pub async fn set_max_controllers(max: Option<usize>) {
+    CONTEXT.get().await.controllers.set_max_len(max);
+}
+
 /// Register a PD controller
 pub async fn register_controller(controller: &'static impl DeviceContainer) -> Result<(), intrusive_list::Error> {
     CONTEXT
@@ -381,8 +1040,7 @@ pub(super) async fn lookup_controller(controller_id: ControllerId) -> Result<&'s
         .get()
         .await
         .controllers
-        .into_iter()
-        .filter_map(|node| node.data::<Device>())
+        .iter_only::<Device>()
         .find(|controller| controller.id == controller_id)
         .ok_or(PdError::InvalidController)
 }
@@ -391,8 +1049,113 @@ pub(super) async fn lookup_controller(controller_id: ControllerId) -> Result<&'s
 /// set to high value since this is intended to prevent an unresponsive device from blocking the service implementation
 const DEFAULT_TIMEOUT: Duration = Duration::from_millis(5000);
 
+/// Default timeout for status commands
+const DEFAULT_STATUS_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Default timeout for resets and other long-running state transitions
+const DEFAULT_RESET_TIMEOUT: Duration = Duration::from_millis(10000);
+
+/// Interval between controller status polls while waiting for a controller to become ready
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Number of ports that can have their status cached, indexed by global port ID
+const PORT_STATUS_CACHE_SIZE: usize = 8;
+
+/// Short-lived cache of port status, invalidated by port events
+struct PortStatusCache {
+    /// Time-to-live for cached entries, caching is disabled if None
+    ttl: Cell<Option<Duration>>,
+    /// Cached status and the time it was retrieved
+    entries: [Cell<Option<(PortStatus, Instant)>>; PORT_STATUS_CACHE_SIZE],
+}
+
+impl PortStatusCache {
+    const fn new() -> Self {
+        Self {
+            ttl: Cell::new(None),
+            entries: [const { Cell::new(None) }; PORT_STATUS_CACHE_SIZE],
+        }
+    }
+
+    /// Set the time-to-live, disabling the cache if None
+    fn set_ttl(&self, ttl: Option<Duration>) {
+        self.ttl.set(ttl);
+        if ttl.is_none() {
+            for entry in &self.entries {
+                entry.set(None);
+            }
+        }
+    }
+
+    /// Returns the cached status for the given port if it hasn't expired
+    fn get(&self, port: GlobalPortId, now: Instant) -> Option<PortStatus> {
+        let ttl = self.ttl.get()?;
+        let (status, timestamp) = self.entries.get(port.0 as usize)?.get()?;
+        if now.checked_duration_since(timestamp)? < ttl {
+            Some(status)
+        } else {
+            None
+        }
+    }
+
+    /// Cache the status for the given port
+    fn insert(&self, port: GlobalPortId, status: PortStatus, now: Instant) {
+        if self.ttl.get().is_none() {
+            return;
+        }
+
+        if let Some(entry) = self.entries.get(port.0 as usize) {
+            entry.set(Some((status, now)));
+        }
+    }
+
+    /// Invalidate the cached status for the given port
+    fn invalidate(&self, port: GlobalPortId) {
+        if let Some(entry) = self.entries.get(port.0 as usize) {
+            entry.set(None);
+        }
+    }
+
+    /// Invalidate the cached status for all ports with pending events
+    fn invalidate_pending(&self, pending: PortEventFlags) {
+        for (i, entry) in self.entries.iter().enumerate() {
+            if pending.is_pending(GlobalPortId(i as u8)) {
+                entry.set(None);
+            }
+        }
+    }
+}
+
+/// Per command kind timeouts
+struct CommandTimeouts {
+    status: Cell<Duration>,
+    reset: Cell<Duration>,
+    other: Cell<Duration>,
+}
+
+impl CommandTimeouts {
+    const fn new() -> Self {
+        Self {
+            status: Cell::new(DEFAULT_STATUS_TIMEOUT),
+            reset: Cell::new(DEFAULT_RESET_TIMEOUT),
+            other: Cell::new(DEFAULT_TIMEOUT),
+        }
+    }
+
+    fn get(&self, kind: CommandKind) -> &Cell<Duration> {
+        match kind {
+            CommandKind::Status => &self.status,
+            CommandKind::Reset => &self.reset,
+            CommandKind::Other => &self.other,
+        }
+    }
+}
+
 /// Type to provide access to the PD controller context for service implementations
-pub struct ContextToken(());
+pub struct ContextToken {
+    port_status_cache: PortStatusCache,
+    timeouts: CommandTimeouts,
+}
 
 impl ContextToken {
     /// Create a new context token, returning None if this function has been called before
@@ -403,7 +1166,29 @@ impl ContextToken {
         }
 
         INIT.store(true, Ordering::SeqCst);
-        Some(ContextToken(()))
+        Some(Self::new())
+    }
+
+    const fn new() -> Self {
+        ContextToken {
+            port_status_cache: PortStatusCache::new(),
+            timeouts: CommandTimeouts::new(),
+        }
+    }
+
+    /// Get the timeout used for the given kind of command
+    pub fn command_timeout(&self, kind: CommandKind) -> Duration {
+        self.timeouts.get(kind).get()
+    }
+
+    /// Override the default timeout for the given kind of command
+    pub fn set_command_timeout(&self, kind: CommandKind, timeout: Duration) {
+        self.timeouts.get(kind).set(timeout);
+    }
+
+    /// Enable caching of port status with the given time-to-live, None disables caching
+    pub fn set_port_status_cache_ttl(&self, ttl: Option<Duration>) {
+        self.port_status_cache.set_ttl(ttl);
     }
 
     /// Send a command to the given controller with no timeout
@@ -447,7 +1232,7 @@ impl ContextToken {
         command: InternalCommandData,
     ) -> Result<InternalResponseData<'static>, PdError> {
         match with_timeout(
-            DEFAULT_TIMEOUT,
+            self.command_timeout(command.kind()),
             self.send_controller_command_no_timeout(controller_id, command),
         )
         .await
@@ -464,6 +1249,38 @@ impl ContextToken {
             .map(|_| ())
     }
 
+    /// Sync the state of the given controller
+    pub async fn sync_controller_state(&self, controller_id: ControllerId) -> Result<(), PdError> {
+        self.send_controller_command(controller_id, InternalCommandData::SyncState)
+            .await
+            .map(|_| ())
+    }
+
+    /// Reset all registered controllers and re-sync their state
+    /// A failure on one controller does not prevent the others from being reset
+    /// Returns the result for each controller, up to `N` results are recorded
+    pub async fn reset_all_controllers<const N: usize>(&self) -> heapless::Vec<(ControllerId, Result<(), PdError>), N> {
+        let mut results = heapless::Vec::new();
+
+        for controller in CONTEXT.get().await.controllers.iter_only::<Device>() {
+            let id = controller.id;
+            let result = match self.reset_controller(id).await {
+                Ok(()) => self.sync_controller_state(id).await,
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = result {
+                error!("Controller{}: Failed to reset: {:?}", id.0, e);
+            }
+
+            if results.push((id, result)).is_err() {
+                error!("Controller{}: No space to record reset result", id.0);
+            }
+        }
+
+        results
+    }
+
     async fn find_node_by_port(&self, port_id: GlobalPortId) -> Result<&IntrusiveNode, PdError> {
         CONTEXT
             .get()
@@ -512,7 +1329,7 @@ impl ContextToken {
         command: lpm::CommandData,
     ) -> Result<lpm::ResponseData, PdError> {
         match with_timeout(
-            DEFAULT_TIMEOUT,
+            self.command_timeout(lpm_command_kind(&command)),
             self.send_port_command_ucsi_no_timeout(port_id, command),
         )
         .await
@@ -563,7 +1380,12 @@ impl ContextToken {
         port_id: GlobalPortId,
         command: PortCommandData,
     ) -> Result<PortResponseData, PdError> {
-        match with_timeout(DEFAULT_TIMEOUT, self.send_port_command_no_timeout(port_id, command)).await {
+        match with_timeout(
+            self.command_timeout(command.kind()),
+            self.send_port_command_no_timeout(port_id, command),
+        )
+        .await
+        {
             Ok(response) => response,
             Err(_) => Err(PdError::Timeout),
         }
@@ -571,13 +1393,20 @@ impl ContextToken {
 
     /// Get the current port events
     pub async fn get_unhandled_events(&self) -> PortEventFlags {
-        CONTEXT.get().await.port_events.wait().await
+        let pending = CONTEXT.get().await.port_events.wait().await;
+        self.port_status_cache.invalidate_pending(pending);
+        pending
     }
 
     /// Get the unhandled events for the given port
     pub async fn get_port_event(&self, port: GlobalPortId) -> Result<PortEventKind, PdError> {
         match self.send_port_command(port, PortCommandData::ClearEvents).await? {
-            PortResponseData::ClearEvents(event) => Ok(event),
+            PortResponseData::ClearEvents(event) => {
+                if event != PortEventKind::none() {
+                    self.port_status_cache.invalidate(port);
+                }
+                Ok(event)
+            }
             r => {
                 error!("Invalid response: expected clear events, got {:?}", r);
                 Err(PdError::InvalidResponse)
@@ -586,9 +1415,29 @@ impl ContextToken {
     }
 
     /// Get the current port status
+    /// Returns cached status if caching is enabled and the cached status has not expired
     pub async fn get_port_status(&self, port: GlobalPortId) -> Result<PortStatus, PdError> {
+        self.get_port_status_with_cache(port, false).await
+    }
+
+    /// Get our power role and the contract power in mW on the given port, `None` if there is no contract
+    pub async fn port_power(&self, port: GlobalPortId) -> Result<Option<(PowerRole, u32)>, PdError> {
+        Ok(self.get_port_status(port).await?.power())
+    }
+
+    /// Get the current port status, `force` bypasses the cache
+    pub async fn get_port_status_with_cache(&self, port: GlobalPortId, force: bool) -> Result<PortStatus, PdError> {
+        if !force {
+            if let Some(status) = self.port_status_cache.get(port, Instant::now()) {
+                return Ok(status);
+            }
+        }
+
         match self.send_port_command(port, PortCommandData::PortStatus).await? {
-            PortResponseData::PortStatus(status) => Ok(status),
+            PortResponseData::PortStatus(status) => {
+                self.port_status_cache.insert(port, status, Instant::now());
+                Ok(status)
+            }
             r => {
                 error!("Invalid response: expected port status, got {:?}", r);
                 Err(PdError::InvalidResponse)
@@ -596,6 +1445,126 @@ impl ContextToken {
         }
     }
 
+    /// Returns true if any port is currently sourcing power
+    pub async fn any_port_sourcing(&self) -> bool {
+        self.any_port(PortStatus::is_sourcing).await
+    }
+
+    /// Returns true if any port is currently sinking power
+    pub async fn any_port_sinking(&self) -> bool {
+        self.any_port(PortStatus::is_sinking).await
+    }
+
+    /// Returns true if the status of any port matches `f`, ports that fail to report status are skipped
+    async fn any_port(&self, f: impl Fn(&PortStatus) -> bool) -> bool {
+        for controller in CONTEXT.get().await.controllers.iter_only::<Device>() {
+            for port in controller.ports {
+                match self.get_port_status(*port).await {
+                    Ok(status) if f(&status) => return true,
+                    Ok(_) => {}
+                    Err(e) => error!("Port{}: Failed to get status: {:?}", port.0, e),
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Get the alternate modes supported by the given port
+    pub async fn get_alternate_modes(&self, port: GlobalPortId) -> Result<AlternateModes, PdError> {
+        match self.send_port_command(port, PortCommandData::AlternateModes).await? {
+            PortResponseData::AlternateModes(modes) => Ok(modes),
+            r => {
+                error!("Invalid response: expected alternate modes, got {:?}", r);
+                Err(PdError::InvalidResponse)
+            }
+        }
+    }
+
+    /// Get the index of the current connector alternate mode of the given port
+    pub async fn get_current_cam(&self, port: GlobalPortId) -> Result<Option<u8>, PdError> {
+        match self.send_port_command(port, PortCommandData::CurrentCam).await? {
+            PortResponseData::CurrentCam(cam) => Ok(cam),
+            r => {
+                error!("Invalid response: expected current CAM, got {:?}", r);
+                Err(PdError::InvalidResponse)
+            }
+        }
+    }
+
+    /// Get the most recent PD message of the given type received on the given port
+    pub async fn get_pd_message(&self, port: GlobalPortId, message_type: PdMessageType) -> Result<PdMessage, PdError> {
+        match self
+            .send_port_command(port, PortCommandData::GetPdMessage { message_type })
+            .await?
+        {
+            PortResponseData::PdMessage(message) => Ok(message),
+            r => {
+                error!("Invalid response: expected PD message, got {:?}", r);
+                Err(PdError::InvalidResponse)
+            }
+        }
+    }
+
+    /// Get the decoded source or sink PDOs advertised by the given port or its partner
+    pub async fn get_pdos(
+        &self,
+        port: GlobalPortId,
+        partner: bool,
+        source: bool,
+    ) -> Result<heapless::Vec<Pdo, MAX_PDOS>, PdError> {
+        let raw = match self
+            .send_port_command(port, PortCommandData::GetPdos { partner, source })
+            .await?
+        {
+            PortResponseData::Pdos(pdos) => pdos,
+            r => {
+                error!("Invalid response: expected PDOs, got {:?}", r);
+                return Err(PdError::InvalidResponse);
+            }
+        };
+
+        let mut pdos = heapless::Vec::new();
+        for pdo in raw.as_slice() {
+            let pdo = if source {
+                Pdo::Source(source::Pdo::try_from(*pdo)?)
+            } else {
+                Pdo::Sink(sink::Pdo::try_from(*pdo)?)
+            };
+            // PdoList holds at most MAX_PDOS entries
+            let _ = pdos.push(pdo);
+        }
+        Ok(pdos)
+    }
+
+    /// Enter or exit the given connector alternate mode on the given port
+    pub async fn set_new_cam(&self, port: GlobalPortId, mode_index: u8, enter: bool) -> Result<(), PdError> {
+        self.send_port_command(port, PortCommandData::SetNewCam { mode_index, enter })
+            .await?
+            .complete_or_err()
+    }
+
+    /// Enter the alternate mode with the given SVID on the given port
+    pub async fn enter_alt_mode(&self, port: GlobalPortId, svid: u16) -> Result<(), PdError> {
+        self.send_port_command(port, PortCommandData::EnterAltMode(svid))
+            .await?
+            .complete_or_err()
+    }
+
+    /// Exit the alternate mode with the given SVID on the given port
+    pub async fn exit_alt_mode(&self, port: GlobalPortId, svid: u16) -> Result<(), PdError> {
+        self.send_port_command(port, PortCommandData::ExitAltMode(svid))
+            .await?
+            .complete_or_err()
+    }
+
+    /// Initiate a data-role swap to the given role on the given port
+    pub async fn request_dr_swap(&self, port: GlobalPortId, role: DataRole) -> Result<(), PdError> {
+        self.send_port_command(port, PortCommandData::DataRoleSwap(role))
+            .await?
+            .complete_or_err()
+    }
+
     /// Get current controller status
     pub async fn get_controller_status(
         &self,
@@ -613,6 +1582,99 @@ impl ContextToken {
         }
     }
 
+    /// Wait until the given controller reports that it is ready or the timeout expires
+    /// Intended to be used after a reset while the controller is still booting
+    pub async fn wait_controller_ready(&self, controller_id: ControllerId, timeout: Duration) -> Result<(), PdError> {
+        with_timeout(timeout, async {
+            loop {
+                match self.get_controller_status(controller_id).await {
+                    Ok(status) if status.ready => return Ok(()),
+                    Ok(status) => trace!("Controller{}: Not ready, mode {}", controller_id.0, status.mode),
+                    Err(PdError::InvalidController) => return Err(PdError::InvalidController),
+                    // Controllers may not respond while booting
+                    Err(e) => trace!("Controller{}: Error getting status {:?}", controller_id.0, e),
+                }
+
+                Timer::after(READY_POLL_INTERVAL).await;
+            }
+        })
+        .await
+        .unwrap_or(Err(PdError::Timeout))
+    }
+
+    /// Run the self-test on the given controller
+    /// Reads the controller status, verifies the FW bank is valid, and reads the status of each port
+    pub async fn self_test_controller<const P: usize>(&self, controller_id: ControllerId) -> ControllerSelfTest<P> {
+        match lookup_controller(controller_id).await {
+            Ok(controller) => self.run_self_test(controller).await,
+            Err(e) => ControllerSelfTest {
+                id: controller_id,
+                status: Err(e),
+                valid_fw_bank: false,
+                ports: heapless::Vec::new(),
+                ports_truncated: false,
+            },
+        }
+    }
+
+    /// Run the self-test on all registered controllers
+    /// A failure on one controller does not prevent the others from being tested
+    pub async fn self_test<const C: usize, const P: usize>(&self) -> SelfTestReport<C, P> {
+        let mut report = SelfTestReport {
+            controllers: heapless::Vec::new(),
+            truncated: false,
+        };
+
+        for controller in CONTEXT.get().await.controllers.iter_only::<Device>() {
+            let result = self.run_self_test(controller).await;
+            if report.controllers.push(result).is_err() {
+                error!("Controller{}: No space to record self-test result", controller.id.0);
+                report.truncated = true;
+            }
+        }
+
+        report
+    }
+
+    async fn run_self_test<const P: usize>(&self, controller: &Device<'_>) -> ControllerSelfTest<P> {
+        let id = controller.id;
+        let mut result = ControllerSelfTest {
+            id,
+            status: Ok(()),
+            valid_fw_bank: false,
+            ports: heapless::Vec::new(),
+            ports_truncated: false,
+        };
+
+        match self.get_controller_status(id).await {
+            Ok(status) => {
+                result.valid_fw_bank = status.valid_fw_bank;
+                if !status.valid_fw_bank {
+                    error!("Controller{}: Self-test booted from backup FW bank", id.0);
+                }
+            }
+            Err(e) => {
+                error!("Controller{}: Self-test failed to get status: {:?}", id.0, e);
+                result.status = Err(e);
+            }
+        }
+
+        for port in controller.ports {
+            // Bypass the cache so the controller is actually exercised
+            let status = self.get_port_status_with_cache(*port, true).await.map(|_| ());
+            if let Err(e) = status {
+                error!("Port{}: Self-test failed to get status: {:?}", port.0, e);
+            }
+
+            if result.ports.push(PortSelfTest { port: *port, status }).is_err() {
+                error!("Controller{}: No space to record port self-test result", id.0);
+                result.ports_truncated = true;
+            }
+        }
+
+        result
+    }
+
     /// Wait for an external command
     pub async fn wait_external_command(
         &self,
@@ -648,3 +1710,690 @@ pub(super) async fn execute_external_controller_command(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use core::cell::RefCell;
+    use std::boxed::Box;
+    use std::sync::{Mutex, MutexGuard};
+    use std::vec::Vec;
+
+    use embassy_futures::block_on;
+    use embassy_futures::select::{select, select3, Either, Either3};
+
+    use super::*;
+    use crate::type_c::{POWER_CAPABILITY_5V_1A5, POWER_CAPABILITY_5V_3A0};
+
+    /// Tests that register controllers share the global controller list, so they run one at a time
+    fn lock_controllers() -> MutexGuard<'static, ()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+        LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[test]
+    fn test_command_kind_timeouts() {
+        const PORT: GlobalPortId = GlobalPortId(7);
+        static PORTS: [GlobalPortId; 1] = [PORT];
+
+        let _guard = lock_controllers();
+        init();
+        // Never receives commands so every command times out
+        let controller: &'static Device<'static> = Box::leak(Box::new(Device::new(ControllerId(7), &PORTS)));
+        block_on(register_controller(controller)).unwrap();
+
+        let token = ContextToken::new();
+        assert_eq!(token.command_timeout(CommandKind::Status), DEFAULT_STATUS_TIMEOUT);
+        assert_eq!(token.command_timeout(CommandKind::Reset), DEFAULT_RESET_TIMEOUT);
+        assert_eq!(token.command_timeout(CommandKind::Other), DEFAULT_TIMEOUT);
+        assert!(DEFAULT_STATUS_TIMEOUT < DEFAULT_RESET_TIMEOUT);
+
+        token.set_command_timeout(CommandKind::Status, Duration::from_millis(20));
+        token.set_command_timeout(CommandKind::Reset, Duration::from_millis(200));
+
+        let start = Instant::now();
+        let status = block_on(token.send_port_command(PORT, PortCommandData::PortStatus));
+        let status_elapsed = start.elapsed();
+        assert!(matches!(status, Err(PdError::Timeout)));
+
+        let start = Instant::now();
+        let reset = block_on(token.send_controller_command(ControllerId(7), InternalCommandData::Reset));
+        let reset_elapsed = start.elapsed();
+        assert!(matches!(reset, Err(PdError::Timeout)));
+
+        assert!(status_elapsed >= Duration::from_millis(20));
+        assert!(reset_elapsed >= Duration::from_millis(200));
+        assert!(status_elapsed < reset_elapsed);
+    }
+
+    #[test]
+    fn test_sink_path_enabled() {
+        const STATUS: PortStatus = PortStatus::new();
+        assert!(!STATUS.sink_path_enabled);
+
+        // A stuck sink path doesn't make a disconnected port look connected
+        let mut status = PortStatus::new();
+        status.sink_path_enabled = true;
+        assert!(!status.is_connected());
+
+        status.connection_state = Some(ConnectionState::Attached);
+        assert!(status.is_connected());
+        assert!(status.sink_path_enabled);
+    }
+
+    #[test]
+    fn test_sink_capability_default() {
+        let mut status = PortStatus::new();
+        status.connection_state = Some(ConnectionState::Attached);
+
+        // Connected but no contract negotiated, budget at the default
+        assert_eq!(
+            status.sink_capability_or(Some(POWER_CAPABILITY_5V_1A5)),
+            Some(POWER_CAPABILITY_5V_1A5)
+        );
+        assert_eq!(status.sink_capability_or(None), None);
+
+        // Explicit contract takes priority
+        status.available_sink_contract = Some(POWER_CAPABILITY_5V_3A0);
+        assert_eq!(
+            status.sink_capability_or(Some(POWER_CAPABILITY_5V_1A5)),
+            Some(POWER_CAPABILITY_5V_3A0)
+        );
+    }
+
+    #[test]
+    fn test_alternate_modes() {
+        const DP: AltModeInfo = AltModeInfo {
+            svid: 0xff01,
+            vdo: 0x1c46,
+        };
+        let mut modes = AlternateModes::new();
+        assert!(modes.as_slice().is_empty());
+
+        modes.push(DP).unwrap();
+        assert_eq!(modes.as_slice(), &[DP]);
+
+        for _ in 1..MAX_ALT_MODES {
+            modes.push(DP).unwrap();
+        }
+        assert_eq!(modes.as_slice().len(), MAX_ALT_MODES);
+        assert_eq!(modes.push(DP), Err(PdError::InvalidParams));
+    }
+
+    #[test]
+    fn test_pd_message_header() {
+        // Source_Capabilities from a PD 3.0 DFP source, message ID 2, two PDOs
+        let message = PdMessage::new(&[0xa1, 0x25, 0x2c, 0x91, 0x01, 0x08, 0xc8, 0xd0, 0x02, 0x00]).unwrap();
+        let header = message.header();
+        assert_eq!(header.message_type, 1);
+        assert_eq!(header.data_role, DataRole::Dfp);
+        assert_eq!(header.spec_revision, 2);
+        assert!(matches!(header.power_role, PowerRole::Source));
+        assert_eq!(header.message_id, 2);
+        assert_eq!(header.num_data_objects, 2);
+        assert!(!header.extended);
+        assert_eq!(message.payload().len(), 8);
+
+        assert_eq!(PdMessage::new(&[0x01]), Err(PdError::InvalidParams));
+        assert_eq!(
+            PdMessage::new(&[0; MAX_PD_MESSAGE_LEN + 1]),
+            Err(PdError::InvalidParams)
+        );
+    }
+
+    #[test]
+    fn test_contract_lost() {
+        let mut previous = PortStatus::new();
+        previous.connection_state = Some(ConnectionState::Attached);
+        previous.available_sink_contract = Some(POWER_CAPABILITY_5V_3A0);
+
+        // Sink contract goes away while still connected
+        let mut current = previous;
+        current.available_sink_contract = None;
+        assert!(current.contract_lost(&previous));
+
+        // Contract still present
+        assert!(!previous.contract_lost(&previous));
+
+        // Disconnect is handled as a plug event
+        let current = PortStatus::new();
+        assert!(!current.contract_lost(&previous));
+
+        // Source contract goes away while still connected
+        let mut previous = PortStatus::new();
+        previous.connection_state = Some(ConnectionState::Attached);
+        previous.available_source_contract = Some(POWER_CAPABILITY_5V_1A5);
+        let mut current = previous;
+        current.available_source_contract = None;
+        assert!(current.contract_lost(&previous));
+    }
+
+    #[test]
+    fn test_contract_summary_sink() {
+        let summary = Contract::Sink(POWER_CAPABILITY_5V_3A0).summary();
+        assert!(matches!(summary.role, PowerRole::Sink));
+        assert_eq!(summary.voltage_mv, 5000);
+        assert_eq!(summary.current_ma, 3000);
+        assert_eq!(summary.power_mw, 15000);
+    }
+
+    #[test]
+    fn test_contract_summary_source() {
+        let summary = Contract::Source(policy::PowerCapability {
+            voltage_mv: 20000,
+            current_ma: 2250,
+        })
+        .summary();
+        assert!(matches!(summary.role, PowerRole::Source));
+        assert_eq!(summary.voltage_mv, 20000);
+        assert_eq!(summary.current_ma, 2250);
+        assert_eq!(summary.power_mw, 45000);
+    }
+
+    #[test]
+    fn test_port_status_cache() {
+        const PORT: GlobalPortId = GlobalPortId(1);
+        let cache = PortStatusCache::new();
+        let mut status = PortStatus::new();
+        status.dual_power = true;
+
+        // Disabled by default
+        cache.insert(PORT, status, Instant::from_millis(0));
+        assert!(cache.get(PORT, Instant::from_millis(0)).is_none());
+
+        cache.set_ttl(Some(Duration::from_millis(100)));
+        cache.insert(PORT, status, Instant::from_millis(0));
+
+        // Within the TTL returns cached data
+        assert!(cache.get(PORT, Instant::from_millis(50)).unwrap().dual_power);
+        assert!(cache.get(GlobalPortId(0), Instant::from_millis(50)).is_none());
+
+        // Expired
+        assert!(cache.get(PORT, Instant::from_millis(100)).is_none());
+
+        // Events invalidate the cache
+        cache.insert(PORT, status, Instant::from_millis(0));
+        cache.invalidate(PORT);
+        assert!(cache.get(PORT, Instant::from_millis(50)).is_none());
+
+        cache.insert(PORT, status, Instant::from_millis(0));
+        let mut pending = PortEventFlags::none();
+        pending.pend_port(PORT);
+        cache.invalidate_pending(pending);
+        assert!(cache.get(PORT, Instant::from_millis(50)).is_none());
+    }
+
+    #[test]
+    fn test_sink_capability_default_not_applied() {
+        // Disconnected ports are never budgeted
+        let mut status = PortStatus::new();
+        assert_eq!(status.sink_capability_or(Some(POWER_CAPABILITY_5V_1A5)), None);
+
+        // Ports that are sourcing are not consumers
+        status.connection_state = Some(ConnectionState::Attached);
+        status.available_source_contract = Some(POWER_CAPABILITY_5V_1A5);
+        assert_eq!(status.sink_capability_or(Some(POWER_CAPABILITY_5V_1A5)), None);
+    }
+
+    #[test]
+    fn test_any_port_sourcing() {
+        static PORTS: [GlobalPortId; 2] = [GlobalPortId(3), GlobalPortId(4)];
+
+        let _guard = lock_controllers();
+        init();
+        let controller: &'static Device<'static> = Box::leak(Box::new(Device::new(ControllerId(3), &PORTS)));
+        block_on(register_controller(controller)).unwrap();
+
+        // First port is sourcing, second is idle
+        let respond = async {
+            loop {
+                let request = controller.receive().await;
+                let response = match request.command {
+                    Command::Port(PortCommand {
+                        port,
+                        data: PortCommandData::PortStatus,
+                    }) => {
+                        let mut status = PortStatus::new();
+                        if port == PORTS[0] {
+                            status.connection_state = Some(ConnectionState::Attached);
+                            status.power_path = PowerPathStatus::new(false, true);
+                        }
+                        Ok(PortResponseData::PortStatus(status))
+                    }
+                    _ => Err(PdError::UnrecognizedCommand),
+                };
+                request.respond(Response::Port(response));
+            }
+        };
+
+        let token = ContextToken::new();
+        let test = async {
+            assert!(token.any_port_sourcing().await);
+            assert!(!token.any_port_sinking().await);
+        };
+
+        block_on(async {
+            match select(respond, test).await {
+                Either::First(()) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+    }
+
+    #[test]
+    fn test_get_pdos() {
+        const PORT: GlobalPortId = GlobalPortId(5);
+        static PORTS: [GlobalPortId; 1] = [PORT];
+        // Fixed 5V@3A and 20V@2.25A source PDOs
+        const SOURCE_PDOS: [u32; 2] = [(100 << 10) | 300, (400 << 10) | 225];
+
+        let _guard = lock_controllers();
+        init();
+        let controller: &'static Device<'static> = Box::leak(Box::new(Device::new(ControllerId(5), &PORTS)));
+        block_on(register_controller(controller)).unwrap();
+
+        let respond = async {
+            loop {
+                let request = controller.receive().await;
+                let response = match request.command {
+                    Command::Port(PortCommand {
+                        data:
+                            PortCommandData::GetPdos {
+                                partner: true,
+                                source: true,
+                            },
+                        ..
+                    }) => {
+                        let mut pdos = PdoList::new();
+                        for pdo in SOURCE_PDOS {
+                            pdos.push(pdo).unwrap();
+                        }
+                        Ok(PortResponseData::Pdos(pdos))
+                    }
+                    _ => Err(PdError::UnrecognizedCommand),
+                };
+                request.respond(Response::Port(response));
+            }
+        };
+
+        let token = ContextToken::new();
+        let test = async {
+            let pdos = token.get_pdos(PORT, true, true).await.unwrap();
+            assert_eq!(pdos.len(), 2);
+            assert!(pdos.iter().all(|pdo| matches!(pdo, Pdo::Source(source::Pdo::Fixed(_)))));
+            assert_eq!(policy::PowerCapability::from(pdos[0]), POWER_CAPABILITY_5V_3A0);
+            assert_eq!(
+                policy::PowerCapability::from(pdos[1]),
+                policy::PowerCapability {
+                    voltage_mv: 20000,
+                    current_ma: 2250,
+                }
+            );
+
+            assert!(matches!(
+                token.get_pdos(PORT, false, false).await,
+                Err(PdError::UnrecognizedCommand)
+            ));
+        };
+
+        block_on(async {
+            match select(respond, test).await {
+                Either::First(()) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+    }
+
+    #[test]
+    fn test_port_status_encoding() {
+        let mut status = PortStatus::new();
+        status.available_source_contract = Some(POWER_CAPABILITY_5V_1A5);
+        status.available_sink_contract = Some(policy::PowerCapability {
+            voltage_mv: 20000,
+            current_ma: 3250,
+        });
+        status.connection_state = Some(ConnectionState::DebugAccessory);
+        status.dual_power = true;
+        status.alt_mode = AltMode::from(0x05);
+        status.power_path = PowerPathStatus::new(true, true);
+        status.sink_path_enabled = true;
+
+        let mut buf = [0u8; PORT_STATUS_ENCODED_LEN];
+        assert_eq!(status.encode_into_slice(&mut buf), Ok(PORT_STATUS_ENCODED_LEN));
+        assert_eq!(
+            buf,
+            [
+                PORT_STATUS_VERSION,
+                0x3f,
+                0x02,
+                0x05,
+                0x88,
+                0x13,
+                0xdc,
+                0x05,
+                0x20,
+                0x4e,
+                0xb2,
+                0x0c
+            ]
+        );
+
+        assert_eq!(PortStatus::decode_from_slice(&buf), Ok(status));
+
+        // Blank status round-trips to blank
+        assert_eq!(
+            PortStatus::new().encode_into_slice(&mut buf),
+            Ok(PORT_STATUS_ENCODED_LEN)
+        );
+        assert_eq!(PortStatus::decode_from_slice(&buf), Ok(PortStatus::new()));
+
+        // Truncated, unknown version, and invalid connection state
+        assert_eq!(
+            PortStatus::decode_from_slice(&buf[..PORT_STATUS_ENCODED_LEN - 1]).err(),
+            Some(PdError::InvalidParams)
+        );
+        buf[0] = PORT_STATUS_VERSION + 1;
+        assert_eq!(PortStatus::decode_from_slice(&buf).err(), Some(PdError::InvalidParams));
+        buf[0] = PORT_STATUS_VERSION;
+        buf[2] = 0xff;
+        assert_eq!(PortStatus::decode_from_slice(&buf).err(), Some(PdError::InvalidParams));
+    }
+
+    #[test]
+    fn test_pdo_list() {
+        let mut pdos = PdoList::new();
+        assert!(pdos.as_slice().is_empty());
+        for i in 0..MAX_PDOS {
+            pdos.push(i as u32).unwrap();
+        }
+        assert_eq!(pdos.as_slice().len(), MAX_PDOS);
+        assert_eq!(pdos.push(0), Err(PdError::InvalidParams));
+    }
+
+    #[test]
+    fn test_port_power() {
+        const SINKING: GlobalPortId = GlobalPortId(6);
+        const DISCONNECTED: GlobalPortId = GlobalPortId(8);
+        static PORTS: [GlobalPortId; 2] = [SINKING, DISCONNECTED];
+
+        let _guard = lock_controllers();
+        init();
+        let controller: &'static Device<'static> = Box::leak(Box::new(Device::new(ControllerId(6), &PORTS)));
+        block_on(register_controller(controller)).unwrap();
+
+        let respond = async {
+            loop {
+                let request = controller.receive().await;
+                let response = match request.command {
+                    Command::Port(PortCommand {
+                        port,
+                        data: PortCommandData::PortStatus,
+                    }) => {
+                        let mut status = PortStatus::new();
+                        if port == SINKING {
+                            // 20V@3A
+                            status.connection_state = Some(ConnectionState::Attached);
+                            status.available_sink_contract = Some(policy::PowerCapability {
+                                voltage_mv: 20000,
+                                current_ma: 3000,
+                            });
+                        }
+                        Ok(PortResponseData::PortStatus(status))
+                    }
+                    _ => Err(PdError::UnrecognizedCommand),
+                };
+                request.respond(Response::Port(response));
+            }
+        };
+
+        let token = ContextToken::new();
+        let test = async {
+            assert!(matches!(
+                token.port_power(SINKING).await,
+                Ok(Some((PowerRole::Sink, 60000)))
+            ));
+            assert!(matches!(token.port_power(DISCONNECTED).await, Ok(None)));
+        };
+
+        block_on(async {
+            match select(respond, test).await {
+                Either::First(()) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+    }
+
+    /// Respond to self-test commands for the given controller
+    async fn respond_self_test(controller: &Device<'_>, valid_fw_bank: bool) {
+        loop {
+            let request = controller.receive().await;
+            let response = match request.command {
+                Command::Controller(InternalCommandData::Status) => {
+                    Response::Controller(Ok(InternalResponseData::Status(ControllerStatus {
+                        mode: "APP",
+                        valid_fw_bank,
+                        fw_version0: 0,
+                        fw_version1: 0,
+                        ready: true,
+                    })))
+                }
+                Command::Port(PortCommand {
+                    data: PortCommandData::PortStatus,
+                    ..
+                }) => Response::Port(Ok(PortResponseData::PortStatus(PortStatus::new()))),
+                _ => Response::Port(Err(PdError::UnrecognizedCommand)),
+            };
+            request.respond(response);
+        }
+    }
+
+    #[test]
+    fn test_self_test() {
+        const CONTROLLER: ControllerId = ControllerId(8);
+        static PORTS: [GlobalPortId; 2] = [GlobalPortId(9), GlobalPortId(10)];
+
+        let _guard = lock_controllers();
+        init();
+        let controller: &'static Device<'static> = Box::leak(Box::new(Device::new(CONTROLLER, &PORTS)));
+        block_on(register_controller(controller)).unwrap();
+
+        let token = ContextToken::new();
+        let test = async {
+            let result = token.self_test_controller::<2>(CONTROLLER).await;
+            assert!(result.passed());
+            assert!(result.status.is_ok());
+            assert!(result.valid_fw_bank);
+            assert_eq!(result.ports.len(), 2);
+            assert!(result
+                .ports
+                .iter()
+                .zip(PORTS.iter())
+                .all(|(r, p)| r.port == *p && r.passed()));
+
+            // Not enough space for all ports
+            let result = token.self_test_controller::<1>(CONTROLLER).await;
+            assert!(result.ports_truncated);
+            assert!(!result.passed());
+
+            let result = token.self_test_controller::<2>(ControllerId(0xff)).await;
+            assert!(matches!(result.status, Err(PdError::InvalidController)));
+            assert!(!result.passed());
+        };
+
+        block_on(async {
+            match select(respond_self_test(controller, true), test).await {
+                Either::First(()) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+    }
+
+    #[test]
+    fn test_self_test_invalid_fw_bank() {
+        const CONTROLLER: ControllerId = ControllerId(9);
+        static PORTS: [GlobalPortId; 1] = [GlobalPortId(11)];
+
+        let _guard = lock_controllers();
+        init();
+        let controller: &'static Device<'static> = Box::leak(Box::new(Device::new(CONTROLLER, &PORTS)));
+        block_on(register_controller(controller)).unwrap();
+
+        let token = ContextToken::new();
+        let test = async {
+            let result = token.self_test_controller::<1>(CONTROLLER).await;
+            assert!(!result.passed());
+            assert!(result.status.is_ok());
+            assert!(!result.valid_fw_bank);
+            // Port checks are unaffected
+            assert!(result.ports.iter().all(|p| p.passed()));
+        };
+
+        block_on(async {
+            match select(respond_self_test(controller, false), test).await {
+                Either::First(()) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+    }
+
+    /// Respond to controller commands, recording them, resets fail if `fail_reset` is set
+    async fn respond_reset(controller: &Device<'_>, fail_reset: bool, commands: &RefCell<Vec<InternalCommandData>>) {
+        loop {
+            let request = controller.receive().await;
+            let response = match request.command {
+                Command::Controller(command) => {
+                    commands.borrow_mut().push(command);
+                    match command {
+                        InternalCommandData::Reset if fail_reset => Err(PdError::Failed),
+                        _ => Ok(InternalResponseData::Complete),
+                    }
+                }
+                _ => Err(PdError::UnrecognizedCommand),
+            };
+            request.respond(Response::Controller(response));
+        }
+    }
+
+    #[test]
+    fn test_reset_all_controllers() {
+        const GOOD: ControllerId = ControllerId(10);
+        const FAILING: ControllerId = ControllerId(11);
+        static GOOD_PORTS: [GlobalPortId; 1] = [GlobalPortId(12)];
+        static FAILING_PORTS: [GlobalPortId; 1] = [GlobalPortId(13)];
+
+        let _guard = lock_controllers();
+        init();
+        let good: &'static Device<'static> = Box::leak(Box::new(Device::new(GOOD, &GOOD_PORTS)));
+        let failing: &'static Device<'static> = Box::leak(Box::new(Device::new(FAILING, &FAILING_PORTS)));
+        block_on(register_controller(good)).unwrap();
+        block_on(register_controller(failing)).unwrap();
+
+        let good_commands = RefCell::new(Vec::new());
+        let failing_commands = RefCell::new(Vec::new());
+        let token = ContextToken::new();
+        // Controllers registered by other tests might not respond, don't wait long on them
+        for kind in [CommandKind::Status, CommandKind::Reset, CommandKind::Other] {
+            token.set_command_timeout(kind, Duration::from_millis(50));
+        }
+
+        let test = async {
+            let results = token.reset_all_controllers::<16>().await;
+            let result = |id| results.iter().find(|(r, _)| *r == id).map(|(_, result)| *result);
+            assert_eq!(result(GOOD), Some(Ok(())));
+            assert_eq!(result(FAILING), Some(Err(PdError::Failed)));
+        };
+
+        block_on(async {
+            match select3(
+                respond_reset(good, false, &good_commands),
+                respond_reset(failing, true, &failing_commands),
+                test,
+            )
+            .await
+            {
+                Either3::Third(()) => (),
+                _ => unreachable!(),
+            }
+        });
+
+        // The failure didn't stop the other controller from being reset and re-synced
+        assert!(matches!(
+            good_commands.borrow().as_slice(),
+            [InternalCommandData::Reset, InternalCommandData::SyncState]
+        ));
+        assert!(matches!(
+            failing_commands.borrow().as_slice(),
+            [InternalCommandData::Reset]
+        ));
+    }
+
+    #[test]
+    fn test_wait_controller_ready() {
+        const CONTROLLER: ControllerId = ControllerId(12);
+        static PORTS: [GlobalPortId; 1] = [GlobalPortId(14)];
+
+        let _guard = lock_controllers();
+        init();
+        let controller: &'static Device<'static> = Box::leak(Box::new(Device::new(CONTROLLER, &PORTS)));
+        block_on(register_controller(controller)).unwrap();
+
+        // Number of status polls that report the controller as still booting
+        let booting_polls = Cell::new(3);
+        let polls = Cell::new(0);
+        let respond = async {
+            loop {
+                let request = controller.receive().await;
+                let response = match request.command {
+                    Command::Controller(InternalCommandData::Status) => {
+                        polls.set(polls.get() + 1);
+                        let ready = polls.get() > booting_polls.get();
+                        Ok(InternalResponseData::Status(ControllerStatus {
+                            mode: if ready { "APP " } else { "BOOT" },
+                            valid_fw_bank: ready,
+                            fw_version0: 0,
+                            fw_version1: 0,
+                            ready,
+                        }))
+                    }
+                    _ => Err(PdError::UnrecognizedCommand),
+                };
+                request.respond(Response::Controller(response));
+            }
+        };
+
+        let token = ContextToken::new();
+        let test = async {
+            assert_eq!(
+                token.wait_controller_ready(CONTROLLER, Duration::from_secs(5)).await,
+                Ok(())
+            );
+            // Only returned once the controller reported ready
+            assert_eq!(polls.get(), 4);
+
+            // Still booting when the timeout expires
+            polls.set(0);
+            booting_polls.set(usize::MAX);
+            assert_eq!(
+                token
+                    .wait_controller_ready(CONTROLLER, Duration::from_millis(250))
+                    .await,
+                Err(PdError::Timeout)
+            );
+            assert!(polls.get() > 1);
+
+            assert_eq!(
+                token
+                    .wait_controller_ready(ControllerId(0xff), Duration::from_secs(5))
+                    .await,
+                Err(PdError::InvalidController)
+            );
+        };
+
+        block_on(async {
+            match select(respond, test).await {
+                Either::First(()) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+    }
+}
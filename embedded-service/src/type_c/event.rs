@@ -15,6 +15,10 @@ bitfield! {
     pub u8, new_power_contract_as_provider, set_new_power_contract_as_provider: 2, 2;
     /// New power contract as consumer
     pub u8, new_power_contract_as_consumer, set_new_power_contract_as_consumer: 3, 3;
+    /// Power contract lost while still connected
+    pub u8, contract_lost, set_contract_lost: 4, 4;
+    /// Requested role swap completed
+    pub u8, role_swap_complete, set_role_swap_complete: 5, 5;
 }
 
 /// Type-safe wrapper for the raw port event kind
@@ -63,6 +67,26 @@ impl PortEventKind {
     pub fn set_new_power_contract_as_consumer(&mut self, value: bool) {
         self.0.set_new_power_contract_as_consumer(value.into());
     }
+
+    /// Returns true if a power contract was lost while still connected
+    pub fn contract_lost(self) -> bool {
+        self.0.contract_lost() != 0
+    }
+
+    /// Sets the contract lost event
+    pub fn set_contract_lost(&mut self, value: bool) {
+        self.0.set_contract_lost(value.into());
+    }
+
+    /// Returns true if a requested role swap completed
+    pub fn role_swap_complete(self) -> bool {
+        self.0.role_swap_complete() != 0
+    }
+
+    /// Sets the role swap complete event
+    pub fn set_role_swap_complete(&mut self, value: bool) {
+        self.0.set_role_swap_complete(value.into());
+    }
 }
 
 /// Bit vector type to store pending port events
@@ -1,4 +1,4 @@
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
 use core::mem::offset_of;
 use core::slice;
 
@@ -9,6 +9,7 @@ use embedded_services::{ec_type, error, info};
 pub struct Service<'a> {
     endpoint: comms::Endpoint,
     ec_memory: RefCell<&'a mut ec_type::structure::ECMemory>,
+    dirty: Cell<ec_type::DirtyRegions>,
 }
 
 impl Service<'_> {
@@ -16,27 +17,49 @@ impl Service<'_> {
         Service {
             endpoint: comms::Endpoint::uninit(EndpointID::External(External::Host)),
             ec_memory: RefCell::new(ec_memory),
+            dirty: Cell::new(ec_type::DirtyRegions::new()),
         }
     }
 
+    /// Memory map regions modified since the last host read
+    pub fn dirty_regions(&self) -> ec_type::DirtyRegions {
+        self.dirty.get()
+    }
+
+    fn mark_dirty(&self, region: ec_type::Region) {
+        let mut dirty = self.dirty.get();
+        dirty.mark(region);
+        self.dirty.set(dirty);
+    }
+
+    fn host_read(&self, offset: usize, length: usize) {
+        let mut dirty = self.dirty.get();
+        dirty.host_read(offset, length);
+        self.dirty.set(dirty);
+    }
+
     fn update_battery_section(&self, msg: &ec_type::message::BatteryMessage) {
         let mut memory_map = self.ec_memory.borrow_mut();
         ec_type::update_battery_section(msg, &mut memory_map);
+        self.mark_dirty(ec_type::Region::Battery);
     }
 
     fn update_capabilities_section(&self, msg: &ec_type::message::CapabilitiesMessage) {
         let mut memory_map = self.ec_memory.borrow_mut();
         ec_type::update_capabilities_section(msg, &mut memory_map);
+        self.mark_dirty(ec_type::Region::Capabilities);
     }
 
     fn update_thermal_section(&self, msg: &ec_type::message::ThermalMessage) {
         let mut memory_map = self.ec_memory.borrow_mut();
         ec_type::update_thermal_section(msg, &mut memory_map);
+        self.mark_dirty(ec_type::Region::Thermal);
     }
 
     fn update_time_alarm_section(&self, msg: &ec_type::message::TimeAlarmMessage) {
         let mut memory_map = self.ec_memory.borrow_mut();
         ec_type::update_time_alarm_section(msg, &mut memory_map);
+        self.mark_dirty(ec_type::Region::TimeAlarm);
     }
 
     async fn route_to_service(&self, offset: usize, length: usize) -> Result<(), ec_type::Error> {
@@ -197,6 +220,8 @@ pub async fn espi_service(mut espi: espi::Espi<'static>, memory_map_buffer: &'st
                             port_event.offset, port_event.length
                         );
                     }
+                } else {
+                    espi_service.host_read(port_event.offset, port_event.length);
                 }
 
                 espi.complete_port(0).await;
@@ -14,7 +14,7 @@ use embedded_batteries_async::smart_battery::{
 use embedded_services::{error, info};
 
 use battery_service::controller::{Controller, ControllerEvent};
-use battery_service::device::{Device, DeviceId, DynamicBatteryMsgs, StaticBatteryMsgs};
+use battery_service::device::{BatteryState, Device, DeviceId, DynamicBatteryMsgs, StaticBatteryMsgs};
 use battery_service::wrapper::Wrapper;
 use bq40z50::Bq40z50;
 use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
@@ -217,6 +217,15 @@ async fn battery_publish_task(fg_device: &'static Device) {
         )
         .await
         .unwrap();
+
+        // ACPI _BST state
+        let state = BatteryState::from_battery_status(cache.battery_status, cache.current_ma);
+        battery_service::comms_send(
+            embedded_services::comms::EndpointID::External(embedded_services::comms::External::Host),
+            &embedded_services::ec_type::message::BatteryMessage::State(state.to_acpi()),
+        )
+        .await
+        .unwrap();
     }
 }
 
@@ -12,7 +12,7 @@ use embassy_sync::signal::Signal;
 use embassy_time::Duration;
 use embedded_services::comms::{self, EndpointID, Internal};
 use power_button_service::button::{Button, ButtonConfig};
-use power_button_service::button_interpreter::{check_button_press, Message};
+use power_button_service::button_interpreter::{check_button_clicks, Message};
 use power_button_service::debounce::{ActiveState, Debouncer};
 use {defmt_rtt as _, panic_probe as _};
 
@@ -92,7 +92,7 @@ async fn button_task(gpio: Input<'static>, config: ButtonConfig) {
     let mut button = Button::new(gpio, config);
 
     loop {
-        match check_button_press(&mut button).await {
+        match check_button_clicks(&mut button).await {
             Some(Message::ShortPress) => {
                 info!("Short press");
                 sender.send(Message::ShortPress).await;
@@ -105,6 +105,10 @@ async fn button_task(gpio: Input<'static>, config: ButtonConfig) {
                 info!("Press and hold");
                 sender.send(Message::PressAndHold).await;
             }
+            Some(Message::DoubleClick) => {
+                info!("Double click");
+                sender.send(Message::DoubleClick).await;
+            }
             None => {}
         }
     }
@@ -178,6 +182,10 @@ async fn main(spawner: Spawner) {
             Message::PressAndHold => {
                 led_r.toggle();
             }
+            Message::DoubleClick => {
+                led_g.toggle();
+                led_b.toggle();
+            }
         }
     }
 }
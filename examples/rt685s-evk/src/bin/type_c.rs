@@ -75,6 +75,22 @@ mod battery {
                     info!("Consumer connected: {} {:?}", id.0, capability);
                     Ok(())
                 }
+                policy::CommsData::Idle => {
+                    info!("Power idle");
+                    Ok(())
+                }
+                policy::CommsData::Active => {
+                    info!("Power active");
+                    Ok(())
+                }
+                policy::CommsData::ProviderEnabled(id, capability) => {
+                    info!("Provider enabled: {} {:?}", id.0, capability);
+                    Ok(())
+                }
+                policy::CommsData::ProviderDisabled(id) => {
+                    info!("Provider disabled: {}", id.0);
+                    Ok(())
+                }
             }
         }
     }
@@ -0,0 +1,150 @@
+//! Two HID devices sharing a single I2C host task, requests are routed by the I2C address the host targets
+use embassy_executor::{Executor, Spawner};
+use embassy_sync::once_lock::OnceLock;
+use embedded_services::buffer::OwnedRef;
+use embedded_services::hid::{self, DeviceId, RegisterFile};
+use embedded_services::{comms, define_static_buffer};
+use hid_service::i2c::Host;
+use log::*;
+use static_cell::StaticCell;
+
+const DEV0: (DeviceId, u8) = (DeviceId(0), 0x2c);
+const DEV1: (DeviceId, u8) = (DeviceId(1), 0x2d);
+static DEVICES: [(DeviceId, u8); 2] = [DEV0, DEV1];
+
+/// Each device reports its ID in the descriptor product ID so the bus can check where a request went
+fn product_id(id: DeviceId) -> u16 {
+    0x1000 + id.0 as u16
+}
+
+/// Mock I2C bus that reads the HID descriptor from each address in turn
+mod bus {
+    use core::convert::Infallible;
+    use core::future::pending;
+
+    use embedded_services::hid::{self, Descriptor};
+    use hid_service::i2c::{Command, I2cSlaveAsync};
+    use log::*;
+
+    use super::{product_id, DEVICES};
+
+    /// Addresses the host reads descriptors from
+    const SCRIPT: [u8; 3] = [super::DEV1.1, super::DEV0.1, super::DEV1.1];
+
+    pub struct Bus {
+        index: usize,
+        expect_read: bool,
+    }
+
+    impl Bus {
+        pub fn new() -> Self {
+            Self {
+                index: 0,
+                expect_read: false,
+            }
+        }
+    }
+
+    impl I2cSlaveAsync for Bus {
+        type Error = Infallible;
+
+        async fn listen(&mut self) -> Result<Command, Self::Error> {
+            if self.expect_read {
+                return Ok(Command::Read);
+            }
+
+            if self.index >= SCRIPT.len() {
+                info!("All requests reached the correct device");
+                pending::<()>().await;
+            }
+
+            info!("Host writing to address {:#x}", SCRIPT[self.index]);
+            Ok(Command::Write)
+        }
+
+        async fn respond_to_write(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+            // Request the HID descriptor
+            buf.copy_from_slice(&hid::RegisterFile::default().hid_desc_reg.to_le_bytes());
+            self.expect_read = true;
+            Ok(())
+        }
+
+        async fn respond_to_read(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+            let address = SCRIPT[self.index];
+            let (id, _) = DEVICES.iter().find(|(_, addr)| *addr == address).unwrap();
+            let descriptor = Descriptor::decode_from_slice(buf).unwrap();
+
+            info!("Host read descriptor from address {:#x}", address);
+            assert_eq!(descriptor.w_product_id, product_id(*id));
+
+            self.index += 1;
+            self.expect_read = false;
+            Ok(())
+        }
+
+        fn address(&self) -> Option<u8> {
+            SCRIPT.get(self.index).copied()
+        }
+    }
+}
+
+#[embassy_executor::task(pool_size = 2)]
+async fn device_task(device: &'static hid::Device, buffer: OwnedRef<'static, u8>) {
+    hid::register_device(device).await.unwrap();
+
+    loop {
+        match device.wait_request().await {
+            hid::Request::Descriptor => {
+                info!("Device {} got descriptor request", device.id.0);
+                let descriptor = hid::Descriptor {
+                    w_product_id: product_id(device.id),
+                    ..Default::default()
+                };
+                device.respond_descriptor(&descriptor, &buffer).await.unwrap();
+            }
+            _ => error!("Device {} got unexpected request", device.id.0),
+        }
+    }
+}
+
+#[embassy_executor::task]
+async fn host_task() {
+    define_static_buffer!(host_buffer, u8, [0; 128]);
+
+    static HOST: OnceLock<Host<bus::Bus>> = OnceLock::new();
+    let host = HOST.get_or_init(|| Host::new(&DEVICES, bus::Bus::new(), host_buffer::get_mut().unwrap()));
+    comms::register_endpoint(host, &host.tp).await.unwrap();
+
+    loop {
+        if let Err(e) = host.process().await {
+            error!("Host error {:?}", e);
+        }
+    }
+}
+
+#[embassy_executor::task]
+async fn run(spawner: Spawner) {
+    define_static_buffer!(dev0_buffer, u8, [0; 64]);
+    define_static_buffer!(dev1_buffer, u8, [0; 64]);
+    static DEVICE0: OnceLock<hid::Device> = OnceLock::new();
+    static DEVICE1: OnceLock<hid::Device> = OnceLock::new();
+
+    embedded_services::init().await;
+
+    // Both devices use the same register layout, so only the address distinguishes them
+    let dev0 = DEVICE0.get_or_init(|| hid::Device::new(DEV0.0, RegisterFile::default()));
+    let dev1 = DEVICE1.get_or_init(|| hid::Device::new(DEV1.0, RegisterFile::default()));
+    spawner.must_spawn(device_task(dev0, dev0_buffer::get_mut().unwrap()));
+    spawner.must_spawn(device_task(dev1, dev1_buffer::get_mut().unwrap()));
+    spawner.must_spawn(host_task());
+}
+
+fn main() {
+    env_logger::builder().filter_level(log::LevelFilter::Info).init();
+
+    static EXECUTOR: StaticCell<Executor> = StaticCell::new();
+    let executor = EXECUTOR.init(Executor::new());
+    executor.run(|spawner| {
+        spawner.must_spawn(run(spawner));
+    });
+}
@@ -59,8 +59,13 @@ mod test_controller {
                         valid_fw_bank: true,
                         fw_version0: 0xbadf00d,
                         fw_version1: 0xdeadbeef,
+                        ready: true,
                     }))
                 }
+                controller::InternalCommandData::SyncState => {
+                    info!("Sync controller state");
+                    Ok(controller::InternalResponseData::Complete)
+                }
             }
         }
 
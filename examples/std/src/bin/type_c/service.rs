@@ -166,6 +166,7 @@ mod test_controller {
                 valid_fw_bank: true,
                 fw_version0: 0xbadf00d,
                 fw_version1: 0xdeadbeef,
+                ready: true,
             })
         }
     }
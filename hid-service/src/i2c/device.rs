@@ -53,19 +53,16 @@ impl<A: AddressMode + Copy, B: I2c<A>> Device<A, B> {
         let desc = res.unwrap();
         info!("HID descriptor: {:#?}", desc);
         self.descriptor.set(Some(desc));
+        self.device.set_descriptor(&desc);
 
         Ok(desc)
     }
 
     pub async fn read_hid_descriptor(&self) -> Result<SharedRef<'static, u8>, Error<B::Error>> {
         let desc = self.get_hid_descriptor().await?;
-
-        let mut borrow = self.buffer.borrow_mut();
-        let buf: &mut [u8] = borrow.borrow_mut();
-
-        let len = desc.encode_into_slice(buf).map_err(Error::Hid)?;
-        trace!("HID descriptor length: {}", len);
-        Ok(self.buffer.reference().slice(0..len))
+        let desc = desc.encode_into_buffer(&self.buffer).map_err(Error::Hid)?;
+        trace!("HID descriptor length: {}", desc.len());
+        Ok(desc)
     }
 
     #[allow(clippy::await_holding_refcell_ref)]
@@ -155,8 +152,12 @@ impl<A: AddressMode + Copy, B: I2c<A>> Device<A, B> {
 
         let response = match req {
             hid::Request::Descriptor => {
-                let desc = self.read_hid_descriptor().await?;
-                Some(hid::Response::Descriptor(desc))
+                let desc = self.get_hid_descriptor().await?;
+                return self
+                    .device
+                    .respond_descriptor(&desc, &self.buffer)
+                    .await
+                    .map_err(Error::Hid);
             }
             hid::Request::ReportDescriptor => {
                 let desc = self.read_report_descriptor().await?;
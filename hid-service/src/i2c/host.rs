@@ -23,7 +23,8 @@ pub enum Access {
 }
 
 pub struct Host<B: I2cSlaveAsync> {
-    id: DeviceId,
+    /// Devices behind this host and their bus addresses
+    devices: &'static [(DeviceId, u8)],
     pub tp: Endpoint,
     response: Signal<NoopRawMutex, Option<hid::Response<'static>>>,
     buffer: OwnedRef<'static, u8>,
@@ -31,9 +32,9 @@ pub struct Host<B: I2cSlaveAsync> {
 }
 
 impl<B: I2cSlaveAsync> Host<B> {
-    pub fn new(id: DeviceId, bus: B, buffer: OwnedRef<'static, u8>) -> Self {
+    pub fn new(devices: &'static [(DeviceId, u8)], bus: B, buffer: OwnedRef<'static, u8>) -> Self {
         Host {
-            id,
+            devices,
             tp: Endpoint::uninit(EndpointID::External(External::Host)),
             response: Signal::new(),
             buffer,
@@ -78,6 +79,29 @@ impl<B: I2cSlaveAsync> Host<B> {
         Ok(())
     }
 
+    /// Find the device targeted by the current transaction
+    /// Uses the bus address if the bus reports one, otherwise the first device with `reg` in its register file
+    async fn select_device(&self, reg: Option<u16>) -> Result<&'static hid::Device, Error<B::Error>> {
+        let address = self.bus.borrow().address();
+        for (id, addr) in self.devices {
+            if address.is_some_and(|address| address != *addr) {
+                continue;
+            }
+
+            let device = hid::get_device(*id).await.ok_or_else(|| {
+                error!("Invalid device id {}", id.0);
+                Error::Hid(hid::Error::InvalidDevice)
+            })?;
+
+            if address.is_some() || reg.is_none_or(|reg| device.regs.contains(reg)) {
+                return Ok(device);
+            }
+        }
+
+        error!("No device for address {:?}, register {:?}", address, reg);
+        Err(Error::Hid(hid::Error::InvalidDevice))
+    }
+
     async fn process_command(&self, device: &hid::Device) -> Result<hid::Command<'static>, Error<B::Error>> {
         trace!("Waiting for command");
         let mut cmd = [0u8; 2];
@@ -165,35 +189,32 @@ impl<B: I2cSlaveAsync> Host<B> {
 
         let reg = u16::from_le_bytes(reg);
         trace!("Register address {:#x}", reg);
-        if let Some(device) = hid::get_device(self.id).await {
-            let request = if reg == device.regs.hid_desc_reg {
-                hid::Request::Descriptor
-            } else if reg == device.regs.report_desc_reg {
-                hid::Request::ReportDescriptor
-            } else if reg == device.regs.input_reg {
-                hid::Request::InputReport
-            } else if reg == device.regs.command_reg {
-                hid::Request::Command(self.process_command(device).await?)
-            } else {
-                error!("Unexpected request address {:#x}", reg);
-                return Err(Error::Hid(hid::Error::InvalidRegisterAddress));
-            };
+        let device = self.select_device(Some(reg)).await?;
+        let request = if reg == device.regs.hid_desc_reg {
+            hid::Request::Descriptor
+        } else if reg == device.regs.report_desc_reg {
+            hid::Request::ReportDescriptor
+        } else if reg == device.regs.input_reg {
+            hid::Request::InputReport
+        } else if reg == device.regs.command_reg {
+            hid::Request::Command(self.process_command(device).await?)
+        } else {
+            error!("Unexpected request address {:#x}", reg);
+            return Err(Error::Hid(hid::Error::InvalidRegisterAddress));
+        };
 
-            hid::send_request(&self.tp, self.id, request)
-                .await
-                .map_err(|_| Error::Hid(hid::Error::Transport))?;
+        hid::send_request(&self.tp, device.id, request)
+            .await
+            .map_err(|_| Error::Hid(hid::Error::Transport))?;
 
-            trace!("Request processed");
-            Ok(())
-        } else {
-            error!("Invalid device id {}", self.id.0);
-            Err(Error::Hid(hid::Error::InvalidDevice))
-        }
+        trace!("Request processed");
+        Ok(())
     }
 
     async fn process_read(&self) -> Result<(), Error<B::Error>> {
         trace!("Got input report read request");
-        hid::send_request(&self.tp, self.id, hid::Request::InputReport)
+        let device = self.select_device(None).await?;
+        hid::send_request(&self.tp, device.id, hid::Request::InputReport)
             .await
             .map_err(|_| Error::Hid(hid::Error::Transport))
     }
@@ -308,7 +329,7 @@ impl<B: I2cSlaveAsync> MailboxDelegate for Host<B> {
             _ if message.to != EndpointID::External(External::Host) => {
                 Err(comms::MailboxDelegateError::InvalidDestination)
             }
-            _ if hid_msg.id != self.id => Err(comms::MailboxDelegateError::InvalidData),
+            _ if !self.devices.iter().any(|(id, _)| *id == hid_msg.id) => Err(comms::MailboxDelegateError::InvalidData),
             _ => Err(comms::MailboxDelegateError::Other),
         }
     }
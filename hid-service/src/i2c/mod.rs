@@ -21,4 +21,9 @@ pub trait I2cSlaveAsync {
     async fn listen(&mut self) -> Result<Command, Self::Error>;
     async fn respond_to_write(&mut self, buf: &mut [u8]) -> Result<(), Self::Error>;
     async fn respond_to_read(&mut self, buf: &[u8]) -> Result<(), Self::Error>;
+
+    /// Address targeted by the most recent transaction, `None` if the bus does not report it
+    fn address(&self) -> Option<u8> {
+        None
+    }
 }
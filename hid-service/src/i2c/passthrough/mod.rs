@@ -35,6 +35,7 @@ macro_rules! define_i2c_passthrough_host_task {
         #[::embassy_executor::task]
         async fn host_task(
             bus: $bus,
+            devices: &'static [(::embedded_services::hid::DeviceId, u8)],
             int_signal: &'static $crate::i2c::passthrough::InterruptSignal<$int_in, $int_out>,
         ) {
             use ::embassy_sync::once_lock::OnceLock;
@@ -44,7 +45,7 @@ macro_rules! define_i2c_passthrough_host_task {
             info!("Creating HIDI2C Host");
             define_static_buffer!(host_buffer, u8, [0; 128]);
             static HOST: OnceLock<Host<$bus>> = OnceLock::new();
-            let host = HOST.get_or_init(|| Host::new(HID_ID0, bus, host_buffer::get_mut().unwrap()));
+            let host = HOST.get_or_init(|| Host::new(devices, bus, host_buffer::get_mut().unwrap()));
             comms::register_endpoint(host, &host.tp).await.unwrap();
 
             loop {
@@ -123,9 +124,11 @@ macro_rules! define_i2c_passthrough_task {
 
                 static INT_SIGNAL: OnceLock<InterruptSignal<$int_in, $int_out>> = OnceLock::new();
                 let int_signal = INT_SIGNAL.get_or_init(|| InterruptSignal::new(int_in, int_out));
+                static DEVICES: OnceLock<[(::embedded_services::hid::DeviceId, u8); 1]> = OnceLock::new();
+                let devices = DEVICES.get_or_init(|| [(device_id, device_addr)]);
 
                 spawner.must_spawn(device_task(device_bus, device_id, device_addr));
-                spawner.must_spawn(host_task(host_bus, int_signal));
+                spawner.must_spawn(host_task(host_bus, devices, int_signal));
                 spawner.must_spawn(interrupt_task(int_signal));
             }
         }
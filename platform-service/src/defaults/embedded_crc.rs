@@ -22,3 +22,14 @@ pub(crate) async fn crc_calculate_u16(
     digest.update(bytes);
     Ok(digest.finalize())
 }
+
+pub(crate) async fn crc_calculate_u8(
+    init: u8,
+    algorithm: &'static Algorithm<u8>,
+    bytes: &[u8],
+) -> Result<u8, EmbeddedCrcError> {
+    let crc = crc::Crc::<u8>::new(algorithm);
+    let mut digest = crc.digest_with_initial(init);
+    digest.update(bytes);
+    Ok(digest.finalize())
+}
@@ -1,5 +1,8 @@
 pub(crate) mod nvram;
 pub(crate) use nvram::*;
 
+pub(crate) mod reset;
+pub(crate) use reset::*;
+
 pub(crate) mod embedded_crc;
 pub(crate) use embedded_crc::*;
@@ -1,11 +1,27 @@
+use core::cell::RefCell;
 use core::ops::Range;
 
-pub(crate) fn nvram_read(_address: usize) -> u32 {
-    0
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+
+/// Number of u32 words of emulated NVRAM
+const NVRAM_WORDS: usize = 8;
+
+// Emulated NVRAM contents, not retained across resets
+static NVRAM: Mutex<CriticalSectionRawMutex, RefCell<[u8; NVRAM_WORDS * 4]>> =
+    Mutex::new(RefCell::new([0; NVRAM_WORDS * 4]));
+
+pub(crate) fn nvram_read(address: usize) -> u32 {
+    NVRAM.lock(|nvram| {
+        let bytes = &nvram.borrow()[address * 4..(address + 1) * 4];
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    })
 }
 
-pub(crate) fn nvram_write(_address: usize, _value: u32) {}
+pub(crate) fn nvram_write(address: usize, value: u32) {
+    NVRAM.lock(|nvram| nvram.borrow_mut()[address * 4..(address + 1) * 4].copy_from_slice(&value.to_le_bytes()));
+}
 
 pub(crate) fn nvram_valid_range() -> Range<usize> {
-    0..0
+    0..NVRAM_WORDS
 }
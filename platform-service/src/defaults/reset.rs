@@ -0,0 +1,5 @@
+use crate::reset::ResetReason;
+
+pub(crate) fn read_reset_reason() -> ResetReason {
+    ResetReason::Unknown
+}
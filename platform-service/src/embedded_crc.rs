@@ -6,7 +6,7 @@ pub struct EmbeddedCrc<W: crc::Width> {
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum EmbeddedCrcError {
     #[default]
     CrcErrorUnknown,
@@ -16,6 +16,33 @@ pub enum EmbeddedCrcError {
     CrcErrorMutexGet,
 }
 
+/// CRC algorithms selectable through [`compute`]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrcAlgorithm {
+    /// CRC-32/ISO-HDLC, the common Ethernet/zlib CRC-32
+    Crc32IsoHdlc,
+    /// CRC-16/CCITT-FALSE, polynomial 0x1021 with initial value 0xFFFF
+    Crc16Ccitt,
+    /// CRC-8/SMBUS, polynomial 0x07 as used for SMBus packet error checking
+    Crc8,
+}
+
+/// Compute the CRC of `bytes` in a single pass with the given algorithm, narrower CRCs are zero-extended
+pub async fn compute(algorithm: CrcAlgorithm, bytes: &[u8]) -> Result<u32, EmbeddedCrcError> {
+    match algorithm {
+        CrcAlgorithm::Crc32IsoHdlc => {
+            crate::crc_calculate_u32(crc::CRC_32_ISO_HDLC.init, &crc::CRC_32_ISO_HDLC, bytes).await
+        }
+        CrcAlgorithm::Crc16Ccitt => crate::crc_calculate_u16(crc::CRC_16_IBM_3740.init, &crc::CRC_16_IBM_3740, bytes)
+            .await
+            .map(u32::from),
+        CrcAlgorithm::Crc8 => crate::crc_calculate_u8(crc::CRC_8_SMBUS.init, &crc::CRC_8_SMBUS, bytes)
+            .await
+            .map(u32::from),
+    }
+}
+
 impl EmbeddedCrc<u32> {
     pub fn new(algorithm: &'static Algorithm<u32>) -> Self {
         Self {
@@ -124,3 +124,15 @@ pub(crate) async fn crc_calculate_u16(
 
     Ok(crc.feed_bytes(bytes) as u16)
 }
+
+pub(crate) async fn crc_calculate_u8(
+    init: u8,
+    algorithm: &'static crc::Algorithm<u8>,
+    bytes: &[u8],
+) -> Result<u8, EmbeddedCrcError> {
+    // The IMXRT CRC accelerator has no 8-bit polynomials, fall back to a software table
+    let crc = crc::Crc::<u8>::new(algorithm);
+    let mut digest = crc.digest_with_initial(init);
+    digest.update(bytes);
+    Ok(digest.finalize())
+}
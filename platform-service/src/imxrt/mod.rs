@@ -1,6 +1,9 @@
 pub(crate) mod nvram;
 pub(crate) use nvram::*;
 
+pub(crate) mod reset;
+pub(crate) use reset::*;
+
 // Set embedded_crc access level outside the crate to initialize CRC hardware object
 pub mod embedded_crc;
 pub(crate) use embedded_crc::*;
@@ -0,0 +1,32 @@
+use embassy_imxrt::pac;
+
+use crate::reset::ResetReason;
+
+// PMC RESETCAUSE register bits
+const RESETCAUSE_POR: u32 = 1 << 0;
+const RESETCAUSE_PADRESET: u32 = 1 << 1;
+const RESETCAUSE_BODRESET: u32 = 1 << 2;
+const RESETCAUSE_ARMSYSRESET: u32 = 1 << 3;
+const RESETCAUSE_WDT0RESET: u32 = 1 << 4;
+const RESETCAUSE_WDT1RESET: u32 = 1 << 5;
+
+pub(crate) fn read_reset_reason() -> ResetReason {
+    // SAFETY: read-only access to a status register
+    let pmc = unsafe { &*pac::Pmc::ptr() };
+    let cause = pmc.resetcause().read().bits();
+
+    // Several bits may be set, report the most specific cause
+    if cause & (RESETCAUSE_WDT0RESET | RESETCAUSE_WDT1RESET) != 0 {
+        ResetReason::Watchdog
+    } else if cause & RESETCAUSE_ARMSYSRESET != 0 {
+        ResetReason::Software
+    } else if cause & RESETCAUSE_BODRESET != 0 {
+        ResetReason::Brownout
+    } else if cause & RESETCAUSE_PADRESET != 0 {
+        ResetReason::ExternalPin
+    } else if cause & RESETCAUSE_POR != 0 {
+        ResetReason::PowerOn
+    } else {
+        ResetReason::Unknown
+    }
+}
@@ -8,6 +8,7 @@ pub mod embedded_crc;
 
 /// Initiate a delayed MCU Reset
 pub mod reset;
+pub use reset::{reset_reason, ResetReason};
 
 #[cfg(any(feature = "imxrt", feature = "imxrt685"))]
 pub mod imxrt;
@@ -24,4 +25,107 @@ pub(crate) use defaults::*;
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn test_crc_algorithms() {
+        use embassy_futures::block_on;
+        use embedded_crc::{compute, CrcAlgorithm};
+
+        // Standard check input, expected values from the CRC catalogue
+        const CHECK: &[u8] = b"123456789";
+        for (algorithm, expected) in [
+            (CrcAlgorithm::Crc32IsoHdlc, 0xcbf4_3926),
+            (CrcAlgorithm::Crc16Ccitt, 0x29b1),
+            (CrcAlgorithm::Crc8, 0xf4),
+        ] {
+            assert_eq!(block_on(compute(algorithm, CHECK)).unwrap(), expected, "{algorithm:?}");
+        }
+
+        assert_eq!(block_on(compute(CrcAlgorithm::Crc32IsoHdlc, &[])).unwrap(), 0);
+        assert_eq!(block_on(compute(CrcAlgorithm::Crc16Ccitt, &[])).unwrap(), 0xffff);
+        assert_eq!(block_on(compute(CrcAlgorithm::Crc8, &[])).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_nvram_out_of_bounds() {
+        use embassy_futures::block_on;
+
+        let size = nvram::size();
+        assert!(size > 0);
+        assert_eq!(
+            block_on(nvram::write(size - 1, &[0; 2])),
+            Err(nvram::AccessError::OutOfBounds {
+                offset: size - 1,
+                len: 2
+            })
+        );
+        assert_eq!(
+            block_on(nvram::write(usize::MAX, &[0])),
+            Err(nvram::AccessError::OutOfBounds {
+                offset: usize::MAX,
+                len: 1
+            })
+        );
+
+        let mut buf = [0; 2];
+        assert!(block_on(nvram::read(size - 1, &mut buf)).is_err());
+        assert_eq!(block_on(nvram::read(size - 1, &mut buf[..1])), Ok(()));
+    }
+
+    #[test]
+    fn test_nvram_partial_write() {
+        use embassy_futures::block_on;
+
+        block_on(nvram::write(0, &[0x11; 8])).unwrap();
+        // Spans a word boundary
+        block_on(nvram::write(3, &[0xaa, 0xbb, 0xcc])).unwrap();
+
+        let mut buf = [0; 8];
+        block_on(nvram::read(0, &mut buf)).unwrap();
+        assert_eq!(buf, [0x11, 0x11, 0x11, 0xaa, 0xbb, 0xcc, 0x11, 0x11]);
+
+        let mut partial = [0; 2];
+        block_on(nvram::read(4, &mut partial)).unwrap();
+        assert_eq!(partial, [0xbb, 0xcc]);
+    }
+
+    #[test]
+    fn test_wear_leveling() {
+        use embassy_futures::block_on;
+
+        // Stay clear of the bytes used by the other NVRAM tests
+        const OFFSET: usize = 8;
+        type Value = nvram::WearLeveled<2, 3>;
+        let value = Value::new(OFFSET);
+        let sequence = |slot: usize| {
+            let mut sequence = [0; 4];
+            block_on(nvram::read(OFFSET + slot * Value::SLOT_LEN, &mut sequence)).unwrap();
+            u32::from_le_bytes(sequence)
+        };
+
+        assert_eq!(block_on(value.read()), Ok(None));
+
+        // Writes rotate through the slots and wrap around to the first
+        for i in 0..5u8 {
+            block_on(value.write(&[i, i + 1])).unwrap();
+            assert_eq!(block_on(value.read()), Ok(Some([i, i + 1])));
+            assert_eq!(sequence(usize::from(i) % 3), u32::from(i));
+        }
+        assert_eq!([sequence(0), sequence(1), sequence(2)], [3, 4, 2]);
+
+        // Corrupt the newest slot, the previous value is returned
+        block_on(nvram::write(OFFSET + Value::SLOT_LEN + 4, &[0xff])).unwrap();
+        assert_eq!(block_on(value.read()), Ok(Some([3, 4])));
+
+        // The next write goes after the newest valid slot
+        block_on(value.write(&[9, 9])).unwrap();
+        assert_eq!(block_on(value.read()), Ok(Some([9, 9])));
+        assert_eq!(sequence(1), 4);
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "imxrt", feature = "imxrt685")))]
+    fn test_default_reset_reason() {
+        assert_eq!(reset_reason(), ResetReason::Unknown);
+    }
 }
@@ -1,7 +1,10 @@
+use core::ops::Range;
+
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::once_lock::OnceLock;
 
+use crate::embedded_crc::{EmbeddedCrc, EmbeddedCrcError};
 use crate::nvram_valid_range;
 
 // Describes a u32 section of non-volatile RAM
@@ -42,6 +45,18 @@ impl<const N: usize> Table<N> {
     }
 }
 
+/// Byte access errors
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AccessError {
+    /// `offset + len` exceeds the NVRAM region size
+    OutOfBounds {
+        /// Requested byte offset
+        offset: usize,
+        /// Requested length in bytes
+        len: usize,
+    },
+}
+
 /// Table initialization errors
 #[derive(Copy, Clone, Debug)]
 pub enum TableError {
@@ -104,3 +119,178 @@ pub async fn lookup_section(index: usize) -> Option<ManagedSection> {
         Some(ManagedSection::new(&layout[index]))
     }
 }
+
+/// Size in bytes of a NVRAM word
+const WORD_SIZE: usize = size_of::<u32>();
+
+/// Size in bytes of the NVRAM region accessible through [`read`] and [`write`]
+pub fn size() -> usize {
+    nvram_valid_range().len() * WORD_SIZE
+}
+
+fn check_bounds(offset: usize, len: usize) -> Result<(), AccessError> {
+    match offset.checked_add(len) {
+        Some(end) if end <= size() => Ok(()),
+        _ => Err(AccessError::OutOfBounds { offset, len }),
+    }
+}
+
+/// Iterate over the words covering `len` bytes at byte `offset`
+/// Yields the word address, the byte range within the word, and the byte range within the caller's buffer
+fn words(offset: usize, len: usize) -> impl Iterator<Item = (usize, Range<usize>, Range<usize>)> {
+    let base = nvram_valid_range().start;
+    let mut done = 0;
+    core::iter::from_fn(move || {
+        if done >= len {
+            return None;
+        }
+
+        let start = (offset + done) % WORD_SIZE;
+        let count = (WORD_SIZE - start).min(len - done);
+        let item = (
+            base + (offset + done) / WORD_SIZE,
+            start..start + count,
+            done..done + count,
+        );
+        done += count;
+        Some(item)
+    })
+}
+
+/// Read `buf.len()` bytes starting at byte `offset` within the NVRAM region
+pub async fn read(offset: usize, buf: &mut [u8]) -> Result<(), AccessError> {
+    check_bounds(offset, buf.len())?;
+
+    for (address, word_range, buf_range) in words(offset, buf.len()) {
+        let word = crate::nvram_read(address).to_le_bytes();
+        buf[buf_range].copy_from_slice(&word[word_range]);
+    }
+    Ok(())
+}
+
+/// Write `data` starting at byte `offset` within the NVRAM region, bytes outside of `data` are preserved
+pub async fn write(offset: usize, data: &[u8]) -> Result<(), AccessError> {
+    check_bounds(offset, data.len())?;
+
+    for (address, word_range, data_range) in words(offset, data.len()) {
+        // Partial words are read-modify-write
+        let mut word = if word_range.len() == WORD_SIZE {
+            [0; WORD_SIZE]
+        } else {
+            crate::nvram_read(address).to_le_bytes()
+        };
+        word[word_range].copy_from_slice(&data[data_range]);
+        crate::nvram_write(address, u32::from_le_bytes(word));
+    }
+    Ok(())
+}
+
+/// Wear-leveling errors
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WearLevelError {
+    /// NVRAM access failed
+    Access(AccessError),
+    /// Checksum calculation failed
+    Crc(EmbeddedCrcError),
+}
+
+impl From<AccessError> for WearLevelError {
+    fn from(value: AccessError) -> Self {
+        Self::Access(value)
+    }
+}
+
+impl From<EmbeddedCrcError> for WearLevelError {
+    fn from(value: EmbeddedCrcError) -> Self {
+        Self::Crc(value)
+    }
+}
+
+/// Size in bytes of the slot sequence number
+const SEQUENCE_LEN: usize = size_of::<u32>();
+
+/// Size in bytes of the slot checksum
+const CHECKSUM_LEN: usize = size_of::<u16>();
+
+/// A `SIZE` byte value whose writes rotate across `SLOTS` slots starting at byte `offset` of the NVRAM region
+///
+/// Each slot holds a sequence number, the value and a CRC-16 over both. Reads return the valid slot
+/// with the highest sequence number, so a torn write falls back to the previous value.
+pub struct WearLeveled<const SIZE: usize, const SLOTS: usize> {
+    offset: usize,
+}
+
+impl<const SIZE: usize, const SLOTS: usize> WearLeveled<SIZE, SLOTS> {
+    /// Bytes of NVRAM used by each slot
+    pub const SLOT_LEN: usize = SEQUENCE_LEN + SIZE + CHECKSUM_LEN;
+
+    /// Bytes of NVRAM used by all slots
+    pub const LEN: usize = Self::SLOT_LEN * SLOTS;
+
+    /// Create a wear-leveled value stored at byte `offset`
+    pub const fn new(offset: usize) -> Self {
+        Self { offset }
+    }
+
+    fn slot_offset(&self, slot: usize) -> usize {
+        self.offset + slot * Self::SLOT_LEN
+    }
+
+    async fn checksum(sequence: u32, value: &[u8; SIZE]) -> Result<u16, EmbeddedCrcError> {
+        let mut crc = EmbeddedCrc::<u16>::new(&crc::CRC_16_IBM_3740);
+        crc.calculate(&sequence.to_le_bytes()).await?;
+        crc.calculate(value).await
+    }
+
+    /// Read a slot, returns None if its checksum is invalid
+    async fn read_slot(&self, slot: usize) -> Result<Option<(u32, [u8; SIZE])>, WearLevelError> {
+        let offset = self.slot_offset(slot);
+        let mut sequence = [0; SEQUENCE_LEN];
+        let mut value = [0; SIZE];
+        let mut checksum = [0; CHECKSUM_LEN];
+        read(offset, &mut sequence).await?;
+        read(offset + SEQUENCE_LEN, &mut value).await?;
+        read(offset + SEQUENCE_LEN + SIZE, &mut checksum).await?;
+
+        let sequence = u32::from_le_bytes(sequence);
+        if Self::checksum(sequence, &value).await? == u16::from_le_bytes(checksum) {
+            Ok(Some((sequence, value)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Find the valid slot with the highest sequence number, returns the slot index, sequence number and value
+    async fn newest(&self) -> Result<Option<(usize, u32, [u8; SIZE])>, WearLevelError> {
+        let mut newest: Option<(usize, u32, [u8; SIZE])> = None;
+        for slot in 0..SLOTS {
+            if let Some((sequence, value)) = self.read_slot(slot).await? {
+                if newest.is_none_or(|(_, newest_sequence, _)| sequence > newest_sequence) {
+                    newest = Some((slot, sequence, value));
+                }
+            }
+        }
+        Ok(newest)
+    }
+
+    /// Read the most recently written value, returns None if no slot is valid
+    pub async fn read(&self) -> Result<Option<[u8; SIZE]>, WearLevelError> {
+        Ok(self.newest().await?.map(|(_, _, value)| value))
+    }
+
+    /// Write a new value to the slot after the newest one
+    pub async fn write(&self, value: &[u8; SIZE]) -> Result<(), WearLevelError> {
+        let (slot, sequence) = match self.newest().await? {
+            Some((slot, sequence, _)) => ((slot + 1) % SLOTS, sequence + 1),
+            None => (0, 0),
+        };
+
+        let offset = self.slot_offset(slot);
+        check_bounds(offset, Self::SLOT_LEN)?;
+        let checksum = Self::checksum(sequence, value).await?;
+        write(offset, &sequence.to_le_bytes()).await?;
+        write(offset + SEQUENCE_LEN, value).await?;
+        write(offset + SEQUENCE_LEN + SIZE, &checksum.to_le_bytes()).await?;
+        Ok(())
+    }
+}
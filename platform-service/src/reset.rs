@@ -6,6 +6,29 @@ use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, lazy_lock::Lazy
 
 use embedded_services::{intrusive_list, IntrusiveList, Node, NodeContainer};
 
+/// Cause of the most recent MCU reset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResetReason {
+    /// Power-on reset
+    PowerOn,
+    /// External reset pin asserted
+    ExternalPin,
+    /// Supply voltage dropped below the brownout threshold
+    Brownout,
+    /// Watchdog timeout
+    Watchdog,
+    /// Software requested reset, see [`system_reset`]
+    Software,
+    /// Cause not reported by the platform
+    Unknown,
+}
+
+/// Query why the MCU last reset, platforms without a reset status register report [`ResetReason::Unknown`]
+pub fn reset_reason() -> ResetReason {
+    crate::read_reset_reason()
+}
+
 static BLOCKERS: LazyLock<IntrusiveList> = LazyLock::new(IntrusiveList::new);
 
 pub struct Blocker {
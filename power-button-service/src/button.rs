@@ -1,5 +1,6 @@
 //! Button Service Definitions
 
+use embassy_futures::select::{select, select_array, Either};
 use embassy_time::{with_timeout, Duration, Instant, TimeoutError, Timer};
 use embedded_hal::digital::InputPin;
 use embedded_hal_async::digital::Wait;
@@ -19,6 +20,7 @@ pub struct ButtonConfig {
     debouncer: Debouncer,
     short_press_threshold: Duration,
     timeout: Duration,
+    double_click_window: Duration,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -46,6 +48,11 @@ impl<I: InputPin + Wait> Button<I> {
         self.config = config;
     }
 
+    /// Sets the debounce sampling interval.
+    pub fn set_debounce_interval(&mut self, interval: Duration) {
+        self.config.debouncer.set_interval(interval);
+    }
+
     /// Checks button state.
     pub async fn get_button_state(&mut self) -> ButtonState {
         match self.config.debouncer.debounce(&mut self.gpio).await {
@@ -56,30 +63,35 @@ impl<I: InputPin + Wait> Button<I> {
 
     /// Asynchronously gets the duration for which the button was pressed.
     pub async fn get_press_duration(&mut self) -> Option<Duration> {
-        let timeout = self.config.timeout;
-
         // Wait for the button to be pressed
         if let ButtonState::ButtonPressed(_) = self.get_button_state().await {
-            // Record the timestamp when the button is pressed
-            let start = Instant::now();
+            return Some(self.get_hold_duration().await);
+        }
 
-            let release_future = async {
-                while let ButtonState::ButtonPressed(_) = self.get_button_state().await {
-                    Timer::after(Duration::from_millis(10)).await;
-                }
-                Instant::now()
-            };
+        None
+    }
 
-            // Wait for the button to be released or timeout
-            let end = with_timeout(timeout, release_future).await;
+    /// Asynchronously gets the duration for which a button that was just pressed is held.
+    pub async fn get_hold_duration(&mut self) -> Duration {
+        let timeout = self.config.timeout;
 
-            return Some(match end {
-                Ok(end) => end - start,
-                Err(TimeoutError) => Instant::now() - start,
-            });
-        }
+        // Record the timestamp when the button is pressed
+        let start = Instant::now();
 
-        None
+        let release_future = async {
+            while let ButtonState::ButtonPressed(_) = self.get_button_state().await {
+                Timer::after(Duration::from_millis(10)).await;
+            }
+            Instant::now()
+        };
+
+        // Wait for the button to be released or timeout
+        let end = with_timeout(timeout, release_future).await;
+
+        match end {
+            Ok(end) => end - start,
+            Err(TimeoutError) => Instant::now() - start,
+        }
     }
 }
 
@@ -90,9 +102,20 @@ impl ButtonConfig {
             debouncer,
             short_press_threshold,
             timeout,
+            double_click_window: Duration::from_millis(300),
         }
     }
 
+    /// Sets the time after a short press within which a second press makes a double click.
+    pub fn set_double_click_window(&mut self, window: Duration) {
+        self.double_click_window = window;
+    }
+
+    /// Gets the double click window duration.
+    pub fn get_double_click_window(&self) -> Duration {
+        self.double_click_window
+    }
+
     /// Gets the timeout duration.
     pub fn get_timeout(&self) -> Duration {
         self.timeout
@@ -110,6 +133,228 @@ impl Default for ButtonConfig {
             debouncer: Debouncer::default(),
             short_press_threshold: Duration::from_millis(2000),
             timeout: Duration::from_millis(5000),
+            double_click_window: Duration::from_millis(300),
         }
     }
 }
+
+/// Maximum number of buttons that can take part in chords.
+pub const MAX_CHORD_BUTTONS: u8 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Identifies a button in a chord, the index of the button passed to [`ChordDetector::wait_chord`].
+pub struct ButtonId(pub u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Identifies a registered chord.
+pub struct ChordId(pub u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Enum representing the errors that can occur when registering a chord.
+pub enum ChordError {
+    /// A chord needs at least one button.
+    NoButtons,
+    /// The button ID is not below [`MAX_CHORD_BUTTONS`].
+    InvalidButton(ButtonId),
+    /// No space to register another chord.
+    Full,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// A set of buttons that must be held together.
+struct Chord {
+    buttons: u32,
+    hold: Duration,
+    /// When the last button of the chord was pressed, if all are held.
+    held_since: Option<Instant>,
+    /// True if the chord has been reported for the current hold.
+    reported: bool,
+}
+
+#[derive(Debug)]
+/// Struct detecting chords of buttons held together, with space for `N` chords.
+pub struct ChordDetector<const N: usize> {
+    chords: [Option<Chord>; N],
+    pressed: u32,
+}
+
+impl<const N: usize> ChordDetector<N> {
+    /// Creates a new ChordDetector with no chords registered.
+    pub fn new() -> Self {
+        Self {
+            chords: [None; N],
+            pressed: 0,
+        }
+    }
+
+    /// Registers a chord reported once all `buttons` are held together for `hold`.
+    pub fn register(&mut self, buttons: &[ButtonId], hold: Duration) -> Result<ChordId, ChordError> {
+        if buttons.is_empty() {
+            return Err(ChordError::NoButtons);
+        }
+
+        let mut mask = 0;
+        for id in buttons {
+            if id.0 >= MAX_CHORD_BUTTONS {
+                return Err(ChordError::InvalidButton(*id));
+            }
+            mask |= 1 << id.0;
+        }
+
+        let (index, slot) = self
+            .chords
+            .iter_mut()
+            .enumerate()
+            .find(|(_, slot)| slot.is_none())
+            .ok_or(ChordError::Full)?;
+        *slot = Some(Chord {
+            buttons: mask,
+            hold,
+            held_since: None,
+            reported: false,
+        });
+        Ok(ChordId(index as u8))
+    }
+
+    /// Records a debounced state change of a button.
+    pub fn update(&mut self, id: ButtonId, state: ButtonState) {
+        if id.0 >= MAX_CHORD_BUTTONS {
+            return;
+        }
+
+        let bit = 1 << id.0;
+        let at = match state {
+            ButtonState::ButtonPressed(at) => {
+                self.pressed |= bit;
+                at
+            }
+            ButtonState::ButtonReleased(at) => {
+                self.pressed &= !bit;
+                at
+            }
+        };
+
+        for chord in self.chords.iter_mut().flatten() {
+            if chord.buttons & bit == 0 {
+                continue;
+            }
+
+            if self.pressed & chord.buttons == chord.buttons {
+                chord.held_since.get_or_insert(at);
+            } else {
+                // Releasing any button cancels the chord
+                chord.held_since = None;
+                chord.reported = false;
+            }
+        }
+    }
+
+    /// Returns a chord held past its hold duration at `now`, each hold is only reported once.
+    pub fn poll(&mut self, now: Instant) -> Option<ChordId> {
+        for (index, chord) in self.chords.iter_mut().enumerate() {
+            let Some(chord) = chord else {
+                continue;
+            };
+
+            if let Some(held_since) = chord.held_since {
+                if !chord.reported && now.saturating_duration_since(held_since) >= chord.hold {
+                    chord.reported = true;
+                    return Some(ChordId(index as u8));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns the earliest time a held chord will pass its hold duration.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.chords
+            .iter()
+            .flatten()
+            .filter(|chord| !chord.reported)
+            .filter_map(|chord| chord.held_since.map(|held_since| held_since + chord.hold))
+            .min()
+    }
+
+    /// Waits for a registered chord on `buttons`, each button's [`ButtonId`] is its index.
+    pub async fn wait_chord<I: InputPin + Wait, const B: usize>(&mut self, buttons: &mut [Button<I>; B]) -> ChordId {
+        loop {
+            if let Some(chord) = self.poll(Instant::now()) {
+                return chord;
+            }
+
+            let deadline = self.next_deadline().unwrap_or(Instant::MAX);
+            let states = select_array(buttons.each_mut().map(|button| button.get_button_state()));
+            if let Either::Second((state, index)) = select(Timer::at(deadline), states).await {
+                self.update(ButtonId(index as u8), state);
+            }
+        }
+    }
+}
+
+impl<const N: usize> Default for ChordDetector<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const POWER: ButtonId = ButtonId(0);
+    const VOLUME_DOWN: ButtonId = ButtonId(1);
+    const HOLD: Duration = Duration::from_secs(2);
+
+    fn at(ms: u64) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    #[test]
+    fn test_chord() {
+        let mut detector = ChordDetector::<2>::new();
+        let chord = detector.register(&[POWER, VOLUME_DOWN], HOLD).unwrap();
+
+        // The hold starts once every button is pressed
+        detector.update(POWER, ButtonState::ButtonPressed(at(0)));
+        assert_eq!(detector.poll(at(2000)), None);
+        detector.update(VOLUME_DOWN, ButtonState::ButtonPressed(at(500)));
+        assert_eq!(detector.next_deadline(), Some(at(2500)));
+        assert_eq!(detector.poll(at(2499)), None);
+        assert_eq!(detector.poll(at(2500)), Some(chord));
+
+        // Reported once per hold
+        assert_eq!(detector.poll(at(5000)), None);
+        assert_eq!(detector.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_chord_released_early() {
+        let mut detector = ChordDetector::<2>::new();
+        detector.register(&[POWER, VOLUME_DOWN], HOLD).unwrap();
+
+        detector.update(POWER, ButtonState::ButtonPressed(at(0)));
+        detector.update(VOLUME_DOWN, ButtonState::ButtonPressed(at(0)));
+        detector.update(POWER, ButtonState::ButtonReleased(at(1500)));
+        assert_eq!(detector.next_deadline(), None);
+        assert_eq!(detector.poll(at(3000)), None);
+
+        // Pressing again restarts the hold
+        detector.update(POWER, ButtonState::ButtonPressed(at(3000)));
+        assert_eq!(detector.poll(at(4999)), None);
+        assert_eq!(detector.poll(at(5000)), Some(ChordId(0)));
+    }
+
+    #[test]
+    fn test_chord_register_errors() {
+        let mut detector = ChordDetector::<1>::new();
+        assert_eq!(detector.register(&[], HOLD), Err(ChordError::NoButtons));
+        assert_eq!(
+            detector.register(&[ButtonId(MAX_CHORD_BUTTONS)], HOLD),
+            Err(ChordError::InvalidButton(ButtonId(MAX_CHORD_BUTTONS)))
+        );
+        assert_eq!(detector.register(&[POWER], HOLD), Ok(ChordId(0)));
+        assert_eq!(detector.register(&[VOLUME_DOWN], HOLD), Err(ChordError::Full));
+    }
+}
@@ -1,11 +1,12 @@
 //! Button Interpreter Module
 
+use embassy_time::{with_timeout, Duration};
 use embedded_hal::digital::InputPin;
 use embedded_hal_async::digital::Wait;
 
-use super::button::Button;
+use super::button::{Button, ButtonConfig, ButtonState};
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// Enum representing the different types of messages that can be sent by the button.
 pub enum Message {
     /// Button pressed for long duration.
@@ -14,23 +15,142 @@ pub enum Message {
     ShortPress,
     /// Button pressed and held.
     PressAndHold,
+    /// Button pressed for short duration twice within the double click window.
+    DoubleClick,
+}
+
+/// Returns the message for a press held for `duration`.
+fn classify(config: &ButtonConfig, duration: Duration) -> Message {
+    if duration.as_millis() >= config.get_timeout().as_millis() {
+        Message::PressAndHold
+    } else if duration.as_millis() >= config.get_short_press_threshold().as_millis() {
+        Message::LongPress
+    } else {
+        Message::ShortPress
+    }
 }
 
 /// Checks the button press duration and returns the corresponding state.
 pub async fn check_button_press<I: InputPin + Wait>(button: &mut Button<I>) -> Option<Message> {
-    let timeout = button.get_config().get_timeout();
-    let short_press_threshold = button.get_config().get_short_press_threshold();
+    // Ignore button release which timed out
+    let duration = button.get_press_duration().await?;
+    Some(classify(button.get_config(), duration))
+}
 
-    if let Some(duration) = button.get_press_duration().await {
-        if duration.as_millis() >= timeout.as_millis() {
-            Some(Message::PressAndHold)
-        } else if duration.as_millis() >= short_press_threshold.as_millis() {
-            Some(Message::LongPress)
-        } else {
-            Some(Message::ShortPress)
+/// Checks the button press duration like [`check_button_press`], also detecting double clicks.
+///
+/// A short press could be a single click or the first half of a double click, so it is only reported once the
+/// double click window passes without a second press. A second press that is held long is reported as a long press.
+pub async fn check_button_clicks<I: InputPin + Wait>(button: &mut Button<I>) -> Option<Message> {
+    let first = check_button_press(button).await?;
+    if first != Message::ShortPress {
+        return Some(first);
+    }
+
+    let window = button.get_config().get_double_click_window();
+    match with_timeout(window, button.get_button_state()).await {
+        Ok(ButtonState::ButtonPressed(_)) => {
+            let duration = button.get_hold_duration().await;
+            match classify(button.get_config(), duration) {
+                Message::ShortPress => Some(Message::DoubleClick),
+                second => Some(second),
+            }
         }
-    } else {
-        // Ignore button release which timed out
-        None
+        // No second press within the window
+        _ => Some(Message::ShortPress),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::convert::Infallible;
+
+    use embassy_futures::block_on;
+    use embassy_time::Instant;
+    use embedded_hal::digital::ErrorType;
+
+    use super::*;
+    use crate::debounce::{ActiveState, Debouncer};
+
+    /// Active low pin that toggles at each edge, in milliseconds from creation
+    struct TimedPin {
+        start: Instant,
+        edges: &'static [u64],
+    }
+
+    impl TimedPin {
+        fn new(edges: &'static [u64]) -> Self {
+            Self {
+                start: Instant::now(),
+                edges,
+            }
+        }
+    }
+
+    impl ErrorType for TimedPin {
+        type Error = Infallible;
+    }
+
+    impl InputPin for TimedPin {
+        fn is_high(&mut self) -> Result<bool, Self::Error> {
+            self.is_low().map(|low| !low)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Self::Error> {
+            let elapsed = self.start.elapsed().as_millis();
+            let passed = self.edges.iter().filter(|edge| **edge <= elapsed).count();
+            Ok(passed % 2 == 1)
+        }
+    }
+
+    impl Wait for TimedPin {
+        async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    /// Runs a press/release sequence through the click detection
+    fn clicks(edges: &'static [u64]) -> Option<Message> {
+        let debouncer = Debouncer::new(1, Duration::from_millis(1), ActiveState::ActiveLow);
+        let mut config = ButtonConfig::new(debouncer, Duration::from_millis(200), Duration::from_millis(1000));
+        config.set_double_click_window(Duration::from_millis(100));
+        let mut button = Button::new(TimedPin::new(edges), config);
+        block_on(check_button_clicks(&mut button))
+    }
+
+    #[test]
+    fn test_single_click() {
+        let start = Instant::now();
+        assert_eq!(clicks(&[0, 50]), Some(Message::ShortPress));
+        // Only resolved once the double click window has passed
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_double_click() {
+        assert_eq!(clicks(&[0, 50, 100, 150]), Some(Message::DoubleClick));
+    }
+
+    #[test]
+    fn test_long_press() {
+        assert_eq!(clicks(&[0, 300]), Some(Message::LongPress));
+        // A long second press isn't a double click
+        assert_eq!(clicks(&[0, 50, 100, 400]), Some(Message::LongPress));
     }
 }
@@ -21,6 +21,7 @@ pub struct Debouncer {
     sample_interval: Duration,
     active_state: ActiveState,
     pressed: bool,
+    bypass: bool,
 }
 
 impl Debouncer {
@@ -32,17 +33,80 @@ impl Debouncer {
             sample_interval,
             active_state,
             pressed: false,
+            bypass: false,
         }
     }
 
+    /// Creates a Debouncer that passes edges straight through, for inputs that are already debounced.
+    pub fn new_bypass(active_state: ActiveState) -> Self {
+        Self {
+            bypass: true,
+            ..Self::new(1, Duration::from_millis(10), active_state)
+        }
+    }
+
+    /// Returns the sampling interval.
+    pub fn interval(&self) -> Duration {
+        self.sample_interval
+    }
+
+    /// Sets the sampling interval.
+    ///
+    /// Samples taken at the old interval are discarded, so an interrupted debounce restarts its window from the
+    /// current stable state.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.sample_interval = interval;
+        self.integrator = if self.pressed { self.threshold } else { 0 };
+    }
+
+    /// Returns true if debouncing is bypassed.
+    pub fn is_bypassed(&self) -> bool {
+        self.bypass
+    }
+
+    /// Returns true if the input is currently active.
+    fn sample<I: InputPin>(&self, gpio: &mut I) -> bool {
+        match self.active_state {
+            ActiveState::ActiveLow => gpio.is_low().unwrap_or(false),
+            ActiveState::ActiveHigh => gpio.is_high().unwrap_or(false),
+        }
+    }
+
+    /// Waits for the input to change state without debouncing.
+    ///
+    /// Waits for the opposite level rather than an edge, so a change right before the wait is armed isn't missed.
+    async fn passthrough<I: InputPin + Wait>(&mut self, gpio: &mut I) -> bool {
+        let pressed = !self.pressed;
+        let wait_low = matches!(
+            (&self.active_state, pressed),
+            (ActiveState::ActiveLow, true) | (ActiveState::ActiveHigh, false)
+        );
+        let result = if wait_low {
+            gpio.wait_for_low().await
+        } else {
+            gpio.wait_for_high().await
+        };
+
+        // Fall back to polling if the pin can't wait for levels
+        if result.is_err() {
+            while self.sample(gpio) != pressed {
+                Timer::after(self.sample_interval).await;
+            }
+        }
+
+        self.pressed = pressed;
+        pressed
+    }
+
     /// Debounces a button press using an integrator.
     pub async fn debounce<I: InputPin + Wait>(&mut self, gpio: &mut I) -> bool {
+        if self.bypass {
+            return self.passthrough(gpio).await;
+        }
+
         loop {
             // Sample the button state
-            let is_pressed = match self.active_state {
-                ActiveState::ActiveLow => gpio.is_low().unwrap_or(false),
-                ActiveState::ActiveHigh => gpio.is_high().unwrap_or(false),
-            };
+            let is_pressed = self.sample(gpio);
 
             // Check if the button is pressed and increment the integrator
             if is_pressed {
@@ -77,6 +141,301 @@ impl Default for Debouncer {
             sample_interval: Duration::from_millis(10),
             active_state: ActiveState::ActiveLow,
             pressed: false,
+            bypass: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::cell::Cell;
+    use core::convert::Infallible;
+    use core::future::pending;
+
+    use embassy_futures::select::{select, Either};
+    use embassy_futures::{block_on, yield_now};
+    use embassy_time::Instant;
+    use embedded_hal::digital::ErrorType;
+
+    use super::*;
+
+    /// Pin that toggles on every edge wait
+    struct MockPin {
+        low: bool,
+    }
+
+    impl ErrorType for MockPin {
+        type Error = Infallible;
+    }
+
+    impl InputPin for MockPin {
+        fn is_high(&mut self) -> Result<bool, Self::Error> {
+            Ok(!self.low)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Self::Error> {
+            Ok(self.low)
+        }
+    }
+
+    impl Wait for MockPin {
+        async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+            self.low = false;
+            Ok(())
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+            self.low = true;
+            Ok(())
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+            self.wait_for_high().await
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+            self.wait_for_low().await
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+            self.low = !self.low;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_bypass() {
+        let interval = Duration::from_millis(10);
+
+        block_on(async {
+            // Bypassed input reports each edge as soon as it happens
+            let mut debouncer = Debouncer::new_bypass(ActiveState::ActiveLow);
+            let mut pin = MockPin { low: true };
+            let start = Instant::now();
+            assert!(debouncer.debounce(&mut pin).await);
+            assert!(!debouncer.debounce(&mut pin).await);
+            assert!(debouncer.debounce(&mut pin).await);
+            assert!(start.elapsed() < interval);
+
+            // Non-bypassed input still needs the threshold number of samples
+            let mut debouncer = Debouncer::new(3, interval, ActiveState::ActiveLow);
+            let mut pin = MockPin { low: true };
+            let start = Instant::now();
+            assert!(debouncer.debounce(&mut pin).await);
+            assert!(start.elapsed() >= interval * 2);
+        });
+    }
+
+    /// Pin that changes level on first use, an edge before a wait is armed is never reported
+    struct RacingPin {
+        low: bool,
+        edge_pending: bool,
+    }
+
+    impl RacingPin {
+        fn edge(&mut self) {
+            if self.edge_pending {
+                self.edge_pending = false;
+                self.low = !self.low;
+            }
+        }
+    }
+
+    impl ErrorType for RacingPin {
+        type Error = Infallible;
+    }
+
+    impl InputPin for RacingPin {
+        fn is_high(&mut self) -> Result<bool, Self::Error> {
+            self.is_low().map(|low| !low)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Self::Error> {
+            // The edge happens right after the pin is sampled
+            let low = self.low;
+            self.edge();
+            Ok(low)
+        }
+    }
+
+    impl Wait for RacingPin {
+        async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+            self.edge();
+            if self.low {
+                pending::<()>().await;
+            }
+            Ok(())
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+            self.edge();
+            if !self.low {
+                pending::<()>().await;
+            }
+            Ok(())
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+            self.wait_for_any_edge().await
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+            self.wait_for_any_edge().await
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+            self.edge();
+            pending().await
+        }
+    }
+
+    #[test]
+    fn test_bypass_edge_before_wait() {
+        let mut debouncer = Debouncer::new_bypass(ActiveState::ActiveLow);
+        let mut pin = RacingPin {
+            low: false,
+            edge_pending: true,
+        };
+
+        block_on(async {
+            match select(debouncer.debounce(&mut pin), Timer::after_millis(100)).await {
+                Either::First(pressed) => assert!(pressed),
+                Either::Second(()) => panic!("Press was missed"),
+            }
+        });
+    }
+
+    /// Pin that reads the next scripted level on every sample, holding the last one
+    struct ScriptedPin {
+        low: &'static [bool],
+        samples: usize,
+    }
+
+    impl ErrorType for ScriptedPin {
+        type Error = Infallible;
+    }
+
+    impl InputPin for ScriptedPin {
+        fn is_high(&mut self) -> Result<bool, Self::Error> {
+            self.is_low().map(|low| !low)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Self::Error> {
+            let low = self.low.get(self.samples).or(self.low.last()).copied().unwrap_or(false);
+            self.samples += 1;
+            Ok(low)
+        }
+    }
+
+    impl Wait for ScriptedPin {
+        async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+            Ok(())
         }
     }
+
+    #[test]
+    fn test_bounce_intervals() {
+        // Bounces before settling, the integrator reaches the threshold on the seventh sample
+        const BOUNCE: &[bool] = &[true, false, true, false, true, true, true];
+
+        for interval in [Duration::from_millis(5), Duration::from_millis(20)] {
+            let mut debouncer = Debouncer::new(3, Duration::from_millis(10), ActiveState::ActiveLow);
+            debouncer.set_interval(interval);
+            assert_eq!(debouncer.interval(), interval);
+
+            let mut pin = ScriptedPin {
+                low: BOUNCE,
+                samples: 0,
+            };
+            let start = Instant::now();
+            assert!(block_on(debouncer.debounce(&mut pin)));
+            assert_eq!(pin.samples, BOUNCE.len());
+            assert!(start.elapsed() >= interval * (BOUNCE.len() as u32 - 1));
+        }
+    }
+
+    /// Pin that's always active, counting samples so a debounce can be interrupted after a known number of them
+    struct CountingPin<'a> {
+        samples: &'a Cell<usize>,
+    }
+
+    impl ErrorType for CountingPin<'_> {
+        type Error = Infallible;
+    }
+
+    impl InputPin for CountingPin<'_> {
+        fn is_high(&mut self) -> Result<bool, Self::Error> {
+            self.is_low().map(|low| !low)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Self::Error> {
+            self.samples.set(self.samples.get() + 1);
+            Ok(true)
+        }
+    }
+
+    impl Wait for CountingPin<'_> {
+        async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_set_interval_restarts_window() {
+        let mut debouncer = Debouncer::new(3, Duration::from_millis(10), ActiveState::ActiveLow);
+        let samples = Cell::new(0);
+        let mut pin = CountingPin { samples: &samples };
+
+        block_on(async {
+            // Interrupted after two of the three samples needed, while waiting for the next sample interval
+            let interrupt = async {
+                while samples.get() < 2 {
+                    yield_now().await;
+                }
+            };
+            assert!(matches!(
+                select(debouncer.debounce(&mut pin), interrupt).await,
+                Either::Second(())
+            ));
+            assert_eq!(samples.get(), 2);
+
+            // Samples taken at the old interval don't count towards the new window
+            debouncer.set_interval(Duration::from_millis(1));
+            samples.set(0);
+            assert!(debouncer.debounce(&mut pin).await);
+            assert_eq!(samples.get(), 3);
+        });
+    }
 }
@@ -1,6 +1,7 @@
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
 
 use embassy_futures::select::select;
+use embassy_time::{Duration, Timer};
 use embedded_services::{
     debug, error, info,
     power::policy::charger::{
@@ -9,16 +10,41 @@ use embedded_services::{
     trace, warn,
 };
 
+/// Charge current ramp configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChargeRamp {
+    /// Charge current increase per step in mA
+    pub step_ma: u16,
+    /// Delay between steps
+    pub step_interval: Duration,
+}
+
 pub struct Wrapper<'a, C: ChargeController> {
     charger_policy_state: &'a charger::Device,
     controller: RefCell<C>,
+    /// Optional ramp applied when increasing the charge current
+    charge_ramp: Option<ChargeRamp>,
+    /// Last charge current written to the charger in mA
+    charge_current_ma: Cell<u16>,
 }
 
 impl<'a, C: ChargeController> Wrapper<'a, C> {
     pub fn new(charger_policy_state: &'a charger::Device, controller: C) -> Self {
+        Self::new_with_charge_ramp(charger_policy_state, controller, None)
+    }
+
+    /// Create a new wrapper that steps the charge current up to its target instead of setting it in one write
+    pub fn new_with_charge_ramp(
+        charger_policy_state: &'a charger::Device,
+        controller: C,
+        charge_ramp: Option<ChargeRamp>,
+    ) -> Self {
         Self {
             charger_policy_state,
             controller: RefCell::new(controller),
+            charge_ramp,
+            charge_current_ma: Cell::new(0),
         }
     }
 
@@ -34,6 +60,29 @@ impl<'a, C: ChargeController> Wrapper<'a, C> {
         self.charger_policy_state.wait_command().await
     }
 
+    /// Set the charge current, ramping up to it if a ramp is configured
+    async fn set_charge_current(&self, controller: &mut C, target_ma: u16) -> Result<(), C::Error> {
+        let mut current_ma = self.charge_current_ma.get();
+        match self.charge_ramp {
+            Some(ramp) if ramp.step_ma > 0 && target_ma > current_ma => {
+                while current_ma < target_ma {
+                    current_ma = current_ma.saturating_add(ramp.step_ma).min(target_ma);
+                    controller.charging_current(current_ma).await?;
+                    self.charge_current_ma.set(current_ma);
+                    if current_ma < target_ma {
+                        Timer::after(ramp.step_interval).await;
+                    }
+                }
+            }
+            // Decreasing the current does not cause inrush
+            _ => {
+                controller.charging_current(target_ma).await?;
+                self.charge_current_ma.set(target_ma);
+            }
+        }
+        Ok(())
+    }
+
     #[allow(clippy::single_match)]
     async fn process_controller_event(&self, _controller: &mut C, event: ChargerEvent) {
         let state = self.get_state().await;
@@ -103,6 +152,7 @@ impl<'a, C: ChargeController> Wrapper<'a, C> {
                     error!("Charger failed initialzation sequence.");
                     Err(charger::ChargerError::BusError)
                 } else {
+                    self.charge_current_ma.set(0);
                     Ok(charger::ChargerResponseData::Ack)
                 }
             }
@@ -122,6 +172,8 @@ impl<'a, C: ChargeController> Wrapper<'a, C> {
                         {
                             Err(charger::ChargerError::BusError)
                         } else {
+                            // No longer charging, any new charge current must ramp from zero
+                            self.charge_current_ma.set(0);
                             // Update power capability but do not change controller state.
                             // That is handled by process_controller_event().
                             // This way capability is cached even if the
@@ -158,6 +210,25 @@ impl<'a, C: ChargeController> Wrapper<'a, C> {
                     }
                 }
             },
+            PolicyEvent::SetChargeCurrent(current_ma) => match state.state {
+                State::Init => {
+                    error!("Charger received charge current request but charger is still initializing.");
+                    Err(charger::ChargerError::InvalidState(state.state))
+                }
+                State::PsuAttached | State::PsuDetached => {
+                    debug!("Charger setting charge current to {} mA", current_ma);
+                    if self
+                        .set_charge_current(controller, current_ma)
+                        .await
+                        .inspect_err(|_| error!("Error setting charge current!"))
+                        .is_err()
+                    {
+                        Err(charger::ChargerError::BusError)
+                    } else {
+                        Ok(charger::ChargerResponseData::Ack)
+                    }
+                }
+            },
         };
 
         // Send response
@@ -182,3 +253,131 @@ impl<'a, C: ChargeController> Wrapper<'a, C> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use std::vec::Vec;
+
+    use core::convert::Infallible;
+
+    use embassy_futures::block_on;
+    use embassy_futures::select::{select, Either};
+    use embedded_batteries_async::charger::{Charger, ErrorType, MilliAmps, MilliVolts};
+    use embedded_services::power::policy::charger::{ChargerId, ChargerResponseData};
+    use embedded_services::power::policy::PowerCapability;
+
+    use super::*;
+
+    struct MockCharger<'a> {
+        currents: &'a RefCell<Vec<u16>>,
+    }
+
+    impl ErrorType for MockCharger<'_> {
+        type Error = Infallible;
+    }
+
+    impl Charger for MockCharger<'_> {
+        async fn charging_current(&mut self, current: MilliAmps) -> Result<MilliAmps, Self::Error> {
+            self.currents.borrow_mut().push(current);
+            Ok(current)
+        }
+
+        async fn charging_voltage(&mut self, voltage: MilliVolts) -> Result<MilliVolts, Self::Error> {
+            Ok(voltage)
+        }
+    }
+
+    impl ChargeController for MockCharger<'_> {
+        type BusError = Infallible;
+
+        async fn wait_event(&mut self) -> ChargerEvent {
+            core::future::pending().await
+        }
+
+        async fn init_charger(&mut self) -> Result<(), Self::BusError> {
+            Ok(())
+        }
+
+        async fn is_psu_attached(&mut self) -> Result<bool, Self::BusError> {
+            Ok(true)
+        }
+
+        async fn attach_handler(&mut self, _capability: PowerCapability) -> Result<(), Self::BusError> {
+            Ok(())
+        }
+
+        async fn detach_handler(&mut self) -> Result<(), Self::BusError> {
+            Ok(())
+        }
+    }
+
+    fn execute(
+        wrapper: &Wrapper<'_, MockCharger<'_>>,
+        device: &charger::Device,
+        event: PolicyEvent,
+    ) -> ChargerResponse {
+        match block_on(select(wrapper.process(), device.execute_command(event))) {
+            Either::First(_) => unreachable!(),
+            Either::Second(response) => response,
+        }
+    }
+
+    #[test]
+    fn test_charge_current_ramp() {
+        let device = charger::Device::new(ChargerId(0));
+        let currents = RefCell::new(Vec::new());
+        let wrapper = Wrapper::new_with_charge_ramp(
+            &device,
+            MockCharger { currents: &currents },
+            Some(ChargeRamp {
+                step_ma: 500,
+                step_interval: Duration::from_millis(1),
+            }),
+        );
+        block_on(device.set_state(InternalState {
+            state: State::PsuAttached,
+            capability: None,
+        }));
+
+        // Ramp up from zero in steps, clamping the last step to the target
+        assert_eq!(
+            execute(&wrapper, &device, PolicyEvent::SetChargeCurrent(1800)),
+            Ok(ChargerResponseData::Ack)
+        );
+        assert_eq!(*currents.borrow(), [500, 1000, 1500, 1800]);
+
+        // Ramp up from the last written current
+        currents.borrow_mut().clear();
+        assert_eq!(
+            execute(&wrapper, &device, PolicyEvent::SetChargeCurrent(2500)),
+            Ok(ChargerResponseData::Ack)
+        );
+        assert_eq!(*currents.borrow(), [2300, 2500]);
+
+        // Decreasing the current is a single write
+        currents.borrow_mut().clear();
+        assert_eq!(
+            execute(&wrapper, &device, PolicyEvent::SetChargeCurrent(1000)),
+            Ok(ChargerResponseData::Ack)
+        );
+        assert_eq!(*currents.borrow(), [1000]);
+    }
+
+    #[test]
+    fn test_charge_current_no_ramp() {
+        let device = charger::Device::new(ChargerId(0));
+        let currents = RefCell::new(Vec::new());
+        let wrapper = Wrapper::new(&device, MockCharger { currents: &currents });
+        block_on(device.set_state(InternalState {
+            state: State::PsuDetached,
+            capability: None,
+        }));
+
+        assert_eq!(
+            execute(&wrapper, &device, PolicyEvent::SetChargeCurrent(1800)),
+            Ok(ChargerResponseData::Ack)
+        );
+        assert_eq!(*currents.borrow(), [1800]);
+    }
+}
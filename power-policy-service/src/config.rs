@@ -1,5 +1,6 @@
 //! Configuration types for the power policy service
 
+use embassy_time::Duration;
 use embedded_services::power::policy::PowerCapability;
 
 #[derive(Clone, Copy)]
@@ -12,6 +13,75 @@ pub struct Config {
     pub provider_unlimited: PowerCapability,
     /// Power capability of every provider in limited power mode
     pub provider_limited: PowerCapability,
+    /// Maximum total power sourced by all providers, unlimited if `None`
+    pub total_budget_mw: Option<u32>,
+    /// A new consumer must exceed the current consumer's power by more than this margin to be selected
+    pub consumer_hysteresis_mw: u32,
+    /// Initial interval between provider recovery attempts
+    pub provider_recovery_interval: Duration,
+    /// Maximum interval between provider recovery attempts, the interval doubles after each failed attempt
+    pub provider_recovery_max_interval: Duration,
+    /// If set, a new consumer is connected before the current consumer is disconnected,
+    /// keeping both sink paths enabled for this long
+    pub consumer_switchover_overlap: Option<Duration>,
+}
+
+/// Config validation errors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// A provider power capability has zero voltage or current
+    ZeroProviderPower,
+    /// Limited provider power is greater than unlimited provider power
+    LimitedExceedsUnlimited,
+    /// Recovery provider power is greater than limited provider power
+    RecoveryExceedsLimited,
+    /// Limited power threshold is below the power of a single unlimited provider
+    ThresholdBelowUnlimitedProvider,
+    /// Total power budget is below the power of a single recovery provider
+    BudgetBelowRecoveryProvider,
+    /// Provider recovery interval is zero or greater than the maximum interval
+    InvalidRecoveryInterval,
+}
+
+impl Config {
+    /// Check for contradictory settings
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let zero_power = |capability: &PowerCapability| capability.voltage_mv == 0 || capability.current_ma == 0;
+        if zero_power(&self.provider_recovery)
+            || zero_power(&self.provider_limited)
+            || zero_power(&self.provider_unlimited)
+        {
+            return Err(ConfigError::ZeroProviderPower);
+        }
+
+        if self.provider_limited > self.provider_unlimited {
+            return Err(ConfigError::LimitedExceedsUnlimited);
+        }
+
+        if self.provider_recovery > self.provider_limited {
+            return Err(ConfigError::RecoveryExceedsLimited);
+        }
+
+        // A single provider at unlimited power would immediately put us into limited power mode
+        if self.limited_power_threshold_mw < self.provider_unlimited.max_power_mw() {
+            return Err(ConfigError::ThresholdBelowUnlimitedProvider);
+        }
+
+        if let Some(total_budget_mw) = self.total_budget_mw {
+            if total_budget_mw < self.provider_recovery.max_power_mw() {
+                return Err(ConfigError::BudgetBelowRecoveryProvider);
+            }
+        }
+
+        if self.provider_recovery_interval == Duration::from_ticks(0)
+            || self.provider_recovery_interval > self.provider_recovery_max_interval
+        {
+            return Err(ConfigError::InvalidRecoveryInterval);
+        }
+
+        Ok(())
+    }
 }
 
 impl Default for Config {
@@ -34,6 +104,91 @@ impl Default for Config {
                 voltage_mv: 5000,
                 current_ma: 1500,
             },
+            total_budget_mw: None,
+            consumer_hysteresis_mw: 0,
+            provider_recovery_interval: Duration::from_millis(1000),
+            provider_recovery_max_interval: Duration::from_millis(32000),
+            consumer_switchover_overlap: None,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_valid() {
+        assert_eq!(Config::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_zero_provider_power() {
+        let config = Config {
+            provider_recovery: PowerCapability {
+                voltage_mv: 5000,
+                current_ma: 0,
+            },
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::ZeroProviderPower));
+    }
+
+    #[test]
+    fn test_limited_exceeds_unlimited() {
+        let config = Config {
+            provider_limited: PowerCapability {
+                voltage_mv: 9000,
+                current_ma: 3000,
+            },
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::LimitedExceedsUnlimited));
+    }
+
+    #[test]
+    fn test_recovery_exceeds_limited() {
+        let config = Config {
+            provider_recovery: PowerCapability {
+                voltage_mv: 5000,
+                current_ma: 2000,
+            },
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::RecoveryExceedsLimited));
+    }
+
+    #[test]
+    fn test_threshold_below_unlimited_provider() {
+        let config = Config {
+            limited_power_threshold_mw: 10000,
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::ThresholdBelowUnlimitedProvider));
+    }
+
+    #[test]
+    fn test_budget_below_recovery_provider() {
+        let config = Config {
+            total_budget_mw: Some(4000),
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::BudgetBelowRecoveryProvider));
+    }
+
+    #[test]
+    fn test_invalid_recovery_interval() {
+        let config = Config {
+            provider_recovery_interval: Duration::from_millis(2000),
+            provider_recovery_max_interval: Duration::from_millis(1000),
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::InvalidRecoveryInterval));
+
+        let config = Config {
+            provider_recovery_interval: Duration::from_ticks(0),
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::InvalidRecoveryInterval));
+    }
+}
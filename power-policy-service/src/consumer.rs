@@ -1,3 +1,4 @@
+use embassy_time::{Duration, Timer};
 use embedded_services::power::policy::charger::Device as ChargerDevice;
 use embedded_services::power::policy::charger::PolicyEvent;
 
@@ -25,6 +26,22 @@ impl Ord for State {
     }
 }
 
+/// Returns true if `candidate` exceeds `current` by more than `hysteresis_mw`
+pub fn exceeds_with_hysteresis(current: PowerCapability, candidate: PowerCapability, hysteresis_mw: u32) -> bool {
+    candidate.max_power_mw() > current.max_power_mw().saturating_add(hysteresis_mw)
+}
+
+/// Limits `capability` to at most `cap_mw` by reducing the current at the same voltage
+pub fn apply_power_cap(capability: PowerCapability, cap_mw: Option<u32>) -> PowerCapability {
+    match cap_mw {
+        Some(cap_mw) if capability.voltage_mv > 0 && capability.max_power_mw() > cap_mw => PowerCapability {
+            voltage_mv: capability.voltage_mv,
+            current_ma: (cap_mw * 1000 / capability.voltage_mv as u32) as u16,
+        },
+        _ => capability,
+    }
+}
+
 impl PowerPolicy {
     /// Iterate over all devices to determine what is now the highest-powered consumer
     async fn find_highest_power_consumer(&self) -> Result<Option<State>, Error> {
@@ -61,6 +78,97 @@ impl PowerPolicy {
         Ok(best_consumer)
     }
 
+    /// Make-before-break switch from the current consumer to a new consumer
+    /// Both sink paths are enabled for `overlap` before the current consumer is disconnected
+    async fn switch_consumer_overlapped(
+        &self,
+        state: &mut InternalState,
+        current_consumer: State,
+        new_consumer: State,
+        overlap: Duration,
+    ) -> Result<(), Error> {
+        info!(
+            "Device {}, connecting new consumer before disconnecting device {}",
+            new_consumer.device_id.0, current_consumer.device_id.0
+        );
+        let idle = match self
+            .context
+            .try_policy_action::<action::Idle>(new_consumer.device_id)
+            .await
+        {
+            Ok(idle) => idle,
+            Err(_) => {
+                error!("Error obtaining device in idle state");
+                return Ok(());
+            }
+        };
+
+        idle.connect_consumer(new_consumer.power_capability).await?;
+        state.current_consumer_state = Some(new_consumer);
+        Timer::after(overlap).await;
+
+        if let Ok(consumer) = self
+            .context
+            .try_policy_action::<action::ConnectedConsumer>(current_consumer.device_id)
+            .await
+        {
+            info!(
+                "Device {}, disconnecting previous consumer",
+                current_consumer.device_id.0
+            );
+            consumer.disconnect().await?;
+        }
+        self.comms_notify(CommsMessage {
+            data: CommsData::ConsumerDisconnected(current_consumer.device_id),
+        })
+        .await;
+
+        for node in self.context.chargers().await {
+            let device = node.data::<ChargerDevice>().ok_or(Error::InvalidDevice)?;
+            device
+                .execute_command(PolicyEvent::PolicyConfiguration(new_consumer.power_capability))
+                .await?;
+        }
+        self.comms_notify(CommsMessage {
+            data: CommsData::ConsumerConnected(new_consumer.device_id, new_consumer.power_capability),
+        })
+        .await;
+
+        Ok(())
+    }
+
+    /// Change the contract of the current consumer without disconnecting it
+    async fn renegotiate_consumer(&self, state: &mut InternalState, new_consumer: State) -> Result<(), Error> {
+        let consumer = self
+            .context
+            .try_policy_action::<action::ConnectedConsumer>(new_consumer.device_id)
+            .await?;
+
+        info!(
+            "Device {}, renegotiating consumer to {}mW",
+            new_consumer.device_id.0,
+            new_consumer.power_capability.max_power_mw()
+        );
+        if let Err(e) = consumer.connect_consumer(new_consumer.power_capability).await {
+            error!("Device {}, failed to renegotiate: {:?}", new_consumer.device_id.0, e);
+            return Err(e);
+        }
+        state.current_consumer_state = Some(new_consumer);
+
+        for node in self.context.chargers().await {
+            let device = node.data::<ChargerDevice>().ok_or(Error::InvalidDevice)?;
+            device
+                .execute_command(PolicyEvent::PolicyConfiguration(new_consumer.power_capability))
+                .await?;
+        }
+        self.comms_notify(CommsMessage {
+            data: CommsData::ConsumerConnected(new_consumer.device_id, new_consumer.power_capability),
+        })
+        .await;
+
+        Ok(())
+    }
+
     /// Connect to a new consumer
     async fn connect_new_consumer(&self, state: &mut InternalState, new_consumer: State) -> Result<(), Error> {
         // Handle our current consumer
@@ -73,6 +181,20 @@ impl PowerPolicy {
                 return Ok(());
             }
 
+            if new_consumer.device_id == current_consumer.device_id
+                && self.renegotiate_consumer(state, new_consumer).await.is_ok()
+            {
+                return Ok(());
+            }
+
+            if let Some(overlap) = self.config.consumer_switchover_overlap {
+                if new_consumer.device_id != current_consumer.device_id {
+                    return self
+                        .switch_consumer_overlapped(state, current_consumer, new_consumer, overlap)
+                        .await;
+                }
+            }
+
             state.current_consumer_state = None;
             // Disconnect the current consumer if needed
             if let Ok(consumer) = self
@@ -129,6 +251,15 @@ impl PowerPolicy {
         Ok(())
     }
 
+    /// Returns the current consumer and its power capability, if any
+    pub async fn current_consumer(&self) -> Option<(DeviceId, PowerCapability)> {
+        self.state
+            .lock()
+            .await
+            .current_consumer_state
+            .map(|consumer| (consumer.device_id, consumer.power_capability))
+    }
+
     /// Determines and connects the best consumer
     pub(super) async fn update_current_consumer(&self) -> Result<(), Error> {
         let mut guard = self.state.lock().await;
@@ -141,12 +272,90 @@ impl PowerPolicy {
         let best_consumer = self.find_highest_power_consumer().await?;
         info!("Best consumer: {:#?}", best_consumer);
         if best_consumer.is_none() {
-            state.current_consumer_state = None;
             // No new consumer available
+            if let Some(current_consumer) = state.current_consumer_state.take() {
+                self.comms_notify(CommsMessage {
+                    data: CommsData::ConsumerDisconnected(current_consumer.device_id),
+                })
+                .await;
+            }
             return Ok(());
         }
-        let best_consumer = best_consumer.unwrap();
+        let mut best_consumer = best_consumer.unwrap();
+
+        // Keep the current consumer unless the new one is better by more than the hysteresis margin
+        if let Some(current_consumer) = state.current_consumer_state {
+            if current_consumer.device_id != best_consumer.device_id {
+                let device = self.context.get_device(current_consumer.device_id).await?;
+                if let Some(power_capability) = device.consumer_capability().await {
+                    if !exceeds_with_hysteresis(
+                        power_capability,
+                        best_consumer.power_capability,
+                        self.config.consumer_hysteresis_mw,
+                    ) {
+                        info!("Best consumer within hysteresis margin, not switching");
+                        best_consumer = State {
+                            device_id: current_consumer.device_id,
+                            power_capability,
+                        };
+                    }
+                }
+            }
+        }
+
+        if let Some(cap_mw) = self.thermal_power_cap.get() {
+            let capped = apply_power_cap(best_consumer.power_capability, Some(cap_mw));
+            if capped != best_consumer.power_capability {
+                info!("Consumer capped to {}mW by thermal limit", cap_mw);
+                best_consumer.power_capability = capped;
+            }
+        }
 
         self.connect_new_consumer(state, best_consumer).await
     }
+
+    /// Limit the power drawn from the current consumer, `None` removes the limit
+    /// The consumer is renegotiated to a lower contract if it supports it, otherwise it is reconnected
+    pub async fn set_thermal_power_cap(&self, cap_mw: Option<u32>) -> Result<(), Error> {
+        info!("Thermal power cap: {:?}mW", cap_mw);
+        self.thermal_power_cap.set(cap_mw);
+        self.update_current_consumer().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const fn capability(current_ma: u16) -> PowerCapability {
+        PowerCapability {
+            voltage_mv: 20000,
+            current_ma,
+        }
+    }
+
+    #[test]
+    fn test_hysteresis_within_margin() {
+        // 45W vs 46W with a 2W margin, keep the current consumer
+        assert!(!exceeds_with_hysteresis(capability(2250), capability(2300), 2000));
+        // Equal sources never switch
+        assert!(!exceeds_with_hysteresis(capability(2250), capability(2250), 0));
+    }
+
+    #[test]
+    fn test_apply_power_cap() {
+        // 60W capped to 30W at the same voltage
+        assert_eq!(apply_power_cap(capability(3000), Some(30000)), capability(1500));
+        // Already under the cap
+        assert_eq!(apply_power_cap(capability(1000), Some(30000)), capability(1000));
+        assert_eq!(apply_power_cap(capability(3000), None), capability(3000));
+    }
+
+    #[test]
+    fn test_hysteresis_exceeds_margin() {
+        // 45W vs 60W with a 2W margin, switch to the new consumer
+        assert!(exceeds_with_hysteresis(capability(2250), capability(3000), 2000));
+        // No margin, any increase switches
+        assert!(exceeds_with_hysteresis(capability(2250), capability(2300), 0));
+    }
 }
@@ -1,5 +1,5 @@
 #![no_std]
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
 use core::ops::DerefMut;
 use embassy_futures::select::{select, Either};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
@@ -8,7 +8,7 @@ use embassy_sync::once_lock::OnceLock;
 use embassy_time::{Duration, Ticker};
 use embedded_services::power::policy::device::Device;
 use embedded_services::power::policy::{action, policy, *};
-use embedded_services::{comms, error, info};
+use embedded_services::{comms, error, info, warn};
 
 pub mod config;
 pub mod consumer;
@@ -16,14 +16,13 @@ pub mod provider;
 
 pub mod charger;
 
-/// How often to attempt to recover provider devices in recovery
-const PROVIDER_RECOVERY_TICKER_DURATION: Duration = const { Duration::from_millis(1000) };
-
 struct InternalState {
     /// Current consumer state, if any
     current_consumer_state: Option<consumer::State>,
     /// Current provider global state
     current_provider_state: provider::State,
+    /// True if there is no connected consumer or provider
+    idle: bool,
 }
 
 impl InternalState {
@@ -31,6 +30,8 @@ impl InternalState {
         Self {
             current_consumer_state: None,
             current_provider_state: provider::State::default(),
+            // Nothing is connected until the first request is processed
+            idle: true,
         }
     }
 }
@@ -45,19 +46,43 @@ pub struct PowerPolicy {
     tp: comms::Endpoint,
     /// Config
     config: config::Config,
-    /// Recovery ticker
-    recovery_ticker: RefCell<Ticker>,
+    /// Recovery ticker and the interval it runs at
+    recovery_ticker: RefCell<(Duration, Ticker)>,
+    /// Recovery attempt backoff
+    recovery_backoff: Cell<provider::RecoveryBackoff>,
+    /// Skip the next provider recovery attempt, set after a fault-caused disconnect
+    recovery_suppressed: Cell<bool>,
+    /// Platform thermal limit on consumed power in mW, if any
+    thermal_power_cap: Cell<Option<u32>>,
+    /// Power reserved from the provider budget in mW
+    reserved_mw: Cell<u32>,
 }
 
 impl PowerPolicy {
     /// Create a new power policy
+    /// Returns None if the config is invalid or a power policy has already been created
     pub fn create(config: config::Config) -> Option<Self> {
+        if let Err(e) = config.validate() {
+            error!("Invalid power policy config: {:?}", e);
+            return None;
+        }
+
         Some(Self {
             context: policy::ContextToken::create()?,
             state: Mutex::new(InternalState::new()),
             tp: comms::Endpoint::uninit(comms::EndpointID::Internal(comms::Internal::Power)),
             config,
-            recovery_ticker: RefCell::new(Ticker::every(PROVIDER_RECOVERY_TICKER_DURATION)),
+            recovery_ticker: RefCell::new((
+                config.provider_recovery_interval,
+                Ticker::every(config.provider_recovery_interval),
+            )),
+            recovery_backoff: Cell::new(provider::RecoveryBackoff::new(
+                config.provider_recovery_interval,
+                config.provider_recovery_max_interval,
+            )),
+            recovery_suppressed: Cell::new(false),
+            thermal_power_cap: Cell::new(None),
+            reserved_mw: Cell::new(0),
         })
     }
 
@@ -69,8 +94,7 @@ impl PowerPolicy {
     async fn process_notify_detach(&self) -> Result<(), Error> {
         self.context.send_response(Ok(policy::ResponseData::Complete)).await;
         self.update_current_consumer().await?;
-        self.update_providers(None).await;
-        Ok(())
+        self.update_providers(None).await
     }
 
     async fn process_notify_consumer_power_capability(&self) -> Result<(), Error> {
@@ -80,24 +104,68 @@ impl PowerPolicy {
     }
 
     async fn process_request_provider_power_capabilities(&self, device: DeviceId) -> Result<(), Error> {
-        self.context.send_response(Ok(policy::ResponseData::Complete)).await;
-        self.update_providers(Some(device)).await;
-        Ok(())
+        if let Err(e) = self.check_provider_allowed(device).await {
+            self.context.send_response(Err(e)).await;
+            return Err(e);
+        }
+        // Respond once the budget is allocated so a clamped provider is reported to the requester
+        let result = self.update_providers(Some(device)).await;
+        self.context
+            .send_response(result.map(|_| policy::ResponseData::Complete))
+            .await;
+        result
     }
 
-    async fn process_notify_disconnect(&self) -> Result<(), Error> {
+    async fn process_notify_disconnect(&self, reason: policy::DisconnectReason) -> Result<(), Error> {
         self.context.send_response(Ok(policy::ResponseData::Complete)).await;
+        if reason == policy::DisconnectReason::Fault {
+            // Give the fault time to clear before trying to restore providers
+            info!("Fault disconnect, suppressing next provider recovery");
+            self.recovery_suppressed.set(true);
+        }
         self.update_current_consumer().await?;
-        self.update_providers(None).await;
-        Ok(())
+        self.update_providers(None).await
     }
 
     /// Send a notification with the comms service
     async fn comms_notify(&self, message: CommsMessage) {
-        let _ = self
+        if let Err(e) = self
             .tp
-            .send(comms::EndpointID::Internal(comms::Internal::Battery), &message)
-            .await;
+            .try_send_checked(comms::EndpointID::Internal(comms::Internal::Battery), &message)
+            .await
+        {
+            warn!("Failed to notify battery endpoint: {:?}", e);
+        }
+    }
+
+    /// Notify when the system enters or exits the idle state, with no connected consumer or provider
+    async fn update_idle(&self) -> Result<(), Error> {
+        let mut idle = self.state.lock().await.current_consumer_state.is_none();
+        if idle {
+            for node in self.context.devices().await {
+                let device = node.data::<Device>().ok_or(Error::InvalidDevice)?;
+                if device.is_provider().await {
+                    idle = false;
+                    break;
+                }
+            }
+        }
+
+        let mut state = self.state.lock().await;
+        if state.idle == idle {
+            return Ok(());
+        }
+
+        state.idle = idle;
+        // Don't hold the lock while notifying
+        drop(state);
+
+        info!("Power policy {}", if idle { "idle" } else { "active" });
+        self.comms_notify(CommsMessage {
+            data: if idle { CommsData::Idle } else { CommsData::Active },
+        })
+        .await;
+        Ok(())
     }
 
     async fn wait_request(&self) -> policy::Request {
@@ -132,17 +200,28 @@ impl PowerPolicy {
                 );
                 self.process_request_provider_power_capabilities(device.id()).await
             }
-            policy::RequestData::NotifyDisconnect => {
-                info!("Received notify disconnect from device {}", device.id().0);
-                self.process_notify_disconnect().await
+            policy::RequestData::NotifyDisconnect(reason) => {
+                info!("Received notify disconnect from device {}: {:?}", device.id().0, reason);
+                self.process_notify_disconnect(reason).await
             }
         }
     }
 
+    /// Process a request, then update the idle state
+    async fn handle_request(&self, request: policy::Request) -> Result<(), Error> {
+        let result = self.process_request(request).await;
+        let idle_result = self.update_idle().await;
+        if let Err(e) = idle_result {
+            error!("Error updating idle state: {:?}", e);
+        }
+        // A failed request is reported over an idle tracking error
+        result.and(idle_result)
+    }
+
     /// Top-level event loop function
     pub async fn process(&self) -> Result<(), Error> {
         match select(self.wait_request(), self.wait_attempt_provider_recovery()).await {
-            Either::First(request) => self.process_request(request).await,
+            Either::First(request) => self.handle_request(request).await,
             Either::Second(true) => {
                 self.attempt_provider_recovery().await;
                 Ok(())
@@ -158,8 +237,9 @@ impl comms::MailboxDelegate for PowerPolicy {}
 pub async fn task(config: config::Config) {
     info!("Starting power policy task");
     static POLICY: OnceLock<PowerPolicy> = OnceLock::new();
-    let policy =
-        POLICY.get_or_init(|| PowerPolicy::create(config).expect("Power policy singleton already initialized"));
+    let policy = POLICY.get_or_init(|| {
+        PowerPolicy::create(config).expect("Invalid config or power policy singleton already initialized")
+    });
 
     if comms::register_endpoint(policy, &policy.tp).await.is_err() {
         error!("Failed to register power policy endpoint");
@@ -172,3 +252,735 @@ pub async fn task(config: config::Config) {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use std::boxed::Box;
+    use std::sync::{Mutex, MutexGuard, OnceLock};
+    use std::vec::Vec;
+
+    use core::future::Future;
+
+    use embassy_futures::join::join;
+    use embassy_futures::select::{select3, select_array, Either3};
+    use embassy_futures::{block_on, yield_now};
+    use embedded_services::comms::{EndpointID, Internal};
+
+    use super::*;
+
+    /// Captures every notification the power policy sends to the battery endpoint
+    struct Recorder {
+        events: RefCell<Vec<CommsData>>,
+    }
+
+    impl Recorder {
+        /// Provider notifications received so far
+        fn provider_events(&self) -> Vec<CommsData> {
+            self.events
+                .borrow()
+                .iter()
+                .copied()
+                .filter(|event| matches!(event, CommsData::ProviderEnabled(_, _) | CommsData::ProviderDisabled(_)))
+                .collect()
+        }
+    }
+
+    impl comms::MailboxDelegate for Recorder {
+        fn receive(&self, message: &comms::Message) -> Result<(), comms::MailboxDelegateError> {
+            if let Some(message) = message.data.get::<CommsMessage>() {
+                self.events.borrow_mut().push(message.data);
+            }
+            Ok(())
+        }
+    }
+
+    /// Provider budget of the shared power policy, enough for every test's providers
+    const TOTAL_BUDGET_MW: u32 = 30000;
+
+    /// Power policy and notification recorder shared by all tests
+    struct Shared {
+        power_policy: &'static PowerPolicy,
+        recorder: &'static Recorder,
+    }
+
+    // Safety: only accessed while holding the lock returned by `shared`
+    unsafe impl Send for Shared {}
+    unsafe impl Sync for Shared {}
+
+    /// Only one power policy can be created per process, so tests share it and run one at a time
+    fn shared() -> (MutexGuard<'static, ()>, &'static Shared) {
+        static LOCK: Mutex<()> = Mutex::new(());
+        static SHARED: OnceLock<Shared> = OnceLock::new();
+
+        let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let shared = SHARED.get_or_init(|| {
+            block_on(embedded_services::init());
+            let recorder: &'static Recorder = Box::leak(Box::new(Recorder {
+                events: RefCell::new(Vec::new()),
+            }));
+            let endpoint: &'static comms::Endpoint = Box::leak(Box::new(comms::Endpoint::uninit(
+                EndpointID::Internal(Internal::Battery),
+            )));
+            block_on(comms::register_endpoint(recorder, endpoint)).unwrap();
+
+            let config = config::Config {
+                consumer_switchover_overlap: Some(Duration::from_millis(10)),
+                total_budget_mw: Some(TOTAL_BUDGET_MW),
+                ..Default::default()
+            };
+            let power_policy: &'static PowerPolicy = Box::leak(Box::new(PowerPolicy::create(config).unwrap()));
+            block_on(comms::register_endpoint(power_policy, &power_policy.tp)).unwrap();
+            Shared { power_policy, recorder }
+        });
+        shared.recorder.events.borrow_mut().clear();
+        (guard, shared)
+    }
+
+    /// Commands received by test devices, in order
+    type CommandLog = RefCell<Vec<(DeviceId, device::CommandData)>>;
+
+    /// Register new devices with the power policy
+    fn register_devices<const N: usize>(ids: [DeviceId; N]) -> [&'static Device; N] {
+        let devices: [&'static Device; N] = ids.map(|id| &*Box::leak(Box::new(Device::new(id))));
+        for device in devices {
+            block_on(policy::register_device(device)).unwrap();
+        }
+        devices
+    }
+
+    /// Complete every command sent to `devices`, recording them in `log`
+    async fn respond<const N: usize>(devices: [&'static Device; N], log: &CommandLog) {
+        loop {
+            let (request, index) = select_array(devices.map(|device| device.receive())).await;
+            log.borrow_mut().push((devices[index].id(), request.command));
+            request.respond(Ok(device::ResponseData::Complete));
+        }
+    }
+
+    /// Register new devices and run `test` while the power policy and the devices process requests
+    fn run_with_devices<const N: usize, F: Future<Output = ()>>(
+        power_policy: &'static PowerPolicy,
+        ids: [DeviceId; N],
+        test: impl FnOnce([&'static Device; N], &'static CommandLog) -> F,
+    ) {
+        let devices = register_devices(ids);
+        let log: &'static CommandLog = Box::leak(Box::new(RefCell::new(Vec::new())));
+
+        let run_policy = async {
+            loop {
+                let _ = power_policy.process().await;
+            }
+        };
+
+        block_on(async {
+            match select3(run_policy, respond(devices, log), test(devices, log)).await {
+                Either3::Third(()) => (),
+                _ => unreachable!(),
+            }
+        });
+    }
+
+    /// Handle exactly one request while `input` drives it
+    /// The recovery ticker isn't involved, so a slow test can't run a recovery attempt instead of the request
+    /// Returns the result of handling the request and the output of `input`
+    fn step<const N: usize, T>(
+        power_policy: &PowerPolicy,
+        devices: [&'static Device; N],
+        log: &CommandLog,
+        input: impl Future<Output = T>,
+    ) -> (Result<(), Error>, T) {
+        let handle_request = async {
+            let request = power_policy.wait_request().await;
+            power_policy.handle_request(request).await
+        };
+
+        block_on(async {
+            match select(join(input, handle_request), respond(devices, log)).await {
+                Either::First((output, result)) => (result, output),
+                Either::Second(()) => unreachable!(),
+            }
+        })
+    }
+
+    #[test]
+    fn test_provider_notifications() {
+        const ID: DeviceId = DeviceId(0);
+        let (_guard, shared) = shared();
+        let power_policy = shared.power_policy;
+        let recorder = shared.recorder;
+        let power = power_policy.config.provider_unlimited;
+
+        let wait_events = |count: usize| async move {
+            while recorder.provider_events().len() < count {
+                yield_now().await;
+            }
+        };
+
+        run_with_devices(power_policy, [ID], |[device], _| async move {
+            let idle = device
+                .try_device_action::<action::Detached>()
+                .await
+                .unwrap()
+                .attach()
+                .await
+                .unwrap();
+            idle.request_provider_power_capability(power).await.unwrap();
+            wait_events(1).await;
+            assert!(device.is_provider().await);
+            assert_eq!(power_policy.active_providers::<4>().await, [ID]);
+
+            device
+                .try_device_action::<action::ConnectedProvider>()
+                .await
+                .unwrap()
+                .detach()
+                .await
+                .unwrap();
+            wait_events(2).await;
+
+            assert_eq!(
+                recorder.provider_events(),
+                [CommsData::ProviderEnabled(ID, power), CommsData::ProviderDisabled(ID)]
+            );
+            assert!(power_policy.active_providers::<4>().await.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_current_consumer() {
+        const ID: DeviceId = DeviceId(1);
+        const POWER: PowerCapability = PowerCapability {
+            voltage_mv: 20000,
+            current_ma: 3000,
+        };
+        let (_guard, shared) = shared();
+        let power_policy = shared.power_policy;
+
+        run_with_devices(power_policy, [ID], |[device], _| async move {
+            assert_eq!(power_policy.current_consumer().await, None);
+
+            let idle = device
+                .try_device_action::<action::Detached>()
+                .await
+                .unwrap()
+                .attach()
+                .await
+                .unwrap();
+            idle.notify_consumer_power_capability(Some(POWER)).await.unwrap();
+            while !device.is_consumer().await {
+                yield_now().await;
+            }
+
+            assert_eq!(power_policy.current_consumer().await, Some((ID, POWER)));
+            assert!(power_policy.active_providers::<4>().await.is_empty());
+
+            device
+                .try_device_action::<action::ConnectedConsumer>()
+                .await
+                .unwrap()
+                .detach()
+                .await
+                .unwrap();
+            while power_policy.current_consumer().await.is_some() {
+                yield_now().await;
+            }
+        });
+    }
+
+    #[test]
+    fn test_consumer_switchover_overlap() {
+        const ID_A: DeviceId = DeviceId(2);
+        const ID_B: DeviceId = DeviceId(3);
+        const POWER_A: PowerCapability = PowerCapability {
+            voltage_mv: 5000,
+            current_ma: 3000,
+        };
+        const POWER_B: PowerCapability = PowerCapability {
+            voltage_mv: 20000,
+            current_ma: 3000,
+        };
+        let (_guard, shared) = shared();
+        let power_policy = shared.power_policy;
+
+        run_with_devices(power_policy, [ID_A, ID_B], |[device_a, device_b], log| async move {
+            let attach = |device: &'static Device, power| async move {
+                let idle = device
+                    .try_device_action::<action::Detached>()
+                    .await
+                    .unwrap()
+                    .attach()
+                    .await
+                    .unwrap();
+                idle.notify_consumer_power_capability(Some(power)).await.unwrap();
+            };
+
+            attach(device_a, POWER_A).await;
+            while power_policy.current_consumer().await != Some((ID_A, POWER_A)) {
+                yield_now().await;
+            }
+
+            // Higher power consumer, B is connected before A is disconnected
+            attach(device_b, POWER_B).await;
+            while log.borrow().len() < 3 {
+                yield_now().await;
+            }
+
+            assert_eq!(
+                *log.borrow(),
+                [
+                    (ID_A, device::CommandData::ConnectConsumer(POWER_A)),
+                    (ID_B, device::CommandData::ConnectConsumer(POWER_B)),
+                    (ID_A, device::CommandData::Disconnect),
+                ]
+            );
+            assert_eq!(power_policy.current_consumer().await, Some((ID_B, POWER_B)));
+            assert!(device_b.is_consumer().await);
+            assert!(!device_a.is_consumer().await);
+
+            // Leave both devices detached
+            for device in [device_a, device_b] {
+                device.detach().await.unwrap();
+            }
+            while power_policy.current_consumer().await.is_some() {
+                yield_now().await;
+            }
+        });
+    }
+
+    #[test]
+    fn test_step_provider_request() {
+        const ID: DeviceId = DeviceId(4);
+        let (_guard, shared) = shared();
+        let power_policy = shared.power_policy;
+        let power = power_policy.config.provider_unlimited;
+        let [device] = register_devices([ID]);
+        let log = RefCell::new(Vec::new());
+
+        let (result, idle) = step(power_policy, [device], &log, async {
+            device
+                .try_device_action::<action::Detached>()
+                .await
+                .unwrap()
+                .attach()
+                .await
+                .unwrap()
+        });
+        assert_eq!(result, Ok(()));
+        assert!(log.borrow().is_empty());
+
+        let (result, ()) = step(power_policy, [device], &log, async {
+            idle.request_provider_power_capability(power).await.unwrap()
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(*log.borrow(), [(ID, device::CommandData::ConnectProvider(power))]);
+        assert!(block_on(device.is_provider()));
+        assert_eq!(block_on(power_policy.active_providers::<4>()), [ID]);
+
+        let (result, _) = step(power_policy, [device], &log, device.detach());
+        assert_eq!(result, Ok(()));
+        assert!(block_on(power_policy.active_providers::<4>()).is_empty());
+    }
+
+    #[test]
+    fn test_fault_disconnect_suppresses_recovery() {
+        const ID: DeviceId = DeviceId(5);
+        let (_guard, shared) = shared();
+        let power_policy = shared.power_policy;
+        let power = power_policy.config.provider_unlimited;
+        let [device] = register_devices([ID]);
+        let log = RefCell::new(Vec::new());
+
+        let (_, idle) = step(power_policy, [device], &log, async {
+            device
+                .try_device_action::<action::Detached>()
+                .await
+                .unwrap()
+                .attach()
+                .await
+                .unwrap()
+        });
+
+        for (reason, expect_recovery) in [
+            (policy::DisconnectReason::Fault, false),
+            (policy::DisconnectReason::Unplug, true),
+        ] {
+            let (result, ()) = step(power_policy, [device], &log, async {
+                idle.request_provider_power_capability(power).await.unwrap()
+            });
+            assert_eq!(result, Ok(()));
+            assert!(block_on(device.is_provider()));
+
+            let (result, _) = step(power_policy, [device], &log, async {
+                device
+                    .try_device_action::<action::ConnectedProvider>()
+                    .await
+                    .unwrap()
+                    .disconnect_with_reason(reason)
+                    .await
+                    .unwrap()
+            });
+            assert_eq!(result, Ok(()));
+            assert_eq!(power_policy.should_attempt_recovery(true), expect_recovery);
+        }
+
+        let (result, _) = step(power_policy, [device], &log, device.detach());
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_thermal_power_cap() {
+        const ID: DeviceId = DeviceId(6);
+        const POWER: PowerCapability = PowerCapability {
+            voltage_mv: 20000,
+            current_ma: 3000,
+        };
+        const CAPPED: PowerCapability = PowerCapability {
+            voltage_mv: 20000,
+            current_ma: 1500,
+        };
+        let (_guard, shared) = shared();
+        let power_policy = shared.power_policy;
+        let [device] = register_devices([ID]);
+        let log = RefCell::new(Vec::new());
+
+        let (_, idle) = step(power_policy, [device], &log, async {
+            device
+                .try_device_action::<action::Detached>()
+                .await
+                .unwrap()
+                .attach()
+                .await
+                .unwrap()
+        });
+        let (result, ()) = step(power_policy, [device], &log, async {
+            idle.notify_consumer_power_capability(Some(POWER)).await.unwrap();
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(block_on(power_policy.current_consumer()), Some((ID, POWER)));
+
+        let set_cap = |cap_mw| {
+            block_on(async {
+                match select(power_policy.set_thermal_power_cap(cap_mw), respond([device], &log)).await {
+                    Either::First(result) => result,
+                    Either::Second(()) => unreachable!(),
+                }
+            })
+        };
+
+        // Cap below the current contract, renegotiate without disconnecting
+        log.borrow_mut().clear();
+        assert_eq!(set_cap(Some(30000)), Ok(()));
+        assert_eq!(*log.borrow(), [(ID, device::CommandData::ConnectConsumer(CAPPED))]);
+        assert_eq!(block_on(power_policy.current_consumer()), Some((ID, CAPPED)));
+        assert!(block_on(device.is_consumer()));
+
+        // Removing the cap restores the full contract
+        log.borrow_mut().clear();
+        assert_eq!(set_cap(None), Ok(()));
+        assert_eq!(*log.borrow(), [(ID, device::CommandData::ConnectConsumer(POWER))]);
+        assert_eq!(block_on(power_policy.current_consumer()), Some((ID, POWER)));
+
+        let (result, _) = step(power_policy, [device], &log, device.detach());
+        assert_eq!(result, Ok(()));
+        assert_eq!(block_on(power_policy.current_consumer()), None);
+    }
+
+    #[test]
+    fn test_consumer_cannot_provide() {
+        const ID: DeviceId = DeviceId(7);
+        const POWER: PowerCapability = PowerCapability {
+            voltage_mv: 20000,
+            current_ma: 3000,
+        };
+        let (_guard, shared) = shared();
+        let power_policy = shared.power_policy;
+        let power = power_policy.config.provider_unlimited;
+        let [device] = register_devices([ID]);
+        let log = RefCell::new(Vec::new());
+
+        let (_, idle) = step(power_policy, [device], &log, async {
+            device
+                .try_device_action::<action::Detached>()
+                .await
+                .unwrap()
+                .attach()
+                .await
+                .unwrap()
+        });
+        let (result, ()) = step(power_policy, [device], &log, async {
+            idle.notify_consumer_power_capability(Some(POWER)).await.unwrap();
+        });
+        assert_eq!(result, Ok(()));
+        assert!(block_on(device.is_consumer()));
+
+        // The idle handle is stale, the device is now a connected consumer
+        log.borrow_mut().clear();
+        let expected = Err(Error::InvalidState(
+            device::StateKind::Idle,
+            device::StateKind::ConnectedConsumer,
+        ));
+        let (result, response) = step(
+            power_policy,
+            [device],
+            &log,
+            idle.request_provider_power_capability(power),
+        );
+        assert_eq!(result, expected);
+        assert_eq!(response, expected);
+        assert!(log.borrow().is_empty());
+        assert!(block_on(device.is_consumer()));
+        assert!(block_on(power_policy.active_providers::<4>()).is_empty());
+
+        let (result, _) = step(power_policy, [device], &log, device.detach());
+        assert_eq!(result, Ok(()));
+        assert_eq!(block_on(power_policy.current_consumer()), None);
+    }
+
+    #[test]
+    fn test_reserve_provider_budget() {
+        const ID: DeviceId = DeviceId(8);
+        const CLAMPED: PowerCapability = PowerCapability {
+            voltage_mv: 5000,
+            current_ma: 1500,
+        };
+        let (_guard, shared) = shared();
+        let power_policy = shared.power_policy;
+        let power = power_policy.config.provider_unlimited;
+        let [device] = register_devices([ID]);
+        let log = RefCell::new(Vec::new());
+        let attach = || async {
+            device
+                .try_device_action::<action::Detached>()
+                .await
+                .unwrap()
+                .attach()
+                .await
+                .unwrap()
+        };
+
+        // Leave 7.5W of the budget for providers
+        let reservation = block_on(power_policy.reserve_provider_budget(TOTAL_BUDGET_MW - 7500)).unwrap();
+        assert_eq!(
+            block_on(power_policy.reserve_provider_budget(7501)).err(),
+            Some(Error::CannotProvide(None))
+        );
+
+        let (_, idle) = step(power_policy, [device], &log, attach());
+        let (result, response) = step(
+            power_policy,
+            [device],
+            &log,
+            idle.request_provider_power_capability(power),
+        );
+        assert_eq!(result, Err(Error::CannotProvide(Some(CLAMPED))));
+        assert_eq!(response, result);
+        assert_eq!(*log.borrow(), [(ID, device::CommandData::ConnectProvider(CLAMPED))]);
+        let (result, _) = step(power_policy, [device], &log, device.detach());
+        assert_eq!(result, Ok(()));
+
+        // Releasing restores the full budget for the next request
+        reservation.release();
+        let (_, idle) = step(power_policy, [device], &log, attach());
+        log.borrow_mut().clear();
+        let (result, ()) = step(power_policy, [device], &log, async {
+            idle.request_provider_power_capability(power).await.unwrap()
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(*log.borrow(), [(ID, device::CommandData::ConnectProvider(power))]);
+
+        let (result, _) = step(power_policy, [device], &log, device.detach());
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_consumer_notification_sequence() {
+        const ID: DeviceId = DeviceId(9);
+        const POWER: PowerCapability = PowerCapability {
+            voltage_mv: 20000,
+            current_ma: 3000,
+        };
+        let (_guard, shared) = shared();
+        let power_policy = shared.power_policy;
+        let recorder = shared.recorder;
+        let [device] = register_devices([ID]);
+        let log = RefCell::new(Vec::new());
+
+        let (result, idle) = step(power_policy, [device], &log, async {
+            device
+                .try_device_action::<action::Detached>()
+                .await
+                .unwrap()
+                .attach()
+                .await
+                .unwrap()
+        });
+        assert_eq!(result, Ok(()));
+        // Attaching alone doesn't leave the idle state
+        assert!(recorder.events.borrow().is_empty());
+
+        let (result, ()) = step(power_policy, [device], &log, async {
+            idle.notify_consumer_power_capability(Some(POWER)).await.unwrap();
+        });
+        assert_eq!(result, Ok(()));
+
+        let (result, _) = step(power_policy, [device], &log, device.detach());
+        assert_eq!(result, Ok(()));
+
+        assert_eq!(
+            *recorder.events.borrow(),
+            [
+                CommsData::ConsumerConnected(ID, POWER),
+                CommsData::Active,
+                CommsData::ConsumerDisconnected(ID),
+                CommsData::Idle,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_idle_notifications() {
+        const ID: DeviceId = DeviceId(10);
+        const POWER: PowerCapability = PowerCapability {
+            voltage_mv: 20000,
+            current_ma: 3000,
+        };
+        let (_guard, shared) = shared();
+        let power_policy = shared.power_policy;
+        let recorder = shared.recorder;
+        let power = power_policy.config.provider_unlimited;
+        let [device] = register_devices([ID]);
+        let log = RefCell::new(Vec::new());
+        let idle_events = || -> Vec<CommsData> {
+            recorder
+                .events
+                .borrow()
+                .iter()
+                .copied()
+                .filter(|data| matches!(data, CommsData::Idle | CommsData::Active))
+                .collect()
+        };
+        let attach = || async {
+            device
+                .try_device_action::<action::Detached>()
+                .await
+                .unwrap()
+                .attach()
+                .await
+                .unwrap()
+        };
+
+        // Attaching alone doesn't connect anything, earlier tests left the policy idle
+        let (result, idle) = step(power_policy, [device], &log, attach());
+        assert_eq!(result, Ok(()));
+        assert!(idle_events().is_empty());
+
+        let (result, ()) = step(power_policy, [device], &log, async {
+            idle.notify_consumer_power_capability(Some(POWER)).await.unwrap();
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(idle_events(), [CommsData::Active]);
+
+        let (result, _) = step(power_policy, [device], &log, device.detach());
+        assert_eq!(result, Ok(()));
+        assert_eq!(idle_events(), [CommsData::Active, CommsData::Idle]);
+
+        // A provider is just as active as a consumer
+        let (_, idle) = step(power_policy, [device], &log, attach());
+        let (result, ()) = step(power_policy, [device], &log, async {
+            idle.request_provider_power_capability(power).await.unwrap()
+        });
+        assert_eq!(result, Ok(()));
+        let (result, _) = step(power_policy, [device], &log, device.detach());
+        assert_eq!(result, Ok(()));
+
+        assert_eq!(
+            idle_events(),
+            [CommsData::Active, CommsData::Idle, CommsData::Active, CommsData::Idle]
+        );
+    }
+
+    #[test]
+    fn test_recovery_backoff() {
+        const ID: DeviceId = DeviceId(11);
+        let (_guard, shared) = shared();
+        let power_policy = shared.power_policy;
+        let config = power_policy.config;
+        let [device] = register_devices([ID]);
+        let log = RefCell::new(Vec::new());
+        let interval = || power_policy.recovery_ticker.borrow().0;
+
+        // Completes every command, failing disconnects if requested
+        let respond_disconnect = |fail: bool| async move {
+            loop {
+                let request = device.receive().await;
+                request.respond(match request.command {
+                    device::CommandData::Disconnect if fail => Err(Error::Failed),
+                    _ => Ok(device::ResponseData::Complete),
+                });
+            }
+        };
+        let attempt_recovery = |fail: bool| {
+            block_on(async {
+                match select(power_policy.attempt_provider_recovery(), respond_disconnect(fail)).await {
+                    Either::First(()) => (),
+                    Either::Second(()) => unreachable!(),
+                }
+            })
+        };
+
+        let (_, idle) = step(power_policy, [device], &log, async {
+            device
+                .try_device_action::<action::Detached>()
+                .await
+                .unwrap()
+                .attach()
+                .await
+                .unwrap()
+        });
+        let (result, ()) = step(power_policy, [device], &log, async {
+            idle.request_provider_power_capability(config.provider_unlimited)
+                .await
+                .unwrap()
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(interval(), config.provider_recovery_interval);
+
+        // A provider that can't be disconnected puts the policy in recovery
+        block_on(async {
+            let provider = power_policy
+                .context
+                .try_policy_action::<action::ConnectedProvider>(ID)
+                .await
+                .unwrap();
+            match select(provider.disconnect(), respond_disconnect(true)).await {
+                Either::First(result) => assert!(result.is_err()),
+                Either::Second(()) => unreachable!(),
+            }
+            power_policy.state.lock().await.current_provider_state.state = provider::PowerState::Recovery;
+        });
+        assert!(block_on(device.is_in_recovery()));
+
+        // The ticker is rescheduled at double the interval after each failed attempt, up to the max
+        let mut expected = config.provider_recovery_interval;
+        for _ in 0..7 {
+            attempt_recovery(true);
+            expected = (expected * 2).min(config.provider_recovery_max_interval);
+            assert_eq!(interval(), expected);
+            assert!(block_on(device.is_in_recovery()));
+        }
+        assert_eq!(interval(), config.provider_recovery_max_interval);
+
+        // Successful recovery goes back to the initial interval
+        attempt_recovery(false);
+        assert_eq!(interval(), config.provider_recovery_interval);
+        assert!(!block_on(device.is_in_recovery()));
+        assert_eq!(
+            block_on(power_policy.state.lock()).current_provider_state.state,
+            provider::PowerState::Unlimited
+        );
+
+        let (result, _) = step(power_policy, [device], &log, device.detach());
+        assert_eq!(result, Ok(()));
+    }
+}
@@ -9,6 +9,11 @@
 //! [attempt_provider_recovery](PowerPolicy::attempt_provider_recovery) is called periodically
 //! which attempts to disconnect all providers in recovery mode. If this succeeds, the system will
 //! return to normal operating mode.
+//! If [total_budget_mw](super::Config::total_budget_mw) is set, providers are granted power in order
+//! until the budget is exhausted. Providers that don't fit are clamped to the remaining budget.
+//! Part of the budget can be set aside with [reserve_provider_budget](PowerPolicy::reserve_provider_budget),
+//! it isn't granted to providers until the [Reservation] is released.
+use embassy_time::{Duration, Ticker};
 use embedded_services::{debug, trace, warn};
 
 use super::*;
@@ -26,11 +31,132 @@ pub enum PowerState {
     Limited,
 }
 
+/// Set of devices, indexed by device ID
+#[derive(Clone, Copy, Default)]
+struct DeviceSet([u32; 8]);
+
+impl DeviceSet {
+    fn insert(&mut self, id: DeviceId) {
+        self.0[id.0 as usize / 32] |= 1 << (id.0 % 32);
+    }
+
+    fn remove(&mut self, id: DeviceId) {
+        self.0[id.0 as usize / 32] &= !(1 << (id.0 % 32));
+    }
+
+    fn contains(&self, id: DeviceId) -> bool {
+        self.0[id.0 as usize / 32] & (1 << (id.0 % 32)) != 0
+    }
+}
+
 /// Power policy provider global state
 #[derive(Clone, Copy, Default)]
 pub(super) struct State {
     /// Current power state
     state: PowerState,
+    /// Providers that have been notified as enabled
+    enabled: DeviceSet,
+}
+
+/// Tracks the power remaining in the global provider budget
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct Budget {
+    /// Remaining power, unlimited if `None`
+    remaining_mw: Option<u32>,
+}
+
+impl Budget {
+    /// Create a new budget with the given total power
+    pub(super) fn new(total_mw: Option<u32>) -> Self {
+        Self { remaining_mw: total_mw }
+    }
+
+    /// Allocate up to `target` from the budget
+    /// Returns the largest capability that fits or `None` if the budget is exhausted
+    pub(super) fn allocate(&mut self, target: PowerCapability) -> Option<PowerCapability> {
+        let Some(remaining_mw) = self.remaining_mw.as_mut() else {
+            return Some(target);
+        };
+
+        let capability = if target.max_power_mw() <= *remaining_mw {
+            target
+        } else {
+            // Keep the same voltage and reduce the current to fit
+            let current_ma = (*remaining_mw * 1000 / target.voltage_mv as u32) as u16;
+            if current_ma == 0 {
+                return None;
+            }
+
+            PowerCapability {
+                voltage_mv: target.voltage_mv,
+                current_ma,
+            }
+        };
+
+        *remaining_mw -= capability.max_power_mw();
+        Some(capability)
+    }
+}
+
+/// Power reserved from the provider budget, returned to the budget when released or dropped
+pub struct Reservation<'a> {
+    power_policy: &'a PowerPolicy,
+    /// Reserved power
+    power_mw: u32,
+}
+
+impl Reservation<'_> {
+    /// Reserved power in mW
+    pub fn power_mw(&self) -> u32 {
+        self.power_mw
+    }
+
+    /// Return the reserved power to the budget
+    /// Connected providers aren't renegotiated, the power is available to the next provider request
+    pub fn release(self) {
+        drop(self)
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let reserved_mw = &self.power_policy.reserved_mw;
+        reserved_mw.set(reserved_mw.get() - self.power_mw);
+    }
+}
+
+/// Exponential backoff between provider recovery attempts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct RecoveryBackoff {
+    /// Initial interval
+    base: Duration,
+    /// Maximum interval
+    max: Duration,
+    /// Current interval
+    interval: Duration,
+}
+
+impl RecoveryBackoff {
+    /// Create a new backoff starting at `base` and capped at `max`
+    pub(super) fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max,
+            interval: base,
+        }
+    }
+
+    /// Double the interval after a failed attempt, returns the new interval
+    pub(super) fn failed(&mut self) -> Duration {
+        self.interval = (self.interval * 2).min(self.max);
+        self.interval
+    }
+
+    /// Reset the interval after a successful recovery, returns the new interval
+    pub(super) fn recovered(&mut self) -> Duration {
+        self.interval = self.base;
+        self.interval
+    }
 }
 
 impl PowerPolicy {
@@ -68,7 +194,13 @@ impl PowerPolicy {
 
     /// Update the power capability of all connected providers
     /// Returns true if we need to enter recovery mode
-    async fn update_provider_capability(&self, target_power: PowerCapability, exit_on_recovery: bool) -> bool {
+    async fn update_provider_capability(
+        &self,
+        target_power: PowerCapability,
+        exit_on_recovery: bool,
+        budget: &mut Budget,
+        provider_state: &mut State,
+    ) -> bool {
         let mut recovery = false;
         for device in self.context.devices().await {
             let device = device.data::<device::Device>();
@@ -84,26 +216,42 @@ impl PowerPolicy {
                 .try_policy_action::<action::ConnectedProvider>(device.id())
                 .await
             {
-                if action.power_capability().await != target_power {
+                let current_power = action.power_capability().await;
+                let connected = match budget.allocate(target_power) {
+                    Some(power) if power == current_power => true,
                     // Attempt to connect at new capability. Don't exit early if this fails so
                     // we can continue to attempt to connect other providers
-                    if action.connect_provider(target_power).await.is_err() {
-                        error!(
-                            "Device{}: Failed to connect provider, attempting to disconnect",
-                            device.id().0
-                        );
-
-                        if action.disconnect().await.is_err() {
-                            error!("Device{}: Failed to disconnect provider", device.id().0);
-
-                            // Early exit if that's what we want
-                            // This is used to avoid excessively switching power capabilities in the recovery flow
-                            if exit_on_recovery {
-                                return true;
-                            }
-
-                            recovery = true;
+                    Some(power) => {
+                        let connected = action.connect_provider(power).await.is_ok();
+                        if connected {
+                            self.notify_provider_enabled(provider_state, device.id(), power).await;
+                        }
+                        connected
+                    }
+                    None => {
+                        warn!("Device{}: Power budget exhausted", device.id().0);
+                        false
+                    }
+                };
+
+                if !connected {
+                    error!(
+                        "Device{}: Failed to connect provider, attempting to disconnect",
+                        device.id().0
+                    );
+
+                    if action.disconnect().await.is_ok() {
+                        self.notify_provider_disabled(provider_state, device.id()).await;
+                    } else {
+                        error!("Device{}: Failed to disconnect provider", device.id().0);
+
+                        // Early exit if that's what we want
+                        // This is used to avoid excessively switching power capabilities in the recovery flow
+                        if exit_on_recovery {
+                            return true;
                         }
+
+                        recovery = true;
                     }
                 }
             }
@@ -112,11 +260,108 @@ impl PowerPolicy {
         recovery
     }
 
+    /// Notify that a provider contract was established
+    async fn notify_provider_enabled(&self, provider_state: &mut State, id: DeviceId, power: PowerCapability) {
+        provider_state.enabled.insert(id);
+        self.comms_notify(CommsMessage {
+            data: CommsData::ProviderEnabled(id, power),
+        })
+        .await;
+    }
+
+    /// Notify that a provider contract was torn down
+    async fn notify_provider_disabled(&self, provider_state: &mut State, id: DeviceId) {
+        if !provider_state.enabled.contains(id) {
+            return;
+        }
+
+        provider_state.enabled.remove(id);
+        self.comms_notify(CommsMessage {
+            data: CommsData::ProviderDisabled(id),
+        })
+        .await;
+    }
+
+    /// Notify for any provider that has disconnected or detached on its own
+    async fn notify_removed_providers(&self, provider_state: &mut State) {
+        for device in self.context.devices().await {
+            if let Some(device) = device.data::<device::Device>() {
+                if provider_state.enabled.contains(device.id()) && !device.is_provider().await {
+                    self.notify_provider_disabled(provider_state, device.id()).await;
+                }
+            }
+        }
+    }
+
+    /// Reserve power from the provider budget for a future consumer
+    /// Providers requesting power after this are limited by the reduced budget until the reservation is released
+    /// Returns [`Error::CannotProvide`] if the power isn't available, the reservation always succeeds without a budget
+    pub async fn reserve_provider_budget(&self, power_mw: u32) -> Result<Reservation<'_>, Error> {
+        if let Some(total_budget_mw) = self.config.total_budget_mw {
+            let used_mw = self.reserved_mw.get() + self.provided_power_mw().await;
+            if used_mw.saturating_add(power_mw) > total_budget_mw {
+                warn!(
+                    "Cannot reserve {}mW, {}mW of {}mW in use",
+                    power_mw, used_mw, total_budget_mw
+                );
+                return Err(Error::CannotProvide(None));
+            }
+        }
+
+        self.reserved_mw.set(self.reserved_mw.get() + power_mw);
+        Ok(Reservation {
+            power_policy: self,
+            power_mw,
+        })
+    }
+
+    /// Total power granted to connected providers
+    async fn provided_power_mw(&self) -> u32 {
+        let mut provided_mw = 0;
+        for device in self.context.devices().await {
+            let Some(device) = device.data::<device::Device>() else {
+                warn!("Found non-power device in devices list");
+                continue;
+            };
+
+            if let Ok(action) = self
+                .context
+                .try_policy_action::<action::ConnectedProvider>(device.id())
+                .await
+            {
+                provided_mw += action.power_capability().await.max_power_mw();
+            }
+        }
+        provided_mw
+    }
+
+    /// Provider budget left after reservations
+    fn provider_budget(&self) -> Budget {
+        Budget::new(
+            self.config
+                .total_budget_mw
+                .map(|total_budget_mw| total_budget_mw.saturating_sub(self.reserved_mw.get())),
+        )
+    }
+
+    /// Check that a device may become a provider, a connected consumer would back-feed its port
+    /// Returns [`Error::InvalidState`] if the device is currently consuming power
+    pub(super) async fn check_provider_allowed(&self, id: DeviceId) -> Result<(), Error> {
+        let state = self.context.get_device(id).await?.state().await.kind();
+        if state == device::StateKind::ConnectedConsumer {
+            error!("Device {}: Cannot provide while consuming", id.0);
+            return Err(Error::InvalidState(device::StateKind::Idle, state));
+        }
+        Ok(())
+    }
+
     /// Update the provider state of currently connected providers
-    pub(super) async fn update_providers(&self, new_provider: Option<DeviceId>) {
+    /// Returns [`Error::CannotProvide`] if the new provider doesn't fit in the remaining power budget
+    pub(super) async fn update_providers(&self, new_provider: Option<DeviceId>) -> Result<(), Error> {
         trace!("Updating providers");
         let mut state = self.state.lock().await;
         let mut already_in_recovery = true;
+        self.notify_removed_providers(&mut state.current_provider_state).await;
 
         if state.current_provider_state.state != PowerState::Recovery {
             // Only update the power state if we're not in recovery mode
@@ -131,19 +376,42 @@ impl PowerPolicy {
             PowerState::Limited => self.config.provider_limited,
         };
 
-        let recovery = self.update_provider_capability(target_power, true).await;
+        let mut budget = self.provider_budget();
+        let recovery = self
+            .update_provider_capability(target_power, true, &mut budget, &mut state.current_provider_state)
+            .await;
+        let mut result = Ok(());
         if let Some(new_provider) = new_provider {
             info!("Connecting new provider");
-            let connected = if let Ok(action) = self.context.try_policy_action::<action::Idle>(new_provider).await {
-                let target_power = if recovery {
-                    // We entered recovery mode so attempt to connect at the recovery power
-                    self.config.provider_recovery
-                } else {
-                    target_power
-                };
-                action.connect_provider(target_power).await.is_ok()
+            let target_power = if recovery {
+                // We entered recovery mode so attempt to connect at the recovery power
+                self.config.provider_recovery
             } else {
-                false
+                target_power
+            };
+
+            let available_power = budget.allocate(target_power);
+            if available_power != Some(target_power) {
+                info!(
+                    "Device {}: Provider clamped by power budget: {:?}",
+                    new_provider.0, available_power
+                );
+                result = Err(Error::CannotProvide(available_power));
+            }
+
+            let connected = match (
+                available_power,
+                self.context.try_policy_action::<action::Idle>(new_provider).await,
+            ) {
+                (Some(power), Ok(action)) => {
+                    let connected = action.connect_provider(power).await.is_ok();
+                    if connected {
+                        self.notify_provider_enabled(&mut state.current_provider_state, new_provider, power)
+                            .await;
+                    }
+                    connected
+                }
+                _ => false,
             };
 
             // Don't enter recovery mode if we can't connect the new provider.
@@ -160,17 +428,63 @@ impl PowerPolicy {
             // then it'll get caught by the next call of attempt_provider_recovery
             info!("Entering recovery mode");
             let _ = self
-                .update_provider_capability(self.config.provider_recovery, false)
+                .update_provider_capability(
+                    self.config.provider_recovery,
+                    false,
+                    &mut self.provider_budget(),
+                    &mut state.current_provider_state,
+                )
                 .await;
             state.current_provider_state.state = PowerState::Recovery;
         }
+
+        result
+    }
+
+    /// Returns the IDs of all providers with an established contract
+    pub async fn active_providers<const N: usize>(&self) -> heapless::Vec<DeviceId, N> {
+        let enabled = self.state.lock().await.current_provider_state.enabled;
+        let mut providers = heapless::Vec::new();
+
+        for id in (0..=u8::MAX).map(DeviceId).filter(|id| enabled.contains(*id)) {
+            if providers.push(id).is_err() {
+                error!("Device {}: No space to record provider", id.0);
+            }
+        }
+
+        providers
     }
 
     /// Wait for the next provider recovery attempt, returns true if we should call `attempt_provider_recovery`
     #[allow(clippy::await_holding_refcell_ref)]
     pub(super) async fn wait_attempt_provider_recovery(&self) -> bool {
-        self.recovery_ticker.borrow_mut().next().await;
-        self.state.lock().await.current_provider_state.state == PowerState::Recovery
+        self.recovery_ticker.borrow_mut().1.next().await;
+        let in_recovery = self.state.lock().await.current_provider_state.state == PowerState::Recovery;
+        self.should_attempt_recovery(in_recovery)
+    }
+
+    /// Returns true if recovery should be attempted, consumes any pending suppression
+    pub(super) fn should_attempt_recovery(&self, in_recovery: bool) -> bool {
+        if self.recovery_suppressed.replace(false) {
+            info!("Provider recovery suppressed");
+            return false;
+        }
+
+        in_recovery
+    }
+
+    /// Update the recovery backoff and reschedule the recovery ticker with the new interval
+    fn update_recovery_backoff(&self, recovered: bool) {
+        let mut backoff = self.recovery_backoff.get();
+        let interval = if recovered {
+            backoff.recovered()
+        } else {
+            backoff.failed()
+        };
+        self.recovery_backoff.set(backoff);
+
+        debug!("Next provider recovery interval: {}ms", interval.as_millis());
+        *self.recovery_ticker.borrow_mut() = (interval, Ticker::every(interval));
     }
 
     pub(super) async fn attempt_provider_recovery(&self) {
@@ -202,17 +516,57 @@ impl PowerPolicy {
 
         if !recovered {
             info!("Failed to recover all providers, staying in recovery mode");
+            self.update_recovery_backoff(false);
             return;
         }
 
         // Attempt to restart in the unlimited power state
         self.state.lock().await.current_provider_state.state = PowerState::Unlimited;
-        self.update_providers(None).await;
+        // Errors are only returned for new providers
+        let _ = self.update_providers(None).await;
         if self.state.lock().await.current_provider_state.state == PowerState::Recovery {
             info!("Failed to update providers, staying in recovery mode");
+            self.update_recovery_backoff(false);
             return;
         }
 
         info!("Successfully recovered from provider recovery mode");
+        self.update_recovery_backoff(true);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PROVIDER: PowerCapability = PowerCapability {
+        voltage_mv: 5000,
+        current_ma: 3000,
+    };
+
+    #[test]
+    fn test_budget_unlimited() {
+        let mut budget = Budget::new(None);
+        assert_eq!(budget.allocate(PROVIDER), Some(PROVIDER));
+        assert_eq!(budget.allocate(PROVIDER), Some(PROVIDER));
+    }
+
+    #[test]
+    fn test_budget_two_providers() {
+        // First provider fits, second is clamped to the remaining 7.5W
+        let mut budget = Budget::new(Some(22500));
+        assert_eq!(budget.allocate(PROVIDER), Some(PROVIDER));
+        assert_eq!(
+            budget.allocate(PROVIDER),
+            Some(PowerCapability {
+                voltage_mv: 5000,
+                current_ma: 1500,
+            })
+        );
+        assert_eq!(budget.allocate(PROVIDER), None);
+
+        // Budget is recomputed from scratch after the first provider detaches, so the second is un-clamped
+        let mut budget = Budget::new(Some(22500));
+        assert_eq!(budget.allocate(PROVIDER), Some(PROVIDER));
     }
 }
@@ -1,21 +1,24 @@
 use core::array::from_fn;
-use core::cell::{Cell, RefCell};
+use core::cell::Cell;
 use core::iter::zip;
 
-use ::tps6699x::registers::field_sets::IntEventBus1;
+use ::tps6699x::registers::field_sets::{IntEventBus1, PowerPathStatus as PowerPathStatusRegister};
 use ::tps6699x::registers::{PdCcPullUp, PpExtVbusSw, PpIntVbusSw};
-use ::tps6699x::{PORT0, PORT1, TPS66993_NUM_PORTS, TPS66994_NUM_PORTS};
+use ::tps6699x::{TPS66993_NUM_PORTS, TPS66994_NUM_PORTS};
 use bitfield::bitfield;
 use embassy_futures::select::select;
 use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::mutex::Mutex;
 use embassy_sync::signal::Signal;
 use embedded_hal_async::i2c::I2c;
 use embedded_services::power::policy::{self, PowerCapability};
-use embedded_services::type_c::controller::{self, Controller, ControllerStatus, PortStatus};
+use embedded_services::type_c::controller::{
+    self, AltModeInfo, AlternateModes, Controller, ControllerStatus, DataRole, PdoList, PortStatus, MAX_PDOS,
+};
 use embedded_services::type_c::event::PortEventKind;
 use embedded_services::type_c::ControllerId;
-use embedded_services::{debug, info, trace, type_c};
-use embedded_usb_pd::pdinfo::PowerPathStatus;
+use embedded_services::{debug, error, info, trace, type_c};
+use embedded_usb_pd::pdinfo::{AltMode, PowerPathStatus};
 use embedded_usb_pd::pdo::{sink, source, Common, Rdo};
 use embedded_usb_pd::type_c::Current as TypecCurrent;
 use embedded_usb_pd::{Error, GlobalPortId, PdError, PortId as LocalPortId, PowerRole};
@@ -23,11 +26,76 @@ use tps6699x::asynchronous::embassy as tps6699x;
 
 use crate::wrapper::ControllerWrapper;
 
+/// DisplayPort standard ID
+const DP_SVID: u16 = 0xff01;
+
+/// SVIDs of the alternate modes the TPS6699x application FW can enter, in CAM order
+/// Only DisplayPort is currently supported
+const TPS6699X_SVIDS: [u16; 1] = [DP_SVID];
+
+/// Returns the CAM index of the alternate mode with the given SVID
+fn alt_mode_index<BE>(svid: u16) -> Result<u8, Error<BE>> {
+    TPS6699X_SVIDS
+        .iter()
+        .position(|mode| *mode == svid)
+        .map(|index| index as u8)
+        .ok_or(Error::Pd(PdError::UnsupportedCommand))
+}
+
+/// Decodes a raw PDO, malformed PDOs are logged and return `None`
+fn decode_pdo<T: TryFrom<u32, Error = PdError>>(port: LocalPortId, pdo_raw: u32) -> Option<T> {
+    match T::try_from(pdo_raw) {
+        Ok(pdo) => Some(pdo),
+        Err(e) => {
+            error!("Port{}: Malformed PDO {:#X}: {:?}", port.0, pdo_raw, e);
+            None
+        }
+    }
+}
+
+/// Collects the valid PDOs of a capabilities register, `pdo` returns the raw PDO at the given index
+fn pdo_list(num_valid_pdos: u8, pdo: impl Fn(usize) -> u32) -> PdoList {
+    let mut pdos = PdoList::new();
+    for index in 0..(num_valid_pdos as usize).min(MAX_PDOS) {
+        // Can't fail, the list holds MAX_PDOS
+        let _ = pdos.push(pdo(index));
+    }
+    pdos
+}
+
+/// Returns the power path status of a port on an `N` port controller
+/// The register reports a pair of ports, even ports use the A switches and odd ports use the B switches
+fn port_power_path<const N: usize>(
+    register: &PowerPathStatusRegister,
+    port: LocalPortId,
+) -> Result<PowerPathStatus, PdError> {
+    if port.0 as usize >= N {
+        return Err(PdError::InvalidPort);
+    }
+
+    Ok(if port.0 % 2 == 0 {
+        PowerPathStatus::new(
+            register.pa_ext_vbus_sw() == PpExtVbusSw::EnabledInput,
+            register.pa_int_vbus_sw() == PpIntVbusSw::EnabledOutput,
+        )
+    } else {
+        PowerPathStatus::new(
+            register.pb_ext_vbus_sw() == PpExtVbusSw::EnabledInput,
+            register.pb_int_vbus_sw() == PpIntVbusSw::EnabledOutput,
+        )
+    })
+}
+
 pub struct Tps6699x<'a, const N: usize, M: RawMutex, B: I2c> {
     port_events: [Cell<PortEventKind>; N],
     port_status: [Cell<PortStatus>; N],
+    /// Power role requested by a PR swap that hasn't completed yet
+    pending_pr_swap: [Cell<Option<PowerRole>>; N],
+    /// Data role requested by a DR swap that hasn't completed yet
+    pending_dr_swap: [Cell<Option<DataRole>>; N],
     sw_event: Signal<M, ()>,
-    tps6699x: RefCell<tps6699x::Tps6699x<'a, M, B>>,
+    /// Locked rather than borrowed so a cancelled operation can't leave the controller borrowed
+    tps6699x: Mutex<M, tps6699x::Tps6699x<'a, M, B>>,
 }
 
 impl<'a, const N: usize, M: RawMutex, B: I2c> Tps6699x<'a, N, M, B> {
@@ -35,8 +103,10 @@ impl<'a, const N: usize, M: RawMutex, B: I2c> Tps6699x<'a, N, M, B> {
         Self {
             port_events: [const { Cell::new(PortEventKind::none()) }; N],
             port_status: [const { Cell::new(PortStatus::new()) }; N],
+            pending_pr_swap: [const { Cell::new(None) }; N],
+            pending_dr_swap: [const { Cell::new(None) }; N],
             sw_event: Signal::new(),
-            tps6699x: RefCell::new(tps6699x),
+            tps6699x: Mutex::new(tps6699x),
         }
     }
 
@@ -55,6 +125,34 @@ impl<'a, const N: usize, M: RawMutex, B: I2c> Tps6699x<'a, N, M, B> {
         let pd_status = tps6699x.get_pd_status(port).await?;
         trace!("Port{} PD status: {:#?}", port.0, pd_status);
 
+        if let Some(role) = self.pending_pr_swap[port.0 as usize].get() {
+            let current_role = if pd_status.is_source() {
+                PowerRole::Source
+            } else {
+                PowerRole::Sink
+            };
+
+            if current_role == role {
+                debug!("Port{}: PR swap to {:?} complete", port.0, role);
+                self.pending_pr_swap[port.0 as usize].set(None);
+                events.set_role_swap_complete(true);
+            }
+        }
+
+        if let Some(role) = self.pending_dr_swap[port.0 as usize].get() {
+            let current_role = if status.data_role() {
+                DataRole::Dfp
+            } else {
+                DataRole::Ufp
+            };
+
+            if current_role == role {
+                debug!("Port{}: DR swap to {:?} complete", port.0, role);
+                self.pending_dr_swap[port.0 as usize].set(None);
+                events.set_role_swap_complete(true);
+            }
+        }
+
         let port_control = tps6699x.get_port_control(port).await?;
         trace!("Port{} control: {:#?}", port.0, port_control);
 
@@ -66,6 +164,13 @@ impl<'a, const N: usize, M: RawMutex, B: I2c> Tps6699x<'a, N, M, B> {
         debug!("Port{} Plug present: {}", port.0, plug_present);
         debug!("Port{} Valid connection: {}", port.0, port_status.is_connected());
 
+        // Update power path status
+        let power_path = tps6699x.get_power_path_status(port).await?;
+        trace!("Port{} power path: {:#?}", port.0, power_path);
+        let power_path = port_power_path::<N>(&power_path, port)?;
+        // Report the sink FET state even when disconnected so a stuck path can be detected
+        port_status.sink_path_enabled = power_path.sink_path;
+
         if port_status.is_connected() {
             // Determine current contract if any
             let pdo_raw = tps6699x.get_active_pdo_contract(port).await?.active_pdo();
@@ -73,42 +178,50 @@ impl<'a, const N: usize, M: RawMutex, B: I2c> Tps6699x<'a, N, M, B> {
             let rdo_raw = tps6699x.get_active_rdo_contract(port).await?.active_rdo();
             trace!("Raw RDO: {:#X}", rdo_raw);
 
+            // A malformed PDO falls back to the implicit type-C contract so the rest of the status is still reported
+            let mut explicit_contract = false;
             if pdo_raw != 0 && rdo_raw != 0 {
-                // Got a valid explicit contract
+                // Explicit contract
                 if pd_status.is_source() {
-                    let pdo = source::Pdo::try_from(pdo_raw).map_err(Error::Pd)?;
-                    let rdo = Rdo::for_pdo(rdo_raw, pdo);
-                    debug!("PDO: {:#?}", pdo);
-                    debug!("RDO: {:#?}", rdo);
-                    port_status.available_source_contract = Some(PowerCapability::from(pdo));
-                    port_status.dual_power = pdo.is_dual_role();
-                } else {
-                    let pdo = sink::Pdo::try_from(pdo_raw).map_err(Error::Pd)?;
+                    if let Some(pdo) = decode_pdo::<source::Pdo>(port, pdo_raw) {
+                        let rdo = Rdo::for_pdo(rdo_raw, pdo);
+                        debug!("PDO: {:#?}", pdo);
+                        debug!("RDO: {:#?}", rdo);
+                        port_status.available_source_contract = Some(PowerCapability::from(pdo));
+                        port_status.dual_power = pdo.is_dual_role();
+                        explicit_contract = true;
+                    }
+                } else if let Some(pdo) = decode_pdo::<sink::Pdo>(port, pdo_raw) {
                     let rdo = Rdo::for_pdo(rdo_raw, pdo);
                     debug!("PDO: {:#?}", pdo);
                     debug!("RDO: {:#?}", rdo);
                     port_status.available_sink_contract = Some(PowerCapability::from(pdo));
-                    port_status.dual_power = pdo.is_dual_role()
+                    port_status.dual_power = pdo.is_dual_role();
+                    explicit_contract = true;
                 }
-            } else if pd_status.is_source() {
-                // Implicit source contract
-                let current = TypecCurrent::try_from(port_control.typec_current()).map_err(Error::Pd)?;
-                debug!("Port{} type-C source current: {:#?}", port.0, current);
-                let new_contract = Some(PowerCapability::from(current));
-                port_status.available_source_contract = new_contract;
-            } else {
-                // Implicit sink contract
-                let pull = pd_status.cc_pull_up();
-                let new_contract = if pull == PdCcPullUp::NoPull {
-                    // No pull up means no contract
-                    debug!("Port{} no pull up", port.0);
-                    None
+            }
+
+            if !explicit_contract {
+                if pd_status.is_source() {
+                    // Implicit source contract
+                    let current = TypecCurrent::try_from(port_control.typec_current()).map_err(Error::Pd)?;
+                    debug!("Port{} type-C source current: {:#?}", port.0, current);
+                    let new_contract = Some(PowerCapability::from(current));
+                    port_status.available_source_contract = new_contract;
                 } else {
-                    let current = TypecCurrent::try_from(pd_status.cc_pull_up()).map_err(Error::Pd)?;
-                    debug!("Port{} type-C sink current: {:#?}", port.0, current);
-                    Some(PowerCapability::from(current))
-                };
-                port_status.available_sink_contract = new_contract;
+                    // Implicit sink contract
+                    let pull = pd_status.cc_pull_up();
+                    let new_contract = if pull == PdCcPullUp::NoPull {
+                        // No pull up means no contract
+                        debug!("Port{} no pull up", port.0);
+                        None
+                    } else {
+                        let current = TypecCurrent::try_from(pd_status.cc_pull_up()).map_err(Error::Pd)?;
+                        debug!("Port{} type-C sink current: {:#?}", port.0, current);
+                        Some(PowerCapability::from(current))
+                    };
+                    port_status.available_sink_contract = new_contract;
+                }
             }
 
             // Update alt-mode status
@@ -116,19 +229,7 @@ impl<'a, const N: usize, M: RawMutex, B: I2c> Tps6699x<'a, N, M, B> {
             debug!("Port{} alt mode: {:#?}", port.0, alt_mode);
             port_status.alt_mode = alt_mode;
 
-            // Update power path status
-            let power_path = tps6699x.get_power_path_status(port).await?;
-            port_status.power_path = match port {
-                PORT0 => PowerPathStatus::new(
-                    power_path.pa_ext_vbus_sw() == PpExtVbusSw::EnabledInput,
-                    power_path.pa_int_vbus_sw() == PpIntVbusSw::EnabledOutput,
-                ),
-                PORT1 => PowerPathStatus::new(
-                    power_path.pb_ext_vbus_sw() == PpExtVbusSw::EnabledInput,
-                    power_path.pb_int_vbus_sw() == PpIntVbusSw::EnabledOutput,
-                ),
-                _ => Err(PdError::InvalidPort)?,
-            };
+            port_status.power_path = power_path;
             debug!("Port{} power path: {:#?}", port.0, port_status.power_path);
         }
 
@@ -146,6 +247,11 @@ impl<'a, const N: usize, M: RawMutex, B: I2c> Tps6699x<'a, N, M, B> {
             events.set_new_power_contract_as_provider(true);
         }
 
+        if port_status.contract_lost(&previous_status) {
+            debug!("Port{}: contract lost", port.0);
+            events.set_contract_lost(true);
+        }
+
         self.port_status[port.0 as usize].set(port_status);
         Ok(events)
     }
@@ -196,17 +302,33 @@ impl<'a, const N: usize, M: RawMutex, B: I2c> Tps6699x<'a, N, M, B> {
         cell.set(current.union(event));
         self.sw_event.signal(());
     }
+
+    /// Enter or exit DisplayPort through the app FW DP configuration register
+    async fn write_dp_mode(&self, port: LocalPortId, enter: bool) -> Result<(), Error<B::Error>> {
+        if port.0 >= self.port_status.len() as u8 {
+            return PdError::InvalidPort.into();
+        }
+
+        let mut tps6699x = self.tps6699x.lock().await;
+        let mut dp_config = tps6699x.get_dp_config(port).await?;
+        if !dp_config.enable_dp_svid() {
+            // Not offered to the partner so it can't be entered
+            return PdError::Rejected.into();
+        }
+
+        dp_config.set_enable_dp_mode(enter);
+        tps6699x.set_dp_config(port, dp_config).await
+    }
 }
 
 impl<const N: usize, M: RawMutex, B: I2c> Controller for Tps6699x<'_, N, M, B> {
     type BusError = B::Error;
 
     /// Controller specific initialization
-    #[allow(clippy::await_holding_refcell_ref)]
     async fn sync_state(&mut self) -> Result<(), Error<Self::BusError>> {
         for i in 0..N {
             let port = LocalPortId(i as u8);
-            let mut tps6699x = self.tps6699x.borrow_mut();
+            let mut tps6699x = self.tps6699x.lock().await;
             let event = self.update_port_status(&mut tps6699x, port).await?;
             self.signal_event(port, event);
         }
@@ -215,9 +337,8 @@ impl<const N: usize, M: RawMutex, B: I2c> Controller for Tps6699x<'_, N, M, B> {
     }
 
     /// Wait for an event on any port
-    #[allow(clippy::await_holding_refcell_ref)]
     async fn wait_port_event(&mut self) -> Result<(), Error<Self::BusError>> {
-        let mut tps6699x = self.tps6699x.borrow_mut();
+        let mut tps6699x = self.tps6699x.lock().await;
         let _ = select(self.wait_interrupt_event(&mut tps6699x), self.wait_sw_event()).await;
 
         for (i, cell) in self.port_events.iter().enumerate() {
@@ -259,10 +380,9 @@ impl<const N: usize, M: RawMutex, B: I2c> Controller for Tps6699x<'_, N, M, B> {
         Ok(self.port_status[port.0 as usize].get())
     }
 
-    #[allow(clippy::await_holding_refcell_ref)]
     async fn enable_sink_path(&mut self, port: LocalPortId, enable: bool) -> Result<(), Error<Self::BusError>> {
         debug!("Port{} enable sink path: {}", port.0, enable);
-        let mut tps6699x = self.tps6699x.borrow_mut();
+        let mut tps6699x = self.tps6699x.lock().await;
         match tps6699x.enable_sink_path(port, enable).await {
             // Temporary workaround for autofet rejection
             // Tracking bug: https://github.com/OpenDevicePartnership/embedded-services/issues/268
@@ -274,14 +394,12 @@ impl<const N: usize, M: RawMutex, B: I2c> Controller for Tps6699x<'_, N, M, B> {
         }
     }
 
-    #[allow(clippy::await_holding_refcell_ref)]
     async fn set_sourcing(&mut self, port: LocalPortId, enable: bool) -> Result<(), Error<Self::BusError>> {
         debug!("Port{} enable source: {}", port.0, enable);
-        let mut tps6699x = self.tps6699x.borrow_mut();
+        let mut tps6699x = self.tps6699x.lock().await;
         tps6699x.enable_source(port, enable).await
     }
 
-    #[allow(clippy::await_holding_refcell_ref)]
     async fn set_source_current(
         &mut self,
         port: LocalPortId,
@@ -290,7 +408,7 @@ impl<const N: usize, M: RawMutex, B: I2c> Controller for Tps6699x<'_, N, M, B> {
     ) -> Result<(), Error<Self::BusError>> {
         debug!("Port{} set source current: {:?}", port.0, current);
 
-        let mut tps6699x = self.tps6699x.borrow_mut();
+        let mut tps6699x = self.tps6699x.lock().await;
         let mut port_control = tps6699x.get_port_control(port).await?;
         port_control.set_typec_current(current.into());
 
@@ -303,7 +421,6 @@ impl<const N: usize, M: RawMutex, B: I2c> Controller for Tps6699x<'_, N, M, B> {
         Ok(())
     }
 
-    #[allow(clippy::await_holding_refcell_ref)]
     async fn request_pr_swap(
         &mut self,
         port: LocalPortId,
@@ -311,30 +428,157 @@ impl<const N: usize, M: RawMutex, B: I2c> Controller for Tps6699x<'_, N, M, B> {
     ) -> Result<(), Error<Self::BusError>> {
         debug!("Port{} request PR swap to {:?}", port.0, role);
 
-        let mut tps6699x = self.tps6699x.borrow_mut();
+        let mut tps6699x = self.tps6699x.lock().await;
         let mut control = tps6699x.get_port_control(port).await?;
         match role {
             PowerRole::Sink => control.set_initiate_swap_to_sink(true),
             PowerRole::Source => control.set_initiate_swap_to_source(true),
         }
 
-        tps6699x.set_port_control(port, control).await
+        tps6699x.set_port_control(port, control).await?;
+        if let Some(pending) = self.pending_pr_swap.get(port.0 as usize) {
+            pending.set(Some(role));
+        }
+        Ok(())
+    }
+
+    async fn enter_alt_mode(&mut self, port: LocalPortId, svid: u16) -> Result<(), Error<Self::BusError>> {
+        debug!("Port{} enter alt mode {:#x}", port.0, svid);
+        alt_mode_index(svid)?;
+        self.write_dp_mode(port, true).await
+    }
+
+    async fn exit_alt_mode(&mut self, port: LocalPortId, svid: u16) -> Result<(), Error<Self::BusError>> {
+        debug!("Port{} exit alt mode {:#x}", port.0, svid);
+        alt_mode_index(svid)?;
+        self.write_dp_mode(port, false).await
+    }
+
+    async fn request_dr_swap(&mut self, port: LocalPortId, role: DataRole) -> Result<(), Error<Self::BusError>> {
+        debug!("Port{} request DR swap to {:?}", port.0, role);
+
+        let mut tps6699x = self.tps6699x.lock().await;
+        let mut control = tps6699x.get_port_control(port).await?;
+        match role {
+            DataRole::Dfp => control.set_initiate_swap_to_dfp(true),
+            DataRole::Ufp => control.set_initiate_swap_to_ufp(true),
+        }
+
+        tps6699x.set_port_control(port, control).await?;
+        if let Some(pending) = self.pending_dr_swap.get(port.0 as usize) {
+            pending.set(Some(role));
+        }
+        Ok(())
+    }
+
+    async fn get_alternate_modes(&mut self, port: LocalPortId) -> Result<AlternateModes, Error<Self::BusError>> {
+        if port.0 >= self.port_status.len() as u8 {
+            return PdError::InvalidPort.into();
+        }
+
+        let mut tps6699x = self.tps6699x.lock().await;
+        let dp_config = tps6699x.get_dp_config(port).await?;
+        trace!("Port{} DP config: {:#?}", port.0, dp_config);
+
+        let mut modes = AlternateModes::new();
+        // DisplayPort is only offered to partners if enabled in the app FW configuration
+        if dp_config.enable_dp_svid() {
+            modes
+                .push(AltModeInfo {
+                    svid: DP_SVID,
+                    vdo: dp_config.dp_capabilities().into(),
+                })
+                .map_err(Error::Pd)?;
+        }
+        Ok(modes)
+    }
+
+    async fn get_current_cam(&mut self, port: LocalPortId) -> Result<Option<u8>, Error<Self::BusError>> {
+        if port.0 >= self.port_status.len() as u8 {
+            return PdError::InvalidPort.into();
+        }
+
+        // DisplayPort is the only supported mode so any active alt-mode must be DisplayPort
+        let status = self.port_status[port.0 as usize].get();
+        Ok(if status.alt_mode != AltMode::none() {
+            Some(0)
+        } else {
+            None
+        })
+    }
+
+    async fn set_new_cam(
+        &mut self,
+        port: LocalPortId,
+        mode_index: u8,
+        enter: bool,
+    ) -> Result<(), Error<Self::BusError>> {
+        debug!("Port{} set new CAM {}: {}", port.0, mode_index, enter);
+        // DisplayPort is the only mode, at CAM index 0
+        if mode_index as usize >= TPS6699X_SVIDS.len() {
+            return PdError::InvalidParams.into();
+        }
+
+        self.write_dp_mode(port, enter).await
     }
 
-    #[allow(clippy::await_holding_refcell_ref)]
     async fn get_controller_status(&mut self) -> Result<ControllerStatus<'static>, Error<Self::BusError>> {
-        let mut tps6699x = self.tps6699x.borrow_mut();
+        let mut tps6699x = self.tps6699x.lock().await;
         let boot_flags = tps6699x.get_boot_flags().await?;
         let customer_use = CustomerUse(tps6699x.get_customer_use().await?);
 
+        let mode: &'static str = tps6699x.get_mode().await?.into();
+        let valid_fw_bank = (boot_flags.active_bank() == 0 && boot_flags.bank0_valid() != 0)
+            || (boot_flags.active_bank() == 1 && boot_flags.bank1_valid() != 0);
+
         Ok(ControllerStatus {
-            mode: tps6699x.get_mode().await?.into(),
-            valid_fw_bank: (boot_flags.active_bank() == 0 && boot_flags.bank0_valid() != 0)
-                || (boot_flags.active_bank() == 1 && boot_flags.bank1_valid() != 0),
+            mode,
+            valid_fw_bank,
             fw_version0: customer_use.ti_fw_version(),
             fw_version1: customer_use.custom_fw_version(),
+            ready: valid_fw_bank && is_app_mode(mode),
         })
     }
+
+    async fn get_pdos(
+        &mut self,
+        port: LocalPortId,
+        partner: bool,
+        source: bool,
+    ) -> Result<PdoList, Error<Self::BusError>> {
+        if port.0 >= self.port_status.len() as u8 {
+            return PdError::InvalidPort.into();
+        }
+
+        // Partner capabilities are the last ones received, local capabilities are the ones we transmit
+        let mut tps6699x = self.tps6699x.lock().await;
+        let pdos = match (partner, source) {
+            (true, true) => {
+                let caps = tps6699x.get_rx_src_caps(port).await?;
+                pdo_list(caps.num_valid_pdos(), |i| caps.pdo(i))
+            }
+            (true, false) => {
+                let caps = tps6699x.get_rx_sink_caps(port).await?;
+                pdo_list(caps.num_valid_pdos(), |i| caps.pdo(i))
+            }
+            (false, true) => {
+                let caps = tps6699x.get_tx_src_caps(port).await?;
+                pdo_list(caps.num_valid_pdos(), |i| caps.pdo(i))
+            }
+            (false, false) => {
+                let caps = tps6699x.get_tx_sink_caps(port).await?;
+                pdo_list(caps.num_valid_pdos(), |i| caps.pdo(i))
+            }
+        };
+        trace!("Port{} PDOs: {:#?}", port.0, pdos);
+        Ok(pdos)
+    }
+}
+
+/// Returns true if the given mode indicates the controller is running application FW
+fn is_app_mode(mode: &str) -> bool {
+    // App mode is reported as "APP " but allow for any variant of the app mode
+    mode.get(..3).is_some_and(|m| m.eq_ignore_ascii_case("app"))
 }
 
 /// TPS66994 controller wrapper
@@ -390,3 +634,400 @@ bitfield! {
     /// TI FW version
     pub u32, ti_fw_version, set_ti_fw_version: 63, 32;
 }
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use core::cell::RefCell;
+    use core::future::{pending, ready};
+    use std::collections::BTreeMap;
+    use std::vec::Vec;
+
+    use ::tps6699x::registers::field_sets::DpConfig;
+    use embassy_futures::block_on;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embedded_hal_async::i2c::{ErrorKind, ErrorType, Operation};
+
+    use super::*;
+
+    /// Port status register
+    const REG_STATUS: u8 = 0x1a;
+    /// Data role bit of the port status register, set when the port is the DFP
+    const STATUS_DATA_ROLE_DFP: u8 = 1 << 6;
+    /// Received source capabilities register
+    const REG_RX_SRC_CAPS: u8 = 0x30;
+    /// Power path status register
+    const REG_POWER_PATH_STATUS: u8 = 0x3f;
+    /// DisplayPort configuration register
+    const REG_DP_CONFIG: u8 = 0x51;
+
+    /// Register contents of an emulated TPS6699x, keyed by port address and register
+    #[derive(Default)]
+    struct Registers {
+        values: RefCell<BTreeMap<(u8, u8), Vec<u8>>>,
+        /// Registers written, in order
+        writes: RefCell<Vec<(u8, u8)>>,
+    }
+
+    impl Registers {
+        /// Set the contents of a register without recording a write
+        fn set(&self, address: u8, reg: u8, value: &[u8]) {
+            self.values.borrow_mut().insert((address, reg), value.to_vec());
+        }
+    }
+
+    /// Bus emulating the TPS6699x register interface, registers read as zero until written
+    ///
+    /// A register read writes the register address then reads the byte count followed by the data.
+    /// A register write carries the register address, the byte count and the data.
+    struct RegisterBus<'a> {
+        registers: &'a Registers,
+    }
+
+    impl ErrorType for RegisterBus<'_> {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for RegisterBus<'_> {
+        async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+            let mut register = None;
+            for operation in operations.iter_mut() {
+                match operation {
+                    Operation::Write(bytes) => match *bytes {
+                        [reg] => register = Some(*reg),
+                        [reg, len, data @ ..] => {
+                            let data = data.get(..*len as usize).ok_or(ErrorKind::Other)?;
+                            self.registers
+                                .values
+                                .borrow_mut()
+                                .insert((address, *reg), data.to_vec());
+                            self.registers.writes.borrow_mut().push((address, *reg));
+                        }
+                        [] => return Err(ErrorKind::Other),
+                    },
+                    Operation::Read(buf) => {
+                        let reg = register.ok_or(ErrorKind::Other)?;
+                        let (len, data) = buf.split_first_mut().ok_or(ErrorKind::Other)?;
+                        *len = data.len() as u8;
+                        data.fill(0);
+                        if let Some(value) = self.registers.values.borrow().get(&(address, reg)) {
+                            for (dst, src) in data.iter_mut().zip(value) {
+                                *dst = *src;
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// I2C address of the given port
+    fn port_address(port: LocalPortId) -> u8 {
+        ::tps6699x::ADDR0[port.0 as usize]
+    }
+
+    /// Bus that never completes its first transaction and fails every one after
+    #[derive(Default)]
+    struct StallingBus {
+        stalled: bool,
+    }
+
+    impl ErrorType for StallingBus {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for StallingBus {
+        async fn transaction(&mut self, _address: u8, _operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+            if !self.stalled {
+                self.stalled = true;
+                pending::<()>().await;
+            }
+            Err(ErrorKind::Other)
+        }
+    }
+
+    fn status(power_path: PowerPathStatus) -> PortStatus {
+        PortStatus {
+            power_path,
+            ..PortStatus::new()
+        }
+    }
+
+    /// Power path register with the A port sinking and the B port sourcing
+    fn sink_source_power_path() -> PowerPathStatusRegister {
+        let mut register = PowerPathStatusRegister::new_zero();
+        register.set_pa_ext_vbus_sw(PpExtVbusSw::EnabledInput);
+        register.set_pb_int_vbus_sw(PpIntVbusSw::EnabledOutput);
+        register
+    }
+
+    #[test]
+    fn test_port_power_path() {
+        let register = sink_source_power_path();
+
+        let port = |id| port_power_path::<TPS66994_NUM_PORTS>(&register, LocalPortId(id)).map(status);
+        assert!(port(0).unwrap().is_sinking());
+        assert!(!port(0).unwrap().is_sourcing());
+        assert!(port(1).unwrap().is_sourcing());
+        assert!(!port(1).unwrap().is_sinking());
+        assert!(matches!(port(2), Err(PdError::InvalidPort)));
+
+        let port = |id| port_power_path::<TPS66993_NUM_PORTS>(&register, LocalPortId(id)).map(status);
+        assert!(port(0).unwrap().is_sinking());
+        assert!(matches!(port(1), Err(PdError::InvalidPort)));
+
+        let idle = PowerPathStatusRegister::new_zero();
+        let port = port_power_path::<TPS66994_NUM_PORTS>(&idle, LocalPortId(0)).map(status);
+        assert!(!port.unwrap().is_sinking());
+        assert!(!port.unwrap().is_sourcing());
+    }
+
+    #[test]
+    fn test_port_power_path_four_ports() {
+        let register = sink_source_power_path();
+
+        // Ports 2 and 3 route to the A and B switches like ports 0 and 1
+        let port = |id| port_power_path::<4>(&register, LocalPortId(id)).map(status);
+        assert!(port(2).unwrap().is_sinking());
+        assert!(!port(2).unwrap().is_sourcing());
+        assert!(port(3).unwrap().is_sourcing());
+        assert!(!port(3).unwrap().is_sinking());
+        assert!(matches!(port(4), Err(PdError::InvalidPort)));
+    }
+
+    #[test]
+    fn test_decode_pdo() {
+        // Fixed 5V@3A
+        const FIXED_PDO: u32 = (100 << 10) | 300;
+        // Augmented PDO with a reserved APDO type
+        const MALFORMED_PDO: u32 = 0xf000_0000;
+
+        let pdo = decode_pdo::<source::Pdo>(LocalPortId(0), FIXED_PDO).unwrap();
+        assert_eq!(PowerCapability::from(pdo), type_c::POWER_CAPABILITY_5V_3A0);
+        let pdo = decode_pdo::<sink::Pdo>(LocalPortId(0), FIXED_PDO).unwrap();
+        assert_eq!(PowerCapability::from(pdo), type_c::POWER_CAPABILITY_5V_3A0);
+
+        // Malformed PDOs don't produce an error, the caller falls back to the implicit contract
+        assert!(decode_pdo::<source::Pdo>(LocalPortId(0), MALFORMED_PDO).is_none());
+        assert!(decode_pdo::<sink::Pdo>(LocalPortId(0), MALFORMED_PDO).is_none());
+    }
+
+    #[test]
+    fn test_sink_path_enabled() {
+        let registers = Registers::default();
+        let mut controller = tps6699x::controller::Controller::<NoopRawMutex, _>::new_tps66994(
+            RegisterBus { registers: &registers },
+            ::tps6699x::ADDR0,
+        )
+        .unwrap();
+        let (tps6699x, _interrupt) = controller.make_parts();
+        let driver = Tps6699x::<TPS66994_NUM_PORTS, NoopRawMutex, _>::new(tps6699x);
+
+        // Sink FET stuck on for a disconnected port
+        let power_path: [u8; 5] = sink_source_power_path().into();
+        for port in [LocalPortId(0), LocalPortId(1)] {
+            registers.set(port_address(port), REG_POWER_PATH_STATUS, &power_path);
+            block_on(async {
+                let mut tps6699x = driver.tps6699x.lock().await;
+                driver.update_port_status(&mut tps6699x, port).await.unwrap();
+            });
+        }
+
+        let status = driver.port_status[0].get();
+        assert!(status.sink_path_enabled);
+        assert!(!status.is_connected());
+        // The power path is only reported for connected ports
+        assert!(!status.is_sinking());
+
+        let status = driver.port_status[1].get();
+        assert!(!status.sink_path_enabled);
+        assert!(!status.is_connected());
+    }
+
+    #[test]
+    fn test_dr_swap_complete() {
+        let registers = Registers::default();
+        let mut controller = tps6699x::controller::Controller::<NoopRawMutex, _>::new_tps66994(
+            RegisterBus { registers: &registers },
+            ::tps6699x::ADDR0,
+        )
+        .unwrap();
+        let (tps6699x, _interrupt) = controller.make_parts();
+        let mut driver = Tps6699x::<TPS66994_NUM_PORTS, NoopRawMutex, _>::new(tps6699x);
+        let port = LocalPortId(0);
+
+        let update = |driver: &Tps6699x<'_, TPS66994_NUM_PORTS, NoopRawMutex, RegisterBus<'_>>| {
+            block_on(async {
+                let mut tps6699x = driver.tps6699x.lock().await;
+                driver.update_port_status(&mut tps6699x, port).await.unwrap()
+            })
+        };
+
+        block_on(driver.request_dr_swap(port, DataRole::Dfp)).unwrap();
+
+        // Still the UFP, the swap hasn't happened yet
+        assert!(!update(&driver).role_swap_complete());
+
+        registers.set(port_address(port), REG_STATUS, &[STATUS_DATA_ROLE_DFP]);
+        assert!(update(&driver).role_swap_complete());
+
+        // Only reported once
+        assert!(!update(&driver).role_swap_complete());
+    }
+
+    #[test]
+    fn test_cancelled_access() {
+        let mut controller = tps6699x::controller::Controller::<NoopRawMutex, _>::new_tps66994(
+            StallingBus::default(),
+            ::tps6699x::ADDR0,
+        )
+        .unwrap();
+        let (tps6699x, _interrupt) = controller.make_parts();
+        let mut driver = Tps6699x::<TPS66994_NUM_PORTS, NoopRawMutex, StallingBus>::new(tps6699x);
+
+        block_on(async {
+            // Cancel an access while it's waiting on the bus
+            select(driver.set_sourcing(LocalPortId(0), true), ready(())).await;
+
+            // The controller is accessible again, the bus error is returned instead of panicking
+            assert!(driver.set_sourcing(LocalPortId(0), true).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_set_new_cam() {
+        let registers = Registers::default();
+        let mut controller = tps6699x::controller::Controller::<NoopRawMutex, _>::new_tps66994(
+            RegisterBus { registers: &registers },
+            ::tps6699x::ADDR0,
+        )
+        .unwrap();
+        let (tps6699x, _interrupt) = controller.make_parts();
+        let mut driver = Tps6699x::<TPS66994_NUM_PORTS, NoopRawMutex, _>::new(tps6699x);
+        let port = LocalPortId(1);
+
+        let dp_config = |driver: &Tps6699x<'_, TPS66994_NUM_PORTS, NoopRawMutex, RegisterBus<'_>>| {
+            block_on(async { driver.tps6699x.lock().await.get_dp_config(port).await.unwrap() })
+        };
+
+        // DisplayPort isn't offered until enabled in the app FW configuration
+        assert!(matches!(
+            block_on(driver.set_new_cam(port, 0, true)),
+            Err(Error::Pd(PdError::Rejected))
+        ));
+        assert!(registers.writes.borrow().is_empty());
+
+        let mut config = DpConfig::new_zero();
+        config.set_enable_dp_svid(true);
+        block_on(async { driver.tps6699x.lock().await.set_dp_config(port, config).await.unwrap() });
+        registers.writes.borrow_mut().clear();
+
+        // Entry and exit are written to the DP configuration of the port
+        block_on(driver.set_new_cam(port, 0, true)).unwrap();
+        assert_eq!(*registers.writes.borrow(), [(port_address(port), REG_DP_CONFIG)]);
+        assert!(dp_config(&driver).enable_dp_mode());
+
+        block_on(driver.set_new_cam(port, 0, false)).unwrap();
+        assert!(!dp_config(&driver).enable_dp_mode());
+        assert!(dp_config(&driver).enable_dp_svid());
+
+        // Only the DP configuration of the commanded port is touched
+        assert!(registers
+            .writes
+            .borrow()
+            .iter()
+            .all(|write| *write == (port_address(port), REG_DP_CONFIG)));
+
+        assert!(matches!(
+            block_on(driver.set_new_cam(port, 1, true)),
+            Err(Error::Pd(PdError::InvalidParams))
+        ));
+    }
+
+    #[test]
+    fn test_alt_mode_entry_exit() {
+        let registers = Registers::default();
+        let mut controller = tps6699x::controller::Controller::<NoopRawMutex, _>::new_tps66994(
+            RegisterBus { registers: &registers },
+            ::tps6699x::ADDR0,
+        )
+        .unwrap();
+        let (tps6699x, _interrupt) = controller.make_parts();
+        let mut driver = Tps6699x::<TPS66994_NUM_PORTS, NoopRawMutex, _>::new(tps6699x);
+        let port = LocalPortId(0);
+
+        let mut config = DpConfig::new_zero();
+        config.set_enable_dp_svid(true);
+        block_on(async { driver.tps6699x.lock().await.set_dp_config(port, config).await.unwrap() });
+        registers.writes.borrow_mut().clear();
+
+        // Unsupported modes don't reach the controller
+        assert!(matches!(
+            block_on(driver.enter_alt_mode(port, 0x8087)),
+            Err(Error::Pd(PdError::UnsupportedCommand))
+        ));
+        assert!(matches!(
+            block_on(driver.exit_alt_mode(port, 0x8087)),
+            Err(Error::Pd(PdError::UnsupportedCommand))
+        ));
+        assert!(registers.writes.borrow().is_empty());
+
+        block_on(driver.enter_alt_mode(port, DP_SVID)).unwrap();
+        assert!(block_on(async { driver.tps6699x.lock().await.get_dp_config(port).await.unwrap() }).enable_dp_mode());
+
+        block_on(driver.exit_alt_mode(port, DP_SVID)).unwrap();
+        assert!(!block_on(async { driver.tps6699x.lock().await.get_dp_config(port).await.unwrap() }).enable_dp_mode());
+        assert_eq!(
+            *registers.writes.borrow(),
+            [(port_address(port), REG_DP_CONFIG), (port_address(port), REG_DP_CONFIG)]
+        );
+
+        assert!(matches!(
+            block_on(driver.enter_alt_mode(LocalPortId(TPS66994_NUM_PORTS as u8), DP_SVID)),
+            Err(Error::Pd(PdError::InvalidPort))
+        ));
+    }
+
+    #[test]
+    fn test_get_pdos() {
+        // Fixed 5V@3A and 20V@2.25A source PDOs
+        const SOURCE_PDOS: [u32; 2] = [(100 << 10) | 300, (400 << 10) | 225];
+
+        let registers = Registers::default();
+        let mut controller = tps6699x::controller::Controller::<NoopRawMutex, _>::new_tps66994(
+            RegisterBus { registers: &registers },
+            ::tps6699x::ADDR0,
+        )
+        .unwrap();
+        let (tps6699x, _interrupt) = controller.make_parts();
+        let mut driver = Tps6699x::<TPS66994_NUM_PORTS, NoopRawMutex, _>::new(tps6699x);
+        let port = LocalPortId(1);
+
+        // Number of valid PDOs followed by the PDOs
+        let mut caps = Vec::from([SOURCE_PDOS.len() as u8]);
+        for pdo in SOURCE_PDOS {
+            caps.extend_from_slice(&pdo.to_le_bytes());
+        }
+        registers.set(port_address(port), REG_RX_SRC_CAPS, &caps);
+
+        let pdos = block_on(driver.get_pdos(port, true, true)).unwrap();
+        assert_eq!(pdos.as_slice(), SOURCE_PDOS);
+
+        // Other capabilities of the port and the other port are empty
+        assert!(block_on(driver.get_pdos(port, true, false))
+            .unwrap()
+            .as_slice()
+            .is_empty());
+        assert!(block_on(driver.get_pdos(LocalPortId(0), true, true))
+            .unwrap()
+            .as_slice()
+            .is_empty());
+        assert!(registers.writes.borrow().is_empty());
+
+        assert!(matches!(
+            block_on(driver.get_pdos(LocalPortId(TPS66994_NUM_PORTS as u8), true, true)),
+            Err(Error::Pd(PdError::InvalidPort))
+        ));
+    }
+}
@@ -0,0 +1,158 @@
+//! Bounded per-port event history
+use core::array;
+use core::iter::{Cycle, Skip, Take};
+
+use embedded_services::type_c::event::PortEventKind;
+
+/// Number of events retained per port
+pub const EVENT_HISTORY_DEPTH: usize = 8;
+
+/// A recorded port event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventRecord {
+    /// Monotonic sequence number, unique per port
+    pub sequence: u32,
+    /// Events raised in this cycle
+    pub event: PortEventKind,
+}
+
+/// Ring buffer of the most recent `K` events for a port
+#[derive(Debug, Clone, Copy)]
+pub struct EventHistory<const K: usize> {
+    entries: [EventRecord; K],
+    /// Index of the oldest entry
+    head: usize,
+    len: usize,
+    next_sequence: u32,
+    overflow: bool,
+}
+
+impl<const K: usize> EventHistory<K> {
+    /// Create an empty history
+    pub const fn new() -> Self {
+        Self {
+            entries: [EventRecord {
+                sequence: 0,
+                event: PortEventKind::none(),
+            }; K],
+            head: 0,
+            len: 0,
+            next_sequence: 0,
+            overflow: false,
+        }
+    }
+
+    /// Record an event, dropping the oldest entry if the buffer is full
+    pub fn push(&mut self, event: PortEventKind) {
+        if K == 0 {
+            self.overflow = true;
+            return;
+        }
+
+        let record = EventRecord {
+            sequence: self.next_sequence,
+            event,
+        };
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+
+        if self.len == K {
+            self.entries[self.head] = record;
+            self.head = (self.head + 1) % K;
+            self.overflow = true;
+        } else {
+            self.entries[(self.head + self.len) % K] = record;
+            self.len += 1;
+        }
+    }
+
+    /// Iterate over the recorded events, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = EventRecord> + '_ {
+        self.entries.iter().copied().cycle().skip(self.head).take(self.len)
+    }
+
+    /// Number of recorded events
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no events have been recorded
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if events have been dropped since the last call to `clear_overflow`
+    pub fn overflowed(&self) -> bool {
+        self.overflow
+    }
+
+    /// Clear the overflow flag
+    pub fn clear_overflow(&mut self) {
+        self.overflow = false;
+    }
+}
+
+impl<const K: usize> IntoIterator for EventHistory<K> {
+    type Item = EventRecord;
+    type IntoIter = Take<Skip<Cycle<array::IntoIter<EventRecord, K>>>>;
+
+    /// Iterate over the recorded events, oldest first
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter().cycle().skip(self.head).take(self.len)
+    }
+}
+
+impl<const K: usize> Default for EventHistory<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn plug_event() -> PortEventKind {
+        let mut event = PortEventKind::none();
+        event.set_plug_inserted_or_removed(true);
+        event
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut history = EventHistory::<4>::new();
+        assert!(history.is_empty());
+
+        for _ in 0..4 {
+            history.push(plug_event());
+        }
+
+        let sequences: Vec<u32> = history.iter().map(|r| r.sequence).collect();
+        assert_eq!(sequences, [0, 1, 2, 3]);
+        assert!(history.iter().all(|r| r.event == plug_event()));
+        assert!(!history.overflowed());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut history = EventHistory::<4>::new();
+
+        for _ in 0..6 {
+            history.push(plug_event());
+        }
+
+        // Oldest two entries are dropped
+        let sequences: Vec<u32> = history.iter().map(|r| r.sequence).collect();
+        assert_eq!(sequences, [2, 3, 4, 5]);
+        assert_eq!(history.len(), 4);
+        assert!(history.overflowed());
+
+        history.clear_overflow();
+        assert!(!history.overflowed());
+        history.push(PortEventKind::none());
+        assert_eq!(history.iter().last().map(|r| r.sequence), Some(6));
+        assert!(history.overflowed());
+    }
+}
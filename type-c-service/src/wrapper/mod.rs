@@ -4,41 +4,135 @@ use core::array::from_fn;
 use core::cell::{Cell, RefCell};
 
 use embassy_futures::select::{select3, select_array, Either3};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::signal::Signal;
 use embedded_services::power::policy::device::StateKind;
-use embedded_services::power::policy::{self, action};
+use embedded_services::power::policy::{self, action, PowerCapability};
 use embedded_services::type_c::controller::{self, Controller, PortStatus};
 use embedded_services::type_c::event::{PortEventFlags, PortEventKind};
 use embedded_services::{error, info, trace, warn};
 use embedded_usb_pd::{type_c::Current as TypecCurrent, Error, PdError, PortId as LocalPortId};
 
+pub mod history;
 mod pd;
 mod power;
 
+use history::{EventHistory, EventRecord, EVENT_HISTORY_DEPTH};
+
 /// Default current to source
 const DEFAULT_SOURCE_CURRENT: TypecCurrent = TypecCurrent::Current1A5;
 /// Threshold power capability before we'll attempt to sink from a dual-role supply
 /// This ensures we don't try to sink from something like a phone
 const DUAL_ROLE_CONSUMER_THRESHOLD_MW: u32 = 15000;
+/// Default number of consecutive bus errors before a controller is considered offline
+pub const DEFAULT_OFFLINE_THRESHOLD: u8 = 5;
 
 /// Takes an implementation of the `Controller` trait and wraps it with logic to handle
 /// message passing and power-policy integration.
+/// `N` is the maximum number of ports, the actual count is determined by the number of power devices
 pub struct ControllerWrapper<'a, const N: usize, C: Controller> {
     /// PD controller to interface with PD service
     pd_controller: controller::Device<'a>,
     /// Power policy devices to interface with power policy service
-    power: [policy::device::Device; N],
+    power: heapless::Vec<policy::device::Device, N>,
     controller: RefCell<C>,
-    active_events: [Cell<PortEventKind>; N],
+    active_events: heapless::Vec<Cell<PortEventKind>, N>,
+    /// Recent events for each port, retained for debugging
+    event_history: heapless::Vec<RefCell<EventHistory<EVENT_HISTORY_DEPTH>>, N>,
+    /// Sink capability to assume for each port when no explicit contract exists
+    default_sink_capability: heapless::Vec<Option<PowerCapability>, N>,
+    /// Consecutive bus errors, any response from the controller resets the count
+    bus_errors: Cell<u8>,
+    /// Number of consecutive bus errors before the controller is considered offline
+    offline_threshold: Cell<u8>,
+    /// Signaled when the controller stops responding
+    offline: Signal<NoopRawMutex, ()>,
 }
 
 impl<'a, const N: usize, C: Controller> ControllerWrapper<'a, N, C> {
     /// Create a new controller wrapper
     pub fn new(pd_controller: controller::Device<'a>, power: [policy::device::Device; N], controller: C) -> Self {
+        Self::new_with_default_sink_capability(pd_controller, power, controller, [None; N])
+    }
+
+    /// Create a new controller wrapper with a default sink capability for each port
+    /// The default is used when a port is connected but no explicit contract has been negotiated
+    pub fn new_with_default_sink_capability(
+        pd_controller: controller::Device<'a>,
+        power: [policy::device::Device; N],
+        controller: C,
+        default_sink_capability: [Option<PowerCapability>; N],
+    ) -> Self {
+        Self::new_with_port_count(
+            pd_controller,
+            power.into_iter().collect(),
+            controller,
+            default_sink_capability.into_iter().collect(),
+        )
+    }
+
+    /// Create a new controller wrapper for a port count only known at runtime, up to `N` ports
+    /// There is one port per power device, `default_sink_capability` is truncated or padded with `None` to match
+    pub fn new_with_port_count(
+        pd_controller: controller::Device<'a>,
+        power: heapless::Vec<policy::device::Device, N>,
+        controller: C,
+        default_sink_capability: heapless::Vec<Option<PowerCapability>, N>,
+    ) -> Self {
+        let num_ports = power.len();
         Self {
             pd_controller,
             power,
             controller: RefCell::new(controller),
-            active_events: [const { Cell::new(PortEventKind::none()) }; N],
+            active_events: (0..num_ports).map(|_| Cell::new(PortEventKind::none())).collect(),
+            event_history: (0..num_ports).map(|_| RefCell::new(EventHistory::new())).collect(),
+            default_sink_capability: (0..num_ports)
+                .map(|i| default_sink_capability.get(i).copied().flatten())
+                .collect(),
+            bus_errors: Cell::new(0),
+            offline_threshold: Cell::new(DEFAULT_OFFLINE_THRESHOLD),
+            offline: Signal::new(),
+        }
+    }
+
+    /// Number of ports on this controller
+    pub fn num_ports(&self) -> usize {
+        self.power.len()
+    }
+
+    /// Set the number of consecutive bus errors before the controller is considered offline
+    pub fn set_offline_threshold(&self, threshold: u8) {
+        self.offline_threshold.set(threshold.max(1));
+    }
+
+    /// Returns true if the controller has stopped responding
+    pub fn is_offline(&self) -> bool {
+        self.bus_errors.get() >= self.offline_threshold.get()
+    }
+
+    /// Wait for the controller to stop responding
+    pub async fn wait_offline(&self) {
+        self.offline.wait().await
+    }
+
+    /// Track consecutive bus errors, signaling once when the offline threshold is reached
+    fn track_bus_result<T>(&self, result: &Result<T, Error<C::BusError>>) {
+        if let Err(Error::Bus(_)) = result {
+            let errors = self.bus_errors.get().saturating_add(1);
+            self.bus_errors.set(errors);
+            if errors == self.offline_threshold.get() {
+                error!(
+                    "Controller{}: Offline after {} consecutive bus errors",
+                    self.pd_controller.id().0,
+                    errors
+                );
+                self.offline.signal(());
+            }
+        } else {
+            if self.is_offline() {
+                info!("Controller{}: Back online", self.pd_controller.id().0);
+            }
+            self.bus_errors.set(0);
         }
     }
 
@@ -57,7 +151,7 @@ impl<'a, const N: usize, C: Controller> ControllerWrapper<'a, N, C> {
         port: LocalPortId,
         status: &PortStatus,
     ) -> Result<(), Error<C::BusError>> {
-        if port.0 > N as u8 {
+        if port.0 as usize >= self.num_ports() {
             error!("Invalid port {}", port.0);
             return PdError::InvalidPort.into();
         }
@@ -76,9 +170,24 @@ impl<'a, const N: usize, C: Controller> ControllerWrapper<'a, N, C> {
             }
 
             if let Ok(state) = power.try_device_action::<action::Detached>().await {
-                if let Err(e) = state.attach().await {
-                    error!("Error attaching power device: {:?}", e);
-                    return PdError::Failed.into();
+                let state = match state.attach().await {
+                    Ok(state) => state,
+                    Err(e) => {
+                        error!("Error attaching power device: {:?}", e);
+                        return PdError::Failed.into();
+                    }
+                };
+
+                // Budget at the default sink capability until a contract is negotiated
+                if status.available_sink_contract.is_none() {
+                    let capability = status.sink_capability_or(self.default_sink_capability[port.0 as usize]);
+                    if capability.is_some() {
+                        info!("Port{}: Using default sink capability", port.0);
+                        if let Err(e) = state.notify_consumer_power_capability(capability).await {
+                            error!("Error setting default power capability: {:?}", e);
+                            return PdError::Failed.into();
+                        }
+                    }
                 }
             } else {
                 // This should never happen
@@ -118,7 +227,7 @@ impl<'a, const N: usize, C: Controller> ControllerWrapper<'a, N, C> {
     async fn process_event(&self, controller: &mut C) {
         let mut port_events = PortEventFlags::none();
 
-        for port in 0..N {
+        for port in 0..self.num_ports() {
             let local_port_id = LocalPortId(port as u8);
             let global_port_id = match self.pd_controller.lookup_global_port(local_port_id) {
                 Ok(port) => port,
@@ -128,7 +237,9 @@ impl<'a, const N: usize, C: Controller> ControllerWrapper<'a, N, C> {
                 }
             };
 
-            let event = match controller.clear_port_events(local_port_id).await {
+            let event = controller.clear_port_events(local_port_id).await;
+            self.track_bus_result(&event);
+            let event = match event {
                 Ok(event) => event,
                 Err(_) => {
                     error!("Error clearing port events",);
@@ -142,8 +253,11 @@ impl<'a, const N: usize, C: Controller> ControllerWrapper<'a, N, C> {
             }
 
             port_events.pend_port(global_port_id);
+            self.event_history[port].borrow_mut().push(event);
 
-            let status = match controller.get_port_status(local_port_id).await {
+            let status = controller.get_port_status(local_port_id).await;
+            self.track_bus_result(&status);
+            let status = match status {
                 Ok(status) => status,
                 Err(_) => {
                     error!("Port{}: Error getting port status", global_port_id.0);
@@ -183,7 +297,7 @@ impl<'a, const N: usize, C: Controller> ControllerWrapper<'a, N, C> {
 
             if event.new_power_contract_as_provider()
                 && self
-                    .process_new_provider_contract(global_port_id, power, &status)
+                    .process_new_provider_contract(controller, global_port_id, power, &status)
                     .await
                     .is_err()
             {
@@ -191,6 +305,21 @@ impl<'a, const N: usize, C: Controller> ControllerWrapper<'a, N, C> {
                 continue;
             }
 
+            if event.contract_lost() && self.process_contract_lost(power, &status).await.is_err() {
+                error!("Port{}: Error processing contract lost", global_port_id.0);
+                continue;
+            }
+
+            if event.role_swap_complete()
+                && self
+                    .process_role_swap_complete(controller, global_port_id, power, local_port_id, &status)
+                    .await
+                    .is_err()
+            {
+                error!("Port{}: Error processing role swap complete", global_port_id.0);
+                continue;
+            }
+
             self.active_events[port].set(event);
         }
 
@@ -208,10 +337,13 @@ impl<'a, const N: usize, C: Controller> ControllerWrapper<'a, N, C> {
         )
         .await
         {
-            Either3::First(r) => match r {
-                Ok(_) => self.process_event(&mut controller).await,
-                Err(_) => error!("Error waiting for port event"),
-            },
+            Either3::First(r) => {
+                self.track_bus_result(&r);
+                match r {
+                    Ok(_) => self.process_event(&mut controller).await,
+                    Err(_) => error!("Error waiting for port event"),
+                }
+            }
             Either3::Second((request, port)) => {
                 let response = self
                     .process_power_command(&mut controller, port, &request.command)
@@ -225,9 +357,24 @@ impl<'a, const N: usize, C: Controller> ControllerWrapper<'a, N, C> {
         }
     }
 
+    /// Recent events for the given port, oldest first
+    pub fn port_event_history(&self, port: LocalPortId) -> Result<impl Iterator<Item = EventRecord>, PdError> {
+        let history = self.event_history.get(port.0 as usize).ok_or(PdError::InvalidPort)?;
+        Ok(history.borrow().into_iter())
+    }
+
+    /// Returns true if events were dropped from the given port's history, clearing the flag
+    pub fn take_port_event_history_overflow(&self, port: LocalPortId) -> Result<bool, PdError> {
+        let history = self.event_history.get(port.0 as usize).ok_or(PdError::InvalidPort)?;
+        let mut history = history.borrow_mut();
+        let overflow = history.overflowed();
+        history.clear_overflow();
+        Ok(overflow)
+    }
+
     /// Register all devices with their respective services
     pub async fn register(&'static self) -> Result<(), Error<C::BusError>> {
-        for device in &self.power {
+        for device in self.power.iter() {
             policy::register_device(device).await.map_err(|_| {
                 error!(
                     "Controller{}: Failed to register power device {}",
@@ -251,3 +398,733 @@ impl<'a, const N: usize, C: Controller> ControllerWrapper<'a, N, C> {
         self.sync_state().await
     }
 }
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use std::boxed::Box;
+    use std::sync::{Mutex, MutexGuard, OnceLock};
+    use std::vec::Vec;
+
+    use embassy_futures::block_on;
+    use embassy_futures::select::{select, Either};
+    use embedded_services::power::policy::{policy, DeviceId};
+    use embedded_services::type_c::controller::{
+        AltModeInfo, AlternateModes, ControllerStatus, DataRole, PdMessage, PdMessageType,
+    };
+    use embedded_services::type_c::{ControllerId, POWER_CAPABILITY_5V_1A5, POWER_CAPABILITY_5V_3A0};
+    use embedded_usb_pd::type_c::ConnectionState;
+    use embedded_usb_pd::{GlobalPortId, PowerRole};
+
+    use super::*;
+
+    static PORTS: [GlobalPortId; 1] = [GlobalPortId(0)];
+
+    /// DisplayPort SVID, the only alternate mode supported by the mock
+    const DP_SVID: u16 = 0xff01;
+
+    /// DisplayPort mode VDO reported by the mock, DFP_D with pin assignments C and D
+    const DP_VDO: u32 = 0x0c05;
+
+    /// Discover Identity ACK from a PD 3.0 UFP sink, message ID 3, one VDO
+    const DISCOVER_IDENTITY: [u8; 6] = [0x8f, 0x16, 0x41, 0xa0, 0x00, 0xff];
+
+    /// Controller that reports a fixed status and pending events for a single port
+    struct MockController {
+        events: PortEventKind,
+        status: PortStatus,
+        pr_swaps: Vec<PowerRole>,
+        dr_swaps: Vec<DataRole>,
+        alt_modes: Vec<(u16, bool)>,
+        /// Alternate modes reported to the service
+        supported_modes: AlternateModes,
+        source_currents: Vec<(u8, TypecCurrent)>,
+        /// Simulate an unresponsive controller
+        bus_error: bool,
+    }
+
+    impl MockController {
+        fn new() -> Self {
+            Self {
+                events: PortEventKind::none(),
+                status: PortStatus::new(),
+                pr_swaps: Vec::new(),
+                dr_swaps: Vec::new(),
+                alt_modes: Vec::new(),
+                supported_modes: {
+                    let mut modes = AlternateModes::new();
+                    modes
+                        .push(AltModeInfo {
+                            svid: DP_SVID,
+                            vdo: DP_VDO,
+                        })
+                        .unwrap();
+                    modes
+                },
+                source_currents: Vec::new(),
+                bus_error: false,
+            }
+        }
+    }
+
+    impl Controller for MockController {
+        type BusError = ();
+
+        async fn sync_state(&mut self) -> Result<(), Error<Self::BusError>> {
+            Ok(())
+        }
+
+        async fn wait_port_event(&mut self) -> Result<(), Error<Self::BusError>> {
+            if self.bus_error {
+                return Err(Error::Bus(()));
+            }
+            Ok(())
+        }
+
+        async fn clear_port_events(&mut self, _port: LocalPortId) -> Result<PortEventKind, Error<Self::BusError>> {
+            Ok(core::mem::replace(&mut self.events, PortEventKind::none()))
+        }
+
+        async fn get_port_status(&mut self, _port: LocalPortId) -> Result<PortStatus, Error<Self::BusError>> {
+            Ok(self.status)
+        }
+
+        async fn enable_sink_path(&mut self, _port: LocalPortId, _enable: bool) -> Result<(), Error<Self::BusError>> {
+            Ok(())
+        }
+
+        async fn set_sourcing(&mut self, _port: LocalPortId, _enable: bool) -> Result<(), Error<Self::BusError>> {
+            Ok(())
+        }
+
+        async fn set_source_current(
+            &mut self,
+            port: LocalPortId,
+            current: TypecCurrent,
+            _signal_event: bool,
+        ) -> Result<(), Error<Self::BusError>> {
+            self.source_currents.push((port.0, current));
+            Ok(())
+        }
+
+        async fn request_pr_swap(&mut self, _port: LocalPortId, role: PowerRole) -> Result<(), Error<Self::BusError>> {
+            self.pr_swaps.push(role);
+            Ok(())
+        }
+
+        async fn get_controller_status(&mut self) -> Result<ControllerStatus<'static>, Error<Self::BusError>> {
+            Ok(ControllerStatus {
+                mode: "APP",
+                valid_fw_bank: true,
+                fw_version0: 0,
+                fw_version1: 0,
+                ready: true,
+            })
+        }
+
+        async fn enter_alt_mode(&mut self, _port: LocalPortId, svid: u16) -> Result<(), Error<Self::BusError>> {
+            if svid != DP_SVID {
+                return PdError::UnsupportedCommand.into();
+            }
+            self.alt_modes.push((svid, true));
+            Ok(())
+        }
+
+        async fn exit_alt_mode(&mut self, _port: LocalPortId, svid: u16) -> Result<(), Error<Self::BusError>> {
+            if svid != DP_SVID {
+                return PdError::UnsupportedCommand.into();
+            }
+            self.alt_modes.push((svid, false));
+            Ok(())
+        }
+
+        async fn request_dr_swap(&mut self, _port: LocalPortId, role: DataRole) -> Result<(), Error<Self::BusError>> {
+            self.dr_swaps.push(role);
+            Ok(())
+        }
+
+        async fn get_alternate_modes(&mut self, _port: LocalPortId) -> Result<AlternateModes, Error<Self::BusError>> {
+            Ok(self.supported_modes)
+        }
+
+        async fn get_current_cam(&mut self, _port: LocalPortId) -> Result<Option<u8>, Error<Self::BusError>> {
+            Ok(match self.alt_modes.last() {
+                Some((svid, true)) => self
+                    .supported_modes
+                    .as_slice()
+                    .iter()
+                    .position(|mode| mode.svid == *svid)
+                    .map(|index| index as u8),
+                _ => None,
+            })
+        }
+
+        async fn get_pd_message(
+            &mut self,
+            _port: LocalPortId,
+            message_type: PdMessageType,
+        ) -> Result<PdMessage, Error<Self::BusError>> {
+            match message_type {
+                PdMessageType::DiscoverIdentity => PdMessage::new(&DISCOVER_IDENTITY).map_err(Error::Pd),
+                _ => PdError::UnrecognizedCommand.into(),
+            }
+        }
+    }
+
+    /// Only one power policy context exists per process, so tests share its token and run one at a time
+    fn policy_token() -> (MutexGuard<'static, ()>, &'static policy::ContextToken) {
+        static LOCK: Mutex<()> = Mutex::new(());
+        static TOKEN: OnceLock<policy::ContextToken> = OnceLock::new();
+
+        let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        (guard, TOKEN.get_or_init(|| policy::ContextToken::create().unwrap()))
+    }
+
+    /// Completes every power policy request, recording what was requested
+    async fn respond_policy(token: &policy::ContextToken, requests: &RefCell<Vec<policy::RequestData>>) {
+        loop {
+            let request = token.wait_request().await;
+            requests.borrow_mut().push(request.data);
+            token.send_response(Ok(policy::ResponseData::Complete)).await;
+        }
+    }
+
+    /// Type-C context token shared by all tests
+    fn type_c_token() -> &'static controller::ContextToken {
+        static TOKEN: OnceLock<controller::ContextToken> = OnceLock::new();
+        TOKEN.get_or_init(|| controller::ContextToken::create().unwrap())
+    }
+
+    /// Complete every PD command sent to the wrapper's controller through the wrapper itself
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn respond_pd_commands<const N: usize>(wrapper: &ControllerWrapper<'static, N, MockController>) {
+        loop {
+            let request = wrapper.pd_controller.receive().await;
+            let mut controller = wrapper.controller.borrow_mut();
+            let response = wrapper.process_pd_command(&mut controller, &request.command).await;
+            request.respond(response);
+        }
+    }
+
+    fn new_wrapper() -> ControllerWrapper<'static, 1, MockController> {
+        ControllerWrapper::new(
+            controller::Device::new(ControllerId(0), &PORTS),
+            [policy::device::Device::new(DeviceId(0))],
+            MockController::new(),
+        )
+    }
+
+    /// Raise events on the mock and process them
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn process_events(wrapper: &ControllerWrapper<'static, 1, MockController>, events: PortEventKind) {
+        let mut controller = wrapper.controller.borrow_mut();
+        controller.events = events;
+        wrapper.process_event(&mut controller).await;
+    }
+
+    #[test]
+    fn test_role_swap_complete() {
+        let wrapper = new_wrapper();
+        let (_guard, token) = policy_token();
+        let requests = RefCell::new(Vec::new());
+
+        let test = async {
+            embedded_services::init().await;
+
+            // Low power dual-role partner, so we request to become the source instead
+            wrapper.controller.borrow_mut().status = PortStatus {
+                available_sink_contract: Some(POWER_CAPABILITY_5V_3A0),
+                connection_state: Some(ConnectionState::Attached),
+                dual_power: true,
+                ..PortStatus::new()
+            };
+            let mut events = PortEventKind::none();
+            events.set_plug_inserted_or_removed(true);
+            events.set_new_power_contract_as_consumer(true);
+            process_events(&wrapper, events).await;
+
+            assert_eq!(wrapper.controller.borrow().pr_swaps, [PowerRole::Source]);
+            assert_eq!(wrapper.power[0].state().await.kind(), StateKind::Idle);
+            assert!(!requests
+                .borrow()
+                .iter()
+                .any(|r| matches!(r, policy::RequestData::RequestProviderCapability(_))));
+
+            // Swap completes with us sourcing
+            wrapper.controller.borrow_mut().status = PortStatus {
+                available_source_contract: Some(POWER_CAPABILITY_5V_3A0),
+                connection_state: Some(ConnectionState::Attached),
+                dual_power: true,
+                ..PortStatus::new()
+            };
+            let mut events = PortEventKind::none();
+            events.set_role_swap_complete(true);
+            process_events(&wrapper, events).await;
+
+            assert_eq!(
+                requests.borrow().last(),
+                Some(&policy::RequestData::RequestProviderCapability(POWER_CAPABILITY_5V_3A0))
+            );
+            assert_eq!(wrapper.power[0].consumer_capability().await, None);
+            assert_eq!(wrapper.active_events[0].get(), events);
+        };
+
+        block_on(async {
+            match select(respond_policy(token, &requests), test).await {
+                Either::First(_) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+    }
+
+    /// Complete every power command sent to the wrapper's devices through the wrapper itself
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn respond_power_commands<const N: usize>(wrapper: &ControllerWrapper<'static, N, MockController>) {
+        loop {
+            let (request, port) = wrapper.wait_power_command().await;
+            let mut controller = wrapper.controller.borrow_mut();
+            let response = wrapper
+                .process_power_command(&mut controller, port, &request.command)
+                .await;
+            request.respond(response);
+        }
+    }
+
+    #[test]
+    fn test_contract_lost_detach() {
+        static PORTS: [GlobalPortId; 1] = [GlobalPortId(4)];
+        const ID: DeviceId = DeviceId(4);
+        let wrapper: &'static ControllerWrapper<'static, 1, MockController> =
+            Box::leak(Box::new(ControllerWrapper::new(
+                controller::Device::new(ControllerId(4), &PORTS),
+                [policy::device::Device::new(ID)],
+                MockController::new(),
+            )));
+        let (_guard, token) = policy_token();
+        let requests = RefCell::new(Vec::new());
+        let disconnect = policy::RequestData::NotifyDisconnect(policy::DisconnectReason::Unspecified);
+
+        let test = async {
+            embedded_services::init().await;
+            policy::register_device(&wrapper.power[0]).await.unwrap();
+            let mut contract_lost = PortEventKind::none();
+            contract_lost.set_contract_lost(true);
+
+            // Sink contract negotiated and the policy connects us as a consumer
+            wrapper.controller.borrow_mut().status = PortStatus {
+                available_sink_contract: Some(POWER_CAPABILITY_5V_3A0),
+                connection_state: Some(ConnectionState::Attached),
+                ..PortStatus::new()
+            };
+            let mut events = PortEventKind::none();
+            events.set_plug_inserted_or_removed(true);
+            events.set_new_power_contract_as_consumer(true);
+            process_events(wrapper, events).await;
+            match token.policy_action(ID).await.unwrap() {
+                action::policy::AnyState::Idle(state) => {
+                    state.connect_consumer(POWER_CAPABILITY_5V_3A0).await.unwrap();
+                }
+                _ => panic!("Expected idle device"),
+            }
+            assert_eq!(wrapper.power[0].state().await.kind(), StateKind::ConnectedConsumer);
+
+            // Source stops supplying while the partner stays attached
+            requests.borrow_mut().clear();
+            wrapper.controller.borrow_mut().status.available_sink_contract = None;
+            process_events(wrapper, contract_lost).await;
+
+            assert_eq!(
+                *requests.borrow(),
+                [disconnect, policy::RequestData::NotifyConsumerCapability(None)]
+            );
+            assert_eq!(wrapper.power[0].state().await.kind(), StateKind::Idle);
+            assert_eq!(wrapper.power[0].consumer_capability().await, None);
+
+            // Same for a provider whose sink stops drawing the contract
+            wrapper.controller.borrow_mut().status = PortStatus {
+                available_source_contract: Some(POWER_CAPABILITY_5V_1A5),
+                connection_state: Some(ConnectionState::Attached),
+                ..PortStatus::new()
+            };
+            match token.policy_action(ID).await.unwrap() {
+                action::policy::AnyState::Idle(state) => {
+                    state.connect_provider(POWER_CAPABILITY_5V_1A5).await.unwrap();
+                }
+                _ => panic!("Expected idle device"),
+            }
+            assert_eq!(wrapper.power[0].state().await.kind(), StateKind::ConnectedProvider);
+            requests.borrow_mut().clear();
+
+            wrapper.controller.borrow_mut().status.available_source_contract = None;
+            process_events(wrapper, contract_lost).await;
+
+            assert_eq!(*requests.borrow(), [disconnect]);
+            assert_eq!(wrapper.power[0].state().await.kind(), StateKind::Idle);
+        };
+
+        block_on(async {
+            match select3(respond_policy(token, &requests), respond_power_commands(wrapper), test).await {
+                Either3::Third(()) => (),
+                _ => unreachable!(),
+            }
+        });
+    }
+
+    #[test]
+    fn test_invalid_port() {
+        let wrapper = new_wrapper();
+        let port = LocalPortId(1);
+        let mut status = PortStatus::new();
+        status.connection_state = Some(ConnectionState::Attached);
+        status.available_sink_contract = Some(POWER_CAPABILITY_5V_3A0);
+
+        // Port N is one past the end and must be rejected
+        let mut controller = wrapper.controller.borrow_mut();
+        assert!(matches!(
+            block_on(wrapper.process_plug_event(&mut controller, &wrapper.power[0], port, &status)),
+            Err(Error::Pd(PdError::InvalidPort))
+        ));
+        assert!(matches!(
+            block_on(wrapper.process_new_consumer_contract(&mut controller, &wrapper.power[0], port, &status)),
+            Err(Error::Pd(PdError::InvalidPort))
+        ));
+        assert!(matches!(
+            wrapper.get_power_device(port),
+            Err(Error::Pd(PdError::InvalidPort))
+        ));
+        assert!(matches!(
+            block_on(wrapper.process_new_provider_contract(
+                &mut controller,
+                GlobalPortId(1),
+                &wrapper.power[0],
+                &status
+            )),
+            Err(Error::Pd(PdError::InvalidPort))
+        ));
+    }
+
+    #[test]
+    fn test_controller_offline() {
+        let wrapper = new_wrapper();
+        wrapper.set_offline_threshold(3);
+        wrapper.controller.borrow_mut().bus_error = true;
+
+        for _ in 0..2 {
+            block_on(wrapper.process());
+        }
+        assert!(!wrapper.is_offline());
+        assert!(!wrapper.offline.signaled());
+
+        block_on(wrapper.process());
+        assert!(wrapper.is_offline());
+        block_on(wrapper.wait_offline());
+
+        // Only signaled once
+        block_on(wrapper.process());
+        assert!(wrapper.is_offline());
+        assert!(!wrapper.offline.signaled());
+
+        // Any response brings the controller back online
+        wrapper.controller.borrow_mut().bus_error = false;
+        block_on(wrapper.process());
+        assert!(!wrapper.is_offline());
+    }
+
+    #[test]
+    fn test_runtime_port_count() {
+        static HUB_PORTS: [GlobalPortId; 2] = [GlobalPortId(0), GlobalPortId(1)];
+
+        for num_ports in 1..=2 {
+            let power = (0..num_ports)
+                .map(|i| policy::device::Device::new(DeviceId(10 + i as u8)))
+                .collect();
+            let default_sink_capability = [Some(POWER_CAPABILITY_5V_3A0)].into_iter().collect();
+            let wrapper: ControllerWrapper<'static, 2, MockController> = ControllerWrapper::new_with_port_count(
+                controller::Device::new(ControllerId(2), &HUB_PORTS[..num_ports]),
+                power,
+                MockController::new(),
+                default_sink_capability,
+            );
+
+            assert_eq!(wrapper.num_ports(), num_ports);
+            assert_eq!(wrapper.active_events.len(), num_ports);
+            assert_eq!(wrapper.event_history.len(), num_ports);
+            // Missing default capabilities are padded
+            assert_eq!(wrapper.default_sink_capability.len(), num_ports);
+            assert_eq!(wrapper.default_sink_capability[0], Some(POWER_CAPABILITY_5V_3A0));
+            assert!(wrapper.default_sink_capability[1..].iter().all(Option::is_none));
+
+            let last = LocalPortId(num_ports as u8 - 1);
+            let past_end = LocalPortId(num_ports as u8);
+            assert!(wrapper.get_power_device(last).is_ok());
+            assert!(wrapper.port_event_history(last).is_ok());
+            assert!(matches!(
+                wrapper.get_power_device(past_end),
+                Err(Error::Pd(PdError::InvalidPort))
+            ));
+            assert!(matches!(
+                wrapper.port_event_history(past_end),
+                Err(PdError::InvalidPort)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_port_event_history() {
+        let wrapper = new_wrapper();
+        let (_guard, token) = policy_token();
+        let requests = RefCell::new(Vec::new());
+
+        let test = async {
+            embedded_services::init().await;
+
+            let mut event = PortEventKind::none();
+            event.set_contract_lost(true);
+            for _ in 0..EVENT_HISTORY_DEPTH + 2 {
+                process_events(&wrapper, event).await;
+            }
+            // Cycles without events aren't recorded
+            process_events(&wrapper, PortEventKind::none()).await;
+
+            let history: Vec<EventRecord> = wrapper.port_event_history(LocalPortId(0)).unwrap().collect();
+            assert_eq!(history.len(), EVENT_HISTORY_DEPTH);
+            assert_eq!(history[0].sequence, 2);
+            assert!(history.iter().all(|r| r.event == event));
+
+            assert_eq!(wrapper.take_port_event_history_overflow(LocalPortId(0)), Ok(true));
+            assert_eq!(wrapper.take_port_event_history_overflow(LocalPortId(0)), Ok(false));
+            assert!(matches!(
+                wrapper.port_event_history(LocalPortId(1)),
+                Err(PdError::InvalidPort)
+            ));
+        };
+
+        block_on(async {
+            match select(respond_policy(token, &requests), test).await {
+                Either::First(_) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+    }
+
+    #[test]
+    fn test_get_pd_message() {
+        let wrapper = new_wrapper();
+        let mut controller = wrapper.controller.borrow_mut();
+        let command = |message_type| {
+            controller::Command::Port(controller::PortCommand {
+                port: GlobalPortId(0),
+                data: controller::PortCommandData::GetPdMessage { message_type },
+            })
+        };
+
+        match block_on(wrapper.process_pd_command(&mut controller, &command(PdMessageType::DiscoverIdentity))) {
+            controller::Response::Port(Ok(controller::PortResponseData::PdMessage(message))) => {
+                assert_eq!(message.as_bytes(), DISCOVER_IDENTITY);
+                let header = message.header();
+                // Vendor_Defined message
+                assert_eq!(header.message_type, 0xf);
+                assert_eq!(header.data_role, DataRole::Ufp);
+                assert_eq!(header.spec_revision, 2);
+                assert!(matches!(header.power_role, PowerRole::Sink));
+                assert_eq!(header.message_id, 3);
+                assert_eq!(header.num_data_objects, 1);
+                assert!(!header.extended);
+            }
+            _ => panic!("Unexpected response"),
+        }
+
+        assert!(matches!(
+            block_on(wrapper.process_pd_command(&mut controller, &command(PdMessageType::Revision))),
+            controller::Response::Port(Err(PdError::UnrecognizedCommand))
+        ));
+    }
+
+    #[test]
+    fn test_clamped_provider_current() {
+        static PORTS: [GlobalPortId; 2] = [GlobalPortId(1), GlobalPortId(2)];
+        // Shared provider budget, less than two 5V@3A providers
+        const BUDGET_MW: u32 = 20000;
+
+        let wrapper: ControllerWrapper<'static, 2, MockController> = ControllerWrapper::new(
+            controller::Device::new(ControllerId(1), &PORTS),
+            [
+                policy::device::Device::new(DeviceId(1)),
+                policy::device::Device::new(DeviceId(2)),
+            ],
+            MockController::new(),
+        );
+
+        // Allocations for two 5V@3A requests, the first in full and the second clamped to the remaining budget
+        // See tests/provider_budget.rs for the allocation by the power policy itself
+        let remaining_mw = BUDGET_MW - POWER_CAPABILITY_5V_3A0.max_power_mw();
+        let allocations = [
+            POWER_CAPABILITY_5V_3A0,
+            PowerCapability {
+                voltage_mv: 5000,
+                current_ma: (remaining_mw * 1000 / 5000) as u16,
+            },
+        ];
+
+        let mut controller = wrapper.controller.borrow_mut();
+        for (port, capability) in allocations.iter().enumerate() {
+            let command = policy::device::CommandData::ConnectProvider(*capability);
+            assert!(
+                block_on(wrapper.process_power_command(&mut controller, LocalPortId(port as u8), &command)).is_ok()
+            );
+        }
+
+        let currents = &controller.source_currents;
+        assert!(matches!(
+            currents.as_slice(),
+            [(0, TypecCurrent::Current3A0), (1, TypecCurrent::UsbDefault)]
+        ));
+        let total_mw: u32 = currents
+            .iter()
+            .map(|(_, current)| PowerCapability::from(*current).max_power_mw())
+            .sum();
+        assert!(total_mw <= BUDGET_MW);
+
+        // Allocations below the type-C default current can't be sourced
+        let command = policy::device::CommandData::ConnectProvider(PowerCapability {
+            voltage_mv: 5000,
+            current_ma: 100,
+        });
+        assert!(block_on(wrapper.process_power_command(&mut controller, LocalPortId(1), &command)).is_err());
+    }
+
+    #[test]
+    fn test_data_role_swap() {
+        let wrapper = new_wrapper();
+        let mut controller = wrapper.controller.borrow_mut();
+
+        for role in [DataRole::Ufp, DataRole::Dfp] {
+            let command = controller::Command::Port(controller::PortCommand {
+                port: GlobalPortId(0),
+                data: controller::PortCommandData::DataRoleSwap(role),
+            });
+            assert!(matches!(
+                block_on(wrapper.process_pd_command(&mut controller, &command)),
+                controller::Response::Port(Ok(controller::PortResponseData::Complete))
+            ));
+        }
+
+        assert_eq!(controller.dr_swaps, [DataRole::Ufp, DataRole::Dfp]);
+    }
+
+    #[test]
+    fn test_alt_mode_entry_exit() {
+        let wrapper = new_wrapper();
+        let mut controller = wrapper.controller.borrow_mut();
+        let command = |data| {
+            controller::Command::Port(controller::PortCommand {
+                port: GlobalPortId(0),
+                data,
+            })
+        };
+
+        for data in [
+            controller::PortCommandData::EnterAltMode(DP_SVID),
+            controller::PortCommandData::ExitAltMode(DP_SVID),
+        ] {
+            assert!(matches!(
+                block_on(wrapper.process_pd_command(&mut controller, &command(data))),
+                controller::Response::Port(Ok(controller::PortResponseData::Complete))
+            ));
+        }
+        assert_eq!(controller.alt_modes, [(DP_SVID, true), (DP_SVID, false)]);
+
+        // Unsupported modes are rejected without reaching the mock
+        assert!(matches!(
+            block_on(wrapper.process_pd_command(
+                &mut controller,
+                &command(controller::PortCommandData::EnterAltMode(0x8087))
+            )),
+            controller::Response::Port(Err(PdError::UnsupportedCommand))
+        ));
+        assert_eq!(controller.alt_modes.len(), 2);
+    }
+
+    #[test]
+    fn test_default_sink_capability() {
+        static PORTS: [GlobalPortId; 1] = [GlobalPortId(3)];
+        let wrapper: ControllerWrapper<'static, 1, MockController> =
+            ControllerWrapper::new_with_default_sink_capability(
+                controller::Device::new(ControllerId(3), &PORTS),
+                [policy::device::Device::new(DeviceId(3))],
+                MockController::new(),
+                [Some(POWER_CAPABILITY_5V_1A5)],
+            );
+        let (_guard, token) = policy_token();
+        let requests = RefCell::new(Vec::new());
+
+        let test = async {
+            embedded_services::init().await;
+
+            // Connected but no contract negotiated yet
+            wrapper.controller.borrow_mut().status = PortStatus {
+                connection_state: Some(ConnectionState::Attached),
+                ..PortStatus::new()
+            };
+            let mut events = PortEventKind::none();
+            events.set_plug_inserted_or_removed(true);
+            process_events(&wrapper, events).await;
+
+            assert_eq!(
+                requests.borrow().last(),
+                Some(&policy::RequestData::NotifyConsumerCapability(Some(
+                    POWER_CAPABILITY_5V_1A5
+                )))
+            );
+            assert_eq!(
+                wrapper.power[0].consumer_capability().await,
+                Some(POWER_CAPABILITY_5V_1A5)
+            );
+        };
+
+        block_on(async {
+            match select(respond_policy(token, &requests), test).await {
+                Either::First(_) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+    }
+
+    #[test]
+    fn test_alternate_modes_query() {
+        static PORTS: [GlobalPortId; 1] = [GlobalPortId(5)];
+        let wrapper: &'static ControllerWrapper<'static, 1, MockController> =
+            Box::leak(Box::new(ControllerWrapper::new(
+                controller::Device::new(ControllerId(5), &PORTS),
+                [policy::device::Device::new(DeviceId(5))],
+                MockController::new(),
+            )));
+
+        let test = async {
+            embedded_services::init().await;
+            controller::register_controller(&wrapper.pd_controller).await.unwrap();
+            let token = type_c_token();
+
+            // Mode list comes from the controller
+            let modes = token.get_alternate_modes(GlobalPortId(5)).await.unwrap();
+            assert_eq!(
+                modes.as_slice(),
+                [AltModeInfo {
+                    svid: DP_SVID,
+                    vdo: DP_VDO
+                }]
+            );
+            assert_eq!(token.get_current_cam(GlobalPortId(5)).await, Ok(None));
+
+            token.enter_alt_mode(GlobalPortId(5), DP_SVID).await.unwrap();
+            assert_eq!(token.get_current_cam(GlobalPortId(5)).await, Ok(Some(0)));
+        };
+
+        block_on(async {
+            match select(respond_pd_commands(wrapper), test).await {
+                Either::First(_) => unreachable!(),
+                Either::Second(()) => (),
+            }
+        });
+    }
+}
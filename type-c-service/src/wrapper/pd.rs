@@ -25,6 +25,71 @@ impl<const N: usize, C: Controller> ControllerWrapper<'_, N, C> {
                 self.active_events[0].set(PortEventKind::none());
                 Ok(controller::PortResponseData::ClearEvents(event))
             }
+            controller::PortCommandData::AlternateModes => match controller.get_alternate_modes(local_port).await {
+                Ok(modes) => Ok(controller::PortResponseData::AlternateModes(modes)),
+                Err(e) => match e {
+                    Error::Bus(_) => Err(PdError::Failed),
+                    Error::Pd(e) => Err(e),
+                },
+            },
+            controller::PortCommandData::CurrentCam => match controller.get_current_cam(local_port).await {
+                Ok(cam) => Ok(controller::PortResponseData::CurrentCam(cam)),
+                Err(e) => match e {
+                    Error::Bus(_) => Err(PdError::Failed),
+                    Error::Pd(e) => Err(e),
+                },
+            },
+            controller::PortCommandData::SetNewCam { mode_index, enter } => {
+                match controller.set_new_cam(local_port, mode_index, enter).await {
+                    Ok(()) => Ok(controller::PortResponseData::Complete),
+                    Err(e) => match e {
+                        Error::Bus(_) => Err(PdError::Failed),
+                        Error::Pd(e) => Err(e),
+                    },
+                }
+            }
+            controller::PortCommandData::DataRoleSwap(role) => match controller.request_dr_swap(local_port, role).await
+            {
+                Ok(()) => Ok(controller::PortResponseData::Complete),
+                Err(e) => match e {
+                    Error::Bus(_) => Err(PdError::Failed),
+                    Error::Pd(e) => Err(e),
+                },
+            },
+            controller::PortCommandData::EnterAltMode(svid) => {
+                match controller.enter_alt_mode(local_port, svid).await {
+                    Ok(()) => Ok(controller::PortResponseData::Complete),
+                    Err(e) => match e {
+                        Error::Bus(_) => Err(PdError::Failed),
+                        Error::Pd(e) => Err(e),
+                    },
+                }
+            }
+            controller::PortCommandData::ExitAltMode(svid) => match controller.exit_alt_mode(local_port, svid).await {
+                Ok(()) => Ok(controller::PortResponseData::Complete),
+                Err(e) => match e {
+                    Error::Bus(_) => Err(PdError::Failed),
+                    Error::Pd(e) => Err(e),
+                },
+            },
+            controller::PortCommandData::GetPdMessage { message_type } => {
+                match controller.get_pd_message(local_port, message_type).await {
+                    Ok(message) => Ok(controller::PortResponseData::PdMessage(message)),
+                    Err(e) => match e {
+                        Error::Bus(_) => Err(PdError::Failed),
+                        Error::Pd(e) => Err(e),
+                    },
+                }
+            }
+            controller::PortCommandData::GetPdos { partner, source } => {
+                match controller.get_pdos(local_port, partner, source).await {
+                    Ok(pdos) => Ok(controller::PortResponseData::Pdos(pdos)),
+                    Err(e) => match e {
+                        Error::Bus(_) => Err(PdError::Failed),
+                        Error::Pd(e) => Err(e),
+                    },
+                }
+            }
         })
     }
 
@@ -38,6 +103,14 @@ impl<const N: usize, C: Controller> ControllerWrapper<'_, N, C> {
                 let status = controller.get_controller_status().await;
                 controller::Response::Controller(status.map(InternalResponseData::Status).map_err(|_| PdError::Failed))
             }
+            controller::InternalCommandData::SyncState => {
+                let result = controller.sync_state().await;
+                controller::Response::Controller(
+                    result
+                        .map(|_| InternalResponseData::Complete)
+                        .map_err(|_| PdError::Failed),
+                )
+            }
             _ => controller::Response::Controller(Err(PdError::UnrecognizedCommand)),
         }
     }
@@ -1,4 +1,8 @@
 //! Module contain power-policy related message handling
+use core::future::{pending, Future};
+use core::pin::pin;
+
+use embassy_futures::select::{select, Either};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embedded_services::{
     ipc::deferred,
@@ -6,19 +10,34 @@ use embedded_services::{
         device::{CommandData, InternalResponseData},
         PowerCapability,
     },
-    type_c::{
-        POWER_CAPABILITY_5V_1A5, POWER_CAPABILITY_5V_3A0, POWER_CAPABILITY_USB_DEFAULT_USB2,
-        POWER_CAPABILITY_USB_DEFAULT_USB3,
-    },
+    type_c::{POWER_CAPABILITY_5V_1A5, POWER_CAPABILITY_5V_3A0, POWER_CAPABILITY_USB_DEFAULT_USB2},
 };
 use embedded_usb_pd::{GlobalPortId, PowerRole};
 
 use super::*;
 
+/// Returns the highest type-C source current that fits within `capability`
+/// The power policy clamps providers to fit its shared budget, so the allocation isn't always an exact type-C current
+fn source_current_within(capability: PowerCapability) -> Option<TypecCurrent> {
+    if capability.voltage_mv != POWER_CAPABILITY_5V_3A0.voltage_mv {
+        return None;
+    }
+
+    if capability.current_ma >= POWER_CAPABILITY_5V_3A0.current_ma {
+        Some(TypecCurrent::Current3A0)
+    } else if capability.current_ma >= POWER_CAPABILITY_5V_1A5.current_ma {
+        Some(TypecCurrent::Current1A5)
+    } else if capability.current_ma >= POWER_CAPABILITY_USB_DEFAULT_USB2.current_ma {
+        Some(TypecCurrent::UsbDefault)
+    } else {
+        None
+    }
+}
+
 impl<const N: usize, C: Controller> ControllerWrapper<'_, N, C> {
     /// Return the power device for the given port
     pub(super) fn get_power_device(&self, port: LocalPortId) -> Result<&policy::device::Device, Error<C::BusError>> {
-        if port.0 > N as u8 {
+        if port.0 as usize >= self.num_ports() {
             return PdError::InvalidPort.into();
         }
         Ok(&self.power[port.0 as usize])
@@ -32,6 +51,10 @@ impl<const N: usize, C: Controller> ControllerWrapper<'_, N, C> {
         port: LocalPortId,
         status: &PortStatus,
     ) -> Result<(), Error<C::BusError>> {
+        if port.0 as usize >= self.num_ports() {
+            return PdError::InvalidPort.into();
+        }
+
         info!("New consumer contract");
 
         if let Some(capability) = status.available_sink_contract {
@@ -51,6 +74,7 @@ impl<const N: usize, C: Controller> ControllerWrapper<'_, N, C> {
             }
         }
 
+        let capability = status.sink_capability_or(self.default_sink_capability[port.0 as usize]);
         let current_state = power.state().await.kind();
         // Don't update the available consumer contract if we're providing power
         if current_state != StateKind::ConnectedProvider {
@@ -63,18 +87,12 @@ impl<const N: usize, C: Controller> ControllerWrapper<'_, N, C> {
             }
 
             if let Ok(state) = power.try_device_action::<action::Idle>().await {
-                if let Err(e) = state
-                    .notify_consumer_power_capability(status.available_sink_contract)
-                    .await
-                {
+                if let Err(e) = state.notify_consumer_power_capability(capability).await {
                     error!("Error setting power contract: {:?}", e);
                     return PdError::Failed.into();
                 }
             } else if let Ok(state) = power.try_device_action::<action::ConnectedConsumer>().await {
-                if let Err(e) = state
-                    .notify_consumer_power_capability(status.available_sink_contract)
-                    .await
-                {
+                if let Err(e) = state.notify_consumer_power_capability(capability).await {
                     error!("Error setting power contract: {:?}", e);
                     return PdError::Failed.into();
                 }
@@ -87,17 +105,45 @@ impl<const N: usize, C: Controller> ControllerWrapper<'_, N, C> {
         Ok(())
     }
 
+    /// Wait for `request` to the power policy while servicing power commands for our ports
+    /// The power policy may command our other ports before it responds, e.g. when allocating its provider budget
+    async fn with_power_commands<T>(&self, controller: &mut C, request: impl Future<Output = T>) -> T {
+        let mut request = pin!(request);
+        loop {
+            match select(&mut request, self.wait_power_command()).await {
+                Either::First(result) => return result,
+                Either::Second((command, port)) => {
+                    let response = self.process_power_command(controller, port, &command.command).await;
+                    command.respond(response);
+                }
+            }
+        }
+    }
+
     /// Handle a new provider contract
+    /// Provider contracts on multiple ports are allocated from the power policy budget, so power commands for our
+    /// ports are serviced while the request is pending
     pub(super) async fn process_new_provider_contract(
         &self,
+        controller: &mut C,
         port: GlobalPortId,
         power: &policy::device::Device,
         status: &PortStatus,
     ) -> Result<(), Error<C::BusError>> {
-        if port.0 > N as u8 {
+        if self.pd_controller.lookup_local_port(port).is_err() {
             return PdError::InvalidPort.into();
         }
 
+        self.with_power_commands(controller, self.request_provider_contract(power, status))
+            .await
+    }
+
+    /// Request or release a provider contract with the power policy
+    async fn request_provider_contract(
+        &self,
+        power: &policy::device::Device,
+        status: &PortStatus,
+    ) -> Result<(), Error<C::BusError>> {
         let current_state = power.state().await.kind();
         // Don't attempt to source if we're consuming power
         if current_state != StateKind::ConnectedConsumer {
@@ -111,16 +157,24 @@ impl<const N: usize, C: Controller> ControllerWrapper<'_, N, C> {
 
             if let Ok(state) = power.try_device_action::<action::Idle>().await {
                 if let Some(contract) = status.available_source_contract {
-                    if let Err(e) = state.request_provider_power_capability(contract).await {
-                        error!("Error setting power contract: {:?}", e);
-                        return PdError::Failed.into();
+                    match state.request_provider_power_capability(contract).await {
+                        // Connected, but at the lower power left in the budget
+                        Ok(()) | Err(policy::Error::CannotProvide(Some(_))) => (),
+                        Err(e) => {
+                            error!("Error setting power contract: {:?}", e);
+                            return PdError::Failed.into();
+                        }
                     }
                 }
             } else if let Ok(state) = power.try_device_action::<action::ConnectedProvider>().await {
                 if let Some(contract) = status.available_source_contract {
-                    if let Err(e) = state.request_provider_power_capability(contract).await {
-                        error!("Error setting power contract: {:?}", e);
-                        return PdError::Failed.into();
+                    match state.request_provider_power_capability(contract).await {
+                        // Connected, but at the lower power left in the budget
+                        Ok(()) | Err(policy::Error::CannotProvide(Some(_))) => (),
+                        Err(e) => {
+                            error!("Error setting power contract: {:?}", e);
+                            return PdError::Failed.into();
+                        }
                     }
                 } else {
                     // No longer need to source, so disconnect
@@ -138,6 +192,109 @@ impl<const N: usize, C: Controller> ControllerWrapper<'_, N, C> {
         Ok(())
     }
 
+    /// Handle a contract lost while still connected
+    pub(super) async fn process_contract_lost(
+        &self,
+        power: &policy::device::Device,
+        status: &PortStatus,
+    ) -> Result<(), Error<C::BusError>> {
+        info!("Contract lost");
+
+        match power.device_action().await {
+            action::device::AnyState::ConnectedConsumer(state) if status.available_sink_contract.is_none() => {
+                let state = match state.disconnect().await {
+                    Ok(state) => state,
+                    Err(e) => {
+                        error!("Error disconnecting consumer: {:?}", e);
+                        return PdError::Failed.into();
+                    }
+                };
+
+                if let Err(e) = state.notify_consumer_power_capability(None).await {
+                    error!("Error clearing power contract: {:?}", e);
+                    return PdError::Failed.into();
+                }
+            }
+            action::device::AnyState::Idle(state) if status.available_sink_contract.is_none() => {
+                if let Err(e) = state.notify_consumer_power_capability(None).await {
+                    error!("Error clearing power contract: {:?}", e);
+                    return PdError::Failed.into();
+                }
+            }
+            action::device::AnyState::ConnectedProvider(state) if status.available_source_contract.is_none() => {
+                if let Err(e) = state.disconnect().await {
+                    error!("Error disconnecting provider: {:?}", e);
+                    return PdError::Failed.into();
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Handle a completed role swap by re-evaluating the power role of the port
+    pub(super) async fn process_role_swap_complete(
+        &self,
+        controller: &mut C,
+        global_port: GlobalPortId,
+        power: &policy::device::Device,
+        port: LocalPortId,
+        status: &PortStatus,
+    ) -> Result<(), Error<C::BusError>> {
+        info!("Port{}: Role swap complete", port.0);
+
+        if status.available_source_contract.is_some() {
+            // Now sourcing, withdraw anything we were consuming
+            let consuming = power.consumer_capability().await.is_some();
+            match power.device_action().await {
+                action::device::AnyState::ConnectedConsumer(state) => {
+                    let state = match state
+                        .disconnect_with_reason(policy::policy::DisconnectReason::RoleSwap)
+                        .await
+                    {
+                        Ok(state) => state,
+                        Err(e) => {
+                            error!("Error disconnecting consumer: {:?}", e);
+                            return PdError::Failed.into();
+                        }
+                    };
+
+                    if let Err(e) = state.notify_consumer_power_capability(None).await {
+                        error!("Error clearing power contract: {:?}", e);
+                        return PdError::Failed.into();
+                    }
+                }
+                action::device::AnyState::Idle(state) if consuming => {
+                    if let Err(e) = state.notify_consumer_power_capability(None).await {
+                        error!("Error clearing power contract: {:?}", e);
+                        return PdError::Failed.into();
+                    }
+                }
+                _ => {}
+            }
+
+            self.process_new_provider_contract(controller, global_port, power, status)
+                .await
+        } else if status.available_sink_contract.is_some() {
+            // Now sinking, stop providing
+            if let Ok(state) = power.try_device_action::<action::ConnectedProvider>().await {
+                if let Err(e) = state
+                    .disconnect_with_reason(policy::policy::DisconnectReason::RoleSwap)
+                    .await
+                {
+                    error!("Error disconnecting provider: {:?}", e);
+                    return PdError::Failed.into();
+                }
+            }
+
+            self.process_new_consumer_contract(controller, power, port, status)
+                .await
+        } else {
+            Ok(())
+        }
+    }
+
     /// Handle a disconnect command
     async fn process_disconnect(
         &self,
@@ -181,11 +338,9 @@ impl<const N: usize, C: Controller> ControllerWrapper<'_, N, C> {
         controller: &mut C,
     ) -> Result<(), Error<C::BusError>> {
         info!("Port{}: Connect provider: {:#?}", port.0, capability);
-        let current = match capability {
-            POWER_CAPABILITY_USB_DEFAULT_USB2 | POWER_CAPABILITY_USB_DEFAULT_USB3 => TypecCurrent::UsbDefault,
-            POWER_CAPABILITY_5V_1A5 => TypecCurrent::Current1A5,
-            POWER_CAPABILITY_5V_3A0 => TypecCurrent::Current3A0,
-            _ => {
+        let current = match source_current_within(capability) {
+            Some(current) => current,
+            None => {
                 error!("Invalid power capability");
                 return PdError::InvalidParams.into();
             }
@@ -207,7 +362,13 @@ impl<const N: usize, C: Controller> ControllerWrapper<'_, N, C> {
         deferred::Request<'_, NoopRawMutex, CommandData, InternalResponseData>,
         LocalPortId,
     ) {
-        let futures: [_; N] = from_fn(|i| self.power[i].receive());
+        let futures: [_; N] = from_fn(|i| async move {
+            match self.power.get(i) {
+                Some(device) => device.receive().await,
+                // Ports beyond the port count never receive commands
+                None => pending().await,
+            }
+        });
         let (request, local_id) = select_array(futures).await;
         trace!("Power command: device{} {:#?}", local_id, request.command);
         (request, LocalPortId(local_id as u8))
@@ -224,34 +385,30 @@ impl<const N: usize, C: Controller> ControllerWrapper<'_, N, C> {
         trace!("Processing power command: device{} {:#?}", port.0, command);
         let power = match self.get_power_device(port) {
             Ok(power) => power,
-            Err(_) => {
+            Err(e) => {
                 error!("Port{}: Error getting power device for port", port.0);
-                return Err(policy::Error::InvalidDevice);
+                return Err(e.into());
             }
         };
 
         match command {
             policy::device::CommandData::ConnectConsumer(capability) => {
                 info!("Port{}: Connect consumer: {:?}", port.0, capability);
-                if controller.enable_sink_path(port, true).await.is_err() {
+                if let Err(e) = controller.enable_sink_path(port, true).await {
                     error!("Error enabling sink path");
-                    return Err(policy::Error::Failed);
+                    return Err(e.into());
                 }
             }
             policy::device::CommandData::ConnectProvider(capability) => {
-                if self
-                    .process_connect_provider(port, *capability, controller)
-                    .await
-                    .is_err()
-                {
+                if let Err(e) = self.process_connect_provider(port, *capability, controller).await {
                     error!("Error processing connect provider");
-                    return Err(policy::Error::Failed);
+                    return Err(e.into());
                 }
             }
             policy::device::CommandData::Disconnect => {
-                if self.process_disconnect(port, controller, power).await.is_err() {
+                if let Err(e) = self.process_disconnect(port, controller, power).await {
                     error!("Error processing disconnect");
-                    return Err(policy::Error::Failed);
+                    return Err(e.into());
                 }
             }
         }
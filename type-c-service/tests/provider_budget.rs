@@ -0,0 +1,157 @@
+//! Provider contracts on both ports of a controller, allocated from the budget of a real power policy
+//! Only one power policy can exist per process, so this runs separately from the wrapper unit tests
+//! which respond to power policy requests themselves
+use std::cell::RefCell;
+use std::future::pending;
+
+use embassy_futures::select::{select3, Either3};
+use embassy_futures::{block_on, yield_now};
+use embedded_services::power::policy::{self, DeviceId, PowerCapability};
+use embedded_services::type_c::controller::{self, Controller, ControllerStatus, PortStatus};
+use embedded_services::type_c::event::PortEventKind;
+use embedded_services::type_c::{ControllerId, POWER_CAPABILITY_5V_3A0};
+use embedded_usb_pd::type_c::{ConnectionState, Current as TypecCurrent};
+use embedded_usb_pd::{Error, GlobalPortId, PortId as LocalPortId, PowerRole};
+use power_policy_service::{config, PowerPolicy};
+use type_c_service::wrapper::ControllerWrapper;
+
+/// Shared provider budget, less than two 5V@3A providers
+const BUDGET_MW: u32 = 20000;
+
+/// Two port controller where both ports establish a 5V@3A provider contract at the same time
+struct MockController {
+    events: [PortEventKind; 2],
+    status: PortStatus,
+    /// Source currents set on each port, in order
+    source_currents: &'static RefCell<Vec<(u8, TypecCurrent)>>,
+}
+
+impl Controller for MockController {
+    type BusError = ();
+
+    async fn sync_state(&mut self) -> Result<(), Error<Self::BusError>> {
+        Ok(())
+    }
+
+    async fn wait_port_event(&mut self) -> Result<(), Error<Self::BusError>> {
+        if self.events.iter().all(|event| *event == PortEventKind::none()) {
+            pending::<()>().await;
+        }
+        Ok(())
+    }
+
+    async fn clear_port_events(&mut self, port: LocalPortId) -> Result<PortEventKind, Error<Self::BusError>> {
+        Ok(std::mem::replace(
+            &mut self.events[port.0 as usize],
+            PortEventKind::none(),
+        ))
+    }
+
+    async fn get_port_status(&mut self, _port: LocalPortId) -> Result<PortStatus, Error<Self::BusError>> {
+        Ok(self.status)
+    }
+
+    async fn enable_sink_path(&mut self, _port: LocalPortId, _enable: bool) -> Result<(), Error<Self::BusError>> {
+        Ok(())
+    }
+
+    async fn set_sourcing(&mut self, _port: LocalPortId, _enable: bool) -> Result<(), Error<Self::BusError>> {
+        Ok(())
+    }
+
+    async fn set_source_current(
+        &mut self,
+        port: LocalPortId,
+        current: TypecCurrent,
+        _signal_event: bool,
+    ) -> Result<(), Error<Self::BusError>> {
+        self.source_currents.borrow_mut().push((port.0, current));
+        Ok(())
+    }
+
+    async fn request_pr_swap(&mut self, _port: LocalPortId, _role: PowerRole) -> Result<(), Error<Self::BusError>> {
+        Ok(())
+    }
+
+    async fn get_controller_status(&mut self) -> Result<ControllerStatus<'static>, Error<Self::BusError>> {
+        Ok(ControllerStatus {
+            mode: "APP",
+            valid_fw_bank: true,
+            fw_version0: 0,
+            fw_version1: 0,
+            ready: true,
+        })
+    }
+}
+
+#[test]
+fn test_two_providers_within_budget() {
+    static PORTS: [GlobalPortId; 2] = [GlobalPortId(0), GlobalPortId(1)];
+    const IDS: [DeviceId; 2] = [DeviceId(0), DeviceId(1)];
+
+    block_on(embedded_services::init());
+
+    // Both ports are allowed full power, only the budget limits them
+    let config = config::Config {
+        limited_power_threshold_mw: 2 * POWER_CAPABILITY_5V_3A0.max_power_mw(),
+        total_budget_mw: Some(BUDGET_MW),
+        ..Default::default()
+    };
+    let power_policy: &'static PowerPolicy = Box::leak(Box::new(PowerPolicy::create(config).unwrap()));
+
+    let source_currents: &'static RefCell<Vec<(u8, TypecCurrent)>> = Box::leak(Box::new(RefCell::new(Vec::new())));
+    let mut events = PortEventKind::none();
+    events.set_new_power_contract_as_provider(true);
+    let wrapper: &'static ControllerWrapper<'static, 2, MockController> = Box::leak(Box::new(ControllerWrapper::new(
+        controller::Device::new(ControllerId(0), &PORTS),
+        IDS.map(policy::device::Device::new),
+        MockController {
+            events: [events; 2],
+            status: PortStatus {
+                available_source_contract: Some(POWER_CAPABILITY_5V_3A0),
+                connection_state: Some(ConnectionState::Attached),
+                ..PortStatus::new()
+            },
+            source_currents,
+        },
+    )));
+    block_on(wrapper.register()).unwrap();
+
+    let run_policy = async {
+        loop {
+            let _ = power_policy.process().await;
+        }
+    };
+    let run_wrapper = async {
+        loop {
+            wrapper.process().await;
+        }
+    };
+    let wait_sourcing = async {
+        while source_currents.borrow().len() < 2 {
+            yield_now().await;
+        }
+    };
+    block_on(async {
+        match select3(run_policy, run_wrapper, wait_sourcing).await {
+            Either3::Third(()) => (),
+            _ => unreachable!(),
+        }
+    });
+
+    // The first port gets its full contract, the second is limited to what's left of the budget
+    assert!(matches!(
+        source_currents.borrow().as_slice(),
+        [(0, TypecCurrent::Current3A0), (1, TypecCurrent::UsbDefault)]
+    ));
+    let total_mw: u32 = source_currents
+        .borrow()
+        .iter()
+        .map(|(_, current)| PowerCapability::from(*current).max_power_mw())
+        .sum();
+    assert!(total_mw <= BUDGET_MW);
+
+    let mut providers = block_on(power_policy.active_providers::<2>());
+    providers.sort_unstable_by_key(|id| id.0);
+    assert_eq!(providers, IDS);
+}